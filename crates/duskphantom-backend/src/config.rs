@@ -24,6 +24,19 @@ pub struct Config {
     pub num_parallel_for_block_gen_asm: usize,
     pub reg_alloc_algo: String,
     pub open_auto_parallel: bool,
+    /// Use `czero.eqz` / `czero.nez` from Zicond extension to lower `select`
+    #[serde(default)]
+    pub open_zicond: bool,
+    /// Stack size of each thread cloned by thread runtime in bytes
+    #[serde(default = "default_thread_stack_size")]
+    pub thread_stack_size: usize,
+    /// Pin each thread of thread runtime to its own cpu
+    #[serde(default)]
+    pub open_thread_affinity: bool,
+}
+
+fn default_thread_stack_size() -> usize {
+    512 << 20
 }
 
 lazy_static! {
@@ -52,6 +65,18 @@ lazy_static! {
                     .unwrap_or_else(|_| "true".to_string())
                     .parse()
                     .unwrap_or(false),
+                open_zicond: env::var("OPEN_ZICOND")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .unwrap_or(false),
+                thread_stack_size: env::var("THREAD_STACK_SIZE")
+                    .ok()
+                    .and_then(|size| size.parse().ok())
+                    .unwrap_or_else(default_thread_stack_size),
+                open_thread_affinity: env::var("OPEN_THREAD_AFFINITY")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .unwrap_or(false),
             }
         }
     };
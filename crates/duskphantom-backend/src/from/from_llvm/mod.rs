@@ -30,6 +30,6 @@ pub use duskphantom_utils::context;
 
 #[cfg(feature = "clang_enabled")]
 #[allow(unused)]
-pub fn gen_from_clang(program: &clang_frontend::Program) -> Result<Program> {
-    builder::IRBuilder::gen_from_clang(program)
+pub fn gen_from_clang(program: &clang_frontend::Program, thread_count: usize) -> Result<Program> {
+    builder::IRBuilder::gen_from_clang(program, thread_count)
 }
@@ -24,7 +24,7 @@ use duskphantom_utils::mem::ObjPtr;
 pub struct IRBuilder;
 
 impl IRBuilder {
-    pub fn gen_from_self(program: &middle::Program) -> Result<Program> {
+    pub fn gen_from_self(program: &middle::Program, thread_count: usize) -> Result<Program> {
         let self_module = &program.module;
         // dbg!(&llvm.types);
         let mut global_vars = Self::build_global_var(&self_module.global_variables)?;
@@ -42,6 +42,7 @@ impl IRBuilder {
             entry: Some("main".to_string()),
             global: global_vars,
             funcs,
+            thread_count,
         };
 
         Ok(prog::Program {
@@ -180,6 +180,12 @@ impl IRBuilder {
                     downcast_ref::<middle::ir::instruction::misc_inst::Phi>(inst.as_ref().as_ref());
                 Self::build_phi_inst(phi, reg_gener, regs, insert_back_for_remove_phi)
             }
+            middle::ir::instruction::InstType::Select => {
+                let select = downcast_ref::<middle::ir::instruction::misc_inst::Select>(
+                    inst.as_ref().as_ref(),
+                );
+                Self::build_select_inst(select, reg_gener, regs)
+            }
             middle::ir::instruction::InstType::Call => {
                 let call = downcast_ref::<middle::ir::instruction::misc_inst::Call>(
                     inst.as_ref().as_ref(),
@@ -427,6 +433,58 @@ impl IRBuilder {
         Ok(ret)
     }
 
+    /// select 不生成分支: 默认用掩码实现 dst = f ^ ((t ^ f) & -cond),
+    /// 开启 Zicond 时用 czero 实现 dst = czero.eqz(t, cond) | czero.nez(f, cond)
+    fn build_select_inst(
+        select: &middle::ir::instruction::misc_inst::Select,
+        reg_gener: &mut RegGenerator,
+        regs: &mut HashMap<Address, Reg>,
+    ) -> Result<Vec<Inst>> {
+        let ty = select.get_value_type();
+        if !matches!(ty, middle::ir::ValueType::Int | middle::ir::ValueType::Bool) {
+            return Err(anyhow!("select of {} is not supported", ty)).with_context(|| context!());
+        }
+
+        let mut ret = Vec::new();
+        let (cond, prepare) =
+            Self::prepare_rs1_i(select.get_cond(), reg_gener, regs).with_context(|| context!())?;
+        ret.extend(prepare);
+        let (true_val, prepare) = Self::prepare_rs1_i(select.get_true_val(), reg_gener, regs)
+            .with_context(|| context!())?;
+        ret.extend(prepare);
+        let (false_val, prepare) = Self::prepare_rs1_i(select.get_false_val(), reg_gener, regs)
+            .with_context(|| context!())?;
+        ret.extend(prepare);
+
+        let dst = reg_gener.gen_virtual_usual_reg();
+        if crate::config::CONFIG.open_zicond {
+            // cond 为 0 时 t 被置零, cond 非 0 时 f 被置零
+            let t = reg_gener.gen_virtual_usual_reg();
+            let f = reg_gener.gen_virtual_usual_reg();
+            let czero_eqz = CzeroEqzInst::new(t.into(), true_val.into(), cond.into());
+            let czero_nez = CzeroNezInst::new(f.into(), false_val.into(), cond.into());
+            let or = OrInst::new(dst.into(), t.into(), f.into());
+            ret.push(czero_eqz.into());
+            ret.push(czero_nez.into());
+            ret.push(or.into());
+        } else {
+            // cond 为 1 时 mask 全 1, 为 0 时 mask 全 0
+            let mask = reg_gener.gen_virtual_usual_reg();
+            let diff = reg_gener.gen_virtual_usual_reg();
+            let masked = reg_gener.gen_virtual_usual_reg();
+            let sub = SubInst::new(mask.into(), REG_ZERO.into(), cond.into());
+            let xor = XorInst::new(diff.into(), true_val.into(), false_val.into());
+            let and = AndInst::new(masked.into(), diff.into(), mask.into());
+            let xor_back = XorInst::new(dst.into(), false_val.into(), masked.into());
+            ret.push(sub.into());
+            ret.push(xor.into());
+            ret.push(and.into());
+            ret.push(xor_back.into());
+        }
+        regs.insert(select as *const _ as Address, dst);
+        Ok(ret)
+    }
+
     /// alloca instruction only instruct allocating memory on stack,not generate one-one instruction
     fn build_alloca_inst(
         alloca: &middle::ir::instruction::memory_op_inst::Alloca,
@@ -45,6 +45,6 @@ pub use duskphantom_utils::mem::ObjPtr;
 type Address = usize;
 
 #[allow(unused)]
-pub fn gen_from_self(program: &middle::Program) -> Result<Program> {
-    builder::IRBuilder::gen_from_self(program)
+pub fn gen_from_self(program: &middle::Program, thread_count: usize) -> Result<Program> {
+    builder::IRBuilder::gen_from_self(program, thread_count)
 }
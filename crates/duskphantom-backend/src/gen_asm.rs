@@ -16,6 +16,8 @@
 
 use std::env;
 
+use crate::config::CONFIG;
+
 // 为各种基础的 数据类型以及其值的表达实现基本的
 pub trait Data {
     fn size() -> u32;
@@ -102,6 +104,9 @@ impl GenTool {
         let mut ret = String::with_capacity(64);
         ret.push_str(format!(".file \"{}\"\n", file).as_str());
         ret.push_str(".option pic\n");
+        if CONFIG.open_zicond {
+            ret.push_str(".option arch, +zicond\n");
+        }
         ret.push_str(
             r#".attribute arch, "rv64i2p1_m2p0_a2p1_f2p2_d2p2_c2p0_zicsr2p0_zifencei2p0""#,
         );
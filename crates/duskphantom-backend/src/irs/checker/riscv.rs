@@ -121,6 +121,8 @@ impl InstChecker for Riscv {
             Inst::Feqs(feqs) => self.check_feqs(feqs),
             Inst::Fles(fles) => self.check_fles(fles),
             Inst::Flts(flts) => self.check_flts(flts),
+            Inst::CzeroEqz(czero) => self.check_czero_eqz(czero),
+            Inst::CzeroNez(czero) => self.check_czero_nez(czero),
             Inst::Lui(lui) => self.check_lui(lui),
         }
     }
@@ -167,6 +169,18 @@ impl Riscv {
             && matches!(sltu.lhs(), Operand::Reg(_))
             && matches!(sltu.rhs(), Operand::Reg(_))
     }
+    fn check_czero_eqz(&self, czero: &CzeroEqzInst) -> bool {
+        matches!(czero.dst(), Operand::Reg(_))
+            && matches!(czero.lhs(), Operand::Reg(_))
+            && matches!(czero.rhs(), Operand::Reg(_))
+    }
+
+    fn check_czero_nez(&self, czero: &CzeroNezInst) -> bool {
+        matches!(czero.dst(), Operand::Reg(_))
+            && matches!(czero.lhs(), Operand::Reg(_))
+            && matches!(czero.rhs(), Operand::Reg(_))
+    }
+
     fn check_sgtu(&self, sgtu: &SgtuInst) -> bool {
         matches!(sgtu.dst(), Operand::Reg(_))
             && matches!(sgtu.lhs(), Operand::Reg(_))
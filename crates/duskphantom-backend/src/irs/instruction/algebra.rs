@@ -37,6 +37,10 @@ impl_three_op_inst!(SgtuInst, "sgtu");
 impl_two_op_inst!(SnezInst, "snez");
 impl_two_op_inst!(SeqzInst, "seqz");
 
+// 条件置零指令 (Zicond)
+impl_three_op_inst!(CzeroEqzInst, "czero.eqz");
+impl_three_op_inst!(CzeroNezInst, "czero.nez");
+
 impl_three_op_inst!(FeqsInst, "feq.s");
 impl_three_op_inst!(FlesInst, "fle.s");
 impl_three_op_inst!(FltsInst, "flt.s");
@@ -75,6 +79,10 @@ mod c {
     impl_inst_convert!(FeqsInst, Feqs);
     impl_inst_convert!(FlesInst, Fles);
     impl_inst_convert!(FltsInst, Flts);
+
+    // for conditional zero
+    impl_inst_convert!(CzeroEqzInst, CzeroEqz);
+    impl_inst_convert!(CzeroNezInst, CzeroNez);
 }
 
 #[cfg(test)]
@@ -82,6 +82,10 @@ pub enum Inst {
     Fles(FlesInst),
     Flts(FltsInst),
 
+    // conditional zero operation
+    CzeroEqz(CzeroEqzInst),
+    CzeroNez(CzeroNezInst),
+
     // data transfer operation
     Mv(MvInst),
     Li(LiInst),
@@ -191,6 +195,8 @@ impl Inst {
             Inst::Feqs(feqs) => feqs.gen_asm(),
             Inst::Fles(fles) => fles.gen_asm(),
             Inst::Flts(flts) => flts.gen_asm(),
+            Inst::CzeroEqz(czero) => czero.gen_asm(),
+            Inst::CzeroNez(czero) => czero.gen_asm(),
             Inst::Lui(lui) => lui.gen_asm(),
         }
     }
@@ -303,6 +309,8 @@ impl RegReplace for Inst {
             Inst::Feqs(feqs) => feqs.replace_use(from, to),
             Inst::Fles(fles) => fles.replace_use(from, to),
             Inst::Flts(flts) => flts.replace_use(from, to),
+            Inst::CzeroEqz(czero) => czero.replace_use(from, to),
+            Inst::CzeroNez(czero) => czero.replace_use(from, to),
             Inst::Lui(lui) => lui.replace_use(from, to),
         }
     }
@@ -353,6 +361,8 @@ impl RegReplace for Inst {
             Inst::Feqs(feqs) => feqs.replace_def(from, to),
             Inst::Fles(fles) => fles.replace_def(from, to),
             Inst::Flts(flts) => flts.replace_def(from, to),
+            Inst::CzeroEqz(czero) => czero.replace_def(from, to),
+            Inst::CzeroNez(czero) => czero.replace_def(from, to),
             Inst::Lui(lui) => lui.replace_def(from, to),
         }
     }
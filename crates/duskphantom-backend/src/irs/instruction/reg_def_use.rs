@@ -75,6 +75,8 @@ impl RegUses for Inst {
             Inst::Feqs(feqs) => feqs.uses(),
             Inst::Fles(fles) => fles.uses(),
             Inst::Flts(flts) => flts.uses(),
+            Inst::CzeroEqz(czero) => czero.uses(),
+            Inst::CzeroNez(czero) => czero.uses(),
             Inst::Lui(lui) => lui.uses(),
         }
     }
@@ -126,6 +128,8 @@ impl RegDefs for Inst {
             Inst::Feqs(feqs) => feqs.defs(),
             Inst::Fles(fles) => fles.defs(),
             Inst::Flts(flts) => flts.defs(),
+            Inst::CzeroEqz(czero) => czero.defs(),
+            Inst::CzeroNez(czero) => czero.defs(),
             Inst::Lui(lui) => lui.defs(),
         }
     }
@@ -16,7 +16,7 @@
 
 // 一个module 是一个 基础独立编译单元, 或者理解成c的一个 独立代码文件
 
-use libthrd::LIB_THRD;
+use libthrd::ThreadRuntime;
 
 use super::*;
 use crate::config::CONFIG;
@@ -29,16 +29,19 @@ pub struct Module {
     pub funcs: Vec<func::Func>,
     // entry func name
     pub entry: Option<String>,
+    // number of threads the thread runtime is generated for
+    pub thread_count: usize,
 }
 
 impl Module {
     #[allow(dead_code)]
-    pub fn new(name: &str) -> Self {
+    pub fn new(name: &str, thread_count: usize) -> Self {
         Module {
             name: name.to_string(),
             global: vec![],
             funcs: vec![],
             entry: None,
+            thread_count,
         }
     }
 
@@ -56,10 +59,16 @@ impl Module {
         None
     }
     pub fn gen_asm(&self) -> String {
+        // thread runtime is linked into every program
+        let runtime = ThreadRuntime::new(self.thread_count);
+        let runtime_vars = runtime.vars();
+        let runtime_funcs = runtime.funcs();
+        let vs: Vec<&var::Var> = self.global.iter().chain(runtime_vars.iter()).collect();
+
         let mut global = String::new();
         if CONFIG.num_parallel_for_global_gen_asm <= 1 {
             println!("num_parallel_for_global_gen_asm <= 1");
-            for v in self.global.iter() {
+            for v in vs.iter() {
                 global.push_str(v.gen_asm().as_str());
                 global.push('\n');
             }
@@ -69,8 +78,7 @@ impl Module {
                 .build()
                 .unwrap();
             global = thread_pool.install(|| {
-                self.global
-                    .par_iter()
+                vs.par_iter()
                     .map(|v| v.gen_asm())
                     .collect::<Vec<String>>()
                     .join("\n")
@@ -78,7 +86,7 @@ impl Module {
         }
         // sort funcs by name
 
-        let mut fs: Vec<&func::Func> = self.funcs.iter().collect();
+        let mut fs: Vec<&func::Func> = self.funcs.iter().chain(runtime_funcs.iter()).collect();
         fs.sort_by_cached_key(|f| f.name());
         let mut funcs = String::with_capacity(1024);
         if CONFIG.num_parallel_for_func_gen_asm <= 1 {
@@ -100,8 +108,6 @@ impl Module {
             });
         };
 
-        funcs.push_str(LIB_THRD);
-
         gen_asm::GenTool::gen_prog("test.c", global.as_str(), funcs.as_str())
     }
 }
@@ -14,446 +14,633 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-pub const LIB_THRD: &str = r#"
-
-# ########## ########## thrd.c ########## ########## #
-
-	.text
-	.globl              STACK_SIZE
-	.globl              tmp_mem
-	.globl              tids
-	.section            .rodata
-	.align              3
-	.type               STACK_SIZE, @object
-	.size               STACK_SIZE, 8
-STACK_SIZE:
-	.dword              536870912
-	.bss
-	.align              3
-	.type               tmp_mem, @object
-	.size               tmp_mem, 2304
-tmp_mem:
-	.zero               2304
-	.type               tids, @object
-	.size               tids, 36
-tids:
-	.zero               36
-
-# ########## ########## thrd_join.c ########## ########## #
-
-	.text
-	.align              1
-	.globl              thrd_join
-	.type               thrd_join, @function
-thrd_join:
-.thrd_join_LFB65:
-	.cfi_startproc
-	addi                sp,sp,-64
-	.cfi_def_cfa_offset 64
-	li                  a0,178
-	sd                  s1,40(sp)
-	sd                  ra,56(sp)
-	.cfi_offset         9, -24
-	.cfi_offset         1, -8
-	la                  s1,tids
-	call                syscall@plt
-	lw                  a5,4(s1)
-	beq                 a0,a5,.thrd_join_L16
-	li                  a5,-1
-	amoadd.w.aqrl       zero,a5,0(s1)
-.thrd_join_L8:
-	li                  a1,0
-	li                  a0,93
-	call                syscall@plt
-	j                   .thrd_join_L8
-.thrd_join_L16:
-	sd                  s2,32(sp)
-	li                  a5,-1
-	amoadd.w.aqrl       zero,a5,0(s1)
-	.cfi_offset         18, -32
-	j                   .thrd_join_L3
-.thrd_join_L4:
-#APP
-# 15 "src/thrd_join.c" 1
-	nop
-# 0 "" 2
-#NO_APP
-.thrd_join_L3:
-	fence               rw,rw
-	lw                  a5,0(s1)
-	fence               r,rw
-	sext.w              a5,a5
-	bgt                 a5,zero,.thrd_join_L4
-	li                  a5,2
-	sw                  a5,4(sp)
-	lw                  a5,4(sp)
-	la                  s2,tmp_mem
-	slliw               a5,a5,8
-	add                 a5,s2,a5
-	lbu                 a5,0(a5)
-	beq                 a5,zero,.thrd_join_L5
-	sd                  s3,24(sp)
-	.cfi_offset         19, -40
-	li                  s3,4096
-	sd                  s0,48(sp)
-	sd                  s4,16(sp)
-	.cfi_offset         8, -16
-	.cfi_offset         20, -48
-	addi                s3,s3,-1792
-.thrd_join_L7:
-	lw                  s0,4(sp)
-	slliw               s0,s0,8
-	add                 s4,s2,s0
-	ld                  a5,112(s4)
-	sd                  a5,8(sp)
-	ld                  a0,8(sp)
-	call                free@plt
-	li                  a2,128
-	li                  a1,0
-	mv                  a0,s4
-	mv                  a3,s0
-	bgeu                s0,s3,.thrd_join_L6
-	mv                  a3,s3
-.thrd_join_L6:
-	sub                 a3,a3,s0
-	call                __memset_chk@plt
-	sd                  zero,8(sp)
-	lw                  a5,4(sp)
-	addiw               a5,a5,1
-	sw                  a5,4(sp)
-	lw                  a5,4(sp)
-	slliw               a5,a5,8
-	add                 a5,s2,a5
-	lbu                 a5,0(a5)
-	bne                 a5,zero,.thrd_join_L7
-	ld                  s0,48(sp)
-	.cfi_restore        8
-	ld                  s3,24(sp)
-	.cfi_restore        19
-	ld                  s4,16(sp)
-	.cfi_restore        20
-.thrd_join_L5:
-	sw                  zero,0(s1)
-	sw                  zero,4(s1)
-	sw                  zero,8(s1)
-	sw                  zero,12(s1)
-	sw                  zero,16(s1)
-	sw                  zero,20(s1)
-	sw                  zero,24(s1)
-	sw                  zero,28(s1)
-	sw                  zero,32(s1)
-	ld                  ra,56(sp)
-	.cfi_restore        1
-	ld                  s2,32(sp)
-	.cfi_restore        18
-	ld                  s1,40(sp)
-	.cfi_restore        9
-	li                  a0,0
-	addi                sp,sp,64
-	.cfi_def_cfa_offset 0
-	jr                  ra
-	.cfi_endproc
-.thrd_join_LFE65:
-	.size               thrd_join, .-thrd_join
-
-# ########## ########## thrd_create.c ########## ########## #
-
-	.text
-	.align              1
-	.globl              __get_tid
-	.type               __get_tid, @function
-__get_tid:
-.LFB6__get_tid:
-	.cfi_startproc
-
-# 判断是不是还没有创建过线程
-	lla                 t1, tids
-	lw                  t1, 0(t1)                                                      # cnt
-	bnez                t1, .LFB6__get_tid_start
-	li                  a0, 0
-	jr                  ra
-
-.LFB6__get_tid_start:
-	addi                sp, sp, -8
-	sd                  ra, 0(sp)
-	call                gettid@plt
-	mv                  a7, a0
-.while_init__get_tid:
-	lla                 a1, tids                                                       # a1 = tids 基址
-	lw                  a0, 0(a1)                                                      # a0 = cnt
-	j                   .while_cond__get_tid
-.while_block__get_tid:
-	addiw               a0, a0, -1
-.while_cond__get_tid:
-	blez                a0, .return__get_tid
-	mv                  a2, a0
-	slliw               a2, a2, 2
-	add                 a2, a1, a2
-	lw                  a2, 0(a2)                                                      # a2 = tids[a0]
-	bne                 a2, a7, .while_block__get_tid
-
-.return__get_tid:
-# 返回 a0 ，这里 a0 就是循环变量
-	ld                  ra, 0(sp)
-	addi                sp, sp, 8
-	addiw               a0, a0, -1                                                     # 下标 与 tid 正好差一位
-	jr                  ra
-	.cfi_endproc
-.LFE6__get_tid:
-	.size               __get_tid, .-__get_tid
-
-	.text
-	.align              1
-	.globl              thrd_create
-	.type               thrd_create, @function
-thrd_create:
-.__thrd_create_prepare: # 准备阶段
-	.cfi_startproc
-
-.__thrd_create_if_not_main_then_return:
-	addi                sp, sp, -16
-	sd                  ra, 0(sp)
-	sd                  a0, 8(sp)                                                      # num
-	call                __get_tid@plt
-	ld                  ra, 0(sp)
-	ld                  a1, 8(sp)                                                      # a1 = num
-	addi                sp, sp, 16
-	beqz                a0, .__thrd_create_fork_prepare                                # a0 = __get_tid()
-# 是子线程调用了 thrd_create
-	jr                  ra                                                             # 注意一下，我上面没有修改过 a0
-
-.__thrd_create_fork_prepare:
-# 也只有主线程能进来这里，那么这一部分是 顺序执行的
-	sub                 a5, s0, sp                                                     # a5 = 主线程的 caller 栈 s0 - sp
-	lla                 t1, tmp_mem
-	sd                  a5, 0(t1)                                                      # s0 - sp
-	sd                  a1, 8(t1)                                                      # num
-	sd                  ra, 16(t1)                                                     # ra
-
-.__thrd_create_if_cnt_0:
-	lla                 t1, tids
-	lw                  t2, 0(t1)
-	bnez                t2, .__thrd_create_fork
-# 如果是 0 ，那么就要初始化一下，总线程数 ++ ，tids[1] = gettid
-	li                  t2, 1
-	sw                  t2, 0(t1)
-	li                  a0,178
-	call                syscall@plt
-	lla                 t1, tids
-	sw                  a0, 4(t1)
-	j                   .__thrd_create_fork
-
-.__thrd_create_fork:
-	lla                 t1, tmp_mem
-	ld                  a0, 0(t1)
-	call                _thrd_create@plt                                               # fork
-	bnez                a0, .__thrd_create_son_while
-	j                   .__thrd_create_main
-
-.__thrd_create_son_while:
-	nop
-	lla                 t1, tmp_mem
-	ld                  a1, 8(t1)                                                      # num
-	bgtz                a1, .__thrd_create_son_while
-	lla                 t1, tmp_mem
-	ld                  ra, 16(t1)                                                     # ra
-	jr                  ra                                                             # 这个时候 a0=id 是返回值
-# 注意，这个时候不要破坏 a0 , 因为 a0 是返回值，也就是 tid
-
-
-.__thrd_create_main: # 主线程
-	lla                 t1, tmp_mem
-	ld                  a1, 8(t1)
-	addi                a1, a1, -1
-	sd                  a1, 8(t1)
-	bgtz                a1, .__thrd_create_fork
-	lla                 t1, tmp_mem
-	ld                  ra, 16(t1)                                                     # ra
-
-	lla                 t1, tmp_mem
-	li                  t2, 0
-
-	li                  a0, 0                                                          # 主线程返回 0
-	jr                  ra
-	.cfi_endproc
-.LFE6:
-	.size               thrd_create, .-thrd_create
-
-# ########## ########## son_leave.c ########## ########## #
-
-	.text
-	.align              1
-	.globl              son_leave
-	.type               son_leave, @function
-son_leave:
-	.cfi_startproc
-	mv                  s8, a0                                                         # 保存一下 cnt 也就是 tid
-	mv                  s11, sp                                                        # s11 = son sp
-
-	lla                 a0, tmp_mem
-	slliw               a2, s8, 8                                                      # cnt << 8
-	add                 a1, a0, a2                                                     # a1 = tmp_mem[cnt]
-	ld                  s10, 120(a1)                                                   # caller 栈大小 = tmp_mem[cnt][120]
-	sub                 sp, sp, s10                                                    # 准备复制
-	mv                  s9, a1                                                         # 暂存 s9 = tmp_mem[cnt]
-	ld                  s7, 8(a1)                                                      # s7 = s0' 也就是 caller 的 s0
-	sub                 s7, s7, s10                                                    # s7 = s7 - s10 得到 caller 的 sp
-
-.LB_copy_frame:
-	mv                  a0, sp                                                         # dest
-	mv                  a1, s7                                                         # src
-	mv                  a2, s10                                                        # 复制这么多的字节
-	call                memcpy@plt                                                     # 浅拷贝 caller 的栈
-
-	mv                  a7, s11                                                        # a7 看起来比较少用，son sp
-
-.LB_restore:
-	mv                  a1,s9                                                          # a1 = tmp_mem[cnt]
-	mv                  a0,s8                                                          # FIXME s0 = cnt 返回值，后面不要改了
-
-	ld                  s2,24(a1)
-	ld                  s3,32(a1)
-	ld                  s4,40(a1)
-	ld                  s5,48(a1)
-	ld                  s6,56(a1)
-	ld                  s7,64(a1)
-	ld                  s8,72(a1)
-	ld                  s9,80(a1)
-	ld                  s10,88(a1)
-	ld                  s11,96(a1)
-
-	ld                  ra, 0(a1)
-	.cfi_restore        1
-	mv                  s0, a7                                                         # 恢复 s0
-	.cfi_restore        8
-	ld                  s1, 16(a1)
-	.cfi_restore        9
-# li a0, 0 # 生成返回值
-# a0 这个时候没有动过，注意
-	addiw               a0, a0, -1
-	jr                  ra
-	.cfi_endproc
-	.size               son_leave, .-son_leave
-
-# ########## ########## son_leave.c ########## ########## #
-
-	.text
-	.align              1
-	.globl              _thrd_create
-	.type               _thrd_create, @function
-_thrd_create:
-._thrd_create_LFB7:
-	.cfi_startproc
-	addi                sp,sp,-64
-	.cfi_def_cfa_offset 64
-	sd                  ra,56(sp)
-	sd                  s0,48(sp)
-	sd                  s1,40(sp)
-	sd                  a0, 32(sp)                                                     # a0 是传入的参数 = caller(s0-sp)
-	.cfi_offset         1, -8
-	.cfi_offset         8, -16
-	.cfi_offset         9, -24
-	addi                s0,sp,64
-	.cfi_def_cfa        8, 0
-
-._thrd_create_L5:
-	lla                 a5,STACK_SIZE
-	ld                  a4,0(a5)
-	addi                a5,s0,-64
-	mv                  a2,a4                                                          # s2 = stack_size
-	li                  a1,16                                                          # a1 = 16 (align)
-	mv                  a0,a5                                                          # a0 = a5 = &stack
-	call                posix_memalign@plt
-	ld                  a5,-64(s0)
-	bnez                a5, ._thrd_create_L7                                           # if stack != NULL
-	li                  a0, -1                                                         # fail
-	ld                  ra,56(sp)
-	.cfi_restore        1
-	ld                  s0,48(sp)
-	.cfi_restore        8
-	.cfi_def_cfa        2, 64
-	ld                  s1,40(sp)
-	.cfi_restore        9
-	addi                sp,sp,64
-	.cfi_def_cfa_offset 0
-	jr                  ra
-
-._thrd_create_L7:
-	ld                  a4,-64(s0)
-	lla                 a5,STACK_SIZE
-	ld                  a5,0(a5)
-	add                 a5,a4,a5
-	sd                  a5,-40(s0)                                                     # top
-	li                  a5,20254720
-	addi                a5,a5,-256
-	sw                  a5,-44(s0)                                                     # flag
-
-	lla                 t1, tids
-	lw                  a5, 0(t1)
-	addiw               a5, a5, 1
-	sw                  a5, 0(t1)
-	sw                  a5, -48(s0)                                                    # cnt
-
-.LBB_backup:
-	lla                 a1, tmp_mem
-	lw                  a0, -48(s0)                                                    # cnt
-	slliw               a0, a0, 8
-	add                 a1,a1,a0                                                       # tmp_mem[cnt]
-	ld                  ra, -8(s0)                                                     # ra
-	sd                  ra,0(a1)
-	ld                  a0, -16(s0)                                                    # s0'
-	sd                  a0, 8(a1)
-	ld                  a0, -24(s0)                                                    # s1
-	sd                  a0,16(a1)
-	sd                  s2,24(a1)
-	sd                  s3,32(a1)
-	sd                  s4,40(a1)
-	sd                  s5,48(a1)
-	sd                  s6,56(a1)
-	sd                  s7,64(a1)
-	sd                  s8,72(a1)
-	sd                  s9,80(a1)
-	sd                  s10,88(a1)
-	sd                  s11,96(a1)
-
-	ld                  a0, -32(s0)                                                    # caller 的 s0 - sp 栈大小
-	sd                  a0, 120(a1)
-
-# addi a0, sp, 64 # 因为 s0 是栈底
-	mv                  a0, s0                                                         # 因为 s0 是栈底
-	sd                  a0, 104(a1)                                                    # sp + 64 当前栈的 栈底
-
-	ld                  a0, -64(s0)
-	sd                  a0, 112(a1)                                                    # stack
-
-.LB_clone:
-	lw                  a5,-48(s0)
-	slli                a4,a5,2
-	lla                 a5,tids
-	add                 a3,a4,a5
-	lw                  a2,-44(s0)                                                     # a2 = flag
-	li                  a6, 0                                                          # a6 = NULL
-	li                  a5,0                                                           # a5 = NULL
-	mv                  a4,a3                                                          # a4 = &tids[cnt] # 存放的是线程 tid
-	lw                  a3,-48(s0)                                                     # a3 = 传入子线程的 id
-	ld                  a1,-40(s0)                                                     # a1 = stack_top
-	lla                 a0,son_leave                                                   # a0 = son_leave
-	call                clone@plt                                                      # TODO 重点
-	j                   .main_leave
-
-.main_leave:
-	li                  a0, 0                                                          # main thread 总是 == 0
-	ld                  ra,56(sp)
-	.cfi_restore        1
-	ld                  s0,48(sp)
-	.cfi_restore        8
-	ld                  s1,40(sp)
-	.cfi_restore        9
-	addi                sp,sp,64
-	.cfi_def_cfa_offset 0
-	jr                  ra
-	.cfi_endproc
-._thrd_create_LFE7:
-	.size               _thrd_create, .-_thrd_create
-
-"#;
+use crate::config::CONFIG;
+use crate::irs::*;
+
+/// Bytes saved for each thread in `tmp_mem`, should be a power of two.
+const SLOT_SIZE: usize = 256;
+
+/// Bytes of a slot used by saved registers and stack info, cleared by `thrd_join`.
+const SLOT_USED_SIZE: i64 = 128;
+
+/// Offsets in slot of a cloned thread, where registers of `thrd_create` caller are saved.
+const SLOT_RA: i64 = 0;
+const SLOT_S0: i64 = 8;
+const SLOT_S1: i64 = 16;
+const SLOT_CALLEE_SAVED: i64 = 24;
+const SLOT_FRAME_BOTTOM: i64 = 104;
+const SLOT_STACK: i64 = 112;
+const SLOT_FRAME_SIZE: i64 = 120;
+
+/// Offsets in slot 0, which `thrd_create` uses to pass arguments to cloned threads.
+const ARG_FRAME_SIZE: i64 = 0;
+const ARG_REMAINING: i64 = 8;
+const ARG_RA: i64 = 16;
+
+/// Callee saved registers restored in cloned threads, besides `s0` and `s1`.
+const CALLEE_SAVED: [Reg; 10] = [
+    REG_S2, REG_S3, REG_S4, REG_S5, REG_S6, REG_S7, REG_S8, REG_S9, REG_S10, REG_S11,
+];
+
+/// Index of the first cloned thread in `tids`, after thread count and main thread.
+const FIRST_CLONED: i64 = 2;
+
+/// `CLONE_VM | CLONE_FS | CLONE_FILES | CLONE_SIGHAND | CLONE_THREAD | CLONE_SYSVSEM
+/// | CLONE_PARENT_SETTID | CLONE_CHILD_CLEARTID | CLONE_CHILD_SETTID`
+const CLONE_FLAGS: i64 = 0x0135_0f00;
+
+/// Syscall numbers on riscv64.
+const SYS_EXIT: i64 = 93;
+const SYS_FUTEX: i64 = 98;
+const SYS_SCHED_SETAFFINITY: i64 = 122;
+const SYS_GETTID: i64 = 178;
+
+/// Operation of `futex` sleeping while the word equals the given value.
+const FUTEX_WAIT: i64 = 0;
+
+/// Thread runtime linked into generated program.
+///
+/// `thrd_create(n)` clones `n` threads sharing address space, each copying frame of caller
+/// and returning its id, where main thread gets 0. `thrd_join` exits threads other than
+/// main thread, and main thread waits for them and frees their stacks.
+///
+/// `tids[0]` is the index of the last cloned thread, `tids[1]` is main thread and `tids[2..]`
+/// are cloned threads, which kernel clears when the thread exits. `tmp_mem` has one slot
+/// per entry of `tids`.
+pub struct ThreadRuntime {
+    /// Number of threads including main thread
+    pub thread_count: usize,
+    /// Stack size of each cloned thread in bytes
+    pub stack_size: usize,
+    /// Pin thread with id `n` to cpu `n`
+    pub affinity: bool,
+}
+
+impl ThreadRuntime {
+    /// Get runtime for given thread count, with other parameters in config.
+    pub fn new(thread_count: usize) -> Self {
+        Self {
+            thread_count: thread_count.max(1),
+            stack_size: CONFIG.thread_stack_size,
+            affinity: CONFIG.open_thread_affinity,
+        }
+    }
+
+    /// Global variables shared by threads.
+    pub fn vars(&self) -> Vec<Var> {
+        let tmp_mem = ArrVar::<u32>::new(
+            "tmp_mem".to_string(),
+            self.tmp_mem_size() / 4,
+            vec![],
+            false,
+        );
+        let tids = ArrVar::<u32>::new("tids".to_string(), self.tids_size() / 4, vec![], false);
+        vec![tmp_mem.into(), tids.into()]
+    }
+
+    /// Functions of runtime, with registers already allocated.
+    pub fn funcs(&self) -> Vec<Func> {
+        vec![
+            self.gen_thrd_join(),
+            self.gen_get_tid(),
+            self.gen_thrd_create(),
+            self.gen_son_leave(),
+            self.gen_clone(),
+        ]
+    }
+
+    fn tids_size(&self) -> usize {
+        4 * (self.thread_count + 1)
+    }
+
+    fn tmp_mem_size(&self) -> usize {
+        SLOT_SIZE * (self.thread_count + 1)
+    }
+
+    /// Cloned threads exit, and kernel clears their ids in `tids` and wakes waiters on it.
+    /// Main thread waits on id of each cloned thread with `futex`, then frees its stack
+    /// and clears its slot, and finally clears `tids`, so threads can be created again.
+    fn gen_thrd_join(&self) -> Func {
+        let mut entry = Block::new(".thrd_join_entry".to_string());
+        entry.extend_insts(vec![
+            AddInst::new(REG_SP.into(), REG_SP.into(), (-32).into())
+                .with_8byte()
+                .into(),
+            SdInst::new(REG_RA, 24.into(), REG_SP).into(),
+            SdInst::new(REG_S0, 16.into(), REG_SP).into(),
+            SdInst::new(REG_S1, 8.into(), REG_SP).into(),
+            CallInst::new("__get_tid".into()).into(),
+            BeqInst::new(REG_A0, REG_ZERO, ".thrd_join_main".into()).into(),
+            JmpInst::new(".thrd_join_exit".into()).into(),
+        ]);
+
+        let mut exit = Block::new(".thrd_join_exit".to_string());
+        exit.extend_insts(vec![
+            LiInst::new(REG_A0.into(), SYS_EXIT.into()).into(),
+            LiInst::new(REG_A1.into(), 0.into()).into(),
+            CallInst::new("syscall".into()).into(),
+            JmpInst::new(".thrd_join_exit".into()).into(),
+        ]);
+
+        // `s0` iterates over cloned threads, up to the last one in `s1`
+        let mut main = Block::new(".thrd_join_main".to_string());
+        main.extend_insts(vec![
+            LlaInst::new(REG_T1, "tids".into()).into(),
+            LwInst::new(REG_S1, 0.into(), REG_T1).into(),
+            LiInst::new(REG_S0.into(), FIRST_CLONED.into()).into(),
+            JmpInst::new(".thrd_join_cond".into()).into(),
+        ]);
+
+        let mut cond = Block::new(".thrd_join_cond".to_string());
+        cond.extend_insts(vec![
+            BgtInst::new(REG_S0, REG_S1, ".thrd_join_clear".into()).into(),
+            JmpInst::new(".thrd_join_wait".into()).into(),
+        ]);
+
+        // Stack is still in use until kernel clears id of thread,
+        // so `futex(&tids[s0], FUTEX_WAIT, id, NULL)` until it's 0
+        let mut wait = Block::new(".thrd_join_wait".to_string());
+        wait.extend_insts(vec![
+            LlaInst::new(REG_A1, "tids".into()).into(),
+            SllInst::new(REG_A2.into(), REG_S0.into(), 2.into()).into(),
+            AddInst::new(REG_A1.into(), REG_A1.into(), REG_A2.into())
+                .with_8byte()
+                .into(),
+            LwInst::new(REG_A3, 0.into(), REG_A1).into(),
+            BeqInst::new(REG_A3, REG_ZERO, ".thrd_join_free".into()).into(),
+            JmpInst::new(".thrd_join_sleep".into()).into(),
+        ]);
+
+        let mut sleep = Block::new(".thrd_join_sleep".to_string());
+        sleep.extend_insts(vec![
+            LiInst::new(REG_A0.into(), SYS_FUTEX.into()).into(),
+            LiInst::new(REG_A2.into(), FUTEX_WAIT.into()).into(),
+            LiInst::new(REG_A4.into(), 0.into()).into(),
+            CallInst::new("syscall".into()).into(),
+            JmpInst::new(".thrd_join_wait".into()).into(),
+        ]);
+
+        let mut free = Block::new(".thrd_join_free".to_string());
+        free.extend_insts(slot_addr(REG_A1, REG_S0));
+        free.extend_insts(vec![
+            LdInst::new(REG_A0, SLOT_STACK.into(), REG_A1).into(),
+            CallInst::new("free".into()).into(),
+        ]);
+        free.extend_insts(slot_addr(REG_A0, REG_S0));
+        free.extend_insts(vec![
+            LiInst::new(REG_A1.into(), 0.into()).into(),
+            LiInst::new(REG_A2.into(), SLOT_USED_SIZE.into()).into(),
+            CallInst::new("memset".into()).into(),
+            AddInst::new(REG_S0.into(), REG_S0.into(), 1.into()).into(),
+            JmpInst::new(".thrd_join_cond".into()).into(),
+        ]);
+
+        let mut clear = Block::new(".thrd_join_clear".to_string());
+        clear.extend_insts(vec![
+            LlaInst::new(REG_A0, "tids".into()).into(),
+            LiInst::new(REG_A1.into(), 0.into()).into(),
+            LiInst::new(REG_A2.into(), (self.tids_size() as i64).into()).into(),
+            CallInst::new("memset".into()).into(),
+            LiInst::new(REG_A0.into(), 0.into()).into(),
+            LdInst::new(REG_RA, 24.into(), REG_SP).into(),
+            LdInst::new(REG_S0, 16.into(), REG_SP).into(),
+            LdInst::new(REG_S1, 8.into(), REG_SP).into(),
+            AddInst::new(REG_SP.into(), REG_SP.into(), 32.into())
+                .with_8byte()
+                .into(),
+            Inst::Ret,
+        ]);
+
+        let mut func = Func::new("thrd_join".to_string(), vec![], entry);
+        func.extend_bbs(vec![exit, main, cond, wait, sleep, free, clear]);
+        func
+    }
+
+    /// Get index of current thread in `tids` minus one, or 0 before threads are created.
+    fn gen_get_tid(&self) -> Func {
+        let mut entry = Block::new(".__get_tid_entry".to_string());
+        entry.extend_insts(vec![
+            LlaInst::new(REG_T1, "tids".into()).into(),
+            LwInst::new(REG_T1, 0.into(), REG_T1).into(),
+            BneInst::new(REG_T1, REG_ZERO, ".__get_tid_start".into()).into(),
+            JmpInst::new(".__get_tid_none".into()).into(),
+        ]);
+
+        let mut none = Block::new(".__get_tid_none".to_string());
+        none.extend_insts(vec![LiInst::new(REG_A0.into(), 0.into()).into(), Inst::Ret]);
+
+        let mut start = Block::new(".__get_tid_start".to_string());
+        start.extend_insts(vec![
+            AddInst::new(REG_SP.into(), REG_SP.into(), (-16).into())
+                .with_8byte()
+                .into(),
+            SdInst::new(REG_RA, 8.into(), REG_SP).into(),
+            LiInst::new(REG_A0.into(), SYS_GETTID.into()).into(),
+            CallInst::new("syscall".into()).into(),
+            LlaInst::new(REG_A1, "tids".into()).into(),
+            LwInst::new(REG_A2, 0.into(), REG_A1).into(),
+            JmpInst::new(".__get_tid_cond".into()).into(),
+        ]);
+
+        let mut cond = Block::new(".__get_tid_cond".to_string());
+        cond.extend_insts(vec![
+            BleInst::new(REG_A2, REG_ZERO, ".__get_tid_return".into()).into(),
+            JmpInst::new(".__get_tid_body".into()).into(),
+        ]);
+
+        let mut body = Block::new(".__get_tid_body".to_string());
+        body.extend_insts(vec![
+            SllInst::new(REG_A3.into(), REG_A2.into(), 2.into()).into(),
+            AddInst::new(REG_A3.into(), REG_A1.into(), REG_A3.into())
+                .with_8byte()
+                .into(),
+            LwInst::new(REG_A3, 0.into(), REG_A3).into(),
+            BeqInst::new(REG_A3, REG_A0, ".__get_tid_return".into()).into(),
+            AddInst::new(REG_A2.into(), REG_A2.into(), (-1).into()).into(),
+            JmpInst::new(".__get_tid_cond".into()).into(),
+        ]);
+
+        let mut ret = Block::new(".__get_tid_return".to_string());
+        ret.extend_insts(vec![
+            AddInst::new(REG_A0.into(), REG_A2.into(), (-1).into()).into(),
+            LdInst::new(REG_RA, 8.into(), REG_SP).into(),
+            AddInst::new(REG_SP.into(), REG_SP.into(), 16.into())
+                .with_8byte()
+                .into(),
+            Inst::Ret,
+        ]);
+
+        let mut func = Func::new("__get_tid".to_string(), vec![], entry);
+        func.extend_bbs(vec![none, start, cond, body, ret]);
+        func
+    }
+
+    /// Called in a cloned thread, it returns immediately. Main thread saves frame size of caller
+    /// and arguments in slot 0, registers itself on first call, then clones threads one by one,
+    /// and aborts if a stack can't be allocated. A cloned thread starts in `son_leave`, which
+    /// returns right after `_thrd_create` with its id. It waits there until all threads are
+    /// cloned, then returns to caller.
+    fn gen_thrd_create(&self) -> Func {
+        let mut entry = Block::new(".thrd_create_entry".to_string());
+        entry.extend_insts(vec![
+            AddInst::new(REG_SP.into(), REG_SP.into(), (-16).into())
+                .with_8byte()
+                .into(),
+            SdInst::new(REG_RA, 0.into(), REG_SP).into(),
+            SdInst::new(REG_A0, 8.into(), REG_SP).into(),
+            CallInst::new("__get_tid".into()).into(),
+            LdInst::new(REG_RA, 0.into(), REG_SP).into(),
+            LdInst::new(REG_A1, 8.into(), REG_SP).into(),
+            AddInst::new(REG_SP.into(), REG_SP.into(), 16.into())
+                .with_8byte()
+                .into(),
+            BeqInst::new(REG_A0, REG_ZERO, ".thrd_create_prepare".into()).into(),
+            Inst::Ret,
+        ]);
+
+        // Frame size of caller is `s0 - sp`, as generated functions keep frame top in `s0`
+        let mut prepare = Block::new(".thrd_create_prepare".to_string());
+        prepare.extend_insts(vec![
+            SubInst::new(REG_A5.into(), REG_S0.into(), REG_SP.into())
+                .with_8byte()
+                .into(),
+            LlaInst::new(REG_T1, "tmp_mem".into()).into(),
+            SdInst::new(REG_A5, ARG_FRAME_SIZE.into(), REG_T1).into(),
+            SdInst::new(REG_A1, ARG_REMAINING.into(), REG_T1).into(),
+            SdInst::new(REG_RA, ARG_RA.into(), REG_T1).into(),
+            LlaInst::new(REG_T1, "tids".into()).into(),
+            LwInst::new(REG_T2, 0.into(), REG_T1).into(),
+            BneInst::new(REG_T2, REG_ZERO, ".thrd_create_fork".into()).into(),
+            JmpInst::new(".thrd_create_register".into()).into(),
+        ]);
+
+        // Main thread takes cpu 0
+        let mut register = Block::new(".thrd_create_register".to_string());
+        register.extend_insts(vec![
+            LiInst::new(REG_T2.into(), 1.into()).into(),
+            SwInst::new(REG_T2, 0.into(), REG_T1).into(),
+            LiInst::new(REG_A0.into(), SYS_GETTID.into()).into(),
+            CallInst::new("syscall".into()).into(),
+            LlaInst::new(REG_T1, "tids".into()).into(),
+            SwInst::new(REG_A0, 4.into(), REG_T1).into(),
+        ]);
+        if self.affinity {
+            register.push_inst(LiInst::new(REG_T1.into(), 1.into()).into());
+            register.extend_insts(set_affinity());
+        }
+        register.push_inst(JmpInst::new(".thrd_create_fork".into()).into());
+
+        let mut fork = Block::new(".thrd_create_fork".to_string());
+        fork.extend_insts(vec![
+            LlaInst::new(REG_T1, "tmp_mem".into()).into(),
+            LdInst::new(REG_A0, ARG_FRAME_SIZE.into(), REG_T1).into(),
+            CallInst::new("_thrd_create".into()).into(),
+            BltInst::new(REG_A0, REG_ZERO, ".thrd_create_fail".into()).into(),
+            BneInst::new(REG_A0, REG_ZERO, ".thrd_create_son_wait".into()).into(),
+            JmpInst::new(".thrd_create_next".into()).into(),
+        ]);
+
+        // Otherwise main thread would wait with cloned threads, and remaining count never reaches 0
+        let mut fail = Block::new(".thrd_create_fail".to_string());
+        fail.extend_insts(vec![
+            CallInst::new("abort".into()).into(),
+            JmpInst::new(".thrd_create_fail".into()).into(),
+        ]);
+
+        let mut next = Block::new(".thrd_create_next".to_string());
+        next.extend_insts(vec![
+            LlaInst::new(REG_T1, "tmp_mem".into()).into(),
+            LdInst::new(REG_A1, ARG_REMAINING.into(), REG_T1).into(),
+            AddInst::new(REG_A1.into(), REG_A1.into(), (-1).into())
+                .with_8byte()
+                .into(),
+            SdInst::new(REG_A1, ARG_REMAINING.into(), REG_T1).into(),
+            BgtInst::new(REG_A1, REG_ZERO, ".thrd_create_fork".into()).into(),
+            LdInst::new(REG_RA, ARG_RA.into(), REG_T1).into(),
+            LiInst::new(REG_A0.into(), 0.into()).into(),
+            Inst::Ret,
+        ]);
+
+        let mut son_wait = Block::new(".thrd_create_son_wait".to_string());
+        son_wait.extend_insts(vec![
+            LlaInst::new(REG_T1, "tmp_mem".into()).into(),
+            LdInst::new(REG_A1, ARG_REMAINING.into(), REG_T1).into(),
+            BgtInst::new(REG_A1, REG_ZERO, ".thrd_create_son_wait".into()).into(),
+            LdInst::new(REG_RA, ARG_RA.into(), REG_T1).into(),
+            Inst::Ret,
+        ]);
+
+        let mut func = Func::new("thrd_create".to_string(), vec![], entry);
+        func.extend_bbs(vec![prepare, register, fork, fail, next, son_wait]);
+        func
+    }
+
+    /// Entry of cloned thread with its index in `tids` as argument. It copies frame of
+    /// `thrd_create` caller onto its own stack, restores registers saved in its slot,
+    /// and returns to caller with its id.
+    fn gen_son_leave(&self) -> Func {
+        let mut entry = Block::new(".son_leave_entry".to_string());
+        entry.push_inst(MvInst::new(REG_S8.into(), REG_A0.into()).into());
+
+        // Cloned thread with index `s8` takes cpu `s8 - 1`
+        if self.affinity {
+            entry.extend_insts(vec![
+                AddInst::new(REG_T0.into(), REG_S8.into(), (-1).into()).into(),
+                LiInst::new(REG_T1.into(), 1.into()).into(),
+                SllInst::new(REG_T1.into(), REG_T1.into(), REG_T0.into())
+                    .with_8byte()
+                    .into(),
+            ]);
+            entry.extend_insts(set_affinity());
+        }
+
+        // Copy frame below saved `s0` to top of own stack
+        entry.push_inst(MvInst::new(REG_S11.into(), REG_SP.into()).into());
+        entry.extend_insts(slot_addr(REG_S9, REG_S8));
+        entry.extend_insts(vec![
+            LdInst::new(REG_S10, SLOT_FRAME_SIZE.into(), REG_S9).into(),
+            SubInst::new(REG_SP.into(), REG_SP.into(), REG_S10.into())
+                .with_8byte()
+                .into(),
+            LdInst::new(REG_S7, SLOT_S0.into(), REG_S9).into(),
+            SubInst::new(REG_S7.into(), REG_S7.into(), REG_S10.into())
+                .with_8byte()
+                .into(),
+            MvInst::new(REG_A0.into(), REG_SP.into()).into(),
+            MvInst::new(REG_A1.into(), REG_S7.into()).into(),
+            MvInst::new(REG_A2.into(), REG_S10.into()).into(),
+            CallInst::new("memcpy".into()).into(),
+        ]);
+
+        // Restore registers, with `s0` at top of own stack
+        entry.extend_insts(vec![
+            MvInst::new(REG_A7.into(), REG_S11.into()).into(),
+            MvInst::new(REG_A1.into(), REG_S9.into()).into(),
+            MvInst::new(REG_A0.into(), REG_S8.into()).into(),
+        ]);
+        for (i, reg) in CALLEE_SAVED.iter().enumerate() {
+            let offset = SLOT_CALLEE_SAVED + 8 * i as i64;
+            entry.push_inst(LdInst::new(*reg, offset.into(), REG_A1).into());
+        }
+        entry.extend_insts(vec![
+            LdInst::new(REG_RA, SLOT_RA.into(), REG_A1).into(),
+            MvInst::new(REG_S0.into(), REG_A7.into()).into(),
+            LdInst::new(REG_S1, SLOT_S1.into(), REG_A1).into(),
+            AddInst::new(REG_A0.into(), REG_A0.into(), (-1).into()).into(),
+            Inst::Ret,
+        ]);
+        Func::new("son_leave".to_string(), vec![], entry)
+    }
+
+    /// Clone one thread from main thread with frame size of `thrd_create` caller as argument.
+    /// It allocates a stack, takes the next index in `tids`, saves registers of caller in slot
+    /// of that index, and clones a thread entering `son_leave`.
+    fn gen_clone(&self) -> Func {
+        let mut entry = Block::new("._thrd_create_entry".to_string());
+        entry.extend_insts(vec![
+            AddInst::new(REG_SP.into(), REG_SP.into(), (-32).into())
+                .with_8byte()
+                .into(),
+            SdInst::new(REG_RA, 24.into(), REG_SP).into(),
+            SdInst::new(REG_S0, 16.into(), REG_SP).into(),
+            SdInst::new(REG_S1, 8.into(), REG_SP).into(),
+            MvInst::new(REG_S1.into(), REG_A0.into()).into(),
+            MvInst::new(REG_A0.into(), REG_SP.into()).into(),
+            LiInst::new(REG_A1.into(), 16.into()).into(),
+            LiInst::new(REG_A2.into(), (self.stack_size as i64).into()).into(),
+            CallInst::new("posix_memalign".into()).into(),
+            BeqInst::new(REG_A0, REG_ZERO, "._thrd_create_allocated".into()).into(),
+            LiInst::new(REG_A0.into(), (-1).into()).into(),
+            JmpInst::new("._thrd_create_return".into()).into(),
+        ]);
+
+        let mut allocated = Block::new("._thrd_create_allocated".to_string());
+        allocated.extend_insts(vec![
+            LlaInst::new(REG_T1, "tids".into()).into(),
+            LwInst::new(REG_S0, 0.into(), REG_T1).into(),
+            AddInst::new(REG_S0.into(), REG_S0.into(), 1.into()).into(),
+            SwInst::new(REG_S0, 0.into(), REG_T1).into(),
+        ]);
+        allocated.extend_insts(slot_addr(REG_A1, REG_S0));
+        for (reg_offset, slot_offset) in [(24, SLOT_RA), (16, SLOT_S0), (8, SLOT_S1)] {
+            allocated.extend_insts(vec![
+                LdInst::new(REG_A0, reg_offset.into(), REG_SP).into(),
+                SdInst::new(REG_A0, slot_offset.into(), REG_A1).into(),
+            ]);
+        }
+        for (i, reg) in CALLEE_SAVED.iter().enumerate() {
+            let offset = SLOT_CALLEE_SAVED + 8 * i as i64;
+            allocated.push_inst(SdInst::new(*reg, offset.into(), REG_A1).into());
+        }
+        allocated.extend_insts(vec![
+            AddInst::new(REG_A0.into(), REG_SP.into(), 32.into())
+                .with_8byte()
+                .into(),
+            SdInst::new(REG_A0, SLOT_FRAME_BOTTOM.into(), REG_A1).into(),
+            LdInst::new(REG_A0, 0.into(), REG_SP).into(),
+            SdInst::new(REG_A0, SLOT_STACK.into(), REG_A1).into(),
+            SdInst::new(REG_S1, SLOT_FRAME_SIZE.into(), REG_A1).into(),
+        ]);
+
+        // clone(son_leave, stack + stack_size, flags, index, &tids[index], NULL, &tids[index]),
+        // so id is set before `clone` returns, and cleared when thread exits
+        allocated.extend_insts(vec![
+            LiInst::new(REG_A1.into(), (self.stack_size as i64).into()).into(),
+            AddInst::new(REG_A1.into(), REG_A0.into(), REG_A1.into())
+                .with_8byte()
+                .into(),
+            LiInst::new(REG_A2.into(), CLONE_FLAGS.into()).into(),
+            MvInst::new(REG_A3.into(), REG_S0.into()).into(),
+            LlaInst::new(REG_A4, "tids".into()).into(),
+            SllInst::new(REG_A5.into(), REG_S0.into(), 2.into()).into(),
+            AddInst::new(REG_A4.into(), REG_A4.into(), REG_A5.into())
+                .with_8byte()
+                .into(),
+            LiInst::new(REG_A5.into(), 0.into()).into(),
+            MvInst::new(REG_A6.into(), REG_A4.into()).into(),
+            LlaInst::new(REG_A0, "son_leave".into()).into(),
+            CallInst::new("clone".into()).into(),
+            LiInst::new(REG_A0.into(), 0.into()).into(),
+            JmpInst::new("._thrd_create_return".into()).into(),
+        ]);
+
+        let mut ret = Block::new("._thrd_create_return".to_string());
+        ret.extend_insts(vec![
+            LdInst::new(REG_RA, 24.into(), REG_SP).into(),
+            LdInst::new(REG_S0, 16.into(), REG_SP).into(),
+            LdInst::new(REG_S1, 8.into(), REG_SP).into(),
+            AddInst::new(REG_SP.into(), REG_SP.into(), 32.into())
+                .with_8byte()
+                .into(),
+            Inst::Ret,
+        ]);
+
+        let mut func = Func::new("_thrd_create".to_string(), vec![], entry);
+        func.extend_bbs(vec![allocated, ret]);
+        func
+    }
+}
+
+/// Load address of slot of thread with index in `index` into `dst`.
+fn slot_addr(dst: Reg, index: Reg) -> Vec<Inst> {
+    let shift = SLOT_SIZE.trailing_zeros() as i64;
+    vec![
+        LlaInst::new(dst, "tmp_mem".into()).into(),
+        SllInst::new(REG_T0.into(), index.into(), shift.into()).into(),
+        AddInst::new(dst.into(), dst.into(), REG_T0.into())
+            .with_8byte()
+            .into(),
+    ]
+}
+
+/// Pin current thread to cpus in mask `t1`, with `sched_setaffinity(0, 8, &mask)`.
+fn set_affinity() -> Vec<Inst> {
+    vec![
+        AddInst::new(REG_SP.into(), REG_SP.into(), (-16).into())
+            .with_8byte()
+            .into(),
+        SdInst::new(REG_T1, 0.into(), REG_SP).into(),
+        LiInst::new(REG_A0.into(), SYS_SCHED_SETAFFINITY.into()).into(),
+        LiInst::new(REG_A1.into(), 0.into()).into(),
+        LiInst::new(REG_A2.into(), 8.into()).into(),
+        MvInst::new(REG_A3.into(), REG_SP.into()).into(),
+        CallInst::new("syscall".into()).into(),
+        AddInst::new(REG_SP.into(), REG_SP.into(), 16.into())
+            .with_8byte()
+            .into(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_runtime(thread_count: usize, affinity: bool) -> ThreadRuntime {
+        ThreadRuntime {
+            thread_count,
+            stack_size: 1 << 20,
+            affinity,
+        }
+    }
+
+    fn gen_asm(runtime: &ThreadRuntime) -> String {
+        let vars = runtime
+            .vars()
+            .iter()
+            .map(|v| v.gen_asm())
+            .collect::<Vec<_>>();
+        let funcs = runtime
+            .funcs()
+            .iter()
+            .map(|f| f.gen_asm())
+            .collect::<Vec<_>>();
+        format!("{}\n{}", vars.join("\n"), funcs.join("\n"))
+    }
+
+    #[test]
+    fn test_thread_count() {
+        for (thread_count, tmp_mem_size, tids_size) in [(1, 512, 8), (3, 1024, 16), (8, 2304, 36)] {
+            let asm = gen_asm(&get_runtime(thread_count, false));
+            assert!(asm.contains(&format!(".size\ttmp_mem, {}\n", tmp_mem_size)));
+            assert!(asm.contains(&format!(".zero\t{}\n", tmp_mem_size)));
+            assert!(asm.contains(&format!(".size\ttids, {}\n", tids_size)));
+            assert!(asm.contains(&format!("li a2,{}\ncall memset\n", tids_size)));
+            assert!(asm.contains("li a2,1048576\ncall posix_memalign\n"));
+        }
+    }
+
+    #[test]
+    fn test_affinity() {
+        let set_affinity = "li a0,122\n";
+        let asm = gen_asm(&get_runtime(5, false));
+        assert!(!asm.contains(set_affinity));
+
+        // Main thread and each cloned thread pin themselves once
+        let asm = gen_asm(&get_runtime(5, true));
+        assert_eq!(asm.matches(set_affinity).count(), 2);
+        assert!(asm.contains("sw a0,4(t1)\nli t1,1\naddi sp,sp,-16\n"));
+        assert!(asm.contains("mv s8,a0\naddiw t0,s8,-1\nli t1,1\nsll t1,t1,t0\n"));
+    }
+
+    #[test]
+    fn test_join_wait_futex() {
+        let join = get_runtime(3, false).gen_thrd_join().gen_asm();
+
+        // Stack of cloned thread is freed only after its id is cleared by kernel
+        let wait = join.find(".thrd_join_wait:\n").unwrap();
+        let sleep = join.find(".thrd_join_sleep:\n").unwrap();
+        let free = join.find(".thrd_join_free:\n").unwrap();
+        assert!(join[wait..sleep].contains("lw a3,0(a1)\nbeq a3,zero,.thrd_join_free\n"));
+        assert!(join[sleep..free].contains("li a0,98\nli a2,0\nli a4,0\ncall syscall\n"));
+        assert!(join[sleep..free].contains("j .thrd_join_wait\n"));
+        assert!(join[free..].contains("call free\n"));
+    }
+
+    #[test]
+    fn test_clone_clear_tid() {
+        let clone = get_runtime(3, false).gen_clone().gen_asm();
+
+        // Address of id is passed both as parent tid and child tid
+        assert!(clone.contains("add a4,a4,a5\nli a5,0\nmv a6,a4\nlla a0,son_leave\ncall clone\n"));
+    }
+
+    #[test]
+    fn test_create_fail() {
+        let create = get_runtime(3, false).gen_thrd_create().gen_asm();
+
+        // Main thread aborts instead of waiting with cloned threads
+        assert!(create.contains(
+            "call _thrd_create\nblt a0,zero,.thrd_create_fail\nbne a0,zero,.thrd_create_son_wait\n"
+        ));
+        assert!(create.contains(".thrd_create_fail:\ncall abort\n"));
+    }
+
+    #[test]
+    fn test_gettid() {
+        let asm = gen_asm(&get_runtime(3, false));
+
+        // Main thread registers its id and threads look up their id with the same call
+        assert_eq!(asm.matches("li a0,178\ncall syscall\n").count(), 2);
+        assert!(!asm.contains("call gettid"));
+    }
+}
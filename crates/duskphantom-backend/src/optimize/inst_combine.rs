@@ -149,7 +149,8 @@ impl Block {
             Inst::Sltu(_) | Inst::Sgtu(_) | Inst::Feqs(_) | Inst::Fles(_) | Inst::Flts(_) => {
                 return Ok(())
             }
-            _ => unimplemented!(),
+            // 条件也可能由 and/or/select 等指令算出, 此时不合并
+            _ => return Ok(()),
         };
         Ok(())
     }
@@ -186,11 +186,27 @@ impl Block {
     fn rem_opt(rem: &mut RemInst, r_g: &mut RegGenerator, new_insts: &mut Vec<Inst>) {
         if let Operand::Imm(imm) = rem.rhs() {
             let num = **imm;
-            if (num & (num - 1) == 0) && (num > 0) {
-                let power = num.trailing_zeros();
-                let mask = (1 << power) - 1;
-                let andi = AndInst::new(rem.dst().clone(), rem.lhs().clone(), (mask as i64).into());
+            if num == 1 {
+                let andi = AndInst::new(rem.dst().clone(), rem.lhs().clone(), (0).into());
                 new_insts.push(andi.into());
+            } else if (num & (num - 1) == 0) && (num > 0) {
+                // x - ((x + bias) & -num), 余数与被除数同号
+                let power = num.trailing_zeros();
+                let biased = Block::div_round_bias(rem.lhs(), power, r_g, new_insts);
+                let mask: Imm = (-num).into();
+                let mask: Operand = if mask.in_limit_12() {
+                    mask.into()
+                } else {
+                    let mid = r_g.gen_virtual_usual_reg();
+                    let li = LiInst::new(mid.into(), mask.into());
+                    new_insts.push(li.into());
+                    mid.into()
+                };
+                let low = r_g.gen_virtual_usual_reg();
+                let and = AndInst::new(low.into(), biased.into(), mask);
+                new_insts.push(and.into());
+                let sub = SubInst::new(rem.dst().clone(), rem.lhs().clone(), low.into());
+                new_insts.push(sub.into());
             } else {
                 let mid = r_g.gen_virtual_usual_reg();
                 let li = LiInst::new(mid.into(), imm.into());
@@ -207,10 +223,14 @@ impl Block {
     fn div_opt(div: &mut DivInst, r_g: &mut RegGenerator, new_insts: &mut Vec<Inst>) {
         if let Operand::Imm(imm) = div.rhs() {
             let num = **imm;
-            if (num & (num - 1) == 0) && (num > 0) {
+            if num == 1 {
+                let srai = SraInst::new(div.dst().clone(), div.lhs().clone(), (0).into());
+                new_insts.push(srai.into());
+            } else if (num & (num - 1) == 0) && (num > 0) {
+                // 算术右移向负无穷取整, 负数需要先加上 num - 1 才能向零取整
                 let power = num.trailing_zeros();
-                let srai =
-                    SraInst::new(div.dst().clone(), div.lhs().clone(), (power as i64).into());
+                let biased = Block::div_round_bias(div.lhs(), power, r_g, new_insts);
+                let srai = SraInst::new(div.dst().clone(), biased.into(), (power as i64).into());
                 new_insts.push(srai.into());
             } else {
                 let mid = r_g.gen_virtual_usual_reg();
@@ -224,6 +244,25 @@ impl Block {
         }
     }
 
+    /// 计算 x + (x < 0 ? 2^power - 1 : 0), 用于除以 2^power 时向零取整
+    fn div_round_bias(
+        lhs: &Operand,
+        power: u32,
+        r_g: &mut RegGenerator,
+        new_insts: &mut Vec<Inst>,
+    ) -> Reg {
+        let sign = r_g.gen_virtual_usual_reg();
+        let srai = SraInst::new(sign.into(), lhs.clone(), (63).into());
+        new_insts.push(srai.into());
+        let bias = r_g.gen_virtual_usual_reg();
+        let srli = SrlInst::new(bias.into(), sign.into(), (64 - power as i64).into());
+        new_insts.push(srli.into());
+        let biased = r_g.gen_virtual_usual_reg();
+        let add = AddInst::new(biased.into(), lhs.clone(), bias.into());
+        new_insts.push(add.into());
+        biased
+    }
+
     fn mul_opt(mul: &mut MulInst, r_g: &mut RegGenerator, new_insts: &mut Vec<Inst>) {
         /// (1 << m) - (1 << n)
         fn _is_sub_pattern(num: i64) -> Option<(u32, u32)> {
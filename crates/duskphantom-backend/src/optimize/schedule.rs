@@ -144,6 +144,8 @@ impl Inst {
             Inst::Seqz(seqz) => arithmetic_char!(seqz),
             Inst::Snez(snez) => arithmetic_char!(snez),
             Inst::Mv(mv) => arithmetic_char!(mv),
+            Inst::CzeroEqz(czero) => arithmetic_char!(czero),
+            Inst::CzeroNez(czero) => arithmetic_char!(czero),
             /* int */
             Inst::LocalAddr(_) => Ok((1, InstType::Integer)),
             Inst::Li(_) | Inst::Lla(_) | Inst::Lui(_) => Ok((1, InstType::Integer)),
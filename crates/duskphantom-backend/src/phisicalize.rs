@@ -109,6 +109,8 @@ pub fn handle_illegal_inst(func: &mut Func) -> Result<()> {
             match inst {
                 Inst::Sltu(sltu) => process_rhs_imm!(sltu, r_g, new_insts),
                 Inst::Sgtu(sgtu) => process_rhs_imm!(sgtu, r_g, new_insts),
+                Inst::CzeroEqz(czero) => process_rhs_imm!(czero, r_g, new_insts),
+                Inst::CzeroNez(czero) => process_rhs_imm!(czero, r_g, new_insts),
                 Inst::Mul(mul) => process_rhs_imm!(mul, r_g, new_insts),
                 Inst::Div(div) => process_rhs_imm!(div, r_g, new_insts),
                 Inst::Rem(rem) => process_rhs_imm!(rem, r_g, new_insts),
@@ -21,6 +21,7 @@ mod from_llvm_tests {
     use anyhow::Result;
     use clang_front_back::clang_frontend;
     use duskphantom_backend::irs::*;
+    use duskphantom_middle::transform::make_parallel::DEFAULT_THREAD_COUNT;
     use insta::assert_debug_snapshot;
 
     fn parse_to_backend(code: &str) -> Result<Program> {
@@ -28,7 +29,7 @@ mod from_llvm_tests {
         std::fs::write(tmp_cfile.path(), code)?;
         let front: clang_frontend::Program =
             clang_frontend::Program::parse_c_file(tmp_cfile.path())?;
-        let program: Program = from_llvm::gen_from_clang(&front)?;
+        let program: Program = from_llvm::gen_from_clang(&front, DEFAULT_THREAD_COUNT)?;
         Ok(program)
     }
 
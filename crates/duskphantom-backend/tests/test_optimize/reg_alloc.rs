@@ -18,6 +18,7 @@ use super::*;
 use analysis::RegLives;
 use duskphantom_frontend as frontend;
 use duskphantom_middle as middle;
+use middle::transform::make_parallel::DEFAULT_THREAD_COUNT;
 
 use insta::{assert_debug_snapshot, assert_snapshot};
 use reg_alloc::reg_alloc;
@@ -25,7 +26,7 @@ use std::collections::{HashMap, HashSet};
 pub fn backend_from_self(code: &str) -> Program {
     let f = frontend::parse(code).unwrap();
     let m = middle::Program::try_from(&f).unwrap();
-    duskphantom_backend::from_self::gen_from_self(&m).unwrap()
+    duskphantom_backend::from_self::gen_from_self(&m, DEFAULT_THREAD_COUNT).unwrap()
 }
 
 pub fn find_func<'a>(b: &'a Program, name: &str) -> &'a Func {
@@ -14,10 +14,11 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::analysis::value_range::get_static_range;
 use crate::ir::instruction::downcast_ref;
 use crate::ir::{
     instruction::{memory_op_inst::GetElementPtr, InstType},
-    Constant, Operand, ValueType,
+    Constant, InstPtr, Operand, ValueType,
 };
 use std::collections::{HashMap, HashSet};
 
@@ -184,6 +185,11 @@ fn can_ptr_alias(a: &Operand, b: &Operand) -> bool {
         return true;
     }
 
+    // Phi merging different objects can alias anything
+    if is_phi(a) || is_phi(b) {
+        return true;
+    }
+
     // Global variable alias only when they're the same
     if let Operand::Global(a) = a {
         if let Operand::Global(b) = b {
@@ -218,6 +224,7 @@ fn can_offset_overlap(a: HashMap<ValueType, Operand>, b: HashMap<ValueType, Oper
 fn split_gep(op: &Operand) -> (Operand, HashMap<ValueType, Operand>) {
     let mut base = op.clone();
     let mut offset = HashMap::new();
+    let mut unknown_offset = false;
     while let Operand::Instruction(inst) = base {
         if inst.get_type() != InstType::GetElementPtr {
             break;
@@ -231,13 +238,15 @@ fn split_gep(op: &Operand) -> (Operand, HashMap<ValueType, Operand>) {
         let mut element_type = gep.element_type.clone();
         for op in inst.get_operand().iter().skip(1) {
             if let Some(old_offset) = offset.get_mut(&element_type) {
-                // Handle only +0 or 0+ in this case, UB otherwise
-                if let Operand::Constant(Constant::Int(0)) = old_offset {
-                    *old_offset = op.clone();
-                } else if let Operand::Constant(Constant::Int(0)) = op {
-                    // Do nothing
-                } else {
-                    unimplemented!("Unsupported GEP offset: {} + {}", old_offset, op);
+                match (&*old_offset, op) {
+                    (Operand::Constant(Constant::Int(0)), _) => *old_offset = op.clone(),
+                    (_, Operand::Constant(Constant::Int(0))) => (),
+                    (Operand::Constant(Constant::Int(a)), Operand::Constant(Constant::Int(b))) => {
+                        *old_offset = Constant::Int(a.wrapping_add(*b)).into();
+                    }
+                    // Sum of non-constant offsets is not tracked, for example nested GEPs
+                    // walking an array built by strength reduction
+                    _ => unknown_offset = true,
                 }
             } else {
                 offset.insert(element_type.clone(), op.clone());
@@ -247,16 +256,76 @@ fn split_gep(op: &Operand) -> (Operand, HashMap<ValueType, Operand>) {
             }
         }
     }
+
+    // Pointer phi has unknown offset from its underlying object
+    if is_phi(&base) {
+        let object = get_base_object(&base);
+        if object != base {
+            return (object, HashMap::new());
+        }
+    }
+
+    // Unknown offset can overlap with any offset into the same object
+    if unknown_offset {
+        return (base, HashMap::new());
+    }
     (base, offset)
 }
 
+/// Get the object a pointer is derived from, looking through GEPs and pointer phis.
+/// If incoming values of a phi derive from different objects, the phi itself is returned.
+pub fn get_base_object(op: &Operand) -> Operand {
+    let base = strip_gep(op);
+    if !is_phi(&base) {
+        return base;
+    }
+    let mut objects = HashSet::new();
+    collect_objects(&base, &mut HashSet::new(), &mut objects);
+    if objects.len() == 1 {
+        objects.into_iter().next().unwrap()
+    } else {
+        base
+    }
+}
+
+fn strip_gep(op: &Operand) -> Operand {
+    let mut base = op.clone();
+    while let Operand::Instruction(inst) = base {
+        if inst.get_type() != InstType::GetElementPtr {
+            break;
+        }
+        base = inst.get_operand()[0].clone();
+    }
+    base
+}
+
+fn collect_objects(op: &Operand, visited: &mut HashSet<InstPtr>, objects: &mut HashSet<Operand>) {
+    let base = strip_gep(op);
+    match base {
+        Operand::Instruction(inst) if inst.get_type() == InstType::Phi => {
+            if visited.insert(inst) {
+                for op in inst.get_operand().iter() {
+                    collect_objects(op, visited, objects);
+                }
+            }
+        }
+        _ => {
+            objects.insert(base);
+        }
+    }
+}
+
+fn is_phi(op: &Operand) -> bool {
+    matches!(op, Operand::Instruction(inst) if inst.get_type() == InstType::Phi)
+}
+
 /// Check if two indexing operands can equal.
 fn can_equal(a: Operand, b: Operand) -> bool {
     match (a, b) {
         // Constants only equal when they're the same
         (Operand::Constant(a), Operand::Constant(b)) => a == b,
 
-        // Other operands can always equal (give up predicate analysis)
-        _ => true,
+        // Other operands can equal only if their ranges overlap
+        (a, b) => !get_static_range(&a).is_disjoint(&get_static_range(&b)),
     }
 }
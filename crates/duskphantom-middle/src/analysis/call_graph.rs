@@ -97,6 +97,57 @@ impl CallGraph {
         self.called_by.get(&func).cloned().unwrap_or_default()
     }
 
+    /// Add a call edge, for example when a call is copied by inlining.
+    pub fn add_edge(&mut self, edge: CallEdge) {
+        self.calls.entry(edge.caller).or_default().insert(edge);
+        self.called_by.entry(edge.callee).or_default().insert(edge);
+    }
+
+    /// Remove a call edge, for example when the call is inlined.
+    pub fn remove_edge(&mut self, edge: CallEdge) {
+        if let Some(calls) = self.calls.get_mut(&edge.caller) {
+            calls.remove(&edge);
+        }
+        if let Some(called_by) = self.called_by.get_mut(&edge.callee) {
+            called_by.remove(&edge);
+        }
+    }
+
+    /// Get strongly connected components of given functions in bottom-up order,
+    /// so that a callee comes before its callers unless they are in the same component.
+    /// Order of functions is kept in each component.
+    pub fn get_sccs(&self, functions: &[FunPtr]) -> Vec<Vec<FunPtr>> {
+        let index_of: HashMap<FunPtr, usize> = functions
+            .iter()
+            .enumerate()
+            .map(|(i, func)| (*func, i))
+            .collect();
+        let succ = functions
+            .iter()
+            .map(|func| {
+                let mut succ: Vec<usize> = self
+                    .get_calls(*func)
+                    .iter()
+                    .filter_map(|edge| index_of.get(&edge.callee).cloned())
+                    .collect();
+                succ.sort();
+                succ.dedup();
+                succ
+            })
+            .collect();
+        let mut tarjan = Tarjan::new(succ);
+        for v in 0..functions.len() {
+            if tarjan.index[v].is_none() {
+                tarjan.visit(v);
+            }
+        }
+        tarjan
+            .sccs
+            .into_iter()
+            .map(|scc| scc.into_iter().map(|v| functions[v]).collect())
+            .collect()
+    }
+
     pub fn remove(&mut self, func: FunPtr) {
         if let Some(calls) = self.calls.remove(&func) {
             for call in calls {
@@ -110,3 +161,63 @@ impl CallGraph {
         }
     }
 }
+
+/// Tarjan's algorithm, which finds components in reverse topological order.
+struct Tarjan {
+    succ: Vec<Vec<usize>>,
+    index: Vec<Option<usize>>,
+    low: Vec<usize>,
+    on_stack: Vec<bool>,
+    stack: Vec<usize>,
+    next_index: usize,
+    sccs: Vec<Vec<usize>>,
+}
+
+impl Tarjan {
+    fn new(succ: Vec<Vec<usize>>) -> Self {
+        let n = succ.len();
+        Self {
+            succ,
+            index: vec![None; n],
+            low: vec![0; n],
+            on_stack: vec![false; n],
+            stack: Vec::new(),
+            next_index: 0,
+            sccs: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, v: usize) {
+        self.index[v] = Some(self.next_index);
+        self.low[v] = self.next_index;
+        self.next_index += 1;
+        self.stack.push(v);
+        self.on_stack[v] = true;
+
+        for i in 0..self.succ[v].len() {
+            let w = self.succ[v][i];
+            match self.index[w] {
+                None => {
+                    self.visit(w);
+                    self.low[v] = self.low[v].min(self.low[w]);
+                }
+                Some(index) if self.on_stack[w] => self.low[v] = self.low[v].min(index),
+                _ => (),
+            }
+        }
+
+        // Pop component if v is its root
+        if Some(self.low[v]) == self.index[v] {
+            let mut scc = Vec::new();
+            while let Some(w) = self.stack.pop() {
+                self.on_stack[w] = false;
+                scc.push(w);
+                if w == v {
+                    break;
+                }
+            }
+            scc.sort();
+            self.sccs.push(scc);
+        }
+    }
+}
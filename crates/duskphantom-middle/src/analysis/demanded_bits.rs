@@ -0,0 +1,142 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+use crate::ir::{instruction::InstType, FunPtr, InstPtr, Operand, ValueType};
+
+use super::known_bits::{get_known_bits, get_shift_amount, high_mask, low_mask_upto, KnownBits};
+
+/// Bits of integer instructions that are used by other instructions.
+/// Bits not demanded can take any value without changing behavior of the program.
+#[derive(Default)]
+pub struct DemandedBits {
+    demanded: HashMap<InstPtr, u32>,
+}
+
+impl DemandedBits {
+    pub fn new(func: FunPtr) -> Self {
+        let mut res = Self::default();
+        let mut worklist = Vec::new();
+        for bb in func.rpo_iter() {
+            for inst in bb.iter() {
+                if inst.get_value_type() == ValueType::Int && inst.get_type() != InstType::Ret {
+                    res.demanded.insert(inst, 0);
+                    worklist.push(inst);
+                }
+            }
+        }
+        res.propagate(worklist);
+        res
+    }
+
+    /// Update demanded bits of an instruction after it gets new users.
+    pub fn update(&mut self, inst: InstPtr) {
+        if self.demanded.contains_key(&inst) {
+            self.propagate(vec![inst]);
+        }
+    }
+
+    /// Get demanded bits of an instruction.
+    /// Instructions unknown to the analysis, like newly created ones, have all bits demanded.
+    pub fn get_demanded(&self, inst: InstPtr) -> u32 {
+        self.demanded.get(&inst).cloned().unwrap_or(u32::MAX)
+    }
+
+    /// Dump demanded bits of integer instructions, except those with all bits demanded.
+    pub fn dump(&self, func: FunPtr) -> String {
+        let mut res = String::new();
+        for bb in func.rpo_iter() {
+            for inst in bb.iter() {
+                let demanded = self.get_demanded(inst);
+                if demanded != u32::MAX {
+                    res += &format!(
+                        "{}: {} demanded {:#010x}\n",
+                        Operand::from(inst),
+                        get_known_bits(&inst.into()),
+                        demanded
+                    );
+                }
+            }
+        }
+        res
+    }
+
+    /// Recompute demanded bits of instructions in worklist from their users, until fixpoint.
+    /// Demanded bits only grow, so iteration from nothing demanded reaches fixpoint.
+    fn propagate(&mut self, mut worklist: Vec<InstPtr>) {
+        while let Some(inst) = worklist.pop() {
+            let old = self.get_demanded(inst);
+            let demanded = inst
+                .get_user()
+                .iter()
+                .fold(old, |acc, user| acc | self.get_demanded_by(*user, inst));
+            if demanded == old {
+                continue;
+            }
+            self.demanded.insert(inst, demanded);
+            for op in inst.get_operand() {
+                if let Operand::Instruction(op) = op {
+                    if self.demanded.contains_key(op) {
+                        worklist.push(*op);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Get bits of `op` demanded by `user`.
+    fn get_demanded_by(&self, user: InstPtr, op: InstPtr) -> u32 {
+        let Some(out) = self.demanded.get(&user).cloned() else {
+            return u32::MAX;
+        };
+        let op: Operand = op.into();
+        user.get_operand()
+            .iter()
+            .enumerate()
+            .filter(|(_, operand)| **operand == op)
+            .fold(0, |acc, (index, _)| {
+                acc | get_operand_demanded(user, index, out)
+            })
+    }
+}
+
+/// Get bits of operand at `index` that `inst` needs to produce `out` bits of its result.
+fn get_operand_demanded(inst: InstPtr, index: usize, out: u32) -> u32 {
+    // Result bits of add, sub and mul only depend on operand bits not higher than them
+    let low = low_mask_upto(out);
+    let amount = inst.get_operand().get(1).and_then(get_shift_amount);
+    match (inst.get_type(), amount) {
+        (InstType::Add | InstType::Sub, _) => low,
+        (InstType::Mul, _) => {
+            let other = get_known_bits(&inst.get_operand()[1 - index]);
+            low >> other.zero.trailing_ones().min(31)
+        }
+        (InstType::Shl, Some(k)) if index == 0 => out >> k,
+        (InstType::LShr, Some(k)) if index == 0 => out << k,
+        (InstType::AShr, Some(k)) if index == 0 => {
+            // Shifted in bits are copies of the sign bit
+            if out & high_mask(k) != 0 {
+                (out << k) | KnownBits::SIGN
+            } else {
+                out << k
+            }
+        }
+        (InstType::Phi, _) => out,
+        (InstType::Select, _) if index > 0 => out,
+        _ => u32::MAX,
+    }
+}
@@ -0,0 +1,583 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::Display;
+
+use crate::ir::{
+    instruction::{downcast_ref, memory_op_inst::GetElementPtr, InstType},
+    Constant, FunPtr, InstPtr, Operand, ValueType,
+};
+
+use super::{
+    loop_tools::{LoopForest, LoopPtr},
+    scalar_evolution::{Linear, ScalarEvolution, Scev},
+    *,
+};
+
+/// Direction of a dependence in a loop, comparing iteration of source with iteration of sink.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Lt,
+    Eq,
+    Gt,
+}
+
+impl Display for Direction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Direction::Lt => write!(f, "<"),
+            Direction::Eq => write!(f, "="),
+            Direction::Gt => write!(f, ">"),
+        }
+    }
+}
+
+/// Possible dependence between two memory accesses in common loops.
+/// Both orders of source and sink are included, so `>` means sink runs in an earlier iteration.
+pub struct Dependence {
+    /// Common loops of both accesses, from outermost to innermost
+    pub loops: Vec<LoopPtr>,
+
+    /// Direction vectors under which both accesses may touch the same address
+    pub directions: Vec<Vec<Direction>>,
+
+    /// Constant distance from iteration of source to iteration of sink in each loop
+    pub distance: Vec<Option<i64>>,
+}
+
+impl Dependence {
+    /// Dependence that may hold in any iterations of common loops.
+    fn new_unknown(loops: Vec<LoopPtr>) -> Self {
+        let directions = get_all_directions(loops.len());
+        let distance = vec![None; loops.len()];
+        Self {
+            loops,
+            directions,
+            distance,
+        }
+    }
+
+    /// Get level of loop in common loops.
+    pub fn get_level(&self, lo: LoopPtr) -> Option<usize> {
+        self.loops.iter().position(|l| *l == lo)
+    }
+
+    /// Check if dependence crosses iterations of loop at `level` in the same iteration
+    /// of its outer loops, which prevents running iterations of that loop in parallel.
+    pub fn is_carried_at(&self, level: usize) -> bool {
+        self.directions
+            .iter()
+            .any(|dv| dv[..level].iter().all(|d| *d == Direction::Eq) && dv[level] != Direction::Eq)
+    }
+
+    /// Check if dependence only holds in the same iteration of all common loops.
+    pub fn is_loop_independent(&self) -> bool {
+        self.directions
+            .iter()
+            .all(|dv| dv.iter().all(|d| *d == Direction::Eq))
+    }
+}
+
+impl Display for Dependence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let directions: Vec<String> = self
+            .directions
+            .iter()
+            .map(|dv| {
+                let dv: Vec<String> = dv.iter().map(Direction::to_string).collect();
+                format!("({})", dv.join(", "))
+            })
+            .collect();
+        let distance: Vec<String> = self
+            .distance
+            .iter()
+            .map(|d| d.map_or("?".to_string(), |d| d.to_string()))
+            .collect();
+        write!(
+            f,
+            "{} distance ({})",
+            directions.join(" "),
+            distance.join(", ")
+        )
+    }
+}
+
+/// Affine function of iteration numbers of loops in a nest, plus a value invariant in the nest.
+#[derive(Clone)]
+struct Affine {
+    coeffs: Vec<i64>,
+    rest: Linear,
+}
+
+impl Affine {
+    fn new_invariant(depth: usize, rest: Linear) -> Self {
+        Self {
+            coeffs: vec![0; depth],
+            rest,
+        }
+    }
+
+    fn add(&self, other: &Affine) -> Affine {
+        Affine {
+            coeffs: self
+                .coeffs
+                .iter()
+                .zip(other.coeffs.iter())
+                .map(|(a, b)| a + b)
+                .collect(),
+            rest: self.rest.add(&other.rest),
+        }
+    }
+
+    fn scale(&self, c: i32) -> Affine {
+        Affine {
+            coeffs: self.coeffs.iter().map(|a| a * c as i64).collect(),
+            rest: self.rest.scale(c),
+        }
+    }
+}
+
+/// Address of a memory access, with the index of each dimension keyed by indexed type.
+struct Access {
+    base: Operand,
+    subscripts: Vec<(ValueType, Option<Affine>)>,
+}
+
+/// Equation `sum(src[k] * I[k]) - sum(dst[k] * I'[k]) = rhs` of one subscript, where `I` and `I'`
+/// are iteration numbers of source and sink. Levels beyond common loops belong to one side only.
+struct Equation {
+    src: Vec<i64>,
+    dst: Vec<i64>,
+    rhs: i64,
+}
+
+/// Array dependence analysis on `GetElementPtr` index expressions.
+///
+/// Subscripts are affine functions of iteration numbers, which start from 0 and are bounded
+/// by trip counts when they're constant. Each dimension is tested separately with GCD test,
+/// strong SIV test for constant distances, and Banerjee test to refine direction vectors.
+pub struct DependenceAnalysis {
+    scev: ScalarEvolution,
+    nests: HashMap<BBPtr, Vec<LoopPtr>>,
+}
+
+impl DependenceAnalysis {
+    pub fn new(forest: &LoopForest) -> Self {
+        let mut nests = HashMap::new();
+        let mut stack: Vec<(LoopPtr, Vec<LoopPtr>)> =
+            forest.forest.iter().map(|lo| (*lo, vec![*lo])).collect();
+        while let Some((lo, nest)) = stack.pop() {
+            for bb in lo.blocks.iter() {
+                nests.insert(*bb, nest.clone());
+            }
+            for sub_loop in lo.sub_loops.iter() {
+                let mut sub_nest = nest.clone();
+                sub_nest.push(*sub_loop);
+                stack.push((*sub_loop, sub_nest));
+            }
+        }
+        Self {
+            scev: ScalarEvolution::new(),
+            nests,
+        }
+    }
+
+    /// Get loops containing instruction, from outermost to innermost.
+    pub fn get_nest(&self, inst: InstPtr) -> Vec<LoopPtr> {
+        inst.get_parent_bb()
+            .and_then(|bb| self.nests.get(&bb).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Get dependence between two loads or stores, or `None` if they never access the same address.
+    /// Other instructions may depend on anything.
+    pub fn get_dependence(&mut self, src: InstPtr, dst: InstPtr) -> Option<Dependence> {
+        let src_nest = self.get_nest(src);
+        let dst_nest = self.get_nest(dst);
+        let depth = src_nest
+            .iter()
+            .zip(dst_nest.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let common = src_nest[..depth].to_vec();
+        let (Some(src_ptr), Some(dst_ptr)) = (get_address(src), get_address(dst)) else {
+            return Some(Dependence::new_unknown(common));
+        };
+        let src_access = self.get_access(&src_ptr, &src_nest);
+        let dst_access = self.get_access(&dst_ptr, &dst_nest);
+
+        // Distinct objects never overlap, and others may overlap anywhere
+        if src_access.base != dst_access.base {
+            return if is_object(&src_access.base) && is_object(&dst_access.base) {
+                None
+            } else {
+                Some(Dependence::new_unknown(common))
+            };
+        }
+        let mut src_types: Vec<&ValueType> = src_access.subscripts.iter().map(|(t, _)| t).collect();
+        let mut dst_types: Vec<&ValueType> = dst_access.subscripts.iter().map(|(t, _)| t).collect();
+        src_types.sort_by_key(|t| t.to_string());
+        dst_types.sort_by_key(|t| t.to_string());
+        if src_types != dst_types {
+            return Some(Dependence::new_unknown(common));
+        }
+
+        // Build equation for each dimension with known subscripts
+        let mut equations = Vec::new();
+        for (ty, src_sub) in src_access.subscripts.iter() {
+            let dst_sub = dst_access
+                .subscripts
+                .iter()
+                .find(|(t, _)| t == ty)
+                .and_then(|(_, sub)| sub.as_ref());
+            let (Some(src_sub), Some(dst_sub)) = (src_sub, dst_sub) else {
+                continue;
+            };
+            let Some(rhs) = dst_sub.rest.sub(&src_sub.rest).get_constant() else {
+                continue;
+            };
+            equations.push(Equation {
+                src: src_sub.coeffs.clone(),
+                dst: dst_sub.coeffs.clone(),
+                rhs: rhs as i64,
+            });
+        }
+        let src_bounds: Vec<Option<i64>> = src_nest.iter().map(|lo| self.get_bound(*lo)).collect();
+        let dst_bounds: Vec<Option<i64>> = dst_nest.iter().map(|lo| self.get_bound(*lo)).collect();
+
+        // GCD test, and strong SIV test for constant distances
+        let mut distance = vec![None; depth];
+        for eq in equations.iter() {
+            let g = eq
+                .src
+                .iter()
+                .chain(eq.dst.iter())
+                .fold(0, |g, a| gcd(g, a.abs()));
+            if (g == 0 && eq.rhs != 0) || (g != 0 && eq.rhs % g != 0) {
+                return None;
+            }
+            let Some((level, a)) = get_strong_siv(eq, depth) else {
+                continue;
+            };
+            let d = -eq.rhs / a;
+            if src_bounds[level].is_some_and(|u| d.abs() > u) {
+                return None;
+            }
+            if distance[level].is_some_and(|old| old != d) {
+                return None;
+            }
+            distance[level] = Some(d);
+        }
+
+        // Refine direction vectors level by level with Banerjee test
+        let mut directions = Vec::new();
+        let mut partial = Vec::new();
+        refine_directions(
+            &equations,
+            &distance,
+            (&src_bounds, &dst_bounds),
+            &mut partial,
+            &mut directions,
+        );
+        if directions.is_empty() {
+            return None;
+        }
+        Some(Dependence {
+            loops: common,
+            directions,
+            distance,
+        })
+    }
+
+    /// Get number of scalar elements the address of load or store advances by
+    /// in each iteration of loop, or `None` if unknown.
+    pub fn get_stride(&mut self, inst: InstPtr, lo: LoopPtr) -> Option<i64> {
+        let nest = self.get_nest(inst);
+        let level = nest.iter().position(|l| *l == lo)?;
+        let access = self.get_access(&get_address(inst)?, &nest);
+        let mut stride = 0;
+        for (ty, sub) in access.subscripts.iter() {
+            stride += sub.as_ref()?.coeffs[level] * ty.size() as i64;
+        }
+        Some(stride)
+    }
+
+    /// Get upper bound of iteration numbers of loop, if its trip count is constant.
+    fn get_bound(&mut self, lo: LoopPtr) -> Option<i64> {
+        let count = self.scev.get_trip_count(lo)?.get_constant()?;
+        Some(count as i64)
+    }
+
+    /// Split address into base object and subscripts of each dimension.
+    fn get_access(&mut self, ptr: &Operand, nest: &[LoopPtr]) -> Access {
+        let mut base = ptr.clone();
+        let mut indices: Vec<(ValueType, Vec<Operand>)> = Vec::new();
+        while let Operand::Instruction(inst) = base {
+            if inst.get_type() != InstType::GetElementPtr {
+                break;
+            }
+            base = inst.get_operand()[0].clone();
+            let gep = downcast_ref::<GetElementPtr>(inst.as_ref().as_ref());
+            let mut element_type = gep.element_type.clone();
+            for op in inst.get_operand().iter().skip(1) {
+                match indices.iter_mut().find(|(t, _)| *t == element_type) {
+                    Some((_, ops)) => ops.push(op.clone()),
+                    None => indices.push((element_type.clone(), vec![op.clone()])),
+                }
+                if let Some(sub_type) = element_type.get_sub_type() {
+                    element_type = sub_type.clone();
+                }
+            }
+        }
+        let subscripts = indices
+            .into_iter()
+            .map(|(ty, ops)| {
+                let mut sum = Some(Affine::new_invariant(nest.len(), Linear::new_constant(0)));
+                for op in ops.iter() {
+                    sum = sum.zip(self.get_affine(op, nest)).map(|(a, b)| a.add(&b));
+                }
+                (ty, sum)
+            })
+            .collect();
+        Access { base, subscripts }
+    }
+
+    /// Get operand as affine function of iteration numbers of loops in nest.
+    fn get_affine(&mut self, op: &Operand, nest: &[LoopPtr]) -> Option<Affine> {
+        let Some((lo, outer)) = nest.split_last() else {
+            let rest = match op {
+                Operand::Constant(Constant::Int(c)) => Linear::new_constant(*c),
+                _ if op.get_type() == ValueType::Int => Linear::new_atom(op.clone()),
+                _ => return None,
+            };
+            return Some(Affine::new_invariant(0, rest));
+        };
+        let (start, step) = match self.scev.get_scev(op, *lo) {
+            Scev::Invariant(linear) => (linear, 0),
+            Scev::AddRec { start, step } => (start, step.get_constant()?),
+            Scev::Unknown => return None,
+        };
+
+        // Start is invariant in this loop, but may vary in outer loops
+        let mut affine = Affine::new_invariant(outer.len(), Linear::new_constant(start.constant));
+        for (op, a) in start.terms.iter() {
+            affine = affine.add(&self.get_affine(op, outer)?.scale(*a));
+        }
+        affine.coeffs.push(step as i64);
+        Some(affine)
+    }
+
+    /// Dump dependence between each pair of loads and stores in function, where one is a store.
+    pub fn dump(&mut self, func: FunPtr) -> String {
+        let mut accesses = Vec::new();
+        for bb in func.dfs_iter() {
+            for inst in bb.iter() {
+                if let InstType::Load | InstType::Store = inst.get_type() {
+                    accesses.push(inst);
+                }
+            }
+        }
+        let mut res = String::new();
+        for (i, src) in accesses.iter().enumerate() {
+            for dst in accesses[i..].iter() {
+                if src.get_type() != InstType::Store && dst.get_type() != InstType::Store {
+                    continue;
+                }
+                let src_op: Operand = (*src).into();
+                let dst_op: Operand = (*dst).into();
+                match self.get_dependence(*src, *dst) {
+                    Some(dep) => res += &format!("{} -> {}: {}\n", src_op, dst_op, dep),
+                    None => res += &format!("{} -> {}: independent\n", src_op, dst_op),
+                }
+            }
+        }
+        res
+    }
+}
+
+/// Get address accessed by load or store.
+fn get_address(inst: InstPtr) -> Option<Operand> {
+    match inst.get_type() {
+        InstType::Load => Some(inst.get_operand()[0].clone()),
+        InstType::Store => Some(inst.get_operand()[1].clone()),
+        _ => None,
+    }
+}
+
+/// Check if operand is a memory object that doesn't overlap with other objects.
+fn is_object(op: &Operand) -> bool {
+    match op {
+        Operand::Global(_) => true,
+        Operand::Instruction(inst) => inst.get_type() == InstType::Alloca,
+        _ => false,
+    }
+}
+
+/// Get level and coefficient if equation only involves one common loop with equal coefficients.
+fn get_strong_siv(eq: &Equation, depth: usize) -> Option<(usize, i64)> {
+    let mut found = None;
+    for level in 0..eq.src.len().max(eq.dst.len()) {
+        let a = eq.src.get(level).cloned().unwrap_or(0);
+        let b = eq.dst.get(level).cloned().unwrap_or(0);
+        if a == 0 && b == 0 {
+            continue;
+        }
+        if found.is_some() || level >= depth || a != b {
+            return None;
+        }
+        found = Some((level, a));
+    }
+    found
+}
+
+/// Extend partial direction vector level by level, keeping those passing Banerjee test.
+fn refine_directions(
+    equations: &[Equation],
+    distance: &[Option<i64>],
+    bounds: (&[Option<i64>], &[Option<i64>]),
+    partial: &mut Vec<Direction>,
+    result: &mut Vec<Vec<Direction>>,
+) {
+    if !equations
+        .iter()
+        .all(|eq| is_banerjee_feasible(eq, partial, bounds))
+    {
+        return;
+    }
+    let level = partial.len();
+    if level == distance.len() {
+        result.push(partial.clone());
+        return;
+    }
+    for dir in [Direction::Lt, Direction::Eq, Direction::Gt] {
+        let matches = match distance[level] {
+            Some(d) => dir == get_direction(d),
+            None => true,
+        };
+        if matches {
+            partial.push(dir);
+            refine_directions(equations, distance, bounds, partial, result);
+            partial.pop();
+        }
+    }
+}
+
+/// Check if equation may have a solution under partial direction vector,
+/// where levels out of the vector may take any direction.
+fn is_banerjee_feasible(
+    eq: &Equation,
+    partial: &[Direction],
+    (src_bounds, dst_bounds): (&[Option<i64>], &[Option<i64>]),
+) -> bool {
+    let (mut min, mut max) = (Some(0), Some(0));
+    for level in 0..eq.src.len().max(eq.dst.len()) {
+        let a = eq.src.get(level).cloned().unwrap_or(0);
+        let b = eq.dst.get(level).cloned().unwrap_or(0);
+        let bound = match (src_bounds.get(level), dst_bounds.get(level)) {
+            (Some(src), Some(dst)) => src.zip(*dst).map(|(s, d)| s.max(d)),
+            (Some(bound), None) | (None, Some(bound)) => *bound,
+            (None, None) => None,
+        };
+        let Some((lo, hi)) = get_term_range(a, b, partial.get(level).cloned(), bound) else {
+            return false;
+        };
+        min = min.zip(lo).map(|(x, y)| x + y);
+        max = max.zip(hi).map(|(x, y)| x + y);
+    }
+    min.is_none_or(|min| min <= eq.rhs) && max.is_none_or(|max| eq.rhs <= max)
+}
+
+/// Coordinate `p + q * bound` of a vertex of the iteration space.
+type Coordinate = (i64, i64);
+
+/// Get range of `a * I - b * I'` for `0 <= I, I' <= bound` under direction, where `None` in range
+/// is unbounded. Returns `None` if direction is impossible.
+fn get_term_range(
+    a: i64,
+    b: i64,
+    dir: Option<Direction>,
+    bound: Option<i64>,
+) -> Option<(Option<i64>, Option<i64>)> {
+    // Vertices of the iteration space as `(I, I')`
+    let (vertices, min_bound): (&[(Coordinate, Coordinate)], i64) = match dir {
+        None => (
+            &[
+                ((0, 0), (0, 0)),
+                ((0, 0), (0, 1)),
+                ((0, 1), (0, 0)),
+                ((0, 1), (0, 1)),
+            ],
+            0,
+        ),
+        Some(Direction::Eq) => (&[((0, 0), (0, 0)), ((0, 1), (0, 1))], 0),
+        Some(Direction::Lt) => (&[((0, 0), (1, 0)), ((0, 0), (0, 1)), ((-1, 1), (0, 1))], 1),
+        Some(Direction::Gt) => (&[((1, 0), (0, 0)), ((0, 1), (0, 0)), ((0, 1), (-1, 1))], 1),
+    };
+    if bound.is_some_and(|u| u < min_bound) {
+        return None;
+    }
+
+    // Value at vertex is `alpha + beta * bound`, unknown bound can be arbitrarily large
+    let mut min = Some(i64::MAX);
+    let mut max = Some(i64::MIN);
+    for ((p, q), (r, s)) in vertices.iter() {
+        let alpha = a * p - b * r;
+        let beta = a * q - b * s;
+        let (lo, hi) = match bound {
+            Some(u) => (Some(alpha + beta * u), Some(alpha + beta * u)),
+            None => {
+                let value = alpha + beta * min_bound;
+                ((beta >= 0).then_some(value), (beta <= 0).then_some(value))
+            }
+        };
+        min = min.zip(lo).map(|(x, y)| x.min(y));
+        max = max.zip(hi).map(|(x, y)| x.max(y));
+    }
+    Some((min, max))
+}
+
+fn get_direction(distance: i64) -> Direction {
+    match distance {
+        d if d > 0 => Direction::Lt,
+        0 => Direction::Eq,
+        _ => Direction::Gt,
+    }
+}
+
+fn get_all_directions(depth: usize) -> Vec<Vec<Direction>> {
+    let mut result = vec![Vec::new()];
+    for _ in 0..depth {
+        result = result
+            .into_iter()
+            .flat_map(|dv| {
+                [Direction::Lt, Direction::Eq, Direction::Gt].map(|d| {
+                    let mut dv = dv.clone();
+                    dv.push(d);
+                    dv
+                })
+            })
+            .collect();
+    }
+    result
+}
+
+fn gcd(a: i64, b: i64) -> i64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
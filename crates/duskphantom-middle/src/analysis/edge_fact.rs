@@ -0,0 +1,49 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::ir::{BBPtr, Operand};
+
+use super::dominator_tree::DominatorTree;
+
+/// A condition known to have a value, because a branch on it was taken.
+pub type Fact = (Operand, bool);
+
+/// Get condition of a conditional branch with distinct successors.
+pub fn get_branch_cond(bb: BBPtr) -> Option<Operand> {
+    let succ = bb.get_succ_bb();
+    if succ.len() != 2 || succ[0] == succ[1] {
+        return None;
+    }
+    let cond = bb.get_last_inst().get_operand().first()?.clone();
+    (!matches!(cond, Operand::Constant(_))).then_some(cond)
+}
+
+/// Get the fact known on edge `pred -> bb`.
+pub fn get_edge_fact(pred: BBPtr, bb: BBPtr) -> Option<Fact> {
+    let cond = get_branch_cond(pred)?;
+    Some((cond, pred.get_succ_bb()[0] == bb))
+}
+
+/// Get facts known at `bb`, from dominating blocks entered only by a conditional edge.
+/// Nearest facts come first.
+pub fn get_facts(bb: BBPtr, dom_tree: &mut DominatorTree) -> impl Iterator<Item = Fact> + '_ {
+    std::iter::successors(Some(bb), |bb| dom_tree.get_idom(*bb)).filter_map(|bb| {
+        match bb.get_pred_bb().as_slice() {
+            [pred] => get_edge_fact(*pred, bb),
+            _ => None,
+        }
+    })
+}
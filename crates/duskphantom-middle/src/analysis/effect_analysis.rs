@@ -24,7 +24,10 @@ use crate::{
 };
 use std::collections::{HashMap, HashSet};
 
-use super::{alias_analysis::EffectRange, call_graph::CallGraph};
+use super::{
+    alias_analysis::{get_base_object, EffectRange},
+    call_graph::CallGraph,
+};
 
 pub struct Effect {
     pub def_range: EffectRange,
@@ -189,7 +192,7 @@ impl EffectAnalysis {
                         // Add instruction effect
                         if call.func.name.contains("memset") {
                             // Treat memset as a store
-                            let ptr = get_base_pointer(inst.get_operand()[0].clone());
+                            let ptr = get_base_object(&inst.get_operand()[0]);
                             self.inst_effect.insert(
                                 inst,
                                 Effect {
@@ -198,7 +201,7 @@ impl EffectAnalysis {
                                 },
                             );
                         } else if call.func.name == "putarray" || call.func.name == "putfarray" {
-                            let ptr = get_base_pointer(inst.get_operand()[1].clone());
+                            let ptr = get_base_object(&inst.get_operand()[1]);
                             self.inst_effect.insert(
                                 inst,
                                 Effect {
@@ -207,7 +210,7 @@ impl EffectAnalysis {
                                 },
                             );
                         } else if call.func.name == "getarray" || call.func.name == "getfarray" {
-                            let ptr = get_base_pointer(inst.get_operand()[0].clone());
+                            let ptr = get_base_object(&inst.get_operand()[0]);
                             self.inst_effect.insert(
                                 inst,
                                 Effect {
@@ -285,30 +288,11 @@ impl EffectAnalysis {
 
 /// Check if operand as store / load position causes outside effect.
 fn check_effect(operand: &Operand) -> bool {
-    match operand {
-        Operand::Instruction(inst) => {
-            if inst.get_type() == InstType::GetElementPtr {
-                check_effect(inst.get_operand().first().unwrap())
-            } else {
-                false
-            }
-        }
+    match get_base_object(operand) {
+        // Phi can merge pointers to outside memory
+        Operand::Instruction(inst) => inst.get_type() == InstType::Phi,
         Operand::Global(_) => true,
         Operand::Parameter(_) => true,
         Operand::Constant(_) => false,
     }
 }
-
-/// Get base pointer of operand (because pointer in function argument can GEP a lot)
-fn get_base_pointer(operand: Operand) -> Operand {
-    match operand {
-        Operand::Instruction(inst) => {
-            if inst.get_type() == InstType::GetElementPtr {
-                get_base_pointer(inst.get_operand().first().unwrap().clone())
-            } else {
-                operand
-            }
-        }
-        _ => operand,
-    }
-}
@@ -0,0 +1,296 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::Display;
+
+use crate::ir::{
+    instruction::{
+        downcast_ref,
+        misc_inst::{ICmp, ICmpOp},
+        InstType,
+    },
+    Constant, InstPtr, Operand, ValueType,
+};
+
+/// Operands are looked through this deep when computing known bits.
+const MAX_DEPTH: usize = 6;
+
+/// Bits of a 32-bit integer known to be zero or one.
+/// Booleans are integers whose bits are zero except the lowest one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KnownBits {
+    pub zero: u32,
+    pub one: u32,
+}
+
+impl Display for KnownBits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for i in (0..32).rev() {
+            let bit = 1 << i;
+            if self.zero & bit != 0 {
+                write!(f, "0")?;
+            } else if self.one & bit != 0 {
+                write!(f, "1")?;
+            } else {
+                write!(f, "?")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl KnownBits {
+    pub const SIGN: u32 = 1 << 31;
+
+    /// Known bits of any value of given type.
+    pub fn unknown(ty: &ValueType) -> Self {
+        match ty {
+            ValueType::Bool => Self { zero: !1, one: 0 },
+            _ => Self::default(),
+        }
+    }
+
+    pub fn constant(c: u32) -> Self {
+        Self { zero: !c, one: c }
+    }
+
+    /// Get bits known to be either zero or one.
+    pub fn known(&self) -> u32 {
+        self.zero | self.one
+    }
+
+    pub fn get_constant(&self) -> Option<u32> {
+        (self.known() == u32::MAX).then_some(self.one)
+    }
+
+    pub fn is_non_negative(&self) -> bool {
+        self.zero & Self::SIGN != 0
+    }
+
+    /// Bits known for a value that is either of the two values.
+    pub fn union(&self, other: &KnownBits) -> KnownBits {
+        Self {
+            zero: self.zero & other.zero,
+            one: self.one & other.one,
+        }
+    }
+
+    /// Bits known for a value satisfying both facts.
+    pub fn intersect(&self, other: &KnownBits) -> KnownBits {
+        Self {
+            zero: self.zero | other.zero,
+            one: self.one | other.one,
+        }
+    }
+
+    pub fn not(&self) -> KnownBits {
+        Self {
+            zero: self.one,
+            one: self.zero,
+        }
+    }
+
+    /// Add two values with a carry-in, tracking which carries are known.
+    pub fn add(&self, other: &KnownBits, carry: bool) -> KnownBits {
+        let possible_sum_zero = (!self.zero)
+            .wrapping_add(!other.zero)
+            .wrapping_add(carry as u32);
+        let possible_sum_one = self.one.wrapping_add(other.one).wrapping_add(carry as u32);
+        let carry_known_zero = !(possible_sum_zero ^ self.zero ^ other.zero);
+        let carry_known_one = possible_sum_one ^ self.one ^ other.one;
+        let known = self.known() & other.known() & (carry_known_zero | carry_known_one);
+        Self {
+            zero: !possible_sum_zero & known,
+            one: possible_sum_one & known,
+        }
+    }
+
+    pub fn shl(&self, amount: u32) -> KnownBits {
+        Self {
+            zero: (self.zero << amount) | low_mask(amount),
+            one: self.one << amount,
+        }
+    }
+
+    pub fn lshr(&self, amount: u32) -> KnownBits {
+        Self {
+            zero: (self.zero >> amount) | high_mask(amount),
+            one: self.one >> amount,
+        }
+    }
+
+    pub fn ashr(&self, amount: u32) -> KnownBits {
+        Self {
+            zero: ((self.zero as i32) >> amount) as u32,
+            one: ((self.one as i32) >> amount) as u32,
+        }
+    }
+
+    /// Decide comparison if known bits of operands conflict.
+    pub fn compare(op: ICmpOp, lhs: KnownBits, rhs: KnownBits) -> Option<bool> {
+        let conflict = (lhs.zero & rhs.one) | (lhs.one & rhs.zero) != 0;
+        match op {
+            ICmpOp::Eq if conflict => Some(false),
+            ICmpOp::Ne if conflict => Some(true),
+            _ => None,
+        }
+    }
+}
+
+/// Mask of lowest `n` bits.
+pub fn low_mask(n: u32) -> u32 {
+    u32::MAX.checked_shr(32 - n).unwrap_or(0)
+}
+
+/// Mask of all bits not higher than the highest bit of `bits`.
+pub fn low_mask_upto(bits: u32) -> u32 {
+    u32::MAX.checked_shr(bits.leading_zeros()).unwrap_or(0)
+}
+
+/// Mask of highest `n` bits.
+pub fn high_mask(n: u32) -> u32 {
+    !low_mask(32 - n)
+}
+
+/// Get constant shift amount of operand, if it is a valid one.
+pub fn get_shift_amount(op: &Operand) -> Option<u32> {
+    match op {
+        Operand::Constant(Constant::Int(k)) if (0..32).contains(k) => Some(*k as u32),
+        _ => None,
+    }
+}
+
+/// Get known bits of an operand, looking through a few levels of instructions.
+pub fn get_known_bits(op: &Operand) -> KnownBits {
+    get_known_bits_with_depth(op, MAX_DEPTH)
+}
+
+fn get_known_bits_with_depth(op: &Operand, depth: usize) -> KnownBits {
+    match op {
+        Operand::Constant(Constant::Int(c)) => KnownBits::constant(*c as u32),
+        Operand::Constant(Constant::Bool(b)) => KnownBits::constant(*b as u32),
+        Operand::Instruction(inst) if depth > 0 => compute(*inst, depth),
+        _ => KnownBits::unknown(&op.get_type()),
+    }
+}
+
+/// Compute known bits of an instruction from its operands.
+fn compute(inst: InstPtr, depth: usize) -> KnownBits {
+    let ty = inst.get_value_type();
+    let operand = |i: usize| get_known_bits_with_depth(&inst.get_operand()[i], depth - 1);
+    let amount = || get_shift_amount(&inst.get_operand()[1]);
+    let bits = match inst.get_type() {
+        InstType::Add => operand(0).add(&operand(1), false),
+        InstType::Sub => operand(0).add(&operand(1).not(), true),
+        InstType::Mul => {
+            let lhs = operand(0);
+            let rhs = operand(1);
+            match rhs.get_constant() {
+                Some(c) if c.is_power_of_two() => lhs.shl(c.trailing_zeros()),
+                _ => {
+                    let zeros = lhs.zero.trailing_ones() + rhs.zero.trailing_ones();
+                    KnownBits {
+                        zero: low_mask(zeros.min(32)),
+                        one: 0,
+                    }
+                }
+            }
+        }
+        InstType::Shl => amount().map_or_else(KnownBits::default, |k| operand(0).shl(k)),
+        InstType::LShr => amount().map_or_else(KnownBits::default, |k| operand(0).lshr(k)),
+        InstType::AShr => amount().map_or_else(KnownBits::default, |k| operand(0).ashr(k)),
+        InstType::SRem | InstType::URem => {
+            let lhs = operand(0);
+            match operand(1).get_constant() {
+                Some(c) if c.is_power_of_two() => {
+                    // Remainder keeps low bits, and is masked if it can not be negative
+                    let mask = c - 1;
+                    if lhs.zero & mask == mask {
+                        KnownBits::constant(0)
+                    } else if inst.get_type() == InstType::URem || lhs.is_non_negative() {
+                        KnownBits {
+                            zero: lhs.zero | !mask,
+                            one: lhs.one & mask,
+                        }
+                    } else {
+                        KnownBits {
+                            zero: lhs.zero & mask,
+                            one: lhs.one & mask,
+                        }
+                    }
+                }
+                _ => KnownBits::default(),
+            }
+        }
+        InstType::SDiv | InstType::UDiv => {
+            // Quotient has no less leading zeros than a non-negative dividend
+            let lhs = operand(0);
+            let rhs = operand(1);
+            if inst.get_type() == InstType::UDiv || (lhs.is_non_negative() && rhs.is_non_negative())
+            {
+                KnownBits {
+                    zero: high_mask(lhs.zero.leading_ones()),
+                    one: 0,
+                }
+            } else {
+                KnownBits::default()
+            }
+        }
+        InstType::And => {
+            let lhs = operand(0);
+            let rhs = operand(1);
+            KnownBits {
+                zero: lhs.zero | rhs.zero,
+                one: lhs.one & rhs.one,
+            }
+        }
+        InstType::Or => {
+            let lhs = operand(0);
+            let rhs = operand(1);
+            KnownBits {
+                zero: lhs.zero & rhs.zero,
+                one: lhs.one | rhs.one,
+            }
+        }
+        InstType::Xor => {
+            let lhs = operand(0);
+            let rhs = operand(1);
+            KnownBits {
+                zero: (lhs.zero & rhs.zero) | (lhs.one & rhs.one),
+                one: (lhs.zero & rhs.one) | (lhs.one & rhs.zero),
+            }
+        }
+        InstType::ZextTo => operand(0),
+        InstType::SextTo => match operand(0).get_constant() {
+            Some(c) => KnownBits::constant((c as i32).wrapping_neg() as u32),
+            None => KnownBits::default(),
+        },
+        InstType::ICmp => {
+            let icmp = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+            match KnownBits::compare(icmp.op, operand(0), operand(1)) {
+                Some(b) => KnownBits::constant(b as u32),
+                None => KnownBits::unknown(&ty),
+            }
+        }
+        InstType::Select => operand(1).union(&operand(2)),
+        InstType::Phi => (0..inst.get_operand().len())
+            .map(operand)
+            .reduce(|acc, bits| acc.union(&bits))
+            .unwrap_or_default(),
+        _ => KnownBits::unknown(&ty),
+    };
+    bits.intersect(&KnownBits::unknown(&ty))
+}
@@ -18,6 +18,8 @@ use dominator_tree::DominatorTree;
 
 use duskphantom_utils::mem::{ObjPool, ObjPtr};
 
+use crate::ir::Operand;
+
 use super::*;
 
 pub type LoopPtr = ObjPtr<LoopTree>;
@@ -40,6 +42,26 @@ impl LoopTree {
     pub fn is_in_loop(&self, bb: &BBPtr) -> bool {
         self.blocks.contains(bb) || self.sub_loops.iter().any(|lo| lo.is_in_loop(bb))
     }
+
+    /// Check if operand is defined outside of loop.
+    pub fn is_invariant(&self, op: &Operand) -> bool {
+        match op {
+            Operand::Instruction(inst) => {
+                inst.get_parent_bb().is_some_and(|bb| !self.is_in_loop(&bb))
+            }
+            _ => true,
+        }
+    }
+
+    /// Get all blocks in loop including sub loops, ordered by id.
+    pub fn get_all_blocks(&self) -> Vec<BBPtr> {
+        let mut blocks: Vec<BBPtr> = self.blocks.iter().cloned().collect();
+        for lo in self.sub_loops.iter() {
+            blocks.extend(lo.get_all_blocks());
+        }
+        blocks.sort_by_key(|bb| bb.id);
+        blocks
+    }
 }
 
 #[allow(dead_code)]
@@ -20,9 +20,15 @@ use std::collections::{HashMap, HashSet};
 
 pub mod alias_analysis;
 pub mod call_graph;
+pub mod demanded_bits;
+pub mod dependence_analysis;
 pub mod dominator_tree;
+pub mod edge_fact;
 pub mod effect_analysis;
+pub mod known_bits;
 pub mod loop_tools;
 pub mod memory_ssa;
 pub mod reachability;
+pub mod scalar_evolution;
 pub mod simple_gvn;
+pub mod value_range;
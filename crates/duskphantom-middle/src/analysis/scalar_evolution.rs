@@ -0,0 +1,567 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::Display;
+
+use crate::ir::{
+    instruction::{
+        downcast_ref,
+        misc_inst::{ICmp, ICmpOp, Phi},
+        InstType,
+    },
+    Constant, IRBuilder, InstPtr, Operand, ValueType,
+};
+
+use super::{
+    loop_tools::{LoopForest, LoopPtr},
+    *,
+};
+
+/// Linear combination of loop invariant values: `c + a1 * x1 + a2 * x2 + ...`.
+/// Arithmetic wraps around like `i32` in IR.
+#[derive(Clone, Debug, Default)]
+pub struct Linear {
+    pub constant: i32,
+    pub terms: Vec<(Operand, i32)>,
+}
+
+impl Linear {
+    pub fn new_constant(constant: i32) -> Self {
+        Self {
+            constant,
+            terms: Vec::new(),
+        }
+    }
+
+    pub fn new_atom(op: Operand) -> Self {
+        Self {
+            constant: 0,
+            terms: vec![(op, 1)],
+        }
+    }
+
+    pub fn get_constant(&self) -> Option<i32> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    /// Get coefficient of given value.
+    pub fn get_coefficient(&self, op: &Operand) -> i32 {
+        self.terms
+            .iter()
+            .find(|(x, _)| x == op)
+            .map_or(0, |(_, a)| *a)
+    }
+
+    pub fn add(&self, other: &Linear) -> Linear {
+        let mut res = self.clone();
+        res.constant = res.constant.wrapping_add(other.constant);
+        for (op, a) in other.terms.iter() {
+            match res.terms.iter_mut().find(|(x, _)| x == op) {
+                Some((_, b)) => *b = b.wrapping_add(*a),
+                None => res.terms.push((op.clone(), *a)),
+            }
+        }
+        res.terms.retain(|(_, a)| *a != 0);
+        res
+    }
+
+    pub fn sub(&self, other: &Linear) -> Linear {
+        self.add(&other.scale(-1))
+    }
+
+    pub fn scale(&self, c: i32) -> Linear {
+        let mut res = Linear {
+            constant: self.constant.wrapping_mul(c),
+            terms: self
+                .terms
+                .iter()
+                .map(|(op, a)| (op.clone(), a.wrapping_mul(c)))
+                .collect(),
+        };
+        res.terms.retain(|(_, a)| *a != 0);
+        res
+    }
+
+    /// Build instructions computing this value before `pos`, and get the result.
+    pub fn emit(&self, builder: &mut IRBuilder, mut pos: InstPtr) -> Operand {
+        let mut res: Option<Operand> = None;
+        for (op, a) in self.terms.iter() {
+            res = Some(match (res, a) {
+                (Some(acc), -1) => {
+                    let sub = builder.get_sub(acc, op.clone());
+                    pos.insert_before(sub);
+                    sub.into()
+                }
+                (acc, a) => {
+                    let term = if *a == 1 {
+                        op.clone()
+                    } else {
+                        let mul = builder.get_mul(op.clone(), Constant::Int(*a).into());
+                        pos.insert_before(mul);
+                        mul.into()
+                    };
+                    match acc {
+                        Some(acc) => {
+                            let add = builder.get_add(acc, term);
+                            pos.insert_before(add);
+                            add.into()
+                        }
+                        None => term,
+                    }
+                }
+            });
+        }
+        match res {
+            Some(acc) if self.constant != 0 => {
+                let add = builder.get_add(acc, Constant::Int(self.constant).into());
+                pos.insert_before(add);
+                add.into()
+            }
+            Some(acc) => acc,
+            None => Constant::Int(self.constant).into(),
+        }
+    }
+
+    /// Multiply two linear combinations, only if one of them is a constant.
+    pub fn mul(&self, other: &Linear) -> Option<Linear> {
+        if let Some(c) = other.get_constant() {
+            Some(self.scale(c))
+        } else {
+            self.get_constant().map(|c| other.scale(c))
+        }
+    }
+}
+
+impl PartialEq for Linear {
+    fn eq(&self, other: &Self) -> bool {
+        self.constant == other.constant
+            && self.terms.len() == other.terms.len()
+            && self
+                .terms
+                .iter()
+                .all(|(op, a)| other.get_coefficient(op) == *a)
+    }
+}
+
+impl Display for Linear {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts: Vec<String> = self
+            .terms
+            .iter()
+            .map(|(op, a)| match a {
+                1 => format!("{}", op),
+                _ => format!("{} * {}", a, op),
+            })
+            .collect();
+        if self.constant != 0 || parts.is_empty() {
+            parts.push(self.constant.to_string());
+        }
+        write!(f, "{}", parts.join(" + "))
+    }
+}
+
+/// Evolution of an integer value in a loop.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Scev {
+    /// Value does not change in the loop.
+    Invariant(Linear),
+
+    /// Affine add-recurrence `{start,+,step}`, the value is `start + k * step` in the k-th iteration.
+    AddRec {
+        start: Linear,
+        step: Linear,
+    },
+
+    Unknown,
+}
+
+impl Display for Scev {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scev::Invariant(linear) => write!(f, "{}", linear),
+            Scev::AddRec { start, step } => write!(f, "{{{},+,{}}}", start, step),
+            Scev::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+impl Scev {
+    pub fn add(&self, other: &Scev) -> Scev {
+        match (self, other) {
+            (Scev::Invariant(a), Scev::Invariant(b)) => Scev::Invariant(a.add(b)),
+            (Scev::AddRec { start, step }, Scev::Invariant(inv))
+            | (Scev::Invariant(inv), Scev::AddRec { start, step }) => Scev::AddRec {
+                start: start.add(inv),
+                step: step.clone(),
+            },
+            (
+                Scev::AddRec { start, step },
+                Scev::AddRec {
+                    start: start2,
+                    step: step2,
+                },
+            ) => Scev::AddRec {
+                start: start.add(start2),
+                step: step.add(step2),
+            },
+            _ => Scev::Unknown,
+        }
+    }
+
+    pub fn scale(&self, c: i32) -> Scev {
+        match self {
+            Scev::Invariant(linear) => Scev::Invariant(linear.scale(c)),
+            Scev::AddRec { start, step } => Scev::AddRec {
+                start: start.scale(c),
+                step: step.scale(c),
+            },
+            Scev::Unknown => Scev::Unknown,
+        }
+    }
+
+    pub fn mul(&self, other: &Scev) -> Scev {
+        match (self, other) {
+            (Scev::Invariant(a), Scev::Invariant(b)) => {
+                a.mul(b).map_or(Scev::Unknown, Scev::Invariant)
+            }
+            (Scev::AddRec { start, step }, Scev::Invariant(inv))
+            | (Scev::Invariant(inv), Scev::AddRec { start, step }) => {
+                match (start.mul(inv), step.mul(inv)) {
+                    (Some(start), Some(step)) => Scev::AddRec { start, step },
+                    _ => Scev::Unknown,
+                }
+            }
+            _ => Scev::Unknown,
+        }
+    }
+}
+
+/// Number of times the back edge of a loop is taken.
+/// Loop continues while `{start,+,step} op bound` holds on the exiting block.
+/// Signed overflow of the recurrence is assumed not to happen.
+#[derive(Clone, PartialEq)]
+pub struct TripCount {
+    pub start: Linear,
+    pub step: i32,
+    pub op: ICmpOp,
+    pub bound: Linear,
+}
+
+impl TripCount {
+    pub fn get_constant(&self) -> Option<usize> {
+        let start = self.start.get_constant()? as i64;
+        let bound = self.bound.get_constant()? as i64;
+        let step = self.step as i64;
+        let count = match self.op {
+            ICmpOp::Slt if step > 0 => (bound - start + step - 1).max(0) / step,
+            ICmpOp::Sle if step > 0 => (bound - start + step).max(0) / step,
+            ICmpOp::Sgt if step < 0 => (start - bound - step - 1).max(0) / -step,
+            ICmpOp::Sge if step < 0 => (start - bound - step).max(0) / -step,
+            ICmpOp::Ne if (bound - start) % step == 0 && (bound - start) / step >= 0 => {
+                (bound - start) / step
+            }
+            ICmpOp::Eq => (start == bound) as i64,
+            _ => return None,
+        };
+
+        // Last tested value should not overflow, otherwise the loop may not exit as expected
+        let last = start + count * step;
+        (i32::MIN as i64..=i32::MAX as i64)
+            .contains(&last)
+            .then_some(count as usize)
+    }
+}
+
+impl Display for TripCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{{{},+,{}}} {} {}",
+            self.start, self.step, self.op, self.bound
+        )?;
+        if let Some(count) = self.get_constant() {
+            write!(f, " = {}", count)?;
+        }
+        Ok(())
+    }
+}
+
+/// Scalar evolution analysis, recognizes affine induction variables of loops.
+/// Results are relative to the queried loop, values defined outside are invariant.
+#[derive(Default)]
+pub struct ScalarEvolution {
+    cache: HashMap<(InstPtr, LoopPtr), Scev>,
+
+    /// Header phis under analysis, which are treated as opaque values
+    in_progress: HashSet<InstPtr>,
+}
+
+impl ScalarEvolution {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get evolution of an operand in given loop.
+    pub fn get_scev(&mut self, op: &Operand, lo: LoopPtr) -> Scev {
+        match op {
+            Operand::Constant(Constant::Int(c)) => Scev::Invariant(Linear::new_constant(*c)),
+            Operand::Parameter(_) if op.get_type() == ValueType::Int => {
+                Scev::Invariant(Linear::new_atom(op.clone()))
+            }
+            Operand::Instruction(inst) if inst.get_value_type() == ValueType::Int => {
+                self.get_inst_scev(*inst, lo)
+            }
+            _ => Scev::Unknown,
+        }
+    }
+
+    fn get_inst_scev(&mut self, inst: InstPtr, lo: LoopPtr) -> Scev {
+        if self.in_progress.contains(&inst) {
+            return Scev::Invariant(Linear::new_atom(inst.into()));
+        }
+        if let Some(scev) = self.cache.get(&(inst, lo)) {
+            return scev.clone();
+        }
+        let in_loop = inst.get_parent_bb().is_some_and(|bb| lo.is_in_loop(&bb));
+        let scev = match self.compute_scev(inst, lo) {
+            Scev::Unknown if !in_loop => Scev::Invariant(Linear::new_atom(inst.into())),
+            scev => scev,
+        };
+
+        // Results depending on phis under analysis are provisional
+        if self.in_progress.is_empty() {
+            self.cache.insert((inst, lo), scev.clone());
+        }
+        scev
+    }
+
+    fn compute_scev(&mut self, inst: InstPtr, lo: LoopPtr) -> Scev {
+        let operands = inst.get_operand();
+        match inst.get_type() {
+            InstType::Add => {
+                let lhs = self.get_scev(&operands[0], lo);
+                let rhs = self.get_scev(&operands[1], lo);
+                lhs.add(&rhs)
+            }
+            InstType::Sub => {
+                let lhs = self.get_scev(&operands[0], lo);
+                let rhs = self.get_scev(&operands[1], lo);
+                lhs.add(&rhs.scale(-1))
+            }
+            InstType::Mul => {
+                let lhs = self.get_scev(&operands[0], lo);
+                let rhs = self.get_scev(&operands[1], lo);
+                lhs.mul(&rhs)
+            }
+            InstType::Shl => match &operands[1] {
+                Operand::Constant(Constant::Int(c)) if (0..31).contains(c) => {
+                    self.get_scev(&operands[0], lo).scale(1 << c)
+                }
+                _ => Scev::Unknown,
+            },
+            InstType::Phi if inst.get_parent_bb() == Some(lo.head) => self.compute_phi(inst, lo),
+            _ => Scev::Unknown,
+        }
+    }
+
+    /// Recognize `phi = [init, preheader], [phi + d, latch]` as `{init,+,d}`.
+    fn compute_phi(&mut self, inst: InstPtr, lo: LoopPtr) -> Scev {
+        let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+        let (outside, inside): (Vec<_>, Vec<_>) = phi
+            .get_incoming_values()
+            .iter()
+            .partition(|(_, bb)| !lo.is_in_loop(bb));
+        let [(init, _)] = outside.as_slice() else {
+            return Scev::Unknown;
+        };
+        let Scev::Invariant(start) = self.get_scev(init, lo) else {
+            return Scev::Unknown;
+        };
+
+        // Evaluate incoming values from latches, treating phi itself as opaque
+        let this = Operand::Instruction(inst);
+        let mut step = None;
+        self.in_progress.insert(inst);
+        for (next, _) in inside.iter() {
+            let Scev::Invariant(next) = self.get_scev(next, lo) else {
+                step = None;
+                break;
+            };
+            if next.get_coefficient(&this) != 1 {
+                step = None;
+                break;
+            }
+            let d = next.sub(&Linear::new_atom(this.clone()));
+            if step.as_ref().is_some_and(|step| *step != d) {
+                step = None;
+                break;
+            }
+            step = Some(d);
+        }
+        self.in_progress.remove(&inst);
+
+        match step {
+            Some(step) if step.get_constant() == Some(0) => Scev::Invariant(start),
+            Some(step) => Scev::AddRec { start, step },
+            None => Scev::Unknown,
+        }
+    }
+
+    /// Get back edge taken count of a loop with a single exiting block,
+    /// which is either the head or the only latch.
+    pub fn get_trip_count(&mut self, lo: LoopPtr) -> Option<TripCount> {
+        let exiting = get_exiting_block(lo)?;
+        if exiting != lo.head {
+            let latches: Vec<_> = lo
+                .head
+                .get_pred_bb()
+                .iter()
+                .filter(|bb| lo.is_in_loop(bb))
+                .cloned()
+                .collect();
+            if latches != [exiting] {
+                return None;
+            }
+        }
+
+        // Normalize exit condition to `lhs op rhs` where loop continues if it holds
+        let succ = exiting.get_succ_bb();
+        let br = exiting.get_last_inst();
+        let Some(Operand::Instruction(cond)) = br.get_operand().first() else {
+            return None;
+        };
+        if succ.len() != 2 || cond.get_type() != InstType::ICmp {
+            return None;
+        }
+        let icmp = downcast_ref::<ICmp>(cond.as_ref().as_ref());
+        let op = if lo.is_in_loop(&succ[0]) {
+            icmp.op
+        } else {
+            negate_predicate(icmp.op)
+        };
+
+        // One side should be an induction variable with constant step, the other invariant
+        let lhs = self.get_scev(icmp.get_lhs(), lo);
+        let rhs = self.get_scev(icmp.get_rhs(), lo);
+        let (start, step, op, bound) = match (lhs, rhs) {
+            (Scev::AddRec { start, step }, Scev::Invariant(bound)) => (start, step, op, bound),
+            (Scev::Invariant(bound), Scev::AddRec { start, step }) => {
+                (start, step, swap_predicate(op), bound)
+            }
+            _ => return None,
+        };
+        Some(TripCount {
+            start,
+            step: step.get_constant().filter(|c| *c != 0)?,
+            op,
+            bound,
+        })
+    }
+
+    /// Get value of an operand when loop exits, if trip count is constant.
+    /// Operand should be defined in loop head or the exiting block.
+    pub fn get_exit_value(&mut self, op: &Operand, lo: LoopPtr) -> Option<Linear> {
+        let exiting = get_exiting_block(lo)?;
+        if let Operand::Instruction(inst) = op {
+            let bb = inst.get_parent_bb()?;
+            if lo.is_in_loop(&bb) && bb != lo.head && bb != exiting {
+                return None;
+            }
+        }
+        match self.get_scev(op, lo) {
+            Scev::Invariant(linear) => Some(linear),
+            Scev::AddRec { start, step } => {
+                let count = self.get_trip_count(lo)?.get_constant()?;
+                Some(start.add(&step.scale(count as i32)))
+            }
+            Scev::Unknown => None,
+        }
+    }
+
+    /// Dump trip count, exit values of head phis and induction variables of each loop.
+    pub fn dump(&mut self, forest: &LoopForest) -> String {
+        let mut res = String::new();
+        let mut stack: Vec<LoopPtr> = forest.forest.iter().rev().cloned().collect();
+        while let Some(lo) = stack.pop() {
+            res += &format!("loop {}:\n", lo.head.name);
+            match self.get_trip_count(lo) {
+                Some(trip_count) => res += &format!("    trip count: {}\n", trip_count),
+                None => res += "    trip count: unknown\n",
+            }
+            for inst in lo.head.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                if let Some(exit_value) = self.get_exit_value(&inst.into(), lo) {
+                    res += &format!("    exit value of {}: {}\n", inst, exit_value);
+                }
+            }
+            let mut blocks: Vec<BBPtr> = lo.blocks.iter().cloned().collect();
+            blocks.sort_by_key(|bb| bb.id);
+            for bb in blocks {
+                for inst in bb.iter() {
+                    if let scev @ Scev::AddRec { .. } = self.get_scev(&inst.into(), lo) {
+                        res += &format!("    {} = {}\n", inst, scev);
+                    }
+                }
+            }
+            stack.extend(lo.sub_loops.iter().rev());
+        }
+        res
+    }
+}
+
+/// Get the only block in loop that has a successor outside of loop.
+pub fn get_exiting_block(lo: LoopPtr) -> Option<BBPtr> {
+    let mut exiting = lo
+        .get_all_blocks()
+        .into_iter()
+        .filter(|bb| bb.get_succ_bb().iter().any(|succ| !lo.is_in_loop(succ)));
+    let bb = exiting.next()?;
+    exiting.next().is_none().then_some(bb)
+}
+
+/// Get predicate `op'` such that `a op b` iff `b op' a`.
+pub fn swap_predicate(op: ICmpOp) -> ICmpOp {
+    match op {
+        ICmpOp::Eq => ICmpOp::Eq,
+        ICmpOp::Ne => ICmpOp::Ne,
+        ICmpOp::Slt => ICmpOp::Sgt,
+        ICmpOp::Sle => ICmpOp::Sge,
+        ICmpOp::Sgt => ICmpOp::Slt,
+        ICmpOp::Sge => ICmpOp::Sle,
+        ICmpOp::Ult => ICmpOp::Ugt,
+        ICmpOp::Ule => ICmpOp::Uge,
+        ICmpOp::Ugt => ICmpOp::Ult,
+        ICmpOp::Uge => ICmpOp::Ule,
+    }
+}
+
+/// Get predicate `op'` such that `a op b` iff `!(a op' b)`.
+pub fn negate_predicate(op: ICmpOp) -> ICmpOp {
+    match op {
+        ICmpOp::Eq => ICmpOp::Ne,
+        ICmpOp::Ne => ICmpOp::Eq,
+        ICmpOp::Slt => ICmpOp::Sge,
+        ICmpOp::Sle => ICmpOp::Sgt,
+        ICmpOp::Sgt => ICmpOp::Sle,
+        ICmpOp::Sge => ICmpOp::Slt,
+        ICmpOp::Ult => ICmpOp::Uge,
+        ICmpOp::Ule => ICmpOp::Ugt,
+        ICmpOp::Ugt => ICmpOp::Ule,
+        ICmpOp::Uge => ICmpOp::Ult,
+    }
+}
@@ -152,6 +152,11 @@ impl<'a> PartialEq for Expr<'a> {
     fn eq(&self, other: &Self) -> bool {
         match (&self.op, &other.op) {
             (Operand::Instruction(inst1), Operand::Instruction(inst2)) => {
+                // Same instance has the same value, stop here to avoid walking shared operands again
+                if inst1 == inst2 {
+                    return true;
+                }
+
                 // If instruction type is not the same, their value is not the same
                 let ty = inst1.get_type();
                 if ty != inst2.get_type() {
@@ -0,0 +1,542 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::Display;
+
+use crate::ir::{
+    instruction::{
+        downcast_ref,
+        misc_inst::{ICmp, ICmpOp, Phi},
+        InstType,
+    },
+    BBPtr, Constant, FunPtr, InstPtr, Operand, ValueType,
+};
+
+use super::{
+    dominator_tree::DominatorTree,
+    edge_fact::{get_edge_fact, get_facts, Fact},
+    loop_tools::{LoopForest, LoopPtr},
+    scalar_evolution::{negate_predicate, swap_predicate, ScalarEvolution, Scev},
+    *,
+};
+
+/// A phi widens its range to the type bounds after changing this many times.
+const WIDEN_LIMIT: usize = 2;
+
+/// Number of passes recomputing ranges after widening, to recover bounds from conditions.
+const NARROW_PASSES: usize = 2;
+
+/// Only this many nearest dominating conditions refine ranges in a block.
+const MAX_FACTS: usize = 8;
+
+/// Operands are looked through this deep when computing a range without analysis.
+const MAX_DEPTH: usize = 4;
+
+/// Inclusive range of a 32-bit integer, stored as `i64` so bounds do not overflow.
+/// Booleans are ranges within `[0, 1]`. An empty range means the value is unreachable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Range {
+    pub lo: i64,
+    pub hi: i64,
+}
+
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            write!(f, "empty")
+        } else {
+            write!(f, "[{}, {}]", self.lo, self.hi)
+        }
+    }
+}
+
+impl Range {
+    pub const MIN: i64 = i32::MIN as i64;
+    pub const MAX: i64 = i32::MAX as i64;
+
+    pub fn new(lo: i64, hi: i64) -> Self {
+        Self { lo, hi }
+    }
+
+    /// Range of all values of given type.
+    pub fn full(ty: &ValueType) -> Self {
+        match ty {
+            ValueType::Bool => Self::new(0, 1),
+            _ => Self::new(Self::MIN, Self::MAX),
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(1, 0)
+    }
+
+    pub fn constant(c: i64) -> Self {
+        Self::new(c, c)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lo > self.hi
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.lo == Self::MIN && self.hi == Self::MAX
+    }
+
+    pub fn get_constant(&self) -> Option<i64> {
+        (self.lo == self.hi).then_some(self.lo)
+    }
+
+    pub fn is_non_negative(&self) -> bool {
+        self.lo >= 0
+    }
+
+    pub fn contains(&self, value: i64) -> bool {
+        self.lo <= value && value <= self.hi
+    }
+
+    pub fn union(&self, other: &Range) -> Range {
+        if self.is_empty() {
+            return *other;
+        }
+        if other.is_empty() {
+            return *self;
+        }
+        Self::new(self.lo.min(other.lo), self.hi.max(other.hi))
+    }
+
+    pub fn intersect(&self, other: &Range) -> Range {
+        Self::new(self.lo.max(other.lo), self.hi.min(other.hi))
+    }
+
+    pub fn is_disjoint(&self, other: &Range) -> bool {
+        self.intersect(other).is_empty()
+    }
+
+    /// Range of integers from candidate bounds, or full range if any of them overflows `i32`,
+    /// since the result wraps around.
+    fn from_bounds(bounds: &[i64]) -> Range {
+        let lo = *bounds.iter().min().unwrap();
+        let hi = *bounds.iter().max().unwrap();
+        if lo < Self::MIN || hi > Self::MAX {
+            return Self::full(&ValueType::Int);
+        }
+        Self::new(lo, hi)
+    }
+
+    /// Get range of `lhs op rhs`, where both are in given ranges.
+    pub fn apply(op: InstType, lhs: Range, rhs: Range) -> Range {
+        if lhs.is_empty() || rhs.is_empty() {
+            return Self::empty();
+        }
+        let full = Self::full(&ValueType::Int);
+        let (a, b, c, d) = (lhs.lo, lhs.hi, rhs.lo, rhs.hi);
+        match op {
+            InstType::Add => Self::from_bounds(&[a + c, b + d]),
+            InstType::Sub => Self::from_bounds(&[a - d, b - c]),
+            InstType::Mul => Self::from_bounds(&[a * c, a * d, b * c, b * d]),
+            InstType::SDiv if c > 0 || d < 0 => {
+                // `MIN / -1` overflows
+                if a == Self::MIN && rhs.contains(-1) {
+                    return full;
+                }
+                Self::from_bounds(&[a / c, a / d, b / c, b / d])
+            }
+            InstType::SDiv => {
+                // Magnitude never grows, except by dividing with zero or `MIN / -1`
+                let m = a.abs().max(b.abs());
+                Self::from_bounds(&[-m, m])
+            }
+            InstType::SRem => {
+                // Result has the sign of dividend, and is smaller than divisor in magnitude
+                let m = (c.abs().max(d.abs()) - 1).max(0);
+                let lo = if a < 0 { a.max(-m) } else { 0 };
+                let hi = if b > 0 { b.min(m) } else { 0 };
+                Self::new(lo, hi)
+            }
+            InstType::And if lhs.is_non_negative() && rhs.is_non_negative() => {
+                Self::new(0, b.min(d))
+            }
+            InstType::And if lhs.is_non_negative() => Self::new(0, b),
+            InstType::And if rhs.is_non_negative() => Self::new(0, d),
+            InstType::Or | InstType::Xor if lhs.is_non_negative() && rhs.is_non_negative() => {
+                // Result has no bit higher than both operands
+                let bits = 64 - b.max(d).leading_zeros();
+                let lo = if op == InstType::Or { a.max(c) } else { 0 };
+                Self::new(lo, (1 << bits) - 1)
+            }
+            InstType::Shl if (0..32).contains(&c) && (0..32).contains(&d) => {
+                Self::from_bounds(&[a << c, a << d, b << c, b << d])
+            }
+            InstType::AShr if (0..32).contains(&c) && (0..32).contains(&d) => {
+                Self::from_bounds(&[a >> c, a >> d, b >> c, b >> d])
+            }
+            InstType::LShr if lhs.is_non_negative() && (0..32).contains(&c) => {
+                Self::new(a >> d.min(31), b >> c)
+            }
+            _ => full,
+        }
+    }
+
+    /// Check if `lhs op rhs` always or never holds, where both are in given ranges.
+    pub fn compare(op: ICmpOp, lhs: Range, rhs: Range) -> Option<bool> {
+        let non_negative = lhs.is_non_negative() && rhs.is_non_negative();
+        match op {
+            ICmpOp::Eq => {
+                if lhs.get_constant().is_some() && lhs == rhs {
+                    return Some(true);
+                }
+                lhs.is_disjoint(&rhs).then_some(false)
+            }
+            ICmpOp::Ne => Self::compare(ICmpOp::Eq, lhs, rhs).map(|b| !b),
+            ICmpOp::Slt => {
+                if lhs.hi < rhs.lo {
+                    return Some(true);
+                }
+                (lhs.lo >= rhs.hi).then_some(false)
+            }
+            ICmpOp::Sle => Self::compare(ICmpOp::Sgt, lhs, rhs).map(|b| !b),
+            ICmpOp::Sgt => Self::compare(ICmpOp::Slt, rhs, lhs),
+            ICmpOp::Sge => Self::compare(ICmpOp::Slt, lhs, rhs).map(|b| !b),
+
+            // Unsigned comparison agrees with signed one on non-negative values
+            ICmpOp::Ult if non_negative => Self::compare(ICmpOp::Slt, lhs, rhs),
+            ICmpOp::Ule if non_negative => Self::compare(ICmpOp::Sle, lhs, rhs),
+            ICmpOp::Ugt if non_negative => Self::compare(ICmpOp::Sgt, lhs, rhs),
+            ICmpOp::Uge if non_negative => Self::compare(ICmpOp::Sge, lhs, rhs),
+            _ => None,
+        }
+    }
+
+    /// Narrow range of `self` given that `self op other` holds, where `other` is in given range.
+    pub fn constrain(&self, op: ICmpOp, other: Range) -> Range {
+        if other.is_empty() {
+            return Self::empty();
+        }
+        let bound = match op {
+            ICmpOp::Eq => other,
+            ICmpOp::Ne => match other.get_constant() {
+                Some(c) if c == self.lo => Self::new(c + 1, Self::MAX),
+                Some(c) if c == self.hi => Self::new(Self::MIN, c - 1),
+                _ => return *self,
+            },
+            ICmpOp::Slt => Self::new(Self::MIN, other.hi - 1),
+            ICmpOp::Sle => Self::new(Self::MIN, other.hi),
+            ICmpOp::Sgt => Self::new(other.lo + 1, Self::MAX),
+            ICmpOp::Sge => Self::new(other.lo, Self::MAX),
+
+            // Unsigned `x < y` with non-negative `y` bounds `x` to `[0, y)`
+            ICmpOp::Ult if other.is_non_negative() => Self::new(0, other.hi - 1),
+            ICmpOp::Ule if other.is_non_negative() => Self::new(0, other.hi),
+            _ => return *self,
+        };
+        self.intersect(&bound)
+    }
+}
+
+/// Value range analysis, computes ranges of integer and boolean values in a function.
+/// Ranges come from constants, arithmetic, trip counts of loops, and conditions of
+/// dominating branches, which are also applied to values flowing into phis along edges.
+#[derive(Default)]
+pub struct ValueRange {
+    ranges: HashMap<InstPtr, Range>,
+
+    /// Nearest conditions known to hold when entering each block
+    facts: HashMap<BBPtr, Vec<Fact>>,
+
+    /// Bounds of induction variables with constant trip count
+    iv_ranges: HashMap<InstPtr, Range>,
+
+    /// Number of times each phi changed, for widening
+    changes: HashMap<InstPtr, usize>,
+}
+
+impl ValueRange {
+    pub fn new(func: FunPtr) -> Self {
+        let mut res = Self::default();
+        let mut dom_tree = DominatorTree::new(func);
+        let blocks: Vec<BBPtr> = func.rpo_iter().collect();
+        for bb in blocks.iter() {
+            let facts = get_facts(*bb, &mut dom_tree).take(MAX_FACTS).collect();
+            res.facts.insert(*bb, facts);
+        }
+        if let Some(forest) = LoopForest::make_forest(func) {
+            res.build_iv_ranges(&forest);
+        }
+
+        // Iterate to a fixed point, widening phis that keep changing
+        loop {
+            let mut changed = false;
+            for bb in blocks.iter() {
+                for inst in bb.iter() {
+                    changed |= res.update(inst, true);
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+
+        // Recompute ranges with the fixed point, which only narrows them
+        for _ in 0..NARROW_PASSES {
+            for bb in blocks.iter() {
+                for inst in bb.iter() {
+                    res.update(inst, false);
+                }
+            }
+        }
+        res.changes.clear();
+        res
+    }
+
+    /// Get range of an operand anywhere it is available.
+    pub fn get_range(&self, op: &Operand) -> Range {
+        match op {
+            Operand::Instruction(inst) => match self.ranges.get(inst) {
+                Some(range) => *range,
+                None => Range::full(&inst.get_value_type()),
+            },
+            _ => get_operand_range(op),
+        }
+    }
+
+    /// Get range of an operand in given block, narrowed by conditions known there.
+    pub fn get_range_at(&self, op: &Operand, bb: BBPtr) -> Range {
+        let range = self.get_range(op);
+        match self.facts.get(&bb) {
+            Some(facts) => self.refine(op, range, facts),
+            None => range,
+        }
+    }
+
+    /// Check if comparison always or never holds where it is computed.
+    pub fn eval_icmp(&self, inst: InstPtr) -> Option<bool> {
+        if inst.get_type() != InstType::ICmp {
+            return None;
+        }
+        let bb = inst.get_parent_bb()?;
+        let icmp = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+        let lhs = self.get_range_at(icmp.get_lhs(), bb);
+        let rhs = self.get_range_at(icmp.get_rhs(), bb);
+        Range::compare(icmp.op, lhs, rhs)
+    }
+
+    /// Dump ranges of instructions, except those with full range.
+    pub fn dump(&self, func: FunPtr) -> String {
+        let mut res = String::new();
+        for bb in func.rpo_iter() {
+            for inst in bb.iter() {
+                let ty = inst.get_value_type();
+                if !matches!(ty, ValueType::Int | ValueType::Bool) {
+                    continue;
+                }
+                let range = self.get_range(&inst.into());
+                if range != Range::full(&ty) {
+                    res += &format!("{}: {}\n", Operand::from(inst), range);
+                }
+            }
+        }
+        res
+    }
+
+    /// Bound header phis that are induction variables of loops with constant trip count.
+    fn build_iv_ranges(&mut self, forest: &LoopForest) {
+        let mut scev = ScalarEvolution::new();
+        let mut stack: Vec<LoopPtr> = forest.forest.clone();
+        while let Some(lo) = stack.pop() {
+            stack.extend(lo.sub_loops.iter());
+            let Some(count) = scev.get_trip_count(lo).and_then(|t| t.get_constant()) else {
+                continue;
+            };
+            for inst in lo.head.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                let Scev::AddRec { start, step } = scev.get_scev(&inst.into(), lo) else {
+                    continue;
+                };
+                if let (Some(start), Some(step)) = (start.get_constant(), step.get_constant()) {
+                    let last = start as i64 + step as i64 * count as i64;
+                    let range = Range::from_bounds(&[start as i64, last]);
+                    self.iv_ranges.insert(inst, range);
+                }
+            }
+        }
+    }
+
+    /// Recompute range of instruction. When widening, phis join with their previous range.
+    /// Returns true if range changed.
+    fn update(&mut self, inst: InstPtr, widen: bool) -> bool {
+        let ty = inst.get_value_type();
+        if !matches!(ty, ValueType::Int | ValueType::Bool) {
+            return false;
+        }
+        let Some(bb) = inst.get_parent_bb() else {
+            return false;
+        };
+        let old = self.ranges.get(&inst).cloned();
+        let mut range = if inst.get_type() == InstType::Phi {
+            self.compute_phi(inst, bb)
+        } else {
+            let operands: Vec<Range> = inst
+                .get_operand()
+                .iter()
+                .map(|op| self.get_range_at(op, bb))
+                .collect();
+            compute(inst, &operands)
+        };
+        if let Some(old) = old {
+            if widen {
+                range = range.union(&old);
+                if range != old {
+                    let changes = self.changes.entry(inst).or_default();
+                    *changes += 1;
+                    if *changes > WIDEN_LIMIT {
+                        let full = Range::full(&ty);
+                        range = Range::new(
+                            if range.lo < old.lo { full.lo } else { range.lo },
+                            if range.hi > old.hi { full.hi } else { range.hi },
+                        );
+                    }
+                }
+            } else {
+                range = range.intersect(&old);
+            }
+        }
+        if let Some(iv_range) = self.iv_ranges.get(&inst) {
+            range = range.intersect(iv_range);
+        }
+        self.ranges.insert(inst, range);
+        old != Some(range)
+    }
+
+    /// Join values flowing into phi, each narrowed by conditions on its edge.
+    /// Values not computed yet come from back edges, and are skipped.
+    fn compute_phi(&self, inst: InstPtr, bb: BBPtr) -> Range {
+        let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+        let mut range = Range::empty();
+        for (op, pred) in phi.get_incoming_values() {
+            if let Operand::Instruction(value) = op {
+                if !self.ranges.contains_key(value) {
+                    continue;
+                }
+            }
+            let mut facts = self.facts.get(pred).cloned().unwrap_or_default();
+            facts.extend(get_edge_fact(*pred, bb));
+            let incoming = self.refine(op, self.get_range(op), &facts);
+            range = range.union(&incoming);
+        }
+        range
+    }
+
+    /// Narrow range of operand with known conditions.
+    fn refine(&self, op: &Operand, mut range: Range, facts: &[Fact]) -> Range {
+        if matches!(op, Operand::Constant(_)) {
+            return range;
+        }
+        for (cond, value) in facts.iter() {
+            if cond == op {
+                range = range.intersect(&Range::constant(*value as i64));
+                continue;
+            }
+            let Operand::Instruction(cond) = cond else {
+                continue;
+            };
+            if cond.get_type() != InstType::ICmp {
+                continue;
+            }
+            let icmp = downcast_ref::<ICmp>(cond.as_ref().as_ref());
+            let pred = if *value {
+                icmp.op
+            } else {
+                negate_predicate(icmp.op)
+            };
+            if icmp.get_lhs() == op {
+                range = range.constrain(pred, self.get_range(icmp.get_rhs()));
+            }
+            if icmp.get_rhs() == op {
+                range = range.constrain(swap_predicate(pred), self.get_range(icmp.get_lhs()));
+            }
+        }
+        range
+    }
+}
+
+/// Get range of an operand without analysis, looking through a few levels of instructions.
+/// Phis are not looked through, as they may be in loops.
+pub fn get_static_range(op: &Operand) -> Range {
+    get_static_range_with_depth(op, MAX_DEPTH)
+}
+
+fn get_static_range_with_depth(op: &Operand, depth: usize) -> Range {
+    let Operand::Instruction(inst) = op else {
+        return get_operand_range(op);
+    };
+    let ty = inst.get_value_type();
+    if depth == 0 || inst.get_type() == InstType::Phi {
+        return Range::full(&ty);
+    }
+    let operands: Vec<Range> = inst
+        .get_operand()
+        .iter()
+        .map(|op| get_static_range_with_depth(op, depth - 1))
+        .collect();
+    compute(*inst, &operands)
+}
+
+/// Get range of a non-instruction operand.
+fn get_operand_range(op: &Operand) -> Range {
+    match op {
+        Operand::Constant(Constant::Int(c)) => Range::constant(*c as i64),
+        Operand::Constant(Constant::Bool(b)) => Range::constant(*b as i64),
+        Operand::Constant(Constant::SignedChar(c)) => Range::constant(*c as i64),
+        _ => Range::full(&op.get_type()),
+    }
+}
+
+/// Compute range of a non-phi instruction from ranges of its operands.
+fn compute(inst: InstPtr, operands: &[Range]) -> Range {
+    let ty = inst.get_value_type();
+    if operands.iter().any(Range::is_empty) {
+        return Range::empty();
+    }
+    let range = match inst.get_type() {
+        InstType::Add
+        | InstType::Sub
+        | InstType::Mul
+        | InstType::SDiv
+        | InstType::SRem
+        | InstType::Shl
+        | InstType::LShr
+        | InstType::AShr
+        | InstType::And
+        | InstType::Or
+        | InstType::Xor => Range::apply(inst.get_type(), operands[0], operands[1]),
+        InstType::ICmp => {
+            let icmp = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+            match Range::compare(icmp.op, operands[0], operands[1]) {
+                Some(b) => Range::constant(b as i64),
+                None => Range::new(0, 1),
+            }
+        }
+        InstType::ZextTo if operands[0].is_non_negative() => operands[0],
+        InstType::SextTo if inst.get_operand()[0].get_type() == ValueType::Bool => {
+            Range::new(-operands[0].hi, -operands[0].lo)
+        }
+        InstType::Select => operands[1].union(&operands[2]),
+        _ => Range::full(&ty),
+    };
+    range.intersect(&Range::full(&ty))
+}
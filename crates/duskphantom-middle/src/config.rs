@@ -20,6 +20,9 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
     pub open_auto_parallel: bool,
+    /// Allow reassociating float additions, for example to parallelize float reductions
+    #[serde(default)]
+    pub open_fast_math: bool,
 }
 
 lazy_static! {
@@ -34,6 +37,10 @@ lazy_static! {
                     .unwrap_or_else(|_| "true".to_string())
                     .parse()
                     .unwrap_or(false),
+                open_fast_math: env::var("OPEN_FAST_MATH")
+                    .unwrap_or_else(|_| "false".to_string())
+                    .parse()
+                    .unwrap_or(false),
             }
         }
     };
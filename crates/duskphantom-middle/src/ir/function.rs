@@ -51,6 +51,13 @@ impl Function {
         self.name == "main"
     }
 
+    /// Return true if exit is reachable from entry, so that function may return.
+    /// Exit of a function that never returns may have been emptied when pruning unreachable blocks.
+    pub fn may_return(&self) -> bool {
+        self.exit
+            .is_some_and(|exit| !exit.is_empty() && self.dfs_iter().any(|bb| bb == exit))
+    }
+
     /// Create a depth-first iterator to traverse the graph structure of basicblocks.
     /// Traverse in the direction of data flow with the function entry as the starting point.
     /// Do not change the graph structure during traversal, which may cause unknown errors
@@ -68,6 +68,18 @@ impl IRBuilder {
         inst
     }
 
+    pub fn get_select(&mut self, cond: Operand, true_val: Operand, false_val: Operand) -> InstPtr {
+        let mut inst = self.new_instruction(Box::new(Select {
+            manager: InstManager::new(true_val.get_type()),
+        }));
+        unsafe {
+            inst.get_manager_mut().add_operand(cond);
+            inst.get_manager_mut().add_operand(true_val);
+            inst.get_manager_mut().add_operand(false_val);
+        };
+        inst
+    }
+
     pub fn get_call(&mut self, func: FunPtr, args: Vec<Operand>) -> InstPtr {
         let mut inst = self.new_instruction(Box::new(Call {
             func,
@@ -387,6 +399,51 @@ impl Instruction for Phi {
     }
 }
 
+/// Choose `true_val` if `cond` holds, otherwise `false_val`.
+pub struct Select {
+    manager: InstManager,
+}
+
+impl Select {
+    pub fn get_cond(&self) -> &Operand {
+        &self.get_operand()[0]
+    }
+    pub fn get_true_val(&self) -> &Operand {
+        &self.get_operand()[1]
+    }
+    pub fn get_false_val(&self) -> &Operand {
+        &self.get_operand()[2]
+    }
+}
+
+impl Display for Select {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%select_{}", self.get_id())
+    }
+}
+
+impl Instruction for Select {
+    gen_common_code!(Select, Select);
+    fn gen_llvm_ir(&self) -> String {
+        let ty = self.get_value_type();
+        format!(
+            "{} = select i1 {}, {} {}, {} {}",
+            self,
+            self.get_cond(),
+            ty,
+            self.get_true_val(),
+            ty,
+            self.get_false_val()
+        )
+    }
+
+    fn copy_self(&self) -> Box<dyn Instruction> {
+        Box::new(Select {
+            manager: InstManager::new(self.get_value_type()),
+        })
+    }
+}
+
 pub struct Call {
     pub func: FunPtr,
     manager: InstManager,
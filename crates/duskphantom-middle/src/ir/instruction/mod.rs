@@ -112,6 +112,7 @@ define_inst_type_enum!(
     ICmp,
     FCmp,
     Phi,
+    Select,
     Call
 );
 
@@ -591,39 +592,12 @@ impl InstManager {
     ///
     /// FIXME: explain why it is unsafe,and describe the safety requirements
     pub unsafe fn replace_operand(&mut self, from: &Operand, to: &Operand) {
-        match from {
-            Operand::Instruction(mut inst) => {
-                inst.get_user_mut().retain(|x| x != &self.self_ptr.unwrap());
-            }
-            Operand::Parameter(mut param) => {
-                param
-                    .get_user_mut()
-                    .retain(|x| x != &self.self_ptr.unwrap());
+        // Users are recorded once per use, so replace each occurrence separately
+        for index in 0..self.operand.len() {
+            if &self.operand[index] == from {
+                self.set_operand(index, to.clone());
             }
-            Operand::Global(mut global) => {
-                global
-                    .get_user_mut()
-                    .retain(|x| x != &self.self_ptr.unwrap());
-            }
-            _ => {}
         }
-        match to {
-            Operand::Instruction(mut inst) => {
-                inst.get_user_mut().push(self.self_ptr.unwrap());
-            }
-            Operand::Parameter(mut param) => {
-                param.add_user(self.self_ptr.unwrap());
-            }
-            Operand::Global(mut global) => {
-                global.add_user(self.self_ptr.unwrap());
-            }
-            _ => {}
-        }
-        self.operand.iter_mut().for_each(|op| {
-            if op == from {
-                *op = to.clone();
-            }
-        });
     }
 
     /// # Safety
@@ -26,11 +26,16 @@ pub mod ir;
 pub mod irgen;
 pub mod transform;
 
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
 use std::pin::Pin;
 
 pub struct Program {
     pub module: ir::Module,
     pub mem_pool: Pin<Box<IRBuilder>>,
+
+    /// State passes keep across runs on this program, one for each state type
+    pass_state: HashMap<TypeId, Box<dyn Any>>,
 }
 
 use anyhow::{Context, Result};
@@ -69,8 +74,18 @@ impl Program {
         Self {
             mem_pool: program_mem_pool,
             module: ir::Module::new(mem_pool),
+            pass_state: HashMap::new(),
         }
     }
+
+    /// Get state of type `T` kept across pass runs, created with default value on first use.
+    pub fn get_pass_state<T: Default + 'static>(&mut self) -> &mut T {
+        self.pass_state
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .downcast_mut()
+            .unwrap()
+    }
 }
 
 impl Drop for Program {
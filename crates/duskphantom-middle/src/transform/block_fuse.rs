@@ -17,11 +17,14 @@
 use anyhow::{Ok, Result};
 
 use crate::{
-    ir::{BBPtr, FunPtr},
+    ir::{instruction::InstType, BBPtr, FunPtr},
     Program,
 };
 
-use super::Transform;
+use super::{
+    pass_stats::{self, FuncTimer},
+    Transform,
+};
 
 pub fn optimize_program(program: &mut Program) -> Result<bool> {
     BlockFuse::new(program).run_and_log()
@@ -46,6 +49,7 @@ impl<'a> Transform for BlockFuse<'a> {
             if func.is_lib() {
                 continue;
             }
+            let _timer = FuncTimer::new(Self::name, func);
             for bb in func.rpo_iter() {
                 changed |= self.fuse_block(bb, func)?;
             }
@@ -66,6 +70,16 @@ impl<'a> BlockFuse<'a> {
             return Ok(false);
         };
         if pred.get_succ_bb().len() == 1 && bb.get_pred_bb().len() == 1 {
+            // Phis have a single incoming value from `pred`, for example in dedicated
+            // exits whose loop branch is folded, replace them before `pred` is removed
+            for mut inst in bb.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                let value = inst.get_operand()[0].clone();
+                inst.replace_self(&value);
+            }
+
             // Last instruction is "br", move the rest to successor block
             for inst in pred.iter_rev().skip(1) {
                 bb.push_front(inst);
@@ -77,6 +91,7 @@ impl<'a> BlockFuse<'a> {
 
             // Remove `pred`
             pred.remove_self();
+            pass_stats::bump(Self::name, "blocks fused", 1);
             return Ok(true);
         }
         Ok(false)
@@ -68,213 +68,139 @@ impl<'a> ConstantFold<'a> {
     }
 
     fn constant_fold_inst(&mut self, mut inst: InstPtr) -> Result<bool> {
-        match inst.get_type() {
-            InstType::Add | InstType::FAdd => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs + rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::Sub | InstType::FSub => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs - rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::Mul | InstType::FMul => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs * rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::UDiv => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
+        let operands: Option<Vec<Constant>> = inst
+            .get_operand()
+            .iter()
+            .map(|op| match op {
+                Operand::Constant(c) => Some(c.clone()),
+                _ => None,
+            })
+            .collect();
+        if let Some(result) = operands.and_then(|operands| fold_inst(inst, operands)) {
+            inst.replace_self(&result.into());
+            return Ok(true);
+        }
+        Ok(false)
+    }
+}
+
+/// Evaluate instruction on constant operands.
+/// Returns `None` if the instruction can not be folded, for example division by zero.
+pub fn fold_inst(inst: InstPtr, operands: Vec<Constant>) -> Option<Constant> {
+    let mut operands = operands.into_iter();
+    let lhs = operands.next()?;
+    let rhs = operands.next();
+    let result = match inst.get_type() {
+        InstType::Add | InstType::FAdd => lhs + rhs?,
+        InstType::Sub | InstType::FSub => lhs - rhs?,
+        InstType::Mul | InstType::FMul => lhs * rhs?,
+        InstType::UDiv => {
+            let lhs: u32 = lhs.into();
+            let rhs: u32 = rhs?.into();
+            lhs.checked_div(rhs)?.into()
+        }
+        InstType::SDiv | InstType::URem | InstType::SRem if is_zero(rhs.as_ref()?) => return None,
+        InstType::SDiv | InstType::FDiv => lhs / rhs?,
+        InstType::URem | InstType::SRem => lhs % rhs?,
+        InstType::Shl => lhs << rhs?,
+        InstType::AShr => lhs >> rhs?,
+        InstType::And => lhs & rhs?,
+        InstType::Or => lhs | rhs?,
+        InstType::Xor => lhs ^ rhs?,
+        InstType::ZextTo | InstType::ItoFp | InstType::FpToI => lhs.cast(&inst.get_value_type()),
+        InstType::Select => match lhs {
+            Constant::Bool(true) => rhs?,
+            Constant::Bool(false) => operands.next()?,
+            _ => return None,
+        },
+        InstType::SextTo => match lhs {
+            Constant::Bool(b) => Constant::Int(if b { -1 } else { 0 }),
+            _ => return None,
+        },
+        InstType::ICmp => {
+            let rhs = rhs?;
+            let cmp_inst = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+            let result = match cmp_inst.op {
+                ICmpOp::Eq => lhs == rhs,
+                ICmpOp::Ne => lhs != rhs,
+                ICmpOp::Slt => lhs < rhs,
+                ICmpOp::Sle => lhs <= rhs,
+                ICmpOp::Sgt => lhs > rhs,
+                ICmpOp::Sge => lhs >= rhs,
+                ICmpOp::Ult => {
                     let lhs: u32 = lhs.into();
                     let rhs: u32 = rhs.into();
-                    let result = lhs / rhs;
-                    inst.replace_self(&Operand::Constant(result.into()));
-                    return Ok(true);
-                }
-            }
-            InstType::SDiv | InstType::FDiv => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs / rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::URem | InstType::SRem => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs % rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::Shl => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs << rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::AShr => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs >> rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::And => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs & rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
+                    lhs < rhs
                 }
-            }
-            InstType::Or => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs | rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::Xor => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = lhs ^ rhs;
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::ZextTo | InstType::ItoFp | InstType::FpToI => {
-                let src = inst.get_operand()[0].clone();
-                if let Operand::Constant(src) = src {
-                    let result = src.cast(&inst.get_value_type());
-                    inst.replace_self(&result.into());
-                    return Ok(true);
-                }
-            }
-            InstType::SextTo => {
-                let src = inst.get_operand()[0].clone();
-                if let Operand::Constant(Constant::Bool(b)) = src {
-                    let result = if b { -1 } else { 0 };
-                    inst.replace_self(&Operand::Constant(result.into()));
-                    return Ok(true);
+                ICmpOp::Ule => {
+                    let lhs: u32 = lhs.into();
+                    let rhs: u32 = rhs.into();
+                    lhs <= rhs
                 }
-            }
-            InstType::ICmp => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                let cmp_inst = downcast_ref::<ICmp>(inst.as_ref().as_ref());
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = match cmp_inst.op {
-                        ICmpOp::Eq => lhs == rhs,
-                        ICmpOp::Ne => lhs != rhs,
-                        ICmpOp::Slt => lhs < rhs,
-                        ICmpOp::Sle => lhs <= rhs,
-                        ICmpOp::Sgt => lhs > rhs,
-                        ICmpOp::Sge => lhs >= rhs,
-                        ICmpOp::Ult => {
-                            let lhs: u32 = lhs.into();
-                            let rhs: u32 = rhs.into();
-                            lhs < rhs
-                        }
-                        ICmpOp::Ule => {
-                            let lhs: u32 = lhs.into();
-                            let rhs: u32 = rhs.into();
-                            lhs <= rhs
-                        }
-                        ICmpOp::Ugt => {
-                            let lhs: u32 = lhs.into();
-                            let rhs: u32 = rhs.into();
-                            lhs > rhs
-                        }
-                        ICmpOp::Uge => {
-                            let lhs: u32 = lhs.into();
-                            let rhs: u32 = rhs.into();
-                            lhs >= rhs
-                        }
-                    };
-                    inst.replace_self(&Operand::Constant(result.into()));
-                    return Ok(true);
+                ICmpOp::Ugt => {
+                    let lhs: u32 = lhs.into();
+                    let rhs: u32 = rhs.into();
+                    lhs > rhs
                 }
-            }
-            InstType::FCmp => {
-                let lhs = inst.get_operand()[0].clone();
-                let rhs = inst.get_operand()[1].clone();
-                let cmp_inst = downcast_ref::<FCmp>(inst.as_ref().as_ref());
-                if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (lhs, rhs) {
-                    let result = match cmp_inst.op {
-                        FCmpOp::False => false,
-                        FCmpOp::True => true,
-                        FCmpOp::Oeq => lhs == rhs,
-                        FCmpOp::One => lhs != rhs,
-                        FCmpOp::Olt => lhs < rhs,
-                        FCmpOp::Ole => lhs <= rhs,
-                        FCmpOp::Ogt => lhs > rhs,
-                        FCmpOp::Oge => lhs >= rhs,
-                        FCmpOp::Ueq => {
-                            let lhs: f32 = lhs.into();
-                            let rhs: f32 = rhs.into();
-                            lhs == rhs || (lhs.is_nan() && rhs.is_nan())
-                        }
-                        FCmpOp::Une => {
-                            let lhs: f32 = lhs.into();
-                            let rhs: f32 = rhs.into();
-                            lhs.is_nan() || rhs.is_nan() || lhs != rhs
-                        }
-                        FCmpOp::Ult => {
-                            let lhs: f32 = lhs.into();
-                            let rhs: f32 = rhs.into();
-                            lhs < rhs || (lhs.is_nan() && !rhs.is_nan())
-                        }
-                        FCmpOp::Ule => {
-                            let lhs: f32 = lhs.into();
-                            let rhs: f32 = rhs.into();
-                            lhs <= rhs || (lhs.is_nan() && !rhs.is_nan())
-                        }
-                        FCmpOp::Ugt => {
-                            let lhs: f32 = lhs.into();
-                            let rhs: f32 = rhs.into();
-                            lhs > rhs || (!lhs.is_nan() && rhs.is_nan())
-                        }
-                        FCmpOp::Uge => {
-                            let lhs: f32 = lhs.into();
-                            let rhs: f32 = rhs.into();
-                            lhs >= rhs || (!lhs.is_nan() && rhs.is_nan())
-                        }
-                        _ => todo!(),
-                    };
-                    inst.replace_self(&Operand::Constant(result.into()));
-                    return Ok(true);
+                ICmpOp::Uge => {
+                    let lhs: u32 = lhs.into();
+                    let rhs: u32 = rhs.into();
+                    lhs >= rhs
                 }
-            }
-            _ => (),
+            };
+            result.into()
         }
-        Ok(false)
-    }
+        InstType::FCmp => {
+            let rhs = rhs?;
+            let cmp_inst = downcast_ref::<FCmp>(inst.as_ref().as_ref());
+            let result = match cmp_inst.op {
+                FCmpOp::False => false,
+                FCmpOp::True => true,
+                FCmpOp::Oeq => lhs == rhs,
+                FCmpOp::One => lhs != rhs,
+                FCmpOp::Olt => lhs < rhs,
+                FCmpOp::Ole => lhs <= rhs,
+                FCmpOp::Ogt => lhs > rhs,
+                FCmpOp::Oge => lhs >= rhs,
+                FCmpOp::Ueq => {
+                    let lhs: f32 = lhs.into();
+                    let rhs: f32 = rhs.into();
+                    lhs == rhs || (lhs.is_nan() && rhs.is_nan())
+                }
+                FCmpOp::Une => {
+                    let lhs: f32 = lhs.into();
+                    let rhs: f32 = rhs.into();
+                    lhs.is_nan() || rhs.is_nan() || lhs != rhs
+                }
+                FCmpOp::Ult => {
+                    let lhs: f32 = lhs.into();
+                    let rhs: f32 = rhs.into();
+                    lhs < rhs || (lhs.is_nan() && !rhs.is_nan())
+                }
+                FCmpOp::Ule => {
+                    let lhs: f32 = lhs.into();
+                    let rhs: f32 = rhs.into();
+                    lhs <= rhs || (lhs.is_nan() && !rhs.is_nan())
+                }
+                FCmpOp::Ugt => {
+                    let lhs: f32 = lhs.into();
+                    let rhs: f32 = rhs.into();
+                    lhs > rhs || (!lhs.is_nan() && rhs.is_nan())
+                }
+                FCmpOp::Uge => {
+                    let lhs: f32 = lhs.into();
+                    let rhs: f32 = rhs.into();
+                    lhs >= rhs || (!lhs.is_nan() && rhs.is_nan())
+                }
+                _ => return None,
+            };
+            result.into()
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn is_zero(c: &Constant) -> bool {
+    matches!(c, Constant::Int(0) | Constant::Bool(false))
 }
@@ -0,0 +1,150 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::call_graph::{CallEdge, CallGraph},
+    context,
+    ir::{
+        instruction::{downcast_ref, misc_inst::Call, InstType},
+        FunPtr, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{pass_stats, Transform};
+
+/// Remove parameters that are never used and return values that are never used by callers.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let mut call_graph = CallGraph::new(program);
+    DeadArgElim::new(program, &mut call_graph).run_and_log()
+}
+
+pub struct DeadArgElim<'a> {
+    program: &'a mut Program,
+    call_graph: &'a mut CallGraph,
+}
+
+impl<'a> Transform for DeadArgElim<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "dead_arg_elim".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            // Signature of library functions and main is fixed
+            if func.is_lib() || func.is_main() {
+                continue;
+            }
+            changed |= self.process_func(func)?;
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> DeadArgElim<'a> {
+    pub fn new(program: &'a mut Program, call_graph: &'a mut CallGraph) -> Self {
+        Self {
+            program,
+            call_graph,
+        }
+    }
+
+    fn process_func(&mut self, mut func: FunPtr) -> Result<bool> {
+        let mut calls: Vec<CallEdge> = self.call_graph.get_called_by(func).into_iter().collect();
+        calls.sort_by_key(|edge| edge.inst.get_id());
+        let live: Vec<bool> = (0..func.params.len())
+            .map(|i| !is_dead_param(func, i))
+            .collect();
+        // Function that never returns has no return to rewrite
+        let dead_ret = func.return_type != ValueType::Void
+            && func.may_return()
+            && calls.iter().all(|edge| edge.inst.get_user().is_empty());
+        if live.iter().all(|x| *x) && !dead_ret {
+            return Ok(false);
+        }
+
+        // Return nothing if return value is unused
+        if dead_ret {
+            let exit = func
+                .exit
+                .ok_or_else(|| anyhow!("function `{}` has no exit", func.name))
+                .with_context(|| context!())?;
+            let mut ret = exit.get_last_inst();
+            ret.insert_after(self.program.mem_pool.get_ret(None));
+            ret.remove_self();
+            func.return_type = ValueType::Void;
+            pass_stats::bump(Self::name, "return values removed", 1);
+        }
+        let removed = live.iter().filter(|x| !**x).count();
+        pass_stats::bump(Self::name, "parameters removed", removed);
+        func.params = func
+            .params
+            .iter()
+            .zip(live.iter())
+            .filter(|(_, live)| **live)
+            .map(|(param, _)| *param)
+            .collect();
+
+        // Rebuild calls with live arguments and new return type
+        for edge in calls {
+            let mut call = edge.inst;
+            let args = call
+                .get_operand()
+                .iter()
+                .zip(live.iter())
+                .filter(|(_, live)| **live)
+                .map(|(arg, _)| arg.clone())
+                .collect();
+            let new_call = self.program.mem_pool.get_call(func, args);
+            call.insert_before(new_call);
+            if dead_ret {
+                call.remove_self();
+            } else {
+                call.replace_self(&new_call.into());
+            }
+            self.call_graph.remove_edge(edge);
+            self.call_graph.add_edge(CallEdge {
+                inst: new_call,
+                ..edge
+            });
+        }
+        Ok(true)
+    }
+}
+
+/// Check if a parameter is unused, or only passed to the same position of recursive calls.
+fn is_dead_param(func: FunPtr, index: usize) -> bool {
+    let param = Operand::Parameter(func.params[index]);
+    func.params[index].get_user().iter().all(|user| {
+        is_self_call(func, *user)
+            && user
+                .get_operand()
+                .iter()
+                .enumerate()
+                .all(|(i, op)| *op != param || i == index)
+    })
+}
+
+fn is_self_call(func: FunPtr, inst: InstPtr) -> bool {
+    inst.get_type() == InstType::Call && downcast_ref::<Call>(inst.as_ref().as_ref()).func == func
+}
@@ -14,14 +14,19 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashSet;
+
 use anyhow::Result;
 
 use crate::analysis::effect_analysis::EffectAnalysis;
 use crate::ir::instruction::InstType;
-use crate::ir::{InstPtr, Operand};
+use crate::ir::{FunPtr, InstPtr, Operand};
 use crate::Program;
 
-use super::Transform;
+use super::{
+    pass_stats::{self, FuncTimer},
+    Transform,
+};
 
 #[allow(unused)]
 pub fn optimize_program(program: &mut Program) -> Result<bool> {
@@ -49,11 +54,8 @@ impl<'a> Transform for DeadCodeElim<'a> {
             if func.is_lib() {
                 continue;
             }
-            for bb in func.po_iter() {
-                for inst in bb.iter() {
-                    changed |= self.dead_code_elim_inst(inst)?;
-                }
-            }
+            let _timer = FuncTimer::new(Self::name, *func);
+            changed |= self.dead_code_elim_func(*func);
         }
 
         // Global variable does not require revisit, remove unused variables at the end
@@ -63,6 +65,7 @@ impl<'a> Transform for DeadCodeElim<'a> {
             .global_variables
             .retain(|var| !var.as_ref().get_user().is_empty());
         let len1 = self.program.module.global_variables.len();
+        pass_stats::bump(Self::name, "globals removed", len0 - len1);
         changed |= len0 != len1;
         Ok(changed)
     }
@@ -76,18 +79,38 @@ impl<'a> DeadCodeElim<'a> {
         }
     }
 
-    fn dead_code_elim_inst(&mut self, mut inst: InstPtr) -> Result<bool> {
-        if !inst.get_user().is_empty() || self.has_side_effect(inst) {
-            return Ok(false);
+    /// Remove instructions that side effects do not depend on.
+    /// Values only used by themselves, like an unused loop counter, are removed as well.
+    fn dead_code_elim_func(&mut self, func: FunPtr) -> bool {
+        let mut live = HashSet::new();
+        let mut worklist = Vec::new();
+        for bb in func.po_iter() {
+            for inst in bb.iter() {
+                if self.has_side_effect(inst) && live.insert(inst) {
+                    worklist.push(inst);
+                }
+            }
         }
-        let operands: Vec<_> = inst.get_operand().into();
-        inst.remove_self();
-        for op in operands {
-            if let Operand::Instruction(inst) = op {
-                self.dead_code_elim_inst(inst)?;
+        while let Some(inst) = worklist.pop() {
+            for op in inst.get_operand() {
+                if let Operand::Instruction(op) = op {
+                    if live.insert(*op) {
+                        worklist.push(*op);
+                    }
+                }
+            }
+        }
+
+        let mut changed = false;
+        for bb in func.po_iter() {
+            let dead: Vec<InstPtr> = bb.iter().filter(|inst| !live.contains(inst)).collect();
+            for mut inst in dead {
+                inst.remove_self();
+                pass_stats::bump(Self::name, "instructions removed", 1);
+                changed = true;
             }
         }
-        Ok(true)
+        changed
     }
 
     fn has_side_effect(&mut self, inst: InstPtr) -> bool {
@@ -14,7 +14,10 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use std::collections::HashMap;
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+};
 
 use crate::ir::instruction::downcast_ref;
 use crate::{
@@ -31,21 +34,67 @@ use crate::{
     Program,
 };
 use anyhow::{anyhow, Context, Result};
-use duskphantom_utils::paral_counter::ParalCounter;
 
-use super::Transform;
+use super::{
+    loop_depth::LoopDepthTracer, pass_stats, pass_stats::IrSize, region_clone::new_block, Transform,
+};
+
+/// Default cost threshold of inlining a call site.
+pub const DEFAULT_THRESHOLD: usize = 200;
+
+/// Cost taken off for each constant argument, which is likely to fold part of callee.
+const CONSTANT_ARG_BONUS: usize = 5;
+
+/// Threshold grows with loop depth of call site, up to this depth.
+const MAX_DEPTH_BONUS: usize = 3;
+
+/// Instructions added by one run are limited to this times module size (or threshold).
+const GROWTH_FACTOR: usize = 2;
+
+/// Instructions inlined into a function over all runs are limited to this times threshold.
+/// Inlining the only call of a function is also limited, as other passes may create new ones.
+const CALLER_GROWTH_FACTOR: usize = 25;
+
+/// Times a recursive function is inlined over all runs. A recursive call may simplify back
+/// to the same size after being inlined, so caller size alone does not stop inlining it.
+const MAX_RECURSIVE_INLINES: usize = 8;
+
+thread_local! {
+    static THRESHOLD: Cell<usize> = const { Cell::new(DEFAULT_THRESHOLD) };
+}
+
+/// Set cost threshold of inlining a call site on current thread.
+pub fn set_threshold(threshold: usize) {
+    THRESHOLD.with(|t| t.set(threshold));
+}
+
+/// Inlining done in previous runs, kept in program.
+#[derive(Default)]
+struct InlinedSize {
+    /// Number of instructions ever inlined into each function
+    caller: HashMap<FunPtr, usize>,
+
+    /// Number of times each recursive function is inlined
+    recursive: HashMap<FunPtr, usize>,
+}
 
 pub fn optimize_program(program: &mut Program) -> Result<bool> {
     let mut call_graph = CallGraph::new(program);
-    let counter = ParalCounter::new(0, usize::MAX);
-    let mut func_inline = FuncInline::new(program, &mut call_graph, counter);
+    let threshold = THRESHOLD.with(|t| t.get());
+    let mut func_inline = FuncInline::new(program, &mut call_graph, threshold);
     func_inline.run_and_log()
 }
 
 pub struct FuncInline<'a> {
     program: &'a mut Program,
     call_graph: &'a mut CallGraph,
-    counter: ParalCounter,
+    threshold: usize,
+
+    /// Number of instructions inlining may still add in this run
+    budget: usize,
+
+    /// Functions in a call graph cycle
+    recursive: HashSet<FunPtr>,
 }
 
 impl<'a> Transform for FuncInline<'a> {
@@ -58,64 +107,127 @@ impl<'a> Transform for FuncInline<'a> {
     }
 
     fn run(&mut self) -> Result<bool> {
-        let mut whole_changed = false;
-        loop {
-            let mut changed = false;
-            for func in self.program.module.functions.clone() {
-                // Do not process library function
-                if func.is_lib() {
-                    continue;
-                }
-
-                // If functions calls other functions, do not process it
-                if !self.call_graph.get_calls(func).is_empty() {
-                    continue;
-                }
-
-                // Process function
-                changed |= self.process_func(func)?;
-                whole_changed |= changed;
-
-                // Update call graph
-                self.call_graph.remove(func);
+        let functions = self.program.module.functions.clone();
+        let module_size: usize = functions
+            .iter()
+            .filter(|func| !func.is_lib())
+            .map(|func| IrSize::of_func(*func).insts)
+            .sum();
+        self.budget = GROWTH_FACTOR * module_size.max(self.threshold);
+
+        // Visit components bottom-up, so that callees are simplified before inlined
+        let sccs = self.call_graph.get_sccs(&functions);
+        for scc in sccs.iter() {
+            let func = scc[0];
+            let is_self_call = |edge: &CallEdge| edge.callee == func;
+            if scc.len() > 1 || self.call_graph.get_calls(func).iter().any(is_self_call) {
+                self.recursive.extend(scc.iter().cloned());
+            }
+        }
+        let mut inlined = HashSet::new();
+        for func in sccs.into_iter().flatten() {
+            // Do not process library function
+            if func.is_lib() {
+                continue;
             }
-            if !changed {
-                break;
+            self.process_func(func, &mut inlined)?;
+        }
+
+        // Delete functions whose calls are all inlined to reduce code size
+        for func in functions {
+            if inlined.contains(&func) && self.call_graph.get_called_by(func).is_empty() {
+                self.program.module.functions.retain(|&f| f != func);
+                pass_stats::bump(Self::name, "functions removed", 1);
             }
         }
-        Ok(whole_changed)
+        Ok(!inlined.is_empty())
     }
 }
 
 impl<'a> FuncInline<'a> {
-    pub fn new(
-        program: &'a mut Program,
-        call_graph: &'a mut CallGraph,
-        counter: ParalCounter,
-    ) -> Self {
+    pub fn new(program: &'a mut Program, call_graph: &'a mut CallGraph, threshold: usize) -> Self {
         Self {
             program,
             call_graph,
-            counter,
+            threshold,
+            budget: 0,
+            recursive: HashSet::new(),
+        }
+    }
+
+    /// Inline calls in func that are worth it, and record inlined callees.
+    fn process_func(&mut self, func: FunPtr, inlined: &mut HashSet<FunPtr>) -> Result<()> {
+        // Compute loop depth of call sites
+        LoopDepthTracer::run_on_func(func)?;
+
+        // Calls copied by inlining are not revisited, which bounds recursive inlining
+        let mut calls: Vec<CallEdge> = self.call_graph.get_calls(func).into_iter().collect();
+        calls.sort_by_key(|edge| edge.inst.get_id());
+        for edge in calls {
+            if self.should_inline(edge) {
+                let callee_size = IrSize::of_func(edge.callee).insts;
+                self.process_call(edge)?;
+                self.budget = self.budget.saturating_sub(callee_size);
+                let inlined_size = self.program.get_pass_state::<InlinedSize>();
+                *inlined_size.caller.entry(edge.caller).or_default() += callee_size;
+                if self.recursive.contains(&edge.callee) {
+                    *inlined_size.recursive.entry(edge.callee).or_default() += 1;
+                }
+                inlined.insert(edge.callee);
+            }
         }
+        Ok(())
     }
 
-    fn process_func(&mut self, func: FunPtr) -> Result<bool> {
-        let mut changed = false;
+    /// Decide if a call site is worth inlining.
+    fn should_inline(&mut self, edge: CallEdge) -> bool {
+        if !edge.callee.may_return() {
+            return false;
+        }
+        let callee_size = IrSize::of_func(edge.callee).insts;
+        let recursive = self.recursive.contains(&edge.callee);
+        let inlined_size = self.program.get_pass_state::<InlinedSize>();
+        let caller_inlined = inlined_size.caller.get(&edge.caller).copied().unwrap_or(0);
+        if caller_inlined + callee_size > CALLER_GROWTH_FACTOR * self.threshold {
+            return false;
+        }
+        let recursive_inlined = inlined_size
+            .recursive
+            .get(&edge.callee)
+            .copied()
+            .unwrap_or(0);
+        if recursive && recursive_inlined >= MAX_RECURSIVE_INLINES {
+            return false;
+        }
 
-        // Eliminate call to func
-        for call in self.call_graph.get_called_by(func) {
-            changed |= self.process_call(call)?;
+        // Inlining the only call removes callee, so code does not grow
+        if !recursive && self.call_graph.get_called_by(edge.callee).len() == 1 {
+            return true;
+        }
+        if callee_size > self.budget {
+            return false;
         }
 
-        // Delete func to reduce code size
-        if changed {
-            self.program.module.functions.retain(|&f| f != func);
+        // Recursive functions never run out of calls, so inline them until caller is too large
+        if recursive {
+            return IrSize::of_func(edge.caller).insts + callee_size <= self.threshold;
         }
-        Ok(changed)
+
+        // Constant arguments are likely to fold, and calls in loops are hotter
+        let args = edge.inst.get_operand();
+        let constant_args = args
+            .iter()
+            .filter(|arg| matches!(arg, Operand::Constant(_)))
+            .count();
+        let cost = callee_size.saturating_sub(CONSTANT_ARG_BONUS * constant_args + args.len() + 1);
+        let depth = edge
+            .inst
+            .get_parent_bb()
+            .map_or(0, |bb| bb.depth.min(MAX_DEPTH_BONUS));
+        cost <= self.threshold * (depth + 1)
     }
 
-    fn process_call(&mut self, edge: CallEdge) -> Result<bool> {
+    fn process_call(&mut self, edge: CallEdge) -> Result<()> {
         let mut inst = edge.inst;
         let call = downcast_ref::<Call>(inst.as_ref().as_ref());
 
@@ -125,9 +237,9 @@ impl<'a> FuncInline<'a> {
         let arg_map = params.zip(args).collect();
 
         // Mirror function, focus on interface basic blocks
-        let new_fun = self.mirror_func(edge.callee, arg_map)?;
+        let new_fun = mirror_func(self.program, edge.callee, arg_map, "inline")?;
         let mut before_entry = call.get_parent_bb().unwrap();
-        let after_exit = self.split_block_at(before_entry, inst)?;
+        let after_exit = self.split_block_at(edge.caller, before_entry, inst)?;
         let fun_entry = new_fun
             .entry
             .ok_or_else(|| anyhow!("function `{}` has no entry", new_fun.name))
@@ -137,6 +249,32 @@ impl<'a> FuncInline<'a> {
             .ok_or_else(|| anyhow!("function `{}` has no exit", new_fun.name))
             .with_context(|| context!())?;
 
+        // Move allocas to caller entry, so that they are not allocated repeatedly in loops,
+        // and add copied calls to call graph
+        let mut caller_entry = edge
+            .caller
+            .entry
+            .ok_or_else(|| anyhow!("function `{}` has no entry", edge.caller.name))
+            .with_context(|| context!())?;
+        for bb in new_fun.dfs_iter() {
+            let insts: Vec<InstPtr> = bb.iter().collect();
+            for new_inst in insts {
+                if new_inst.get_type() == InstType::Alloca {
+                    caller_entry.push_front(new_inst);
+                } else if new_inst.get_type() == InstType::Call {
+                    let callee = downcast_ref::<Call>(new_inst.as_ref().as_ref()).func;
+                    if !callee.is_lib() {
+                        self.call_graph.add_edge(CallEdge {
+                            inst: new_inst,
+                            caller: edge.caller,
+                            callee,
+                        });
+                    }
+                }
+            }
+        }
+        self.call_graph.remove_edge(edge);
+
         // Wire before_entry -> fun_entry
         before_entry.push_back(self.program.mem_pool.get_br(None));
         before_entry.set_true_bb(fun_entry);
@@ -158,15 +296,21 @@ impl<'a> FuncInline<'a> {
         // Wire func_exit -> after_exit
         fun_exit.push_back(self.program.mem_pool.get_br(None));
         fun_exit.set_true_bb(after_exit);
-        Ok(true)
+        pass_stats::bump(Self::name, "call sites inlined", 1);
+        Ok(())
     }
 
     /// Split given basic block at the position of given instruction.
     /// Given instruction and instruction afterwards will be put to exit block.
-    /// Returns new exit block.
-    fn split_block_at(&mut self, mut entry: BBPtr, inst: InstPtr) -> Result<BBPtr> {
-        let exit_name = self.unique_name("split", &entry.name);
-        let mut exit = self.program.mem_pool.new_basicblock(exit_name);
+    /// Returns new exit block, which becomes exit of `func` if `entry` was.
+    fn split_block_at(
+        &mut self,
+        mut func: FunPtr,
+        mut entry: BBPtr,
+        inst: InstPtr,
+    ) -> Result<BBPtr> {
+        let mut exit = new_block(&mut self.program.mem_pool, &entry.name, "split");
+        exit.depth = entry.depth;
         let mut split = false;
 
         // Copy instructions after found target instruction
@@ -181,118 +325,134 @@ impl<'a> FuncInline<'a> {
 
         // Replace `entry` with `entry -> exit`
         entry.replace_exit(exit);
+        if func.exit == Some(entry) {
+            func.exit = Some(exit);
+        }
 
         // Return created block
         Ok(exit)
     }
+}
 
-    /// Mirror a function with given mapping.
-    /// The function should not be added to program, please wire entry and exit to existing function.
-    fn mirror_func(&mut self, func: FunPtr, arg_map: HashMap<ParaPtr, Operand>) -> Result<FunPtr> {
-        let func_entry = func
-            .entry
-            .ok_or_else(|| anyhow!("function `{}` has no entry", func.name))
-            .with_context(|| context!())?;
-        let func_exit = func
-            .exit
-            .ok_or_else(|| anyhow!("function `{}` has no exit", func.name))
-            .with_context(|| context!())?;
-
-        // Initialize inst and block mapping and new function
-        let mut inst_map: HashMap<InstPtr, InstPtr> = HashMap::new();
-        let mut block_map: HashMap<BBPtr, BBPtr> = HashMap::new();
-        let mut new_fun = self
-            .program
-            .mem_pool
-            .new_function(String::new(), func.return_type.clone());
-
-        // Copy blocks and instructions
-        for bb in func.dfs_iter() {
-            let name = self.unique_name("inline", &bb.name);
-            let mut new_bb = self.program.mem_pool.new_basicblock(name);
-            block_map.insert(bb, new_bb);
-            for inst in bb.iter() {
-                let new_inst = self
-                    .program
-                    .mem_pool
-                    .copy_instruction(inst.as_ref().as_ref());
-                inst_map.insert(inst, new_inst);
-                new_bb.push_back(new_inst);
-            }
+/// Mirror a function with given mapping, new blocks are tagged with `meta`.
+/// The function is not added to program, please wire entry and exit to existing function,
+/// or add it to module with new parameters.
+pub fn mirror_func(
+    program: &mut Program,
+    func: FunPtr,
+    arg_map: HashMap<ParaPtr, Operand>,
+    meta: &str,
+) -> Result<FunPtr> {
+    let func_entry = func
+        .entry
+        .ok_or_else(|| anyhow!("function `{}` has no entry", func.name))
+        .with_context(|| context!())?;
+    let func_exit = func
+        .exit
+        .ok_or_else(|| anyhow!("function `{}` has no exit", func.name))
+        .with_context(|| context!())?;
+
+    // Initialize inst and block mapping and new function
+    let mut inst_map: HashMap<InstPtr, InstPtr> = HashMap::new();
+    let mut block_map: HashMap<BBPtr, BBPtr> = HashMap::new();
+    let mut new_fun = program
+        .mem_pool
+        .new_function(String::new(), func.return_type.clone());
+
+    // Copy blocks and instructions
+    for bb in func.dfs_iter() {
+        let mut new_bb = new_block(&mut program.mem_pool, &bb.name, meta);
+        block_map.insert(bb, new_bb);
+        for inst in bb.iter() {
+            let new_inst = program.mem_pool.copy_instruction(inst.as_ref().as_ref());
+            inst_map.insert(inst, new_inst);
+            new_bb.push_back(new_inst);
         }
+    }
 
-        // Set entry and exit for new function
-        new_fun.entry = block_map.get(&func_entry).cloned();
-        new_fun.exit = block_map.get(&func_exit).cloned();
-
-        // Copy operands from old instruction to new instruction,
-        // replace operands to local instruction and inlined argument
-        for bb in func.dfs_iter() {
-            for inst in bb.iter() {
-                let mut new_inst = inst_map
-                    .get(&inst)
-                    .cloned()
-                    .ok_or_else(|| anyhow!("instruction not found in inst_map: {}", inst))
-                    .with_context(|| context!())?;
-                if inst.get_type() == InstType::Phi {
-                    let inst = downcast_ref::<Phi>(inst.as_ref().as_ref());
-                    let new_inst = downcast_mut::<Phi>(new_inst.as_mut());
-
-                    // Replace operand for phi instruction
-                    for (old_op, old_bb) in inst.get_incoming_values().iter() {
-                        let new_bb = block_map
-                            .get(old_bb)
+    // Set entry and exit for new function
+    new_fun.entry = block_map.get(&func_entry).cloned();
+    new_fun.exit = block_map.get(&func_exit).cloned();
+
+    // Copy operands from old instruction to new instruction,
+    // replace operands to local instruction and inlined argument
+    for bb in func.dfs_iter() {
+        for inst in bb.iter() {
+            let mut new_inst = inst_map
+                .get(&inst)
+                .cloned()
+                .ok_or_else(|| anyhow!("instruction not found in inst_map: {}", inst))
+                .with_context(|| context!())?;
+            if inst.get_type() == InstType::Phi {
+                let inst = downcast_ref::<Phi>(inst.as_ref().as_ref());
+                let new_inst = downcast_mut::<Phi>(new_inst.as_mut());
+
+                // Replace operand for phi instruction
+                for (old_op, old_bb) in inst.get_incoming_values().iter() {
+                    let new_bb = block_map
+                        .get(old_bb)
+                        .cloned()
+                        .ok_or_else(|| anyhow!("bb not found in block_map: {}", old_bb.name))
+                        .with_context(|| context!())?;
+                    if let Operand::Instruction(old_op) = old_op {
+                        let new_op = inst_map
+                            .get(old_op)
+                            .cloned()
+                            .ok_or_else(|| anyhow!("instruction not found in inst_map: {}", old_op))
+                            .with_context(|| context!())?;
+                        new_inst.add_incoming_value(new_op.into(), new_bb);
+                    } else if let Operand::Parameter(old_op) = old_op {
+                        let new_op = arg_map
+                            .get(old_op)
                             .cloned()
-                            .ok_or_else(|| anyhow!("bb not found in block_map: {}", old_bb.name))
+                            .ok_or_else(|| anyhow!("parameter not found in arg_map: {}", old_op))
                             .with_context(|| context!())?;
-                        if let Operand::Instruction(old_op) = old_op {
-                            let new_op = inst_map.get(old_op).cloned().unwrap();
-                            new_inst.add_incoming_value(new_op.into(), new_bb);
-                        } else if let Operand::Parameter(old_op) = old_op {
-                            let new_op = arg_map.get(old_op).cloned().unwrap();
-                            new_inst.add_incoming_value(new_op, new_bb);
-                        } else {
-                            // Copy operands manually because `copy_instruction` does not copy them
-                            new_inst.add_incoming_value(old_op.clone(), new_bb);
-                        }
+                        new_inst.add_incoming_value(new_op, new_bb);
+                    } else {
+                        // Copy operands manually because `copy_instruction` does not copy them
+                        new_inst.add_incoming_value(old_op.clone(), new_bb);
                     }
-                } else {
-                    // Replace operand for normal instruction
-                    for old_op in inst.get_operand().iter() {
-                        if let Operand::Instruction(old_op) = old_op {
-                            let new_op = inst_map.get(old_op).cloned().unwrap();
-                            new_inst.add_operand(new_op.into());
-                        } else if let Operand::Parameter(old_op) = old_op {
-                            let new_op = arg_map.get(old_op).cloned().unwrap();
-                            new_inst.add_operand(new_op);
-                        } else {
-                            // Copy operands manually because `copy_instruction` does not copy them
-                            new_inst.add_operand(old_op.clone());
-                        }
+                }
+            } else {
+                // Replace operand for normal instruction
+                for old_op in inst.get_operand().iter() {
+                    if let Operand::Instruction(old_op) = old_op {
+                        let new_op = inst_map
+                            .get(old_op)
+                            .cloned()
+                            .ok_or_else(|| anyhow!("instruction not found in inst_map: {}", old_op))
+                            .with_context(|| context!())?;
+                        new_inst.add_operand(new_op.into());
+                    } else if let Operand::Parameter(old_op) = old_op {
+                        let new_op = arg_map
+                            .get(old_op)
+                            .cloned()
+                            .ok_or_else(|| anyhow!("parameter not found in arg_map: {}", old_op))
+                            .with_context(|| context!())?;
+                        new_inst.add_operand(new_op);
+                    } else {
+                        // Copy operands manually because `copy_instruction` does not copy them
+                        new_inst.add_operand(old_op.clone());
                     }
                 }
             }
         }
+    }
 
-        // Assign mapped basic blocks to successor
-        for bb in func.dfs_iter() {
-            let mut new_bb = block_map.get(&bb).cloned().unwrap();
-            let succ_bb = bb.get_succ_bb();
-            if !succ_bb.is_empty() {
-                let new_succ = block_map.get(&succ_bb[0]).cloned().unwrap();
-                new_bb.set_true_bb(new_succ);
-            }
-            if succ_bb.len() >= 2 {
-                let new_succ = block_map.get(&succ_bb[1]).cloned().unwrap();
-                new_bb.set_false_bb(new_succ);
-            }
+    // Assign mapped basic blocks to successor
+    for bb in func.dfs_iter() {
+        let mut new_bb = block_map.get(&bb).cloned().unwrap();
+        let succ_bb = bb.get_succ_bb();
+        if !succ_bb.is_empty() {
+            let new_succ = block_map.get(&succ_bb[0]).cloned().unwrap();
+            new_bb.set_true_bb(new_succ);
+        }
+        if succ_bb.len() >= 2 {
+            let new_succ = block_map.get(&succ_bb[1]).cloned().unwrap();
+            new_bb.set_false_bb(new_succ);
         }
-
-        // Return new function
-        Ok(new_fun)
     }
 
-    fn unique_name(&mut self, meta: &str, base_name: &str) -> String {
-        format!("{}_{}{}", base_name, meta, self.counter.get_id().unwrap())
-    }
+    // Return new function
+    Ok(new_fun)
 }
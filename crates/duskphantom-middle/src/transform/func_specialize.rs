@@ -0,0 +1,267 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::call_graph::{CallEdge, CallGraph},
+    ir::{
+        instruction::{downcast_ref, misc_inst::Call, InstType},
+        Constant, FunPtr, InstPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    func_inline::mirror_func, loop_depth::LoopDepthTracer, pass_stats, pass_stats::IrSize,
+    Transform,
+};
+
+/// Maximum number of clones specialized from a function. Clones inlined away still count,
+/// otherwise specializing and inlining recursive calls would go on forever.
+const MAX_CLONES: usize = 8;
+
+/// Maximum number of clones specialized in a program, counted the same way.
+const MAX_TOTAL_CLONES: usize = 32;
+
+/// Functions larger than this are not cloned.
+const MAX_CLONE_SIZE: usize = 2000;
+
+/// Clone functions for constant arguments of hot call sites, so that the constants fold in clone.
+/// A call site is hot if it is in a loop, or its callee is recursive.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let call_graph = CallGraph::new(program);
+    FuncSpecialize::new(program, call_graph).run_and_log()
+}
+
+/// Constant arguments of a call, `None` for arguments that are not constant.
+type ArgKey = Vec<Option<Constant>>;
+
+/// Clones specialized in previous runs, kept in program.
+#[derive(Default)]
+struct SpecializeHistory {
+    /// Function each clone is specialized from
+    origin: HashMap<FunPtr, FunPtr>,
+
+    /// Number of clones specialized from each function
+    count: HashMap<FunPtr, usize>,
+
+    /// Number of clones specialized in program
+    total: usize,
+}
+
+pub struct FuncSpecialize<'a> {
+    program: &'a mut Program,
+    call_graph: CallGraph,
+
+    /// Functions in a call graph cycle
+    recursive: HashSet<FunPtr>,
+
+    /// Clones created for each function and constant arguments
+    clones: HashMap<(FunPtr, ArgKey), FunPtr>,
+}
+
+impl<'a> Transform for FuncSpecialize<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "func_specialize".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let functions = self.program.module.functions.clone();
+        for scc in self.call_graph.get_sccs(&functions) {
+            let func = scc[0];
+            let is_self_call = |edge: &CallEdge| edge.callee == func;
+            if scc.len() > 1 || self.call_graph.get_calls(func).iter().any(is_self_call) {
+                self.recursive.extend(scc);
+            }
+        }
+
+        // Compute loop depth of call sites
+        for func in functions.iter() {
+            if func.is_lib() {
+                continue;
+            }
+            LoopDepthTracer::run_on_func(*func)?;
+        }
+
+        // Clones are not specialized again, so that specialization does not nest
+        let mut changed = false;
+        let origin = &self.program.get_pass_state::<SpecializeHistory>().origin;
+        let clones: HashSet<FunPtr> = origin.keys().cloned().collect();
+        for func in functions {
+            if func.is_lib() || func.is_main() || clones.contains(&func) {
+                continue;
+            }
+            if IrSize::of_func(func).insts > MAX_CLONE_SIZE {
+                continue;
+            }
+            changed |= self.process_func(func)?;
+        }
+
+        // Remove specialized functions whose outside calls are all redirected to clones
+        let call_graph = CallGraph::new(self.program);
+        let specialized: HashSet<FunPtr> = self.clones.keys().map(|(func, _)| *func).collect();
+        for func in specialized {
+            let called_by = call_graph.get_called_by(func);
+            if called_by.iter().all(|edge| edge.caller == func) {
+                self.program.module.functions.retain(|f| *f != func);
+                pass_stats::bump(Self::name, "functions removed", 1);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> FuncSpecialize<'a> {
+    pub fn new(program: &'a mut Program, call_graph: CallGraph) -> Self {
+        Self {
+            program,
+            call_graph,
+            recursive: HashSet::new(),
+            clones: HashMap::new(),
+        }
+    }
+
+    /// Redirect hot call sites with constant arguments to clones of func.
+    fn process_func(&mut self, func: FunPtr) -> Result<bool> {
+        let mut calls: Vec<CallEdge> = self.call_graph.get_called_by(func).into_iter().collect();
+        calls.sort_by_key(|edge| edge.inst.get_id());
+
+        // If all calls agree on constant arguments, constant propagation already handles them
+        let keys: HashSet<ArgKey> = calls.iter().map(|edge| get_arg_key(edge.inst)).collect();
+        if keys.len() <= 1 {
+            return Ok(false);
+        }
+
+        let mut changed = false;
+        for edge in calls {
+            let key = get_arg_key(edge.inst);
+            if key.iter().all(|arg| arg.is_none()) || !self.is_hot(edge) {
+                continue;
+            }
+            let clone = match self.clones.get(&(func, key.clone())).copied() {
+                Some(clone) => clone,
+                None if self.can_clone(func) => {
+                    let clone = self.make_clone(func, &key)?;
+                    self.clones.insert((func, key.clone()), clone);
+                    clone
+                }
+                None => continue,
+            };
+            self.redirect_call(edge.inst, clone, &key);
+            pass_stats::bump(Self::name, "call sites specialized", 1);
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Check if func is within per-function and program-wide clone limits.
+    fn can_clone(&mut self, func: FunPtr) -> bool {
+        let history = self.program.get_pass_state::<SpecializeHistory>();
+        let count = history.count.get(&func).copied().unwrap_or(0);
+        count < MAX_CLONES && history.total < MAX_TOTAL_CLONES
+    }
+
+    fn is_hot(&self, edge: CallEdge) -> bool {
+        let depth = edge.inst.get_parent_bb().map_or(0, |bb| bb.depth);
+        depth > 0 || self.recursive.contains(&edge.callee)
+    }
+
+    /// Clone func with constant arguments substituted, and add it to module after func and its clones.
+    fn make_clone(&mut self, func: FunPtr, key: &ArgKey) -> Result<FunPtr> {
+        let mut arg_map = HashMap::new();
+        let mut params = Vec::new();
+        for (param, arg) in func.params.iter().zip(key.iter()) {
+            match arg {
+                Some(c) => {
+                    arg_map.insert(*param, c.clone().into());
+                }
+                None => {
+                    let new_param = self
+                        .program
+                        .mem_pool
+                        .new_parameter(param.name.clone(), param.value_type.clone());
+                    arg_map.insert(*param, Operand::Parameter(new_param));
+                    params.push(new_param);
+                }
+            }
+        }
+        let mut clone = mirror_func(self.program, func, arg_map, "spec")?;
+        clone.params = params;
+        clone.name = format!("{}_spec{}", func.name, clone.entry.map_or(0, |bb| bb.id));
+
+        // Recursive calls with the same constants stay in clone
+        for bb in clone.dfs_iter() {
+            for inst in bb.iter().collect::<Vec<_>>() {
+                if inst.get_type() == InstType::Call
+                    && downcast_ref::<Call>(inst.as_ref().as_ref()).func == func
+                    && get_arg_key(inst) == *key
+                {
+                    self.redirect_call(inst, clone, key);
+                }
+            }
+        }
+
+        let history = self.program.get_pass_state::<SpecializeHistory>();
+        history.origin.insert(clone, func);
+        *history.count.entry(func).or_default() += 1;
+        history.total += 1;
+        let siblings: HashSet<FunPtr> = history
+            .origin
+            .iter()
+            .filter(|(_, origin)| **origin == func)
+            .map(|(clone, _)| *clone)
+            .collect();
+        let functions = &mut self.program.module.functions;
+        let index = functions
+            .iter()
+            .rposition(|f| *f == func || siblings.contains(f))
+            .map_or(0, |i| i + 1);
+        functions.insert(index, clone);
+        pass_stats::bump(Self::name, "functions cloned", 1);
+        Ok(clone)
+    }
+
+    /// Replace call with a call to clone, passing only arguments that are not constant.
+    fn redirect_call(&mut self, mut call: InstPtr, clone: FunPtr, key: &ArgKey) {
+        let args = call
+            .get_operand()
+            .iter()
+            .zip(key.iter())
+            .filter(|(_, c)| c.is_none())
+            .map(|(arg, _)| arg.clone())
+            .collect();
+        let new_call = self.program.mem_pool.get_call(clone, args);
+        call.insert_before(new_call);
+        call.replace_self(&new_call.into());
+    }
+}
+
+fn get_arg_key(call: InstPtr) -> ArgKey {
+    call.get_operand()
+        .iter()
+        .map(|arg| match arg {
+            Operand::Constant(c) => Some(c.clone()),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,201 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::{
+        alias_analysis::EffectRange, call_graph::CallGraph, effect_analysis::EffectAnalysis,
+    },
+    context,
+    ir::{
+        instruction::{downcast_ref, misc_inst::Call, InstType},
+        BBPtr, FunPtr, GlobalPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{pass_stats, Transform};
+
+/// Turn scalar globals only accessed by non-recursive `main` into local variables,
+/// and remove globals that are never read. Run `mem2reg` afterwards to promote them.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let call_graph = CallGraph::new(program);
+    let effect_analysis = EffectAnalysis::new(program);
+    GlobalLocalize::new(program, &call_graph, &effect_analysis).run_and_log()
+}
+
+pub struct GlobalLocalize<'a> {
+    program: &'a mut Program,
+    call_graph: &'a CallGraph,
+    effect_analysis: &'a EffectAnalysis,
+}
+
+impl<'a> Transform for GlobalLocalize<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "global_localize".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let main = self
+            .program
+            .module
+            .functions
+            .iter()
+            .find(|func| func.is_main())
+            .cloned();
+
+        // Globals can only be localized if `main` runs exactly once
+        let main_blocks: HashSet<BBPtr> = match main {
+            Some(main) if self.call_graph.get_called_by(main).is_empty() => {
+                main.dfs_iter().collect()
+            }
+            _ => HashSet::new(),
+        };
+
+        let mut changed = false;
+        for global in self.program.module.global_variables.clone() {
+            if self.is_write_only(&global.into()) {
+                remove_writes(&global.into());
+                self.program
+                    .module
+                    .global_variables
+                    .retain(|g| *g != global);
+                pass_stats::bump(Self::name, "globals removed", 1);
+                changed = true;
+            } else if let Some(main) = main {
+                if is_local_to(global, &main_blocks) {
+                    self.localize(global, main)?;
+                    self.program
+                        .module
+                        .global_variables
+                        .retain(|g| *g != global);
+                    pass_stats::bump(Self::name, "globals localized", 1);
+                    changed = true;
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> GlobalLocalize<'a> {
+    pub fn new(
+        program: &'a mut Program,
+        call_graph: &'a CallGraph,
+        effect_analysis: &'a EffectAnalysis,
+    ) -> Self {
+        Self {
+            program,
+            call_graph,
+            effect_analysis,
+        }
+    }
+
+    /// Check if memory at pointer is only written, by stores or library calls that do not read.
+    fn is_write_only(&self, ptr: &Operand) -> bool {
+        let users = match ptr {
+            Operand::Global(global) => global.get_user(),
+            Operand::Instruction(inst) => inst.get_user(),
+            _ => return false,
+        };
+        users.iter().all(|user| match user.get_type() {
+            InstType::Store => user.get_operand()[0] != *ptr,
+            InstType::GetElementPtr => self.is_write_only(&(*user).into()),
+            InstType::Call => {
+                let callee = downcast_ref::<Call>(user.as_ref().as_ref()).func;
+                let reads_nothing = self.effect_analysis.inst_effect.get(user).is_some_and(
+                    |effect| matches!(&effect.use_range, EffectRange::Some(set) if set.is_empty()),
+                );
+                callee.is_lib() && reads_nothing && !self.effect_analysis.has_io(*user)
+            }
+            _ => false,
+        })
+    }
+
+    /// Replace global with an alloca in `main`, initialized at function start.
+    fn localize(&mut self, global: GlobalPtr, main: FunPtr) -> Result<()> {
+        let mut entry = main
+            .entry
+            .ok_or_else(|| anyhow!("function `{}` has no entry", main.name))
+            .with_context(|| context!())?;
+        let mut alloca = self
+            .program
+            .mem_pool
+            .get_alloca(global.value_type.clone(), 1);
+        let init = self
+            .program
+            .mem_pool
+            .get_store(global.initializer.clone().into(), alloca.into());
+        entry.push_front(alloca);
+        alloca.insert_after(init);
+
+        let op = Operand::Global(global);
+        let mut users = global.get_user().to_vec();
+        users.dedup();
+        for mut user in users {
+            for index in 0..user.get_operand().len() {
+                if user.get_operand()[index] == op {
+                    user.set_operand(index, alloca.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Check if global is a scalar directly loaded and stored in given blocks only.
+fn is_local_to(global: GlobalPtr, blocks: &HashSet<BBPtr>) -> bool {
+    if !matches!(
+        global.value_type,
+        ValueType::Int | ValueType::Float | ValueType::Bool
+    ) {
+        return false;
+    }
+    let op = Operand::Global(global);
+    global.get_user().iter().all(|user| {
+        let is_access = match user.get_type() {
+            InstType::Load => true,
+            InstType::Store => user.get_operand()[0] != op,
+            _ => false,
+        };
+        is_access && user.get_parent_bb().is_some_and(|bb| blocks.contains(&bb))
+    })
+}
+
+/// Remove all writes to pointer, and address computations leading to them.
+fn remove_writes(ptr: &Operand) {
+    let users = match ptr {
+        Operand::Global(global) => global.get_user().to_vec(),
+        Operand::Instruction(inst) => inst.get_user().to_vec(),
+        _ => return,
+    };
+    for mut user in users {
+        if user.get_type() == InstType::GetElementPtr {
+            remove_writes(&user.into());
+        }
+        // Instruction may be removed already if it uses pointer twice
+        if user.get_parent_bb().is_some() {
+            user.remove_self();
+        }
+    }
+}
@@ -0,0 +1,463 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::{
+        dominator_tree::DominatorTree,
+        effect_analysis::EffectAnalysis,
+        memory_ssa::{MemorySSA, Node, NodePtr},
+    },
+    ir::{
+        instruction::{
+            downcast_ref,
+            memory_op_inst::GetElementPtr,
+            misc_inst::{Call, FCmp, FCmpOp, ICmp, ICmpOp, Phi},
+            InstType,
+        },
+        BBPtr, FunPtr, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{
+    pass_stats::{self, FuncTimer},
+    region_clone::copy_with_operands,
+    Transform,
+};
+
+/// Remove fully and partially redundant computations with global value numbering.
+///
+/// Phis are numbered by their incoming values, so that a phi of equal values in all
+/// predecessors is congruent to computing the value after the merge. A computation at
+/// a merge block is translated into each predecessor through phis and memory phis.
+/// If it's available in all predecessors, it's replaced with a phi of the available values,
+/// and if it's only missing in one predecessor, it's inserted there first.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let effect_analysis = EffectAnalysis::new(program);
+    let memory_ssa = MemorySSA::new(program, &effect_analysis);
+    GvnPre::new(program, &memory_ssa).run_and_log()
+}
+
+pub struct GvnPre<'a> {
+    program: &'a mut Program,
+    memory_ssa: &'a MemorySSA<'a>,
+}
+
+impl<'a> Transform for GvnPre<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "gvn_pre".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            changed |= self.run_func(func);
+        }
+        Ok(changed)
+    }
+}
+
+/// Expression computing a value, where operands are value numbers.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Expression {
+    Op(InstType, ValueType, Vec<usize>),
+    ICmp(ICmpOp, Vec<usize>),
+    FCmp(FCmpOp, Vec<usize>),
+    GetElementPtr(ValueType, Vec<usize>),
+    Call(FunPtr, Vec<usize>),
+    /// Load of type from address, in memory state after a MemorySSA node
+    Load(ValueType, usize, NodePtr),
+    Phi(BBPtr, Vec<usize>),
+}
+
+/// Value numbers of operands and expressions, and operands holding each value.
+#[derive(Default)]
+struct ValueTable {
+    operand_num: HashMap<Operand, usize>,
+    expr_num: HashMap<Expression, usize>,
+    members: HashMap<usize, Vec<Operand>>,
+}
+
+impl ValueTable {
+    /// Get value number of operand, where operands other than instructions are numbered
+    /// on first use. Returns `None` for instructions not numbered yet.
+    fn get_num(&mut self, op: &Operand) -> Option<usize> {
+        if let Some(num) = self.operand_num.get(op) {
+            return Some(*num);
+        }
+        if let Operand::Instruction(_) = op {
+            return None;
+        }
+        let num = self.new_num();
+        self.add_member(op.clone(), num);
+        Some(num)
+    }
+
+    /// Get value number of expression, creating a new number if it's not computed before.
+    fn get_expr_num(&mut self, expr: Option<Expression>) -> usize {
+        let Some(expr) = expr else {
+            return self.new_num();
+        };
+        if let Some(num) = self.expr_num.get(&expr) {
+            return *num;
+        }
+        let num = self.new_num();
+        self.expr_num.insert(expr, num);
+        num
+    }
+
+    fn new_num(&mut self) -> usize {
+        self.members.insert(self.members.len(), Vec::new());
+        self.members.len() - 1
+    }
+
+    fn add_member(&mut self, op: Operand, num: usize) {
+        self.operand_num.insert(op.clone(), num);
+        self.members.entry(num).or_default().push(op);
+    }
+}
+
+impl<'a> GvnPre<'a> {
+    pub fn new(program: &'a mut Program, memory_ssa: &'a MemorySSA<'a>) -> Self {
+        Self {
+            program,
+            memory_ssa,
+        }
+    }
+
+    /// Number instructions in reverse post order, replacing each with an available operand
+    /// of the same value, or removing its partial redundancy at merge blocks.
+    fn run_func(&mut self, func: FunPtr) -> bool {
+        let mut changed = false;
+        let mut table = ValueTable::default();
+        let mut dom_tree = DominatorTree::new(func);
+        let mut visited = HashSet::new();
+        for bb in func.rpo_iter() {
+            visited.insert(bb);
+            for mut inst in bb.iter().collect::<Vec<_>>() {
+                if inst.get_value_type() == ValueType::Void {
+                    continue;
+                }
+                let num = match self.get_expression(&mut table, inst) {
+                    // Phi of the same value in all predecessors holds that value
+                    Some(Expression::Phi(_, nums))
+                        if nums.iter().all(|n| Some(n) == nums.first()) =>
+                    {
+                        nums[0]
+                    }
+                    expr => table.get_expr_num(expr),
+                };
+                if let Some(leader) = get_available(&table, &mut dom_tree, num, inst) {
+                    inst.replace_self(&leader);
+                    pass_stats::bump(Self::name, "redundancies removed", 1);
+                    changed = true;
+                    continue;
+                }
+                if bb.get_pred_bb().len() > 1
+                    && self.eliminate_partial(&mut table, &mut dom_tree, &visited, inst, num)
+                {
+                    pass_stats::bump(Self::name, "partial redundancies removed", 1);
+                    changed = true;
+                    continue;
+                }
+                table.add_member(inst.into(), num);
+            }
+        }
+        changed
+    }
+
+    /// Get expression computed by instruction, or `None` if it's different from any other.
+    fn get_expression(&self, table: &mut ValueTable, inst: InstPtr) -> Option<Expression> {
+        let nums = inst
+            .get_operand()
+            .iter()
+            .map(|op| table.get_num(op))
+            .collect::<Option<Vec<_>>>()?;
+        let memory = match inst.get_type() {
+            InstType::Load => Some(self.memory_ssa.get_inst_node(inst)?.get_use_node()),
+            _ => None,
+        };
+        self.make_expression(inst, nums, memory)
+    }
+
+    /// Make expression of instruction with given operand numbers and memory state for loads.
+    fn make_expression(
+        &self,
+        inst: InstPtr,
+        mut nums: Vec<usize>,
+        memory: Option<NodePtr>,
+    ) -> Option<Expression> {
+        let ty = inst.get_type();
+        if matches!(
+            ty,
+            InstType::Add
+                | InstType::Mul
+                | InstType::FAdd
+                | InstType::FMul
+                | InstType::And
+                | InstType::Or
+                | InstType::Xor
+        ) {
+            nums.sort_unstable();
+        }
+        let expr = match ty {
+            InstType::Alloca | InstType::Store | InstType::Br | InstType::Ret => return None,
+            InstType::Load => Expression::Load(inst.get_value_type(), nums[0], memory?),
+            InstType::Phi => {
+                let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+                let mut incoming: Vec<(BBPtr, usize)> = phi
+                    .get_incoming_values()
+                    .iter()
+                    .zip(nums)
+                    .map(|((_, bb), num)| (*bb, num))
+                    .collect();
+                incoming.sort_by_key(|(bb, _)| bb.id);
+                let nums = incoming.into_iter().map(|(_, num)| num).collect();
+                Expression::Phi(inst.get_parent_bb()?, nums)
+            }
+            InstType::Call => {
+                if self.memory_ssa.effect_analysis.has_effect(inst) {
+                    return None;
+                }
+                let call = downcast_ref::<Call>(inst.as_ref().as_ref());
+                Expression::Call(call.func, nums)
+            }
+            InstType::ICmp => {
+                let cmp = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+                Expression::ICmp(cmp.op, nums)
+            }
+            InstType::FCmp => {
+                let cmp = downcast_ref::<FCmp>(inst.as_ref().as_ref());
+                Expression::FCmp(cmp.op, nums)
+            }
+            InstType::GetElementPtr => {
+                let gep = downcast_ref::<GetElementPtr>(inst.as_ref().as_ref());
+                Expression::GetElementPtr(gep.element_type.clone(), nums)
+            }
+            _ => Expression::Op(ty, inst.get_value_type(), nums),
+        };
+        Some(expr)
+    }
+
+    /// Replace instruction in a merge block with a phi of its values in predecessors,
+    /// inserting it into at most one visited predecessor where it's missing.
+    fn eliminate_partial(
+        &mut self,
+        table: &mut ValueTable,
+        dom_tree: &mut DominatorTree,
+        visited: &HashSet<BBPtr>,
+        mut inst: InstPtr,
+        num: usize,
+    ) -> bool {
+        if let InstType::Phi | InstType::Alloca = inst.get_type() {
+            return false;
+        }
+        let Some(mut bb) = inst.get_parent_bb() else {
+            return false;
+        };
+        let mut incoming = Vec::new();
+        let mut missing = None;
+        for pred in bb.get_pred_bb().clone() {
+            let Some((operands, expr)) = self.translate(table, inst, pred) else {
+                return false;
+            };
+            let value = match expr.as_ref().and_then(|expr| table.expr_num.get(expr)) {
+                Some(n) => get_available_at_end(table, dom_tree, *n, pred),
+                None => None,
+            };
+            let value = value.or_else(|| get_forwarded(table, expr.as_ref()?));
+            match value {
+                Some(value) if value != inst.into() => incoming.push((value, pred)),
+                _ if missing.is_some() => return false,
+                _ => missing = Some((pred, operands, expr)),
+            }
+        }
+
+        // Insert into the only missing predecessor, where it's computed anyway after jumping
+        // to this block, so no fault is introduced. Unvisited predecessors are not inserted
+        // into, because their instructions are numbered later and can't use it.
+        if let Some((pred, operands, expr)) = missing {
+            if incoming.is_empty() || pred.get_succ_bb().len() != 1 || !visited.contains(&pred) {
+                return false;
+            }
+            let new_inst = copy_with_operands(&mut self.program.mem_pool, inst, operands);
+            pred.get_last_inst().insert_before(new_inst);
+            let new_num = table.get_expr_num(expr);
+            table.add_member(new_inst.into(), new_num);
+            incoming.push((new_inst.into(), pred));
+        }
+
+        // Reuse a phi with the same incoming values if there is one
+        let phi_nums = {
+            let mut incoming = incoming.clone();
+            incoming.sort_by_key(|(_, bb)| bb.id);
+            incoming
+                .iter()
+                .map(|(op, _)| table.get_num(op))
+                .collect::<Option<Vec<_>>>()
+        };
+        let phi_expr = phi_nums.map(|nums| Expression::Phi(bb, nums));
+        let existing = phi_expr
+            .as_ref()
+            .and_then(|expr| table.expr_num.get(expr))
+            .and_then(|n| {
+                table.members[n]
+                    .iter()
+                    .find(|op| is_phi_in(op, bb))
+                    .cloned()
+            });
+        let phi: Operand = match existing {
+            Some(phi) => phi,
+            None => {
+                let phi = self
+                    .program
+                    .mem_pool
+                    .get_phi(inst.get_value_type(), incoming);
+                bb.push_front(phi);
+                if let Some(expr) = phi_expr {
+                    table.expr_num.insert(expr, num);
+                }
+                table.add_member(phi.into(), num);
+                phi.into()
+            }
+        };
+        inst.replace_self(&phi);
+        true
+    }
+
+    /// Translate instruction in a merge block into predecessor, by taking incoming values
+    /// of phis in the block, and memory state of memory phis for loads.
+    /// Returns operands and expression in predecessor.
+    fn translate(
+        &self,
+        table: &mut ValueTable,
+        inst: InstPtr,
+        pred: BBPtr,
+    ) -> Option<(Vec<Operand>, Option<Expression>)> {
+        let bb = inst.get_parent_bb()?;
+        let mut operands = Vec::new();
+        for op in inst.get_operand() {
+            let op = match op {
+                Operand::Instruction(def) if def.get_parent_bb() == Some(bb) => {
+                    if def.get_type() != InstType::Phi {
+                        return None;
+                    }
+                    let phi = downcast_ref::<Phi>(def.as_ref().as_ref());
+                    phi.get_incoming_value(pred)?.clone()
+                }
+                _ => op.clone(),
+            };
+            operands.push(op);
+        }
+        let nums = operands
+            .iter()
+            .map(|op| table.get_num(op))
+            .collect::<Option<Vec<_>>>()?;
+
+        // Memory state comes from predecessor if it's defined by memory phi in this block
+        let memory = match inst.get_type() {
+            InstType::Load => {
+                let node = self.memory_ssa.get_inst_node(inst)?.get_use_node();
+                match node.as_ref() {
+                    Node::Phi(_, args, _) if self.memory_ssa.get_node_block(node) == Some(bb) => {
+                        let (_, node) = args.iter().find(|(arg_bb, _)| *arg_bb == pred)?;
+                        Some(*node)
+                    }
+                    Node::Normal(_, _, _, def) if def.get_parent_bb() == Some(bb) => return None,
+                    _ => Some(node),
+                }
+            }
+            _ => None,
+        };
+        Some((operands, self.make_expression(inst, nums, memory)))
+    }
+}
+
+/// Get operand of value available right before instruction.
+fn get_available(
+    table: &ValueTable,
+    dom_tree: &mut DominatorTree,
+    num: usize,
+    inst: InstPtr,
+) -> Option<Operand> {
+    let bb = inst.get_parent_bb()?;
+    table.members.get(&num)?.iter().find_map(|op| match op {
+        // Members in the same block are numbered before this instruction
+        Operand::Instruction(member) => {
+            let member_bb = member.get_parent_bb()?;
+            let available = if member_bb == bb {
+                *member != inst
+            } else {
+                dom_tree.is_dominate(member_bb, bb)
+            };
+            available.then(|| op.clone())
+        }
+        _ => Some(op.clone()),
+    })
+}
+
+/// Get operand of value available at the end of block.
+fn get_available_at_end(
+    table: &ValueTable,
+    dom_tree: &mut DominatorTree,
+    num: usize,
+    bb: BBPtr,
+) -> Option<Operand> {
+    table.members.get(&num)?.iter().find_map(|op| match op {
+        Operand::Instruction(member) => {
+            let member_bb = member.get_parent_bb()?;
+            dom_tree.is_dominate(member_bb, bb).then(|| op.clone())
+        }
+        _ => Some(op.clone()),
+    })
+}
+
+/// Get value stored to the address of load by the store defining its memory state.
+fn get_forwarded(table: &mut ValueTable, expr: &Expression) -> Option<Operand> {
+    let Expression::Load(ty, addr, node) = expr else {
+        return None;
+    };
+    let Node::Normal(_, _, _, store) = node.as_ref() else {
+        return None;
+    };
+    if store.get_type() != InstType::Store {
+        return None;
+    }
+    let value = store.get_operand()[0].clone();
+    let store_addr = table.get_num(&store.get_operand()[1])?;
+    (store_addr == *addr && value.get_type() == *ty).then_some(value)
+}
+
+fn is_phi_in(op: &Operand, bb: BBPtr) -> bool {
+    match op {
+        Operand::Instruction(inst) => {
+            inst.get_type() == InstType::Phi && inst.get_parent_bb() == Some(bb)
+        }
+        _ => false,
+    }
+}
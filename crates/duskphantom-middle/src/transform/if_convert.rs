@@ -0,0 +1,212 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+
+use crate::{
+    ir::{
+        instruction::{downcast_mut, downcast_ref, misc_inst::Phi, InstType},
+        BBPtr, ValueType,
+    },
+    Program,
+};
+
+use super::{
+    pass_stats::{self, FuncTimer},
+    Transform,
+};
+
+/// Maximum number of instructions hoisted from each side of a branch.
+const MAX_SPECULATED: usize = 4;
+
+/// Maximum number of selects created for a branch.
+const MAX_SELECTS: usize = 2;
+
+/// Turn small side-effect-free diamonds and triangles into selects.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    IfConvert::new(program).run_and_log()
+}
+
+pub struct IfConvert<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for IfConvert<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "if_convert".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            for bb in func.rpo_iter().collect::<Vec<_>>() {
+                // Block may be removed as a side of previous branch
+                if bb.is_empty() {
+                    continue;
+                }
+                changed |= self.convert_branch(bb);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> IfConvert<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    /// Convert `bb -> { true side, false side } -> merge` into `bb -> merge` with selects,
+    /// where either side can be missing, in which case `bb` jumps to merge directly.
+    fn convert_branch(&mut self, mut bb: BBPtr) -> bool {
+        let succ = bb.get_succ_bb().clone();
+        if succ.len() != 2 || succ[0] == succ[1] {
+            return false;
+        }
+        let (true_bb, false_bb) = (succ[0], succ[1]);
+        let is_side = |side: BBPtr| side.get_pred_bb().len() == 1 && side.get_succ_bb().len() == 1;
+        let merge = match (is_side(true_bb), is_side(false_bb)) {
+            (true, true) if true_bb.get_succ_bb()[0] == false_bb.get_succ_bb()[0] => {
+                true_bb.get_succ_bb()[0]
+            }
+            (true, _) if true_bb.get_succ_bb()[0] == false_bb => false_bb,
+            (_, true) if false_bb.get_succ_bb()[0] == true_bb => true_bb,
+            _ => return false,
+        };
+        if merge == bb {
+            return false;
+        }
+
+        // Value of phi on each path comes from side block, or from `bb` if side is missing
+        let true_pred = if true_bb == merge { bb } else { true_bb };
+        let false_pred = if false_bb == merge { bb } else { false_bb };
+        let sides: Vec<BBPtr> = [true_bb, false_bb]
+            .into_iter()
+            .filter(|side| *side != merge)
+            .collect();
+        if !sides.iter().all(|side| can_speculate(*side)) {
+            return false;
+        }
+        let mut phis = Vec::new();
+        for phi in merge.iter() {
+            if phi.get_type() != InstType::Phi {
+                continue;
+            }
+            if !matches!(phi.get_value_type(), ValueType::Int | ValueType::Bool) {
+                return false;
+            }
+            let incoming = downcast_ref::<Phi>(phi.as_ref().as_ref());
+            let (Some(true_val), Some(false_val)) = (
+                incoming.get_incoming_value(true_pred).cloned(),
+                incoming.get_incoming_value(false_pred).cloned(),
+            ) else {
+                return false;
+            };
+            phis.push((phi, true_val, false_val));
+        }
+        if phis.len() > MAX_SELECTS {
+            return false;
+        }
+
+        // Hoist side instructions before branch
+        let mut br = bb.get_last_inst();
+        let cond = br.get_operand()[0].clone();
+        for side in sides.iter() {
+            for mut inst in side.iter().collect::<Vec<_>>() {
+                if inst.get_type() == InstType::Br {
+                    inst.remove_self();
+                } else {
+                    unsafe {
+                        inst.move_self();
+                    }
+                    br.insert_before(inst);
+                }
+            }
+        }
+
+        // Replace incoming values of both paths with a select from `bb`
+        for (mut phi, true_val, false_val) in phis {
+            let select = self
+                .program
+                .mem_pool
+                .get_select(cond.clone(), true_val, false_val);
+            br.insert_before(select);
+            let phi = downcast_mut::<Phi>(phi.as_mut());
+            phi.remove_incoming_value(true_pred.id);
+            phi.remove_incoming_value(false_pred.id);
+            phi.add_incoming_value(select.into(), bb);
+            pass_stats::bump(Self::name, "selects created", 1);
+        }
+
+        // Rewire `bb` to jump to merge unconditionally, and remove sides
+        if true_bb == merge {
+            bb.remove_false_bb();
+        } else if false_bb == merge {
+            bb.remove_true_bb();
+        } else {
+            bb.remove_false_bb();
+            bb.replace_succ_bb_only(true_bb, merge);
+        }
+        for mut side in sides {
+            side.remove_self();
+        }
+        br.insert_after(self.program.mem_pool.get_br(None));
+        br.remove_self();
+        pass_stats::bump(Self::name, "branches converted", 1);
+        true
+    }
+}
+
+/// Check if all instructions of block can run unconditionally without side effect.
+fn can_speculate(bb: BBPtr) -> bool {
+    let mut count = 0;
+    for inst in bb.iter() {
+        match inst.get_type() {
+            InstType::Br => (),
+            InstType::Add
+            | InstType::FAdd
+            | InstType::Sub
+            | InstType::FSub
+            | InstType::Mul
+            | InstType::FMul
+            | InstType::FDiv
+            | InstType::Shl
+            | InstType::LShr
+            | InstType::AShr
+            | InstType::And
+            | InstType::Or
+            | InstType::Xor
+            | InstType::ZextTo
+            | InstType::SextTo
+            | InstType::ItoFp
+            | InstType::FpToI
+            | InstType::ICmp
+            | InstType::FCmp
+            | InstType::GetElementPtr
+            | InstType::Select => count += 1,
+            _ => return false,
+        }
+    }
+    count <= MAX_SPECULATED
+}
@@ -18,6 +18,12 @@ use std::collections::HashSet;
 
 use anyhow::{anyhow, Context, Result};
 
+use crate::analysis::{
+    demanded_bits::DemandedBits,
+    known_bits::{get_known_bits, get_shift_amount, high_mask, low_mask, low_mask_upto},
+    scalar_evolution::swap_predicate,
+    value_range::{Range, ValueRange},
+};
 use crate::ir::instruction::downcast_ref;
 use crate::{
     context,
@@ -27,12 +33,12 @@ use crate::{
             misc_inst::{FCmp, FCmpOp, ICmp, ICmpOp},
             InstType,
         },
-        BBPtr, Constant, FunPtr, InstPtr, Operand,
+        BBPtr, Constant, FunPtr, InstPtr, Operand, ValueType,
     },
     Program,
 };
 
-use super::Transform;
+use super::{pass_stats::FuncTimer, Transform};
 
 pub fn optimize_program(program: &mut Program) -> Result<bool> {
     SymbolicEval::new(program).run_and_log()
@@ -42,6 +48,16 @@ pub struct SymbolicEval<'a> {
     program: &'a mut Program,
     reachable: HashSet<BBPtr>,
     func: FunPtr,
+    demanded_bits: DemandedBits,
+}
+
+/// Rewrite of an instruction justified by value ranges.
+enum RangeRewrite {
+    /// Comparison always or never holds
+    Constant(bool),
+
+    /// Non-negative value divided by `2^k` is a shift by `k`, no rounding fix needed
+    Shift(i32),
 }
 
 impl<'a> Transform for SymbolicEval<'a> {
@@ -59,8 +75,17 @@ impl<'a> Transform for SymbolicEval<'a> {
             if func.is_lib() {
                 continue;
             }
+            let _timer = FuncTimer::new(Self::name, func);
             self.func = func;
             self.reachable = self.build_reachable_set()?;
+
+            // Query all ranges before changing anything, so that they are not stale
+            let value_range = ValueRange::new(func);
+            for (inst, rewrite) in self.get_range_rewrites(&value_range) {
+                changed |= self.range_simplify(inst, rewrite)?;
+            }
+
+            self.demanded_bits = DemandedBits::new(func);
             for bb in func.rpo_iter() {
                 if !self.reachable.contains(&bb) {
                     continue;
@@ -81,6 +106,7 @@ impl<'a> SymbolicEval<'a> {
             program,
             func,
             reachable: HashSet::new(),
+            demanded_bits: DemandedBits::default(),
         }
     }
 
@@ -93,7 +119,8 @@ impl<'a> SymbolicEval<'a> {
         changed |= self.constant_fold(inst)?
             || self.canonicalize_gep(inst)?
             || self.useless_elim(inst)?
-            || self.inst_combine(inst)?;
+            || self.inst_combine(inst)?
+            || self.bits_simplify(inst)?;
         Ok(changed)
     }
 
@@ -357,6 +384,14 @@ impl<'a> SymbolicEval<'a> {
                     return Ok(true);
                 }
             }
+            InstType::Select => {
+                let cond = inst.get_operand()[0].clone();
+                if let Operand::Constant(Constant::Bool(cond)) = cond {
+                    let result = inst.get_operand()[if cond { 1 } else { 2 }].clone();
+                    inst.replace_self(&result);
+                    return Ok(true);
+                }
+            }
             _ => (),
         }
         Ok(false)
@@ -483,6 +518,21 @@ impl<'a> SymbolicEval<'a> {
                     return Ok(true);
                 }
             }
+            InstType::Select => {
+                let cond = inst.get_operand()[0].clone();
+                let true_val = inst.get_operand()[1].clone();
+                let false_val = inst.get_operand()[2].clone();
+                if true_val == false_val {
+                    inst.replace_self(&true_val);
+                    return Ok(true);
+                }
+                if true_val == Constant::Bool(true).into()
+                    && false_val == Constant::Bool(false).into()
+                {
+                    inst.replace_self(&cond);
+                    return Ok(true);
+                }
+            }
             _ => (),
         }
 
@@ -656,11 +706,260 @@ impl<'a> SymbolicEval<'a> {
                     }
                 }
             }
+            InstType::Select => {
+                let cond = inst.get_operand()[0].clone();
+                let true_val = inst.get_operand()[1].clone();
+                let false_val = inst.get_operand()[2].clone();
+
+                // select c, (select c, x, _), y = select c, x, y
+                if let Operand::Instruction(true_inst) = &true_val {
+                    if true_inst.get_type() == InstType::Select
+                        && true_inst.get_operand()[0] == cond
+                    {
+                        let new_inst = self.program.mem_pool.get_select(
+                            cond,
+                            true_inst.get_operand()[1].clone(),
+                            false_val,
+                        );
+                        inst.insert_after(new_inst);
+                        inst.replace_self(&new_inst.into());
+                        self.symbolic_eval(new_inst)?;
+                        return Ok(true);
+                    }
+                }
+
+                // select c, x, (select c, _, y) = select c, x, y
+                if let Operand::Instruction(false_inst) = false_val {
+                    if false_inst.get_type() == InstType::Select
+                        && false_inst.get_operand()[0] == cond
+                    {
+                        let new_inst = self.program.mem_pool.get_select(
+                            cond,
+                            true_val,
+                            false_inst.get_operand()[2].clone(),
+                        );
+                        inst.insert_after(new_inst);
+                        inst.replace_self(&new_inst.into());
+                        self.symbolic_eval(new_inst)?;
+                        return Ok(true);
+                    }
+                }
+            }
             _ => (),
         }
         Ok(false)
     }
 
+    /// Get instructions simplified with ranges of their operands.
+    fn get_range_rewrites(&self, value_range: &ValueRange) -> Vec<(InstPtr, RangeRewrite)> {
+        let mut rewrites = Vec::new();
+        for bb in self.func.rpo_iter() {
+            if !self.reachable.contains(&bb) {
+                continue;
+            }
+            for inst in bb.iter() {
+                match inst.get_type() {
+                    InstType::ICmp => {
+                        if let Some(result) = value_range.eval_icmp(inst) {
+                            rewrites.push((inst, RangeRewrite::Constant(result)));
+                        }
+                    }
+                    InstType::SDiv => {
+                        let lhs = &inst.get_operand()[0];
+                        if let Operand::Constant(Constant::Int(rhs)) = inst.get_operand()[1] {
+                            if rhs > 1
+                                && rhs & (rhs - 1) == 0
+                                && value_range.get_range_at(lhs, bb).is_non_negative()
+                            {
+                                let shift = rhs.trailing_zeros() as i32;
+                                rewrites.push((inst, RangeRewrite::Shift(shift)));
+                            }
+                        }
+                    }
+                    _ => (),
+                }
+            }
+        }
+        rewrites
+    }
+
+    /// Apply rewrite found with ranges. Original instruction is removed.
+    fn range_simplify(&mut self, mut inst: InstPtr, rewrite: RangeRewrite) -> Result<bool> {
+        match rewrite {
+            RangeRewrite::Constant(result) => {
+                inst.replace_self(&Constant::Bool(result).into());
+            }
+            RangeRewrite::Shift(shift) => {
+                let lhs = inst.get_operand()[0].clone();
+                let new_inst = self
+                    .program
+                    .mem_pool
+                    .get_ashr(lhs, Constant::Int(shift).into());
+                inst.insert_after(new_inst);
+                inst.replace_self(&new_inst.into());
+            }
+        }
+        Ok(true)
+    }
+
+    /// Simplify instruction with known bits of its operands and demanded bits of its result.
+    /// If changed, original instruction is removed.
+    fn bits_simplify(&mut self, mut inst: InstPtr) -> Result<bool> {
+        let inst_type = inst.get_type();
+        let ty = inst.get_value_type();
+        let pure = matches!(
+            inst_type,
+            InstType::Add
+                | InstType::Sub
+                | InstType::Mul
+                | InstType::SDiv
+                | InstType::UDiv
+                | InstType::SRem
+                | InstType::URem
+                | InstType::Shl
+                | InstType::LShr
+                | InstType::AShr
+                | InstType::And
+                | InstType::Or
+                | InstType::Xor
+                | InstType::ZextTo
+                | InstType::SextTo
+                | InstType::ICmp
+                | InstType::Select
+                | InstType::Phi
+        );
+        if !pure || !matches!(ty, ValueType::Int | ValueType::Bool) {
+            return Ok(false);
+        }
+
+        // Replace with constant if all demanded bits are known
+        let demanded = match ty {
+            ValueType::Int => self.demanded_bits.get_demanded(inst),
+            _ => 1,
+        };
+        let known = get_known_bits(&inst.into());
+        if known.known() & demanded == demanded {
+            let value = known.one & demanded;
+            let result = match ty {
+                ValueType::Int => Constant::Int(value as i32),
+                _ => Constant::Bool(value != 0),
+            };
+            inst.replace_self(&result.into());
+            return Ok(true);
+        }
+
+        match inst_type {
+            InstType::Add | InstType::Sub => {
+                let lhs = inst.get_operand()[0].clone();
+                let rhs = inst.get_operand()[1].clone();
+
+                // Operand with no bits as low as demanded ones does not change the result
+                let low = low_mask_upto(demanded);
+                if get_known_bits(&rhs).zero & low == low {
+                    inst.replace_self(&lhs);
+                    return Ok(true);
+                }
+                if inst_type == InstType::Add && get_known_bits(&lhs).zero & low == low {
+                    inst.replace_self(&rhs);
+                    return Ok(true);
+                }
+            }
+            InstType::AShr | InstType::LShr => {
+                let lhs = inst.get_operand()[0].clone();
+                let rhs = inst.get_operand()[1].clone();
+
+                // `(x << k) >> k` is `x` if bits shifted in are the same as bits shifted out,
+                // or they are not demanded
+                if let (Some(k), Some((x, inner_k))) = (get_shift_amount(&rhs), match_shl(&lhs)) {
+                    let top = high_mask(k);
+                    let known = get_known_bits(&x);
+                    let redundant = match inst_type {
+                        InstType::AShr => {
+                            let sign = high_mask(k + 1);
+                            known.zero & sign == sign || known.one & sign == sign
+                        }
+                        _ => known.zero & top == top,
+                    };
+                    if k == inner_k && (redundant || demanded & top == 0) {
+                        self.replace_with_existing(inst, x);
+                        return Ok(true);
+                    }
+                }
+            }
+            InstType::Shl | InstType::Mul => {
+                // `(x >> k) << k` is `x` if bits shifted out are zero, or they are not demanded
+                if let Some((inner, k)) = match_shl(&inst.into()) {
+                    let Operand::Instruction(inner) = inner else {
+                        return Ok(false);
+                    };
+                    if matches!(inner.get_type(), InstType::AShr | InstType::LShr)
+                        && get_shift_amount(&inner.get_operand()[1]) == Some(k)
+                    {
+                        let x = inner.get_operand()[0].clone();
+                        let low = low_mask(k);
+                        if get_known_bits(&x).zero & low == low || demanded & low == 0 {
+                            self.replace_with_existing(inst, x);
+                            return Ok(true);
+                        }
+                    }
+                }
+            }
+            InstType::ICmp => {
+                if let Some(result) = self.simplify_bool_ext_cmp(inst) {
+                    inst.replace_self(&result);
+                    return Ok(true);
+                }
+            }
+            _ => (),
+        }
+        Ok(false)
+    }
+
+    /// Simplify comparison of an extended boolean with a constant to the boolean,
+    /// its negation or a constant. New instruction is inserted after `inst` if needed.
+    fn simplify_bool_ext_cmp(&mut self, mut inst: InstPtr) -> Option<Operand> {
+        let icmp = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+        let (op, ext, c) = match (icmp.get_lhs(), icmp.get_rhs()) {
+            (Operand::Instruction(ext), Operand::Constant(Constant::Int(c))) => (icmp.op, *ext, *c),
+            (Operand::Constant(Constant::Int(c)), Operand::Instruction(ext)) => {
+                (swap_predicate(icmp.op), *ext, *c)
+            }
+            _ => return None,
+        };
+        let b = ext.get_operand().first()?.clone();
+        let true_value = match ext.get_type() {
+            InstType::ZextTo => 1,
+            InstType::SextTo => -1,
+            _ => return None,
+        };
+        if b.get_type() != ValueType::Bool {
+            return None;
+        }
+
+        // Evaluate comparison for both values of boolean
+        let eval = |v: i64| Range::compare(op, Range::constant(v), Range::constant(c as i64));
+        match (eval(0)?, eval(true_value)?) {
+            (false, true) => Some(b),
+            (true, false) => {
+                let not = self
+                    .program
+                    .mem_pool
+                    .get_xor(b, Constant::Bool(true).into());
+                inst.insert_after(not);
+                Some(not.into())
+            }
+            (result, _) => Some(Constant::Bool(result).into()),
+        }
+    }
+
+    /// Replace instruction with an existing value, which gets more bits demanded.
+    fn replace_with_existing(&mut self, mut inst: InstPtr, value: Operand) {
+        inst.replace_self(&value);
+        if let Operand::Instruction(value) = value {
+            self.demanded_bits.update(value);
+        }
+    }
+
     /// Merge `getelementptr` instruction.
     /// If changed, original instruction is removed.
     #[allow(unused)]
@@ -719,7 +1018,7 @@ impl<'a> SymbolicEval<'a> {
         Ok(reachable)
     }
 
-    /// Remove an edge and remove all unreachable basic blocks.
+    /// Remove an edge and remove all unreachable basic blocks and their instructions.
     /// TODO: Is there a more efficient implementation?
     fn remove_edge(&mut self, mut bb: BBPtr, cond: bool) -> Result<()> {
         // Remove path based on condition
@@ -732,10 +1031,14 @@ impl<'a> SymbolicEval<'a> {
         // Build new reachable set
         let reachable = self.build_reachable_set()?;
 
-        // Remove all unreachable basic blocks from old reachable set
+        // Remove all unreachable basic blocks from old reachable set, with their instructions,
+        // so that later passes don't see uses from unreachable blocks
         for bb in self.reachable.iter() {
             if !reachable.contains(bb) {
                 bb.clone().remove_self();
+                for mut inst in bb.iter().collect::<Vec<_>>() {
+                    inst.remove_self();
+                }
             }
         }
 
@@ -744,3 +1047,18 @@ impl<'a> SymbolicEval<'a> {
         Ok(())
     }
 }
+
+/// Match `x << k`, or `x * 2^k`, returning `x` and `k`.
+fn match_shl(op: &Operand) -> Option<(Operand, u32)> {
+    let Operand::Instruction(inst) = op else {
+        return None;
+    };
+    let x = inst.get_operand().first()?.clone();
+    match (inst.get_type(), inst.get_operand().get(1)?) {
+        (InstType::Shl, amount) => Some((x, get_shift_amount(amount)?)),
+        (InstType::Mul, Operand::Constant(Constant::Int(c))) if c.count_ones() == 1 && *c > 0 => {
+            Some((x, c.trailing_zeros()))
+        }
+        _ => None,
+    }
+}
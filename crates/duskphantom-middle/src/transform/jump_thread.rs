@@ -0,0 +1,435 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::{
+        dominator_tree::DominatorTree,
+        edge_fact::{get_branch_cond, get_edge_fact, get_facts, Fact},
+        scalar_evolution::{negate_predicate, swap_predicate},
+    },
+    context,
+    ir::{
+        instruction::{
+            downcast_mut, downcast_ref,
+            misc_inst::{ICmp, ICmpOp, Phi},
+            InstType,
+        },
+        BBPtr, Constant, FunPtr, InstPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    constant_fold::fold_inst,
+    lcssa::{get_outside_uses, LCSSA},
+    pass_stats::{self, FuncTimer},
+    region_clone::RegionClone,
+    Transform,
+};
+
+/// Blocks with more non-phi instructions than this (excluding the branch) are not duplicated.
+const MAX_DUP_SIZE: usize = 6;
+
+/// At most this many edges are threaded in a function per run.
+const MAX_THREADS: usize = 64;
+
+/// Orderings of two compared values, used to check implication between comparisons.
+const LT: u8 = 1;
+const EQ: u8 = 2;
+const GT: u8 = 4;
+
+/// Fold branches whose condition is implied by a dominating branch, and thread
+/// predecessors through small blocks to the successor their condition is known to take.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    JumpThread::new(program).run_and_log()
+}
+
+pub struct JumpThread<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for JumpThread<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "jump_thread".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            changed |= self.run_func(func)?;
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> JumpThread<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    /// Fold branches until none is implied, then thread one edge and repeat,
+    /// because threading invalidates dominator tree.
+    fn run_func(&mut self, func: FunPtr) -> Result<bool> {
+        let mut changed = false;
+        let mut threads = 0;
+        loop {
+            let mut dom_tree = DominatorTree::new(func);
+            let blocks: Vec<BBPtr> = func.rpo_iter().collect();
+            let mut folded = false;
+            for bb in blocks.iter() {
+                folded |= self.fold_branch(*bb, &mut dom_tree);
+            }
+            if folded {
+                remove_unreachable(func, blocks);
+                changed = true;
+                continue;
+            }
+            if threads >= MAX_THREADS || !self.thread_one(func, &mut dom_tree)? {
+                break;
+            }
+            threads += 1;
+            changed = true;
+        }
+        Ok(changed)
+    }
+
+    /// Replace branch with a jump if its condition is implied by dominating branches.
+    fn fold_branch(&mut self, mut bb: BBPtr, dom_tree: &mut DominatorTree) -> bool {
+        let Some(cond) = get_branch_cond(bb) else {
+            return false;
+        };
+        let facts: Vec<Fact> = get_facts(bb, dom_tree).collect();
+        let Some(value) = eval_cond(&cond, &HashMap::new(), &facts) else {
+            return false;
+        };
+        if value {
+            bb.remove_false_bb();
+        } else {
+            bb.remove_true_bb();
+        }
+        let mut br = bb.get_last_inst();
+        br.insert_after(self.program.mem_pool.get_br(None));
+        br.remove_self();
+        pass_stats::bump(Self::name, "branches folded", 1);
+        true
+    }
+
+    /// Find a predecessor whose edge determines the branch of a small block, and redirect
+    /// it to a copy of the block that jumps to the known successor.
+    fn thread_one(&mut self, func: FunPtr, dom_tree: &mut DominatorTree) -> Result<bool> {
+        for bb in func.rpo_iter() {
+            let Some(cond) = get_branch_cond(bb) else {
+                continue;
+            };
+            let preds = bb.get_pred_bb().clone();
+            let size = bb
+                .iter()
+                .filter(|inst| inst.get_type() != InstType::Phi)
+                .count();
+            if preds.len() < 2 || size > MAX_DUP_SIZE + 1 {
+                continue;
+            }
+
+            // Threading into a loop header would create a loop with multiple entries
+            if preds.iter().any(|pred| dom_tree.is_dominate(bb, *pred)) {
+                continue;
+            }
+            for pred in preds {
+                if pred
+                    .get_succ_bb()
+                    .iter()
+                    .filter(|succ| **succ == bb)
+                    .count()
+                    != 1
+                {
+                    continue;
+                }
+                let values = get_edge_values(pred, bb);
+                let mut facts: Vec<Fact> = get_facts(pred, dom_tree).collect();
+                facts.extend(get_edge_fact(pred, bb));
+                if let Some(value) = eval_cond(&cond, &values, &facts) {
+                    let next = bb.get_succ_bb()[if value { 0 } else { 1 }];
+                    self.thread_edge(pred, bb, next)?;
+                    return Ok(true);
+                }
+            }
+        }
+        Ok(false)
+    }
+
+    /// Redirect `pred -> bb` to a copy of `bb` that jumps to `next`.
+    fn thread_edge(&mut self, mut pred: BBPtr, mut bb: BBPtr, next: BBPtr) -> Result<()> {
+        let blocks = HashSet::from([bb]);
+        let mut repairs = Vec::new();
+        for inst in bb.iter() {
+            let mut users = inst.get_user().to_vec();
+            users.sort_by_key(|user| user.get_id());
+            users.dedup();
+            for user in users {
+                for (pos, incoming) in get_outside_uses(inst, user, &blocks) {
+                    repairs.push((inst, user, pos, incoming));
+                }
+            }
+        }
+
+        // Copy instructions, with phis replaced by values from predecessor
+        let mut operand_map: HashMap<Operand, Operand> = HashMap::new();
+        for inst in bb.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+            let value = phi
+                .get_incoming_value(pred)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} should have incoming value from {}", phi, pred.name))
+                .with_context(|| context!())?;
+            operand_map.insert(inst.into(), value);
+        }
+        let clone = RegionClone::new(
+            &mut self.program.mem_pool,
+            &[bb],
+            "thread",
+            operand_map,
+            |inst| inst.get_type() != InstType::Br,
+        );
+        let mut new_bb = clone.block_map[&bb];
+
+        // Rewire `pred -> new_bb -> next`, phis in `next` get the copied values
+        new_bb.push_back(self.program.mem_pool.get_br(None));
+        new_bb.set_true_bb(next);
+        clone.extend_phis(next);
+        pred.replace_succ_bb_only(bb, new_bb);
+
+        // Values of `bb` used outside now have two definitions, merge them with phis
+        let mut defs: HashMap<InstPtr, HashMap<BBPtr, Operand>> = HashMap::new();
+        for (inst, mut user, pos, incoming) in repairs {
+            let op: Operand = inst.into();
+            let def = defs.entry(inst).or_insert_with(|| {
+                HashMap::from([(bb, op.clone()), (new_bb, clone.map_operand(&op))])
+            });
+            let value = LCSSA::new(&mut self.program.mem_pool).get_value_at_end(
+                pos,
+                def,
+                &inst.get_value_type(),
+            )?;
+            if value == op {
+                continue;
+            }
+            match incoming {
+                Some(pred) => {
+                    downcast_mut::<Phi>(user.as_mut()).replace_incoming_value_at(pred, value)
+                }
+                None => user.replace_operand(&op, &value),
+            }
+        }
+
+        // Remove incoming values from `pred` only after copies are made,
+        // as this may replace phis with a single incoming value
+        bb.remove_pred_bb(pred);
+        pass_stats::bump(Self::name, "edges threaded", 1);
+        Ok(())
+    }
+}
+
+/// Get values of instructions in `bb` known on edge `pred -> bb`: phis take their
+/// incoming values, and instructions on constants are folded.
+fn get_edge_values(pred: BBPtr, bb: BBPtr) -> HashMap<Operand, Operand> {
+    let mut values = HashMap::new();
+    for inst in bb.iter() {
+        if inst.get_type() == InstType::Phi {
+            let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+            if let Some(value) = phi.get_incoming_value(pred) {
+                values.insert(inst.into(), value.clone());
+            }
+            continue;
+        }
+        let operands: Option<Vec<Constant>> = inst
+            .get_operand()
+            .iter()
+            .map(|op| match values.get(op).unwrap_or(op) {
+                Operand::Constant(c) => Some(c.clone()),
+                _ => None,
+            })
+            .collect();
+        if let Some(result) = operands.and_then(|operands| fold_inst(inst, operands)) {
+            values.insert(inst.into(), result.into());
+        }
+    }
+    values
+}
+
+/// Evaluate condition with known values and facts.
+fn eval_cond(cond: &Operand, values: &HashMap<Operand, Operand>, facts: &[Fact]) -> Option<bool> {
+    let map_operand = |op: &Operand| values.get(op).cloned().unwrap_or(op.clone());
+    let cond = map_operand(cond);
+    if let Operand::Constant(Constant::Bool(value)) = cond {
+        return Some(value);
+    }
+    if let Some((_, value)) = facts.iter().find(|(fact, _)| *fact == cond) {
+        return Some(*value);
+    }
+    let Operand::Instruction(inst) = cond else {
+        return None;
+    };
+    if inst.get_type() != InstType::ICmp {
+        return None;
+    }
+    let icmp = downcast_ref::<ICmp>(inst.as_ref().as_ref());
+    let query = (
+        icmp.op,
+        map_operand(icmp.get_lhs()),
+        map_operand(icmp.get_rhs()),
+    );
+    if let (Operand::Constant(lhs), Operand::Constant(rhs)) = (&query.1, &query.2) {
+        let result = fold_inst(inst, vec![lhs.clone(), rhs.clone()])?;
+        return Some(result == Constant::Bool(true));
+    }
+    facts.iter().find_map(|(fact, value)| {
+        let Operand::Instruction(fact) = fact else {
+            return None;
+        };
+        if fact.get_type() != InstType::ICmp {
+            return None;
+        }
+        let icmp = downcast_ref::<ICmp>(fact.as_ref().as_ref());
+        let op = if *value {
+            icmp.op
+        } else {
+            negate_predicate(icmp.op)
+        };
+        let fact = (op, icmp.get_lhs().clone(), icmp.get_rhs().clone());
+        imply(&fact, &query)
+    })
+}
+
+/// Check if comparison `fact` being true implies `query` is true or false.
+fn imply(fact: &(ICmpOp, Operand, Operand), query: &(ICmpOp, Operand, Operand)) -> Option<bool> {
+    let (fact_op, fact_lhs, fact_rhs) = fact;
+    let (query_op, query_lhs, query_rhs) = query;
+
+    // Same operands, possibly swapped: compare sets of possible orderings
+    let fact_op = if fact_lhs == query_lhs && fact_rhs == query_rhs {
+        Some(*fact_op)
+    } else if fact_lhs == query_rhs && fact_rhs == query_lhs {
+        Some(swap_predicate(*fact_op))
+    } else {
+        None
+    };
+    if let Some(fact_op) = fact_op {
+        let (fact_set, fact_signed) = get_orderings(fact_op);
+        let (query_set, query_signed) = get_orderings(*query_op);
+        if fact_signed.is_some() && query_signed.is_some() && fact_signed != query_signed {
+            return None;
+        }
+        if fact_set & !query_set == 0 {
+            return Some(true);
+        }
+        if fact_set & query_set == 0 {
+            return Some(false);
+        }
+        return None;
+    }
+
+    // Same value compared with constants: compare ranges
+    let (fact_op, value, fact_bound) = get_constant_cmp(fact)?;
+    let (query_op, query_value, query_bound) = get_constant_cmp(query)?;
+    if value != query_value {
+        return None;
+    }
+    let (lo, hi) = get_range(fact_op, fact_bound)?;
+    if query_op == ICmpOp::Ne {
+        if query_bound < lo || query_bound > hi {
+            return Some(true);
+        }
+        return (lo == query_bound && hi == query_bound).then_some(false);
+    }
+    let (query_lo, query_hi) = get_range(query_op, query_bound)?;
+    if query_lo <= lo && hi <= query_hi {
+        return Some(true);
+    }
+    if hi < query_lo || query_hi < lo {
+        return Some(false);
+    }
+    None
+}
+
+/// Get comparison in the form of `value op constant`.
+fn get_constant_cmp(cmp: &(ICmpOp, Operand, Operand)) -> Option<(ICmpOp, Operand, i64)> {
+    match cmp {
+        (op, value, Operand::Constant(Constant::Int(c))) => Some((*op, value.clone(), *c as i64)),
+        (op, Operand::Constant(Constant::Int(c)), value) => {
+            Some((swap_predicate(*op), value.clone(), *c as i64))
+        }
+        _ => None,
+    }
+}
+
+/// Get range of signed values satisfying `value op bound`.
+fn get_range(op: ICmpOp, bound: i64) -> Option<(i64, i64)> {
+    let (min, max) = (i32::MIN as i64, i32::MAX as i64);
+    match op {
+        ICmpOp::Eq => Some((bound, bound)),
+        ICmpOp::Slt => Some((min, bound - 1)),
+        ICmpOp::Sle => Some((min, bound)),
+        ICmpOp::Sgt => Some((bound + 1, max)),
+        ICmpOp::Sge => Some((bound, max)),
+        _ => None,
+    }
+}
+
+/// Get orderings of `lhs` and `rhs` where `lhs op rhs` holds, and whether the comparison
+/// is signed. Equality comparisons are valid for both.
+fn get_orderings(op: ICmpOp) -> (u8, Option<bool>) {
+    match op {
+        ICmpOp::Eq => (EQ, None),
+        ICmpOp::Ne => (LT | GT, None),
+        ICmpOp::Slt => (LT, Some(true)),
+        ICmpOp::Sle => (LT | EQ, Some(true)),
+        ICmpOp::Sgt => (GT, Some(true)),
+        ICmpOp::Sge => (GT | EQ, Some(true)),
+        ICmpOp::Ult => (LT, Some(false)),
+        ICmpOp::Ule => (LT | EQ, Some(false)),
+        ICmpOp::Ugt => (GT, Some(false)),
+        ICmpOp::Uge => (GT | EQ, Some(false)),
+    }
+}
+
+/// Remove given blocks no longer reachable from entry.
+fn remove_unreachable(func: FunPtr, blocks: Vec<BBPtr>) {
+    let reachable: HashSet<BBPtr> = func.dfs_iter().collect();
+    for mut bb in blocks {
+        if reachable.contains(&bb) {
+            continue;
+        }
+        bb.remove_self();
+        for mut inst in bb.iter().collect::<Vec<_>>() {
+            inst.remove_self();
+        }
+    }
+}
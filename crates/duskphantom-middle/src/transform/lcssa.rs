@@ -0,0 +1,282 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::{
+        dominator_tree::DominatorTree,
+        loop_tools::{LoopForest, LoopPtr},
+    },
+    context,
+    ir::{
+        instruction::{downcast_mut, downcast_ref, misc_inst::Phi, InstType},
+        BBPtr, FunPtr, InstPtr, Instruction, Operand, ValueType,
+    },
+    transform::{
+        loop_optimization::loop_forest_post_order, loop_simplify::take_incoming_values,
+        region_clone::new_block,
+    },
+    IRBuilder,
+};
+
+type IRBuilderWraper = Pin<Box<IRBuilder>>;
+
+/// Build loop-closed SSA form, where values defined in a loop are only used outside of it
+/// by phis in exit blocks. Exits are made dedicated first, so that they are only entered from loop.
+///
+/// Transforms that add edges leaving a loop only need to extend exit phis,
+/// and call `insert_dedicated_exits` if the new edges come from outside the loop.
+pub struct LCSSA<'a> {
+    ir_builder: &'a mut IRBuilderWraper,
+}
+
+impl<'a> LCSSA<'a> {
+    pub fn new(ir_builder: &'a mut IRBuilderWraper) -> Self {
+        Self { ir_builder }
+    }
+
+    pub fn run(&mut self, func: FunPtr, loop_forest: &mut LoopForest) -> Result<()> {
+        loop_forest_post_order(loop_forest, |lo| self.insert_dedicated_exits(lo))?;
+        let mut dom_tree = DominatorTree::new(func);
+        loop_forest_post_order(loop_forest, |lo| self.close_loop(lo, &mut dom_tree))
+    }
+
+    /// Build LCSSA form for a single loop.
+    pub fn form_loop(&mut self, func: FunPtr, lo: LoopPtr) -> Result<()> {
+        self.insert_dedicated_exits(lo)?;
+        let mut dom_tree = DominatorTree::new(func);
+        self.close_loop(lo, &mut dom_tree)
+    }
+
+    /// Split exits that are also entered from outside of loop.
+    /// Incoming values from loop are merged by phis in the new exit blocks.
+    pub fn insert_dedicated_exits(&mut self, lo: LoopPtr) -> Result<()> {
+        for exit in get_exits(lo) {
+            let (inside, outside): (Vec<BBPtr>, Vec<BBPtr>) = exit
+                .get_pred_bb()
+                .iter()
+                .partition(|pred| lo.is_in_loop(pred));
+            if outside.is_empty() {
+                continue;
+            }
+
+            let mut dedicated = new_block(self.ir_builder, &exit.name, "dedicated");
+            let mut tail = self.ir_builder.get_br(None);
+            dedicated.push_back(tail);
+            for mut pred in inside.iter().cloned() {
+                pred.replace_succ_bb_only(exit, dedicated);
+            }
+            dedicated.set_true_bb(exit);
+
+            for mut inst in exit.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                let phi = downcast_mut::<Phi>(inst.as_mut());
+                let incoming_values = take_incoming_values(phi, &inside);
+                let new_phi = self
+                    .ir_builder
+                    .get_phi(phi.get_value_type(), incoming_values);
+                tail.insert_before(new_phi);
+                phi.add_incoming_value(new_phi.into(), dedicated);
+            }
+
+            // New block is in the innermost outer loop that contains exit
+            let mut parent = lo.parent_loop;
+            while let Some(mut plo) = parent {
+                if plo.is_in_loop(&exit) {
+                    plo.blocks.insert(dedicated);
+                    break;
+                }
+                parent = plo.parent_loop;
+            }
+        }
+        Ok(())
+    }
+
+    /// Insert phis in exits for values used outside of loop, and rewrite the uses.
+    /// Exits should be dedicated.
+    fn close_loop(&mut self, lo: LoopPtr, dom_tree: &mut DominatorTree) -> Result<()> {
+        let blocks = lo.get_all_blocks();
+        let block_set: HashSet<BBPtr> = blocks.iter().cloned().collect();
+        let exits = get_exits(lo);
+        for bb in blocks {
+            for inst in bb.iter() {
+                let mut users = inst.get_user().to_vec();
+                users.sort_by_key(|user| user.get_id());
+                users.dedup();
+                let uses: Vec<_> = users
+                    .into_iter()
+                    .flat_map(|user| {
+                        get_outside_uses(inst, user, &block_set)
+                            .into_iter()
+                            .map(move |(pos, incoming)| (user, pos, incoming))
+                    })
+                    .collect();
+                if uses.is_empty() {
+                    continue;
+                }
+
+                // Value is available at all exits it dominates
+                let ty = inst.get_value_type();
+                let mut defs: HashMap<BBPtr, Operand> = HashMap::new();
+                for mut exit in exits.iter().cloned() {
+                    if !dom_tree.is_dominate(bb, exit) {
+                        continue;
+                    }
+                    let incoming_values = exit
+                        .get_pred_bb()
+                        .iter()
+                        .map(|pred| (inst.into(), *pred))
+                        .collect();
+                    let phi = self.ir_builder.get_phi(ty.clone(), incoming_values);
+                    exit.push_front(phi);
+                    defs.insert(exit, phi.into());
+                }
+
+                let op: Operand = inst.into();
+                for (mut user, pos, incoming) in uses {
+                    let value = self.get_value_at_end(pos, &mut defs, &ty)?;
+                    match incoming {
+                        Some(pred) => downcast_mut::<Phi>(user.as_mut())
+                            .replace_incoming_value_at(pred, value),
+                        None => {
+                            for index in 0..user.get_operand().len() {
+                                if user.get_operand()[index] == op {
+                                    user.set_operand(index, value.clone());
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Get value at the end of block given definitions at block starts,
+    /// inserting phis where definitions from multiple predecessors merge.
+    pub fn get_value_at_end(
+        &mut self,
+        mut bb: BBPtr,
+        defs: &mut HashMap<BBPtr, Operand>,
+        ty: &ValueType,
+    ) -> Result<Operand> {
+        if let Some(value) = defs.get(&bb) {
+            return Ok(value.clone());
+        }
+        let preds = bb.get_pred_bb().clone();
+        if preds.is_empty() {
+            return Err(anyhow!("no definition reaches {}", bb.name)).with_context(|| context!());
+        }
+        if preds.len() == 1 {
+            let value = self.get_value_at_end(preds[0], defs, ty)?;
+            defs.insert(bb, value.clone());
+            return Ok(value);
+        }
+
+        // Phi is registered before visiting predecessors, in case of cycles
+        let mut phi = self.ir_builder.get_phi(ty.clone(), vec![]);
+        bb.push_front(phi);
+        let phi_op: Operand = phi.into();
+        defs.insert(bb, phi_op.clone());
+        for pred in preds {
+            let value = self.get_value_at_end(pred, defs, ty)?;
+            downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(value, pred);
+        }
+
+        // Remove phi if it only merges a single value with itself
+        let mut values: Vec<Operand> = phi
+            .get_operand()
+            .iter()
+            .filter(|op| **op != phi_op)
+            .cloned()
+            .collect();
+        values.dedup();
+        if let [value] = values.as_slice() {
+            let value = value.clone();
+            phi.replace_self(&value);
+            for def in defs.values_mut() {
+                if *def == phi_op {
+                    *def = value.clone();
+                }
+            }
+            return Ok(value);
+        }
+        Ok(phi_op)
+    }
+}
+
+/// Check if loop is in LCSSA form with dedicated exits.
+pub fn is_lcssa(lo: LoopPtr) -> bool {
+    let blocks = lo.get_all_blocks();
+    let block_set: HashSet<BBPtr> = blocks.iter().cloned().collect();
+    let dedicated = get_exits(lo).iter().all(|exit| {
+        exit.get_pred_bb()
+            .iter()
+            .all(|pred| block_set.contains(pred))
+    });
+    dedicated
+        && blocks.iter().all(|bb| {
+            bb.iter().all(|inst| {
+                inst.get_user()
+                    .iter()
+                    .all(|user| get_outside_uses(inst, *user, &block_set).is_empty())
+            })
+        })
+}
+
+/// Get blocks outside of loop with a predecessor in loop, ordered by first appearance.
+pub fn get_exits(lo: LoopPtr) -> Vec<BBPtr> {
+    let mut exits = Vec::new();
+    for bb in lo.get_all_blocks() {
+        for succ in bb.get_succ_bb().iter() {
+            if !lo.is_in_loop(succ) && !exits.contains(succ) {
+                exits.push(*succ);
+            }
+        }
+    }
+    exits
+}
+
+/// Get positions where user uses value outside of loop, excluding exit phis with incoming values
+/// from loop. Each position is the block of user, or the incoming block if user is a phi.
+pub fn get_outside_uses(
+    value: InstPtr,
+    user: InstPtr,
+    blocks: &HashSet<BBPtr>,
+) -> Vec<(BBPtr, Option<BBPtr>)> {
+    if user.get_type() == InstType::Phi {
+        let op: Operand = value.into();
+        let phi = downcast_ref::<Phi>(user.as_ref().as_ref());
+        return phi
+            .get_incoming_values()
+            .iter()
+            .filter(|(val, pred)| *val == op && !blocks.contains(pred))
+            .map(|(_, pred)| (*pred, Some(*pred)))
+            .collect();
+    }
+    match user.get_parent_bb() {
+        Some(bb) if !blocks.contains(&bb) => vec![(bb, None)],
+        _ => Vec::new(),
+    }
+}
@@ -22,7 +22,10 @@ use crate::{
     Program,
 };
 
-use super::Transform;
+use super::{
+    pass_stats::{self, FuncTimer},
+    Transform,
+};
 
 pub fn optimize_program<'a>(
     program: &'a mut Program,
@@ -51,6 +54,7 @@ impl<'a, 'b> Transform for LoadElim<'a, 'b> {
             if func.is_lib() {
                 continue;
             }
+            let _timer = FuncTimer::new(Self::name, func);
             for bb in func.rpo_iter() {
                 for inst in bb.iter() {
                     changed |= self.process_inst(inst, func)?;
@@ -92,6 +96,7 @@ impl<'a, 'b> LoadElim<'a, 'b> {
         if let Some(predicted) = predicted {
             inst.replace_self(&predicted);
             self.memory_ssa.remove_node(load_node);
+            pass_stats::bump(Self::name, "loads eliminated", 1);
             return Ok(true);
         }
         Ok(false)
@@ -14,7 +14,10 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
-use crate::analysis::loop_tools::{LoopForest, LoopTree};
+use crate::{
+    analysis::loop_tools::{LoopForest, LoopTree},
+    ir::FunPtr,
+};
 
 use anyhow::{Ok, Result};
 
@@ -29,6 +32,17 @@ impl LoopDepthTracer {
         Ok(())
     }
 
+    /// Compute loop depth of all blocks in a function, where blocks outside loops get 0.
+    pub fn run_on_func(func: FunPtr) -> Result<()> {
+        for mut bb in func.dfs_iter() {
+            bb.depth = 0;
+        }
+        if let Some(forest) = LoopForest::make_forest(func) {
+            Self::run(&forest)?;
+        }
+        Ok(())
+    }
+
     fn run_a_loop(depth: usize, loop_tree: &LoopTree) -> Result<()> {
         for mut bb in loop_tree.blocks.iter().cloned() {
             bb.depth = depth;
@@ -0,0 +1,350 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::{
+        alias_analysis::EffectRange,
+        effect_analysis::EffectAnalysis,
+        loop_tools::{LoopForest, LoopPtr},
+    },
+    ir::{
+        instruction::{downcast_mut, misc_inst::Phi, InstType},
+        InstPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    loop_fusion::get_while_loop,
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats::{self, FuncTimer},
+    region_clone::{new_block, RegionClone},
+    Transform,
+};
+
+/// Loops with more instructions than this are not split.
+const MAX_SPLIT_SIZE: usize = 128;
+
+/// Split loops into a loop of statements without loop-carried dependence,
+/// followed by a loop of the other statements, so that the first one can be parallelized.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let effect_analysis = EffectAnalysis::new(program);
+    LoopFission::new(program, &effect_analysis).run_and_log()
+}
+
+pub struct LoopFission<'a> {
+    program: &'a mut Program,
+    effect_analysis: &'a EffectAnalysis,
+}
+
+impl<'a> Transform for LoopFission<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_fission".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+
+            // Splitting a loop only adds blocks before it, so other loops remain valid
+            loop_forest_post_order(&mut forest, |lo| {
+                changed |= self.split_loop(lo);
+                Ok(())
+            })?;
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> LoopFission<'a> {
+    pub fn new(program: &'a mut Program, effect_analysis: &'a EffectAnalysis) -> Self {
+        Self {
+            program,
+            effect_analysis,
+        }
+    }
+
+    /// Split loop `preheader -> head -> body -> head -> exit` into
+    /// `preheader -> head' -> body' -> head' -> new preheader -> head -> body -> head -> exit`,
+    /// where the copy only runs statements without loop-carried dependence,
+    /// and the original loop runs the others. Both loops run the exit condition.
+    fn split_loop(&mut self, lo: LoopPtr) -> bool {
+        let Some(shape) = get_while_loop(lo) else {
+            return false;
+        };
+        let (head, body) = (shape.head, shape.body);
+        if body != shape.latch || lo.blocks.len() != 2 {
+            return false;
+        }
+        let size = head.iter().count() + body.iter().count();
+        if size > MAX_SPLIT_SIZE {
+            return false;
+        }
+
+        // Exit condition and the values it depends on are run by both loops
+        let mut control: HashSet<InstPtr> = HashSet::new();
+        let mut worklist: Vec<Operand> = head.get_last_inst().get_operand().to_vec();
+        while let Some(op) = worklist.pop() {
+            let Operand::Instruction(inst) = op else {
+                continue;
+            };
+            if lo.is_invariant(&op) || !control.insert(inst) {
+                continue;
+            }
+            if let InstType::Load | InstType::Store | InstType::Call = inst.get_type() {
+                return false;
+            }
+            worklist.extend(inst.get_operand().iter().cloned());
+        }
+        let mut indvars = control
+            .iter()
+            .filter(|inst| inst.get_type() == InstType::Phi);
+        let (Some(indvar), None) = (indvars.next(), indvars.next()) else {
+            return false;
+        };
+        let indvar: Operand = (*indvar).into();
+
+        let (all, empty) = (EffectRange::All, EffectRange::new());
+        let get_effect = |inst: &InstPtr| match self.effect_analysis.inst_effect.get(inst) {
+            Some(effect) => Some((&effect.def_range, &effect.use_range)),
+            None if inst.get_type() == InstType::Call => Some((&all, &empty)),
+            None => None,
+        };
+
+        // Loop control and values computed from it or memory not written in loop
+        // can be recomputed by both loops
+        let mut loop_def = EffectRange::new();
+        for inst in head.iter().chain(body.iter()) {
+            if let Some((def, _)) = get_effect(&inst) {
+                loop_def.merge(def);
+            }
+        }
+        let mut shared = control.clone();
+        for inst in head.iter().chain(body.iter()) {
+            let can_share = match inst.get_type() {
+                InstType::Phi | InstType::Store | InstType::Call | InstType::Br => false,
+                InstType::Load => {
+                    get_effect(&inst).is_some_and(|(_, used)| !used.can_alias(&loop_def))
+                }
+                _ => true,
+            };
+            let operands_shared = inst.get_operand().iter().all(|op| match op {
+                Operand::Instruction(def) => lo.is_invariant(op) || shared.contains(def),
+                _ => true,
+            });
+            if can_share && operands_shared {
+                shared.insert(inst);
+            }
+        }
+
+        // Statements depending on each other through values or memory are in the same group
+        let stmts: Vec<InstPtr> = head
+            .iter()
+            .chain(body.iter())
+            .filter(|inst| inst.get_type() != InstType::Br && !shared.contains(inst))
+            .collect();
+        let index: HashMap<InstPtr, usize> = stmts
+            .iter()
+            .enumerate()
+            .map(|(i, inst)| (*inst, i))
+            .collect();
+        let mut group: Vec<usize> = (0..stmts.len()).collect();
+        for (i, inst) in stmts.iter().enumerate() {
+            for op in inst.get_operand() {
+                if let Operand::Instruction(def) = op {
+                    if let Some(j) = index.get(def) {
+                        union(&mut group, i, *j);
+                    }
+                }
+            }
+        }
+        let effects: Vec<_> = stmts.iter().map(get_effect).collect();
+        for i in 0..stmts.len() {
+            for j in i + 1..stmts.len() {
+                let (Some((def_i, use_i)), Some((def_j, use_j))) = (effects[i], effects[j]) else {
+                    continue;
+                };
+                let is_call = |k: usize| stmts[k].get_type() == InstType::Call;
+                if is_call(i)
+                    || is_call(j)
+                    || def_i.can_alias(def_j)
+                    || def_i.can_alias(use_j)
+                    || use_i.can_alias(def_j)
+                {
+                    union(&mut group, i, j);
+                }
+            }
+        }
+
+        // Groups with phis, calls or memory conflicts across iterations are sequential
+        let mut sequential: HashSet<usize> = HashSet::new();
+        for i in 0..stmts.len() {
+            let root = find(&mut group, i);
+            if let InstType::Phi | InstType::Call = stmts[i].get_type() {
+                sequential.insert(root);
+            }
+            let Some((def_i, use_i)) = effects[i] else {
+                continue;
+            };
+            for (def_j, use_j) in effects[i..].iter().flatten() {
+                if def_i.can_conflict(def_j, &indvar)
+                    || def_i.can_conflict(use_j, &indvar)
+                    || use_i.can_conflict(def_j, &indvar)
+                {
+                    sequential.insert(root);
+                }
+            }
+        }
+        let parallel: HashSet<InstPtr> = (0..stmts.len())
+            .filter(|i| !sequential.contains(&find(&mut group, *i)))
+            .map(|i| stmts[i])
+            .collect();
+        if sequential.is_empty()
+            || !parallel
+                .iter()
+                .any(|inst| inst.get_type() == InstType::Store)
+        {
+            return false;
+        }
+
+        self.copy_parallel_part(lo, &shared, &parallel);
+        pass_stats::bump(Self::name, "loops split", 1);
+        true
+    }
+
+    /// Copy loop before itself with only shared values and the parallel statements,
+    /// then remove the parallel statements and unused shared values from both loops.
+    fn copy_parallel_part(
+        &mut self,
+        lo: LoopPtr,
+        shared: &HashSet<InstPtr>,
+        parallel: &HashSet<InstPtr>,
+    ) {
+        let Some(shape) = get_while_loop(lo) else {
+            return;
+        };
+        let (mut preheader, head, body) = (shape.preheader, shape.head, shape.body);
+        let is_copied = |inst: InstPtr| {
+            inst.get_type() == InstType::Br || shared.contains(&inst) || parallel.contains(&inst)
+        };
+
+        // Copy blocks with only shared values and the parallel statements
+        let clone = RegionClone::new(
+            &mut self.program.mem_pool,
+            &[head, body],
+            "fission",
+            HashMap::new(),
+            is_copied,
+        );
+        let new_shared: HashSet<InstPtr> = shared
+            .iter()
+            .filter_map(|inst| match clone.operand_map.get(&(*inst).into()) {
+                Some(Operand::Instruction(new_inst)) => Some(*new_inst),
+                _ => None,
+            })
+            .collect();
+
+        // Preheader enters the copy, which exits to a new preheader of the original loop
+        let (new_head, new_body) = (clone.map_block(head), clone.map_block(body));
+        let mut new_preheader = new_block(&mut self.program.mem_pool, &preheader.name, "fission");
+        new_preheader.push_back(self.program.mem_pool.get_br(None));
+        clone.copy_edges(|succ| clone.block_map.get(&succ).cloned().or(Some(new_preheader)));
+        new_preheader.set_true_bb(head);
+        preheader.replace_succ_bb_only(head, new_head);
+        for mut inst in head.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            downcast_mut::<Phi>(inst.as_mut()).replace_incoming_value(preheader, new_preheader);
+        }
+
+        // Parallel statements used after loop take values from the copy, and are removed
+        let mut removed: Vec<InstPtr> = head
+            .iter()
+            .chain(body.iter())
+            .filter(|inst| parallel.contains(inst))
+            .collect();
+        for inst in removed.iter().cloned() {
+            let op: Operand = inst.into();
+            let mut users = inst.get_user().to_vec();
+            users.sort_by_key(|user| user.get_id());
+            users.dedup();
+            for mut user in users {
+                if user
+                    .get_parent_bb()
+                    .is_some_and(|bb| bb == head || bb == body)
+                {
+                    continue;
+                }
+                for index in 0..user.get_operand().len() {
+                    if user.get_operand()[index] == op {
+                        user.set_operand(index, clone.map_operand(&op));
+                    }
+                }
+            }
+        }
+        removed.reverse();
+        for mut inst in removed {
+            inst.remove_self();
+        }
+        for bb in [body, head, new_body, new_head] {
+            for mut inst in bb.iter_rev().collect::<Vec<_>>() {
+                let is_shared = match inst.get_type() {
+                    InstType::Br | InstType::Phi => false,
+                    _ => shared.contains(&inst) || new_shared.contains(&inst),
+                };
+                if is_shared && inst.get_user().is_empty() {
+                    inst.remove_self();
+                }
+            }
+        }
+        if let Some(mut parent) = lo.parent_loop {
+            parent.blocks.extend([new_head, new_body, new_preheader]);
+        }
+    }
+}
+
+/// Find representative of a group, compressing paths on the way.
+fn find(group: &mut [usize], mut i: usize) -> usize {
+    while group[i] != i {
+        group[i] = group[group[i]];
+        i = group[i];
+    }
+    i
+}
+
+/// Merge groups of two statements.
+fn union(group: &mut [usize], i: usize, j: usize) {
+    let (i, j) = (find(group, i), find(group, j));
+    group[i] = j;
+}
@@ -0,0 +1,398 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+
+use crate::{
+    analysis::{
+        effect_analysis::{Effect, EffectAnalysis},
+        loop_tools::{LoopForest, LoopPtr},
+        scalar_evolution::{get_exiting_block, ScalarEvolution, Scev},
+    },
+    ir::{
+        instruction::{
+            downcast_mut, downcast_ref, memory_op_inst::GetElementPtr, misc_inst::Phi, InstType,
+        },
+        BBPtr, InstPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats::{self, FuncTimer},
+    Transform,
+};
+
+/// Loops are not fused if the fused loop would have more instructions than this.
+const MAX_FUSED_SIZE: usize = 128;
+
+/// Fuse adjacent loops with the same trip count, where the second loop accesses memory
+/// written by the first loop in the same iteration.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let effect_analysis = EffectAnalysis::new(program);
+    LoopFusion::new(program, &effect_analysis).run_and_log()
+}
+
+pub struct LoopFusion<'a> {
+    program: &'a mut Program,
+    effect_analysis: &'a EffectAnalysis,
+}
+
+impl<'a> Transform for LoopFusion<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_fusion".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+            let mut loops = Vec::new();
+            loop_forest_post_order(&mut forest, |lo| {
+                loops.push(lo);
+                Ok(())
+            })?;
+
+            // Loop forest is not updated after fusion, so fuse at most one pair per run
+            let mut scev = ScalarEvolution::new();
+            for first in loops.iter().cloned() {
+                let Some(second) = self.get_fusion_candidate(first, &loops, &mut scev) else {
+                    continue;
+                };
+                self.fuse_loops(first, second)?;
+                changed = true;
+                break;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> LoopFusion<'a> {
+    pub fn new(program: &'a mut Program, effect_analysis: &'a EffectAnalysis) -> Self {
+        Self {
+            program,
+            effect_analysis,
+        }
+    }
+
+    /// Get the loop right after the given loop that can be fused into it.
+    fn get_fusion_candidate(
+        &self,
+        first: LoopPtr,
+        loops: &[LoopPtr],
+        scev: &mut ScalarEvolution,
+    ) -> Option<LoopPtr> {
+        let shape = get_while_loop(first)?;
+
+        // Exit of the first loop should be the preheader of the second loop, and only compute
+        // values independent of the first loop, which are moved before it
+        if shape.exit.get_pred_bb().len() != 1 {
+            return None;
+        }
+        for inst in shape.exit.iter() {
+            match inst.get_type() {
+                InstType::Br => (),
+                InstType::Load
+                | InstType::Store
+                | InstType::Call
+                | InstType::Phi
+                | InstType::Alloca
+                | InstType::Ret => return None,
+                _ if uses_loop(inst, first) => return None,
+                _ => (),
+            }
+        }
+        let second = *loops
+            .iter()
+            .find(|lo| lo.pre_header == Some(shape.exit) && lo.parent_loop == first.parent_loop)?;
+        get_while_loop(second)?;
+        let size: usize = [first, second]
+            .iter()
+            .flat_map(|lo| lo.blocks.iter())
+            .map(|bb| bb.iter().count())
+            .sum();
+        if size > MAX_FUSED_SIZE {
+            return None;
+        }
+
+        // Both loops should iterate the same number of times
+        let trip_count = scev.get_trip_count(first)?;
+        if scev.get_trip_count(second)? != trip_count {
+            return None;
+        }
+
+        // Second loop should not use values of the first loop, and values of its head
+        // should only be used outside of it if they are phis, which are moved to the fused head
+        for bb in second.blocks.iter() {
+            if bb.iter().any(|inst| uses_loop(inst, first)) {
+                return None;
+            }
+        }
+        for inst in second.head.iter() {
+            if inst.get_type() == InstType::Phi {
+                continue;
+            }
+            let used_outside = inst.get_user().iter().any(|user| {
+                user.get_parent_bb()
+                    .is_none_or(|user_bb| !second.is_in_loop(&user_bb))
+            });
+            if used_outside {
+                return None;
+            }
+        }
+
+        self.check_memory(first, second, scev).then_some(second)
+    }
+
+    /// Check if memory accessed by both loops is only accessed in the same iteration,
+    /// and at least one location written by a loop is accessed by the other.
+    fn check_memory(&self, first: LoopPtr, second: LoopPtr, scev: &mut ScalarEvolution) -> bool {
+        let Some(first_access) = self.get_memory_access(first) else {
+            return false;
+        };
+        let Some(second_access) = self.get_memory_access(second) else {
+            return false;
+        };
+        let mut reuse = false;
+        for (a, effect_a) in first_access.iter() {
+            for (b, effect_b) in second_access.iter() {
+                let conflict = effect_a.def_range.can_alias(&effect_b.def_range)
+                    || effect_a.def_range.can_alias(&effect_b.use_range)
+                    || effect_a.use_range.can_alias(&effect_b.def_range);
+                if !conflict {
+                    continue;
+                }
+                if !is_same_iteration_address(scev, a, first, b, second) {
+                    return false;
+                }
+                reuse = true;
+            }
+        }
+        reuse
+    }
+
+    /// Get address and effect of loads and stores in loop, or `None` if loop has calls.
+    fn get_memory_access(&self, lo: LoopPtr) -> Option<Vec<(Operand, &Effect)>> {
+        let mut access = Vec::new();
+        for bb in lo.get_all_blocks() {
+            for inst in bb.iter() {
+                let address = match inst.get_type() {
+                    InstType::Call => return None,
+                    InstType::Load => inst.get_operand()[0].clone(),
+                    InstType::Store => inst.get_operand()[1].clone(),
+                    _ => continue,
+                };
+                access.push((address, self.effect_analysis.inst_effect.get(&inst)?));
+            }
+        }
+        Some(access)
+    }
+
+    /// Turn `pre1 -> head1 -> body1 ... -> latch1 -> head1 -> pre2 -> head2 -> body2 ... -> latch2`
+    /// into `pre1 -> head1 -> body1 ... -> latch1 -> head2 -> body2 ... -> latch2 -> head1`,
+    /// where head2 no longer tests exit condition, and its phis are moved to head1.
+    fn fuse_loops(&mut self, first: LoopPtr, second: LoopPtr) -> Result<()> {
+        let (Some(shape1), Some(shape2)) = (get_while_loop(first), get_while_loop(second)) else {
+            return Ok(());
+        };
+        let (mut head1, mut head2) = (shape1.head, shape2.head);
+        let (mut latch1, mut latch2) = (shape1.latch, shape2.latch);
+        let (mut exit1, exit2) = (shape1.exit, shape2.exit);
+
+        // Both loops are entered from the first preheader, which computes values of the old one
+        let mut preheader_br = shape1.preheader.get_last_inst();
+        for mut inst in exit1.iter().collect::<Vec<_>>() {
+            if inst.get_type() == InstType::Br {
+                break;
+            }
+            unsafe {
+                inst.move_self();
+            }
+            preheader_br.insert_before(inst);
+        }
+        let phis1: Vec<InstPtr> = head1
+            .iter()
+            .take_while(|inst| inst.get_type() == InstType::Phi)
+            .collect();
+        let phis2: Vec<InstPtr> = head2
+            .iter()
+            .take_while(|inst| inst.get_type() == InstType::Phi)
+            .collect();
+        let mut first_non_phi = head1
+            .iter()
+            .find(|inst| inst.get_type() != InstType::Phi)
+            .unwrap_or(head1.get_last_inst());
+        for mut phi in phis2 {
+            unsafe {
+                phi.move_self();
+            }
+            first_non_phi.insert_before(phi);
+            downcast_mut::<Phi>(phi.as_mut()).replace_incoming_value(exit1, shape1.preheader);
+        }
+
+        // First latch continues to the second body, and the second latch becomes the back edge
+        latch1.replace_succ_bb_only(head1, head2);
+        latch2.replace_succ_bb_only(head2, head1);
+        for mut phi in phis1 {
+            downcast_mut::<Phi>(phi.as_mut()).replace_incoming_value(latch1, latch2);
+        }
+
+        // Fused loop exits from the first head to the second exit
+        head1.replace_succ_bb_only(exit1, exit2);
+        for mut inst in exit2.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            let phi = downcast_mut::<Phi>(inst.as_mut());
+            if let Some(value) = phi.get_incoming_value(head2).cloned() {
+                phi.add_incoming_value(value, head1);
+            }
+        }
+        exit1.remove_self();
+        if head2.get_succ_bb()[0] == shape2.body {
+            head2.remove_false_bb();
+        } else {
+            head2.remove_true_bb();
+        }
+        let mut br = head2.get_last_inst();
+        br.insert_after(self.program.mem_pool.get_br(None));
+        br.remove_self();
+
+        if let Some(mut parent) = first.parent_loop {
+            parent.blocks.remove(&exit1);
+        }
+        pass_stats::bump(Self::name, "loops fused", 1);
+        Ok(())
+    }
+}
+
+/// Blocks of a loop in while form.
+pub(crate) struct WhileLoop {
+    pub preheader: BBPtr,
+    pub head: BBPtr,
+    pub body: BBPtr,
+    pub latch: BBPtr,
+    pub exit: BBPtr,
+}
+
+/// Match an innermost loop `preheader -> head -> body ... -> latch -> head -> exit`,
+/// where head is the only exiting block, and latch jumps to head unconditionally.
+pub(crate) fn get_while_loop(lo: LoopPtr) -> Option<WhileLoop> {
+    if !lo.sub_loops.is_empty() {
+        return None;
+    }
+    let preheader = lo.pre_header?;
+    let head = lo.head;
+    let head_pred = head.get_pred_bb();
+    if head_pred.len() != 2 || get_exiting_block(lo)? != head {
+        return None;
+    }
+    let latch = *head_pred.iter().find(|bb| **bb != preheader)?;
+    if latch == head || latch.get_succ_bb().len() != 1 {
+        return None;
+    }
+    let head_succ = head.get_succ_bb();
+    if head_succ.len() != 2 {
+        return None;
+    }
+    let (body, exit) = match (lo.is_in_loop(&head_succ[0]), lo.is_in_loop(&head_succ[1])) {
+        (true, false) => (head_succ[0], head_succ[1]),
+        (false, true) => (head_succ[1], head_succ[0]),
+        _ => return None,
+    };
+    Some(WhileLoop {
+        preheader,
+        head,
+        body,
+        latch,
+        exit,
+    })
+}
+
+/// Check if instruction uses values defined in loop.
+fn uses_loop(inst: InstPtr, lo: LoopPtr) -> bool {
+    inst.get_operand().iter().any(|op| match op {
+        Operand::Instruction(def) => def
+            .get_parent_bb()
+            .is_some_and(|def_bb| lo.is_in_loop(&def_bb)),
+        _ => false,
+    })
+}
+
+/// Check if two addresses are the same in the same iteration of their loops, and differ
+/// across iterations. GEP indices are compared by evolution in their own loops.
+fn is_same_iteration_address(
+    scev: &mut ScalarEvolution,
+    a: &Operand,
+    lo_a: LoopPtr,
+    b: &Operand,
+    lo_b: LoopPtr,
+) -> bool {
+    let (mut a, mut b) = (a.clone(), b.clone());
+    let mut varying = false;
+    loop {
+        if a == b {
+            return varying && lo_a.is_invariant(&a) && lo_b.is_invariant(&b);
+        }
+        let (Operand::Instruction(gep_a), Operand::Instruction(gep_b)) = (&a, &b) else {
+            return false;
+        };
+        if gep_a.get_type() != InstType::GetElementPtr
+            || gep_b.get_type() != InstType::GetElementPtr
+            || gep_a.get_operand().len() != gep_b.get_operand().len()
+            || downcast_ref::<GetElementPtr>(gep_a.as_ref().as_ref()).element_type
+                != downcast_ref::<GetElementPtr>(gep_b.as_ref().as_ref()).element_type
+        {
+            return false;
+        }
+        for (x, y) in gep_a.get_operand().iter().zip(gep_b.get_operand()).skip(1) {
+            match (scev.get_scev(x, lo_a), scev.get_scev(y, lo_b)) {
+                (
+                    Scev::AddRec { start, step },
+                    Scev::AddRec {
+                        start: start_b,
+                        step: step_b,
+                    },
+                ) if start == start_b && step == step_b => {
+                    if step.get_constant().is_some_and(|c| c != 0) {
+                        varying = true;
+                    }
+                }
+                (Scev::Invariant(x), Scev::Invariant(y)) if x == y => (),
+                _ => return false,
+            }
+        }
+        let (base_a, base_b) = (
+            gep_a.get_operand()[0].clone(),
+            gep_b.get_operand()[0].clone(),
+        );
+        (a, b) = (base_a, base_b);
+    }
+}
@@ -0,0 +1,472 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::Cell;
+
+use anyhow::Result;
+
+use crate::{
+    analysis::{
+        dependence_analysis::{DependenceAnalysis, Direction},
+        loop_tools::{LoopForest, LoopPtr},
+        scalar_evolution::{get_exiting_block, ScalarEvolution},
+    },
+    ir::{
+        instruction::{
+            downcast_mut, downcast_ref,
+            misc_inst::{ICmp, ICmpOp, Phi},
+            InstType,
+        },
+        BBPtr, Constant, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats::{self, FuncTimer},
+    region_clone::new_block,
+    Transform,
+};
+
+/// Cost of an access whose address jumps over other elements in each iteration.
+const NON_UNIT_STRIDE_COST: usize = 8;
+
+thread_local! {
+    static TILE_SIZE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Set number of inner iterations in each tile of a loop nest on current thread,
+/// or `None` to disable tiling.
+pub fn set_tile_size(tile_size: Option<usize>) {
+    TILE_SIZE.with(|t| t.set(tile_size));
+}
+
+/// Interchange perfectly nested loops so that the innermost loop accesses memory
+/// with unit stride, and tile them if tile size is set.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let tile_size = TILE_SIZE.with(|t| t.get());
+    LoopInterchange::new(program, tile_size).run_and_log()
+}
+
+pub struct LoopInterchange<'a> {
+    program: &'a mut Program,
+    tile_size: Option<usize>,
+}
+
+impl<'a> Transform for LoopInterchange<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_interchange".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+            let mut innermost = Vec::new();
+            loop_forest_post_order(&mut forest, |lo| {
+                if lo.sub_loops.is_empty() {
+                    innermost.push(lo);
+                }
+                Ok(())
+            })?;
+
+            // Interchanging only moves instructions between blocks, and tiling only adds
+            // blocks around a nest, so other nests remain valid. Analysis is rebuilt instead
+            // of updated after each change.
+            let mut dependence = DependenceAnalysis::new(&forest);
+            for inner in innermost {
+                let Some(nest) = get_perfect_nest(inner) else {
+                    continue;
+                };
+                if !is_interchange_legal(&mut dependence, &nest) {
+                    continue;
+                }
+                if get_cost(&mut dependence, &nest, nest.outer)
+                    < get_cost(&mut dependence, &nest, nest.inner)
+                {
+                    interchange(&nest);
+                    pass_stats::bump(Self::name, "loops interchanged", 1);
+                    changed = true;
+                    dependence = DependenceAnalysis::new(&forest);
+                }
+                if let Some(tile_size) = self.tile_size {
+                    if self.tile(inner, tile_size) {
+                        pass_stats::bump(Self::name, "loop nests tiled", 1);
+                        changed = true;
+                        dependence = DependenceAnalysis::new(&forest);
+                    }
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> LoopInterchange<'a> {
+    pub fn new(program: &'a mut Program, tile_size: Option<usize>) -> Self {
+        Self { program, tile_size }
+    }
+
+    /// Split inner loop `for (y = init; y < bound; y += step)` of a nest into tiles,
+    /// `for (t = init; t < bound; t += step * size)` runs the nest with inner loop
+    /// `for (y = t; y < min(t + step * size, bound); y += step)`.
+    /// Tile loop is entered from the old preheader, and exits to the old exit of the nest.
+    fn tile(&mut self, inner: LoopPtr, size: usize) -> bool {
+        let Some(nest) = get_perfect_nest(inner) else {
+            return false;
+        };
+        let control = &nest.inner_control;
+        let step = match control.step.checked_mul(size as i32) {
+            Some(step) if control.step > 0 && step > 0 => step,
+            _ => return false,
+        };
+        let cmp = downcast_ref::<ICmp>(control.cmp.as_ref().as_ref());
+        let bound = control.cmp.get_operand()[1].clone();
+        if cmp.op != ICmpOp::Slt
+            || control.cmp.get_operand()[0] != control.phi.into()
+            || !nest.enter_on_true
+        {
+            return false;
+        }
+        let trip_count = ScalarEvolution::new().get_trip_count(inner);
+        if trip_count.is_some_and(|count| count.get_constant().is_some_and(|c| c <= size)) {
+            return false;
+        }
+
+        // Nest should exit to a block without phis, which then becomes exit of tile loop
+        let outer = nest.outer;
+        let (Some(mut preheader), mut head) = (outer.pre_header, outer.head) else {
+            return false;
+        };
+        let Some(exit) = head
+            .get_succ_bb()
+            .iter()
+            .find(|bb| !outer.is_in_loop(bb))
+            .cloned()
+        else {
+            return false;
+        };
+        if exit.get_first_inst().get_type() == InstType::Phi {
+            return false;
+        }
+
+        // Build tile loop
+        let mem_pool = &mut self.program.mem_pool;
+        let mut tile_head = new_block(mem_pool, &head.name, "tile");
+        let mut tile_preheader = new_block(mem_pool, &head.name, "tile");
+        let mut tile_latch = new_block(mem_pool, &head.name, "tile");
+        let mut tile = mem_pool.get_phi(ValueType::Int, vec![(control.init.clone(), preheader)]);
+        let tile_cmp = mem_pool.get_icmp(ICmpOp::Slt, ValueType::Int, tile.into(), bound.clone());
+        tile_head.push_back(tile);
+        tile_head.push_back(tile_cmp);
+        tile_head.push_back(mem_pool.get_br(Some(tile_cmp.into())));
+        let end = mem_pool.get_add(tile.into(), Constant::Int(step).into());
+        let in_bound = mem_pool.get_icmp(ICmpOp::Slt, ValueType::Int, end.into(), bound.clone());
+        let upper = mem_pool.get_select(in_bound.into(), end.into(), bound.clone());
+        tile_preheader.push_back(end);
+        tile_preheader.push_back(in_bound);
+        tile_preheader.push_back(upper);
+        tile_preheader.push_back(mem_pool.get_br(None));
+        let next = mem_pool.get_add(tile.into(), Constant::Int(step).into());
+        tile_latch.push_back(next);
+        tile_latch.push_back(mem_pool.get_br(None));
+
+        // Link tile loop around the nest
+        preheader.replace_succ_bb_only(head, tile_head);
+        head.replace_succ_bb_only(exit, tile_latch);
+        tile_head.set_true_bb(tile_preheader);
+        tile_head.set_false_bb(exit);
+        tile_preheader.set_true_bb(head);
+        tile_latch.set_true_bb(tile_head);
+        downcast_mut::<Phi>(tile.as_mut()).add_incoming_value(next.into(), tile_latch);
+        for mut inst in head.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            downcast_mut::<Phi>(inst.as_mut()).replace_incoming_value(preheader, tile_preheader);
+        }
+
+        // Inner loop iterates in current tile
+        let mut phi = control.phi;
+        let mut cmp = control.cmp;
+        downcast_mut::<Phi>(phi.as_mut()).replace_incoming_value_at(control.preheader, tile.into());
+        cmp.replace_operand(&bound, &upper.into());
+        if let Some(mut parent) = outer.parent_loop {
+            parent
+                .blocks
+                .extend([tile_head, tile_preheader, tile_latch]);
+        }
+        true
+    }
+}
+
+/// Instructions controlling loop `for (phi = init; cmp; phi = inc)`, where head only
+/// contains `phi`, `cmp` and its branch.
+struct Control {
+    phi: InstPtr,
+    cmp: InstPtr,
+    inc: InstPtr,
+    init: Operand,
+    step: i32,
+    preheader: BBPtr,
+    latch: BBPtr,
+}
+
+/// Loop with a single sub loop, where blocks out of the sub loop only run the outer control.
+struct PerfectNest {
+    outer: LoopPtr,
+    inner: LoopPtr,
+    outer_control: Control,
+    inner_control: Control,
+    /// Both loops enter their body when condition is true, or both when it is false
+    enter_on_true: bool,
+}
+
+/// Get perfect nest with given loop as inner loop, where bounds of inner loop
+/// are invariant in outer loop.
+fn get_perfect_nest(inner: LoopPtr) -> Option<PerfectNest> {
+    let outer = inner.parent_loop?;
+    if outer.sub_loops.len() != 1 || !inner.sub_loops.is_empty() {
+        return None;
+    }
+    let (outer_control, outer_on_true) = get_control(outer, outer, inner)?;
+    let (inner_control, inner_on_true) = get_control(inner, outer, inner)?;
+    if outer_on_true != inner_on_true {
+        return None;
+    }
+
+    // Outer blocks out of inner loop only jump and increment outer induction variable
+    for bb in outer.blocks.iter() {
+        if *bb == outer.head {
+            continue;
+        }
+        for inst in bb.iter() {
+            let is_jump = inst.get_type() == InstType::Br && bb.get_succ_bb().len() == 1;
+            if !is_jump && inst != outer_control.inc {
+                return None;
+            }
+        }
+    }
+
+    // Values of inner loop are only used in inner loop, and inner loop has no calls
+    for bb in inner.blocks.iter() {
+        for inst in bb.iter() {
+            if inst.get_type() == InstType::Call {
+                return None;
+            }
+            let used_outside = inst.get_user().iter().any(|user| {
+                user.get_parent_bb()
+                    .is_none_or(|user_bb| !inner.is_in_loop(&user_bb))
+            });
+            if used_outside {
+                return None;
+            }
+        }
+    }
+    Some(PerfectNest {
+        outer,
+        inner,
+        outer_control,
+        inner_control,
+        enter_on_true: inner_on_true,
+    })
+}
+
+/// Get control of loop in nest whose bounds are invariant in `outer`, and whether
+/// loop enters body when condition is true.
+fn get_control(lo: LoopPtr, outer: LoopPtr, inner: LoopPtr) -> Option<(Control, bool)> {
+    let preheader = lo.pre_header?;
+    let head = lo.head;
+    if get_exiting_block(lo)? != head {
+        return None;
+    }
+    let latch = head
+        .get_pred_bb()
+        .iter()
+        .find(|bb| lo.is_in_loop(bb))
+        .cloned()?;
+    if latch == head {
+        return None;
+    }
+    let insts: Vec<InstPtr> = head.iter().collect();
+    let [phi, cmp, br] = insts[..] else {
+        return None;
+    };
+    if phi.get_type() != InstType::Phi
+        || cmp.get_type() != InstType::ICmp
+        || br.get_operand() != [cmp.into()]
+        || cmp.get_user() != [br]
+    {
+        return None;
+    }
+
+    // Condition compares induction variable with an invariant bound
+    let phi_op: Operand = phi.into();
+    let cmp_ops = cmp.get_operand();
+    let bound = if cmp_ops[0] == phi_op {
+        &cmp_ops[1]
+    } else if cmp_ops[1] == phi_op {
+        &cmp_ops[0]
+    } else {
+        return None;
+    };
+    if !outer.is_invariant(bound) {
+        return None;
+    }
+
+    // Induction variable is only incremented by a constant
+    let phi_inst = downcast_ref::<Phi>(phi.as_ref().as_ref());
+    let init = phi_inst.get_incoming_value(preheader)?.clone();
+    let Operand::Instruction(inc) = phi_inst.get_incoming_value(latch)?.clone() else {
+        return None;
+    };
+    if !outer.is_invariant(&init) || inc.get_type() != InstType::Add || inc.get_user() != [phi] {
+        return None;
+    }
+    let step = match inc.get_operand() {
+        [op, Operand::Constant(Constant::Int(step))] if *op == phi_op => *step,
+        [Operand::Constant(Constant::Int(step)), op] if *op == phi_op => *step,
+        _ => return None,
+    };
+
+    // Induction variable is only used by control and inner loop
+    for user in phi.get_user() {
+        let in_inner = user
+            .get_parent_bb()
+            .is_some_and(|bb| inner.is_in_loop(&bb) && bb != inner.head);
+        if *user != cmp && *user != inc && !in_inner {
+            return None;
+        }
+    }
+    let enter_on_true = lo.is_in_loop(&head.get_succ_bb()[0]);
+    Some((
+        Control {
+            phi,
+            cmp,
+            inc,
+            init,
+            step,
+            preheader,
+            latch,
+        },
+        enter_on_true,
+    ))
+}
+
+/// Check if running inner loop outside preserves order of dependent accesses, which requires
+/// no dependence to run forward in one loop and backward in the other.
+fn is_interchange_legal(dependence: &mut DependenceAnalysis, nest: &PerfectNest) -> bool {
+    let accesses = get_accesses(nest.inner);
+    for (i, src) in accesses.iter().enumerate() {
+        for dst in accesses[i..].iter() {
+            if src.get_type() != InstType::Store && dst.get_type() != InstType::Store {
+                continue;
+            }
+            let Some(dep) = dependence.get_dependence(*src, *dst) else {
+                continue;
+            };
+            let Some(level) = dep.get_level(nest.outer) else {
+                return false;
+            };
+            let reversed = dep.directions.iter().any(|dv| {
+                dv[..level].iter().all(|d| *d == Direction::Eq)
+                    && matches!(
+                        (dv[level], dv[level + 1]),
+                        (Direction::Lt, Direction::Gt) | (Direction::Gt, Direction::Lt)
+                    )
+            });
+            if reversed {
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Get cost of memory accesses in nest if `lo` is the innermost loop. Accesses invariant
+/// in `lo` are free, and accesses to adjacent elements are cheaper than others.
+fn get_cost(dependence: &mut DependenceAnalysis, nest: &PerfectNest, lo: LoopPtr) -> usize {
+    get_accesses(nest.inner)
+        .into_iter()
+        .map(|inst| match dependence.get_stride(inst, lo) {
+            Some(0) => 0,
+            Some(1 | -1) | None => 1,
+            Some(_) => NON_UNIT_STRIDE_COST,
+        })
+        .sum()
+}
+
+fn get_accesses(lo: LoopPtr) -> Vec<InstPtr> {
+    lo.get_all_blocks()
+        .iter()
+        .flat_map(|bb| bb.iter())
+        .filter(|inst| matches!(inst.get_type(), InstType::Load | InstType::Store))
+        .collect()
+}
+
+/// Swap control of outer and inner loops, so that the outer head iterates the inner
+/// induction variable, and the inner head iterates the outer one.
+fn interchange(nest: &PerfectNest) {
+    let (outer, inner) = (&nest.outer_control, &nest.inner_control);
+    let (mut outer_head, mut inner_head) = (nest.outer.head, nest.inner.head);
+    let (mut outer_phi, mut inner_phi) = (outer.phi, inner.phi);
+    let (mut outer_cmp, mut inner_cmp) = (outer.cmp, inner.cmp);
+    let (mut outer_inc, mut inner_inc) = (outer.inc, inner.inc);
+    let (mut outer_br, mut inner_br) = (outer_head.get_last_inst(), inner_head.get_last_inst());
+    for inst in [
+        &mut outer_phi,
+        &mut inner_phi,
+        &mut outer_cmp,
+        &mut inner_cmp,
+        &mut outer_inc,
+        &mut inner_inc,
+    ] {
+        unsafe {
+            inst.move_self();
+        }
+    }
+
+    // Move phis and conditions across heads
+    outer_head.push_front(inner_phi);
+    inner_head.push_front(outer_phi);
+    outer_br.insert_before(inner_cmp);
+    inner_br.insert_before(outer_cmp);
+    outer_br.replace_operand(&outer_cmp.into(), &inner_cmp.into());
+    inner_br.replace_operand(&inner_cmp.into(), &outer_cmp.into());
+    let outer_phi_mut = downcast_mut::<Phi>(outer_phi.as_mut());
+    outer_phi_mut.replace_incoming_value(outer.preheader, inner.preheader);
+    outer_phi_mut.replace_incoming_value(outer.latch, inner.latch);
+    let inner_phi_mut = downcast_mut::<Phi>(inner_phi.as_mut());
+    inner_phi_mut.replace_incoming_value(inner.preheader, outer.preheader);
+    inner_phi_mut.replace_incoming_value(inner.latch, outer.latch);
+
+    // Move increments across latches
+    outer.latch.get_last_inst().insert_before(inner_inc);
+    inner.latch.get_last_inst().insert_before(outer_inc);
+}
@@ -0,0 +1,257 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::loop_tools::{LoopForest, LoopPtr},
+    context,
+    ir::{
+        instruction::{downcast_mut, downcast_ref, misc_inst::Phi, InstType},
+        BBPtr, FunPtr, InstPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    lcssa::{get_exits, LCSSA},
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats::{self, FuncTimer},
+    region_clone::RegionClone,
+    Transform,
+};
+
+/// Maximum number of iterations peeled from a loop.
+const MAX_PEEL_COUNT: usize = 2;
+
+/// Maximum number of instructions copied when peeling a loop.
+const MAX_PEEL_SIZE: usize = 64;
+
+/// Peel the first iterations of loops, after which some head phis become invariant.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    LoopPeel::new(program).run_and_log()
+}
+
+pub struct LoopPeel<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for LoopPeel<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_peel".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+
+            // Copies of sub loops are not in loop forest, so peel at most one loop per run
+            let mut peeled = false;
+            loop_forest_post_order(&mut forest, |lo| {
+                if peeled {
+                    return Ok(());
+                }
+                let count = get_peel_count(lo);
+                let size: usize = lo.get_all_blocks().iter().map(|bb| bb.iter().count()).sum();
+                if count == 0 || size * count > MAX_PEEL_SIZE {
+                    return Ok(());
+                }
+                self.peel_loop(func, lo, count)?;
+                peeled = true;
+                Ok(())
+            })?;
+            changed |= peeled;
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> LoopPeel<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    /// Peel the first `count` iterations of loop, which should have a preheader and a latch
+    /// ending with an unconditional jump. Loop is kept in LCSSA form with dedicated exits.
+    pub fn peel_loop(&mut self, func: FunPtr, lo: LoopPtr, count: usize) -> Result<()> {
+        LCSSA::new(&mut self.program.mem_pool).form_loop(func, lo)?;
+        for _ in 0..count {
+            self.peel_one(lo)?;
+        }
+        pass_stats::bump(Self::name, "loops peeled", 1);
+        pass_stats::bump(Self::name, "iterations peeled", count);
+        Ok(())
+    }
+
+    /// Copy loop for one iteration before it, where head phis take initial values,
+    /// and latch of the copy becomes the new preheader.
+    fn peel_one(&mut self, mut lo: LoopPtr) -> Result<()> {
+        let (mut preheader, latch) = get_preheader_and_latch(lo)
+            .ok_or_else(|| anyhow!("loop {} is not simplified", lo.head.name))
+            .with_context(|| context!())?;
+        let head = lo.head;
+        let blocks = lo.get_all_blocks();
+
+        // Head phis take initial values in the copy
+        let mut operand_map: HashMap<Operand, Operand> = HashMap::new();
+        for inst in head.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            let init = downcast_ref::<Phi>(inst.as_ref().as_ref())
+                .get_incoming_value(preheader)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} has no incoming value from preheader", inst))
+                .with_context(|| context!())?;
+            operand_map.insert(inst.into(), init);
+        }
+
+        // Copy blocks, where backedge of the copy enters loop, and exit phis are extended
+        let clone = RegionClone::new(
+            &mut self.program.mem_pool,
+            &blocks,
+            "peel",
+            operand_map,
+            |_| true,
+        );
+        clone.copy_edges(|succ| {
+            Some(if succ == head {
+                head
+            } else {
+                clone.map_block(succ)
+            })
+        });
+        for exit in get_exits(lo) {
+            clone.extend_phis(exit);
+        }
+
+        // Preheader enters the copy, and head phis take values from the copy
+        let new_latch = clone.map_block(latch);
+        preheader.replace_succ_bb_only(head, clone.map_block(head));
+        for mut inst in head.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            let next = downcast_ref::<Phi>(inst.as_ref().as_ref())
+                .get_incoming_value(latch)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} has no incoming value from latch", inst))
+                .with_context(|| context!())?;
+            let phi = downcast_mut::<Phi>(inst.as_mut());
+            phi.replace_incoming_value(preheader, new_latch);
+            phi.replace_incoming_value_at(new_latch, clone.map_operand(&next));
+        }
+
+        lo.pre_header = Some(new_latch);
+        if let Some(mut parent) = lo.parent_loop {
+            parent.blocks.extend(clone.block_map.values());
+        }
+        LCSSA::new(&mut self.program.mem_pool).insert_dedicated_exits(lo)
+    }
+}
+
+/// Get preheader and latch of loop, where preheader and latch should be the only predecessors
+/// of head, and latch should jump to head unconditionally.
+fn get_preheader_and_latch(lo: LoopPtr) -> Option<(BBPtr, BBPtr)> {
+    let preheader = lo.pre_header?;
+    let head_pred = lo.head.get_pred_bb();
+    if head_pred.len() != 2 {
+        return None;
+    }
+    let latch = *head_pred.iter().find(|bb| **bb != preheader)?;
+    if latch == lo.head || latch.get_succ_bb().len() != 1 {
+        return None;
+    }
+    Some((preheader, latch))
+}
+
+/// Get number of iterations to peel, after which some head phis become invariant.
+/// A phi becomes invariant after one iteration if its value from latch is invariant,
+/// or after one more iteration than the phi it takes from latch.
+fn get_peel_count(lo: LoopPtr) -> usize {
+    let Some((preheader, latch)) = get_preheader_and_latch(lo) else {
+        return 0;
+    };
+    let phis: Vec<InstPtr> = lo
+        .head
+        .iter()
+        .take_while(|inst| inst.get_type() == InstType::Phi)
+        .collect();
+
+    // Phi with all incoming values equal is seen as that value
+    let resolve = |op: Operand| -> Operand {
+        match op {
+            Operand::Instruction(inst) if phis.contains(&inst) => {
+                let incoming = downcast_ref::<Phi>(inst.as_ref().as_ref()).get_incoming_values();
+                if incoming.iter().all(|(val, _)| *val == incoming[0].0) {
+                    incoming[0].0.clone()
+                } else {
+                    Operand::Instruction(inst)
+                }
+            }
+            _ => op,
+        }
+    };
+    let get_next = |phi: InstPtr| {
+        let phi = downcast_ref::<Phi>(phi.as_ref().as_ref());
+        phi.get_incoming_value(latch).cloned().map(resolve)
+    };
+
+    let mut count = 0;
+    for phi in phis.iter().cloned() {
+        let init = downcast_ref::<Phi>(phi.as_ref().as_ref()).get_incoming_value(preheader);
+        let Some(mut next) = get_next(phi) else {
+            continue;
+        };
+        if init == Some(&next) {
+            continue;
+        }
+
+        // Follow phis taken from latch, until reaching an invariant value
+        let mut depth = 1;
+        while !lo.is_invariant(&next) && depth <= MAX_PEEL_COUNT {
+            match next {
+                Operand::Instruction(inst) if phis.contains(&inst) => {
+                    next = match get_next(inst) {
+                        Some(next) => next,
+                        None => break,
+                    };
+                    depth += 1;
+                }
+                _ => break,
+            }
+        }
+        if lo.is_invariant(&next) && depth <= MAX_PEEL_COUNT {
+            count = count.max(depth);
+        }
+    }
+    count
+}
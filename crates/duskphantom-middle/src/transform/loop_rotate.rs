@@ -0,0 +1,250 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::loop_tools::{LoopForest, LoopPtr},
+    context,
+    ir::{
+        instruction::{downcast_mut, downcast_ref, misc_inst::Phi, InstType},
+        FunPtr, InstPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    lcssa::{get_outside_uses, LCSSA},
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats::{self, FuncTimer},
+    region_clone::{copy_with_operands, new_block},
+    Transform,
+};
+
+/// Headers with more instructions than this are not duplicated.
+const MAX_HEADER_SIZE: usize = 16;
+
+/// Rotate loops from while form into do-while form guarded by a single test.
+/// Runs after other loop passes, because `loop_unroll` and `make_parallel` match loops in while form.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    LoopRotate::new(program).run_and_log()
+}
+
+pub struct LoopRotate<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for LoopRotate<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_rotate".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+            loop_forest_post_order(&mut forest, |lo| {
+                changed |= self.rotate_loop(func, lo)?;
+                Ok(())
+            })?;
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> LoopRotate<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    /// Turn `preheader -> head -> body ... -> latch -> head`, where head tests exit condition,
+    /// into `preheader -> new preheader -> body ... -> latch -> head -> body`,
+    /// where preheader tests exit condition on initial values, and head becomes the new latch.
+    fn rotate_loop(&mut self, func: FunPtr, mut lo: LoopPtr) -> Result<bool> {
+        let Some(mut preheader) = lo.pre_header else {
+            return Ok(false);
+        };
+        let head = lo.head;
+
+        // Head should exit loop, and latch should not
+        let head_pred = head.get_pred_bb().clone();
+        if head_pred.len() != 2 {
+            return Ok(false);
+        }
+        let latch = if head_pred[0] == preheader {
+            head_pred[1]
+        } else {
+            head_pred[0]
+        };
+        if latch == head || latch.get_succ_bb().len() != 1 {
+            return Ok(false);
+        }
+        let head_succ = head.get_succ_bb().clone();
+        if head_succ.len() != 2 || head.iter().count() > MAX_HEADER_SIZE {
+            return Ok(false);
+        }
+        let mut body = match (lo.is_in_loop(&head_succ[0]), lo.is_in_loop(&head_succ[1])) {
+            (true, false) => head_succ[0],
+            (false, true) => head_succ[1],
+            _ => return Ok(false),
+        };
+        if body.get_pred_bb().len() != 1 {
+            return Ok(false);
+        }
+
+        // Values of head only leave loop through exit phis, and exit may be split
+        LCSSA::new(&mut self.program.mem_pool).form_loop(func, lo)?;
+        let head_succ = head.get_succ_bb().clone();
+        let exit = if head_succ[0] == body {
+            head_succ[1]
+        } else {
+            head_succ[0]
+        };
+
+        // Copy head into preheader as guard, where head phis take initial values
+        let mut map: HashMap<Operand, Operand> = HashMap::new();
+        let mut head_values = Vec::new();
+        let mut tail = preheader.get_last_inst();
+        for inst in head.iter() {
+            match inst.get_type() {
+                InstType::Br => break,
+                InstType::Phi => {
+                    let init = downcast_ref::<Phi>(inst.as_ref().as_ref())
+                        .get_incoming_value(preheader)
+                        .cloned()
+                        .ok_or_else(|| anyhow!("{} has no incoming value from preheader", inst))
+                        .with_context(|| context!())?;
+                    map.insert(inst.into(), init);
+                }
+                _ => {
+                    let operands = inst.get_operand().iter().map(|op| map_operand(&map, op));
+                    let new_inst = copy_with_operands(&mut self.program.mem_pool, inst, operands);
+                    tail.insert_before(new_inst);
+                    map.insert(inst.into(), new_inst.into());
+                }
+            }
+            head_values.push(inst);
+        }
+
+        // Preheader enters body through a new preheader, or skips loop
+        let cond = map_operand(&map, &head.get_last_inst().get_operand()[0]);
+        let mut new_preheader = new_block(&mut self.program.mem_pool, &preheader.name, "rotate");
+        new_preheader.push_back(self.program.mem_pool.get_br(None));
+        if head_succ[0] == body {
+            preheader.replace_succ_bb_only(head, new_preheader);
+            preheader.set_false_bb(exit);
+        } else {
+            preheader.replace_succ_bb_only(head, exit);
+            preheader.set_false_bb(new_preheader);
+        }
+        new_preheader.set_true_bb(body);
+        tail.insert_after(self.program.mem_pool.get_br(Some(cond)));
+        tail.remove_self();
+        for (bb, pred) in [(exit, preheader), (body, new_preheader)] {
+            for mut inst in bb.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                let phi = downcast_mut::<Phi>(inst.as_mut());
+                if let Some(value) = phi.get_incoming_value(head).cloned() {
+                    phi.add_incoming_value(map_operand(&map, &value), pred);
+                }
+            }
+        }
+
+        // Outside of head, values of head come from guard or the previous head through body phis
+        let head_set = HashSet::from([head]);
+        let mut body_phis: Vec<(InstPtr, InstPtr)> = Vec::new();
+        for inst in head_values.iter().cloned() {
+            let mut users = inst.get_user().to_vec();
+            users.sort_by_key(|user| user.get_id());
+            users.dedup();
+            let op: Operand = inst.into();
+            let mut body_phi = None;
+            for mut user in users {
+                for (_, incoming) in get_outside_uses(inst, user, &head_set) {
+                    let phi = *body_phi.get_or_insert_with(|| {
+                        let init = map_operand(&map, &op);
+                        self.program
+                            .mem_pool
+                            .get_phi(inst.get_value_type(), vec![(init, new_preheader)])
+                    });
+                    match incoming {
+                        Some(pred) => downcast_mut::<Phi>(user.as_mut())
+                            .replace_incoming_value_at(pred, phi.into()),
+                        None => {
+                            for index in 0..user.get_operand().len() {
+                                if user.get_operand()[index] == op {
+                                    user.set_operand(index, phi.into());
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some(phi) = body_phi {
+                body.push_front(phi);
+                body_phis.push((inst, phi));
+            }
+        }
+
+        // Head now only runs after latch, so its phis take values from latch
+        let mut next_values: HashMap<InstPtr, Operand> = HashMap::new();
+        for mut inst in head_values {
+            if inst.get_type() != InstType::Phi {
+                next_values.insert(inst, inst.into());
+                continue;
+            }
+            let next = downcast_ref::<Phi>(inst.as_ref().as_ref())
+                .get_incoming_value(latch)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} has no incoming value from latch", inst))
+                .with_context(|| context!())?;
+            inst.replace_self(&next);
+            next_values.insert(inst, next);
+        }
+        for (inst, mut phi) in body_phis {
+            downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(next_values[&inst].clone(), head);
+        }
+
+        lo.head = body;
+        lo.pre_header = Some(new_preheader);
+        if let Some(mut parent) = lo.parent_loop {
+            parent.blocks.insert(new_preheader);
+        }
+        LCSSA::new(&mut self.program.mem_pool).insert_dedicated_exits(lo)?;
+        pass_stats::bump(Self::name, "loops rotated", 1);
+        Ok(true)
+    }
+}
+
+fn map_operand(map: &HashMap<Operand, Operand>, op: &Operand) -> Operand {
+    map.get(op).cloned().unwrap_or(op.clone())
+}
@@ -22,7 +22,7 @@ use crate::{
     analysis::loop_tools::{LoopForest, LoopPtr},
     ir::{
         instruction::{downcast_mut, misc_inst::Phi, InstType},
-        Instruction, Operand,
+        BBPtr, Instruction, Operand,
     },
     transform::loop_optimization::loop_forest_post_order,
     IRBuilder,
@@ -54,20 +54,14 @@ impl<'a> LoopSimplifier<'a> {
 
     fn insert_unique_backedge_block(&mut self, mut lo: LoopPtr) -> Result<()> {
         let head = lo.head;
-        let backedge_blocks_index = head
+        let backedge_blocks = head
             .get_pred_bb()
             .iter()
-            .enumerate()
-            .filter_map(|(index, &bb)| {
-                if bb != lo.pre_header.unwrap() {
-                    Some(index)
-                } else {
-                    None
-                }
-            })
+            .filter(|&&bb| bb != lo.pre_header.unwrap())
+            .cloned()
             .collect::<Vec<_>>();
 
-        if backedge_blocks_index.len() == 1 {
+        if backedge_blocks.len() == 1 {
             return Ok(());
         }
 
@@ -81,10 +75,7 @@ impl<'a> LoopSimplifier<'a> {
         while let InstType::Phi = inst.get_type() {
             let phi = downcast_mut::<Phi>(inst.as_mut());
 
-            let incoming_values = backedge_blocks_index
-                .iter()
-                .map(|index| phi.get_incoming_values()[*index].clone())
-                .collect::<Vec<_>>();
+            let incoming_values = take_incoming_values(phi, &backedge_blocks);
 
             let new_phi = self
                 .ir_builder
@@ -92,10 +83,6 @@ impl<'a> LoopSimplifier<'a> {
 
             tail.insert_before(new_phi);
 
-            for (i, index) in backedge_blocks_index.iter().enumerate() {
-                phi.get_incoming_values_mut().remove(index - i);
-                unsafe { phi.get_manager_mut().remove_operand(index - i) };
-            }
             phi.add_incoming_value(Operand::Instruction(new_phi), unique_backedge_block);
 
             if let Some(next) = inst.get_next() {
@@ -103,12 +90,7 @@ impl<'a> LoopSimplifier<'a> {
             }
         }
 
-        backedge_blocks_index
-            .into_iter()
-            .map(|index| head.get_pred_bb()[index])
-            .collect::<Vec<_>>()
-            .into_iter()
-            .for_each(|mut bb| {
+        backedge_blocks.into_iter().for_each(|mut bb| {
                 bb.replace_succ_bb_only(head, unique_backedge_block);
             }); //
 
@@ -121,35 +103,25 @@ impl<'a> LoopSimplifier<'a> {
     fn insert_preheader(&mut self, mut lo: LoopPtr) -> Result<()> {
         let header = lo.head;
 
-        // 获得不在循环中的bb和对应的index
+        // 获得不在循环中的bb
         let out_bb = header
             .get_pred_bb()
             .iter()
-            .enumerate()
-            .filter_map(|(index, bb)| {
-                if !lo.is_in_loop(bb) {
-                    Some((index, *bb))
-                } else {
-                    None
-                }
-            })
+            .filter(|bb| !lo.is_in_loop(bb))
+            .cloned()
             .collect::<Vec<_>>();
 
-        if out_bb.len() == 1 && out_bb[0].1.get_succ_bb().len() == 1 {
-            lo.pre_header = Some(out_bb[0].1);
+        if out_bb.len() == 1 && out_bb[0].get_succ_bb().len() == 1 {
+            lo.pre_header = Some(out_bb[0]);
             return Ok(());
         }
 
         let mut preheader = self
             .ir_builder
             .new_basicblock("preheader".to_string() + &header.name);
-        let out_bb_index = out_bb
-            .into_iter()
-            .map(|(index, mut out_bb)| {
-                out_bb.replace_succ_bb_only(header, preheader);
-                index
-            })
-            .collect::<Vec<_>>();
+        for mut bb in out_bb.iter().cloned() {
+            bb.replace_succ_bb_only(header, preheader);
+        }
 
         preheader.set_true_bb(header);
 
@@ -162,15 +134,7 @@ impl<'a> LoopSimplifier<'a> {
             }
 
             let phi = downcast_mut::<Phi>(phi.as_mut());
-            let incoming_values = out_bb_index
-                .iter()
-                .map(|&index| phi.get_incoming_values()[index].clone())
-                .collect::<Vec<_>>();
-
-            out_bb_index.iter().enumerate().for_each(|(i, index)| {
-                phi.get_incoming_values_mut().remove(index - i);
-                unsafe { phi.get_manager_mut().remove_operand(index - i) };
-            });
+            let incoming_values = take_incoming_values(phi, &out_bb);
 
             let new_phi = self
                 .ir_builder
@@ -190,3 +154,16 @@ impl<'a> LoopSimplifier<'a> {
         Ok(())
     }
 }
+
+/// Remove incoming values of given blocks from phi, and return them.
+/// Phi incoming order may differ from predecessor order, so values are matched by block.
+pub(crate) fn take_incoming_values(phi: &mut Phi, blocks: &[BBPtr]) -> Vec<(Operand, BBPtr)> {
+    blocks
+        .iter()
+        .filter_map(|bb| {
+            let value = phi.get_incoming_value(*bb)?.clone();
+            phi.remove_incoming_value(bb.id);
+            Some((value, *bb))
+        })
+        .collect()
+}
@@ -0,0 +1,516 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::{
+        loop_tools::{LoopForest, LoopPtr},
+        scalar_evolution::{swap_predicate, ScalarEvolution, TripCount},
+    },
+    context,
+    ir::{
+        instruction::{
+            downcast_mut, downcast_ref,
+            misc_inst::{ICmp, ICmpOp, Phi},
+            InstType,
+        },
+        BBPtr, Constant, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats,
+    region_clone::{new_block, RegionClone},
+    Transform,
+};
+
+/// Fully unroll a loop if its unrolled body has at most this many instructions.
+const FULL_UNROLL_BUDGET: usize = 256;
+
+/// Partially unroll a loop so that its unrolled body has at most this many instructions.
+const PARTIAL_UNROLL_BUDGET: usize = 64;
+
+/// Maximum number of body copies in partial unrolling.
+const MAX_UNROLL_FACTOR: usize = 8;
+
+/// Unroll innermost loops. Partial unrolling is only performed if `allow_partial` is set,
+/// because the remainder loop should not be unrolled again.
+pub fn optimize_program(program: &mut Program, allow_partial: bool) -> Result<bool> {
+    LoopUnroll::new(program, allow_partial).run_and_log()
+}
+
+pub struct LoopUnroll<'a> {
+    program: &'a mut Program,
+    allow_partial: bool,
+}
+
+impl<'a> Transform for LoopUnroll<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_unroll".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+            loop_forest_post_order(&mut forest, |lo| {
+                changed |= self.unroll_loop(lo)?;
+                Ok(())
+            })?;
+        }
+        Ok(changed)
+    }
+}
+
+/// An innermost loop in the shape of `preheader -> head -> body ... -> latch -> head`,
+/// where head is the only block that exits the loop.
+struct UnrollInfo {
+    preheader: BBPtr,
+    head: BBPtr,
+    latch: BBPtr,
+
+    /// The first block of body, which is the in-loop successor of head
+    body_entry: BBPtr,
+
+    /// Blocks of loop body, in reverse post order from `body_entry`
+    body: Vec<BBPtr>,
+
+    /// Phi instructions in head
+    phis: Vec<InstPtr>,
+
+    /// Exit condition in head, loop continues if it's true
+    cond: InstPtr,
+
+    /// Induction variable phi, with the loop-continue comparison
+    indvar: InstPtr,
+    op: ICmpOp,
+    bound: Operand,
+
+    /// Trip count of body, computed from the induction variable
+    trip_count: TripCount,
+
+    /// Number of instructions in head and body
+    size: usize,
+}
+
+impl<'a> LoopUnroll<'a> {
+    pub fn new(program: &'a mut Program, allow_partial: bool) -> Self {
+        Self {
+            program,
+            allow_partial,
+        }
+    }
+
+    fn unroll_loop(&mut self, lo: LoopPtr) -> Result<bool> {
+        let Some(info) = Self::analyze(lo, &mut ScalarEvolution::new()) else {
+            return Ok(false);
+        };
+
+        // Fully unroll loop if trip count is a small constant
+        if let Some(trip_count) = info.trip_count.get_constant() {
+            if trip_count.saturating_mul(info.size) <= FULL_UNROLL_BUDGET {
+                self.full_unroll(lo, &info, trip_count)?;
+                pass_stats::bump(Self::name, "loops fully unrolled", 1);
+                return Ok(true);
+            }
+        }
+
+        // Otherwise unroll by a factor determined by size budget
+        let factor = MAX_UNROLL_FACTOR.min(PARTIAL_UNROLL_BUDGET / info.size);
+        if self.allow_partial && factor >= 2 && Self::is_monotonic(&info) {
+            if let Some(ahead) = Self::get_ahead(&info, factor) {
+                self.partial_unroll(lo, &info, factor, ahead)?;
+                pass_stats::bump(Self::name, "loops partially unrolled", 1);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Check if loop can be unrolled, and collect information for unrolling.
+    fn analyze(lo: LoopPtr, scev: &mut ScalarEvolution) -> Option<UnrollInfo> {
+        if !lo.sub_loops.is_empty() {
+            return None;
+        }
+        let head = lo.head;
+        let preheader = lo.pre_header?;
+
+        // Head should have exactly two predecessors: preheader and latch
+        let head_pred = head.get_pred_bb();
+        if head_pred.len() != 2 {
+            return None;
+        }
+        let latch = *head_pred.iter().find(|bb| **bb != preheader)?;
+
+        // Head should branch to body or exit
+        let head_succ = head.get_succ_bb();
+        if head_succ.len() != 2 || !lo.is_in_loop(&head_succ[0]) || lo.is_in_loop(&head_succ[1]) {
+            return None;
+        }
+        let body_entry = head_succ[0];
+        if body_entry == head || body_entry.get_pred_bb().len() != 1 {
+            return None;
+        }
+
+        // Body should not exit loop, and phis in body should only merge values inside body
+        let body: Vec<BBPtr> = lo
+            .blocks
+            .iter()
+            .filter(|bb| **bb != head)
+            .cloned()
+            .collect();
+        for bb in body.iter() {
+            if bb.get_succ_bb().iter().any(|succ| !lo.is_in_loop(succ)) {
+                return None;
+            }
+            for inst in bb.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+                if phi
+                    .get_incoming_values()
+                    .iter()
+                    .any(|(_, pred)| *pred == head)
+                {
+                    return None;
+                }
+            }
+        }
+
+        // Head should only contain phi, condition and branch
+        let mut phis = Vec::new();
+        let mut cond = None;
+        for inst in head.iter() {
+            match inst.get_type() {
+                InstType::Phi if cond.is_none() => phis.push(inst),
+                InstType::ICmp if cond.is_none() => cond = Some(inst),
+                InstType::Br => {}
+                _ => return None,
+            }
+        }
+        let cond = cond?;
+        if head.get_last_inst().get_operand().first() != Some(&Operand::Instruction(cond)) {
+            return None;
+        }
+
+        // Condition should compare induction variable with a loop invariant
+        let icmp = downcast_ref::<ICmp>(cond.as_ref().as_ref());
+        let (indvar, op, bound) = match (icmp.get_lhs(), icmp.get_rhs()) {
+            (Operand::Instruction(lhs), rhs) if phis.contains(lhs) => (*lhs, icmp.op, rhs),
+            (lhs, Operand::Instruction(rhs)) if phis.contains(rhs) => {
+                (*rhs, swap_predicate(icmp.op), lhs)
+            }
+            _ => return None,
+        };
+        if !lo.is_invariant(bound) {
+            return None;
+        }
+        let trip_count = scev.get_trip_count(lo)?;
+
+        // Sort body blocks in reverse post order, so that operands are cloned before use
+        let body = Self::sort_body(body_entry, &body.into_iter().collect());
+        let size = body
+            .iter()
+            .chain([head].iter())
+            .map(|bb| bb.iter().count())
+            .sum();
+        Some(UnrollInfo {
+            preheader,
+            head,
+            latch,
+            body_entry,
+            body,
+            phis,
+            cond,
+            indvar,
+            op,
+            bound: bound.clone(),
+            trip_count,
+            size,
+        })
+    }
+
+    fn sort_body(body_entry: BBPtr, body: &HashSet<BBPtr>) -> Vec<BBPtr> {
+        let mut visited = HashSet::new();
+        let mut post_order = Vec::new();
+        let mut stack = vec![(body_entry, false)];
+        while let Some((bb, expanded)) = stack.pop() {
+            if expanded {
+                post_order.push(bb);
+                continue;
+            }
+            if !body.contains(&bb) || !visited.insert(bb) {
+                continue;
+            }
+            stack.push((bb, true));
+            for succ in bb.get_succ_bb().iter().rev() {
+                stack.push((*succ, false));
+            }
+        }
+        post_order.reverse();
+        post_order
+    }
+
+    /// Check if loop condition stays true for all smaller steps once it's true for a larger step,
+    /// so that `factor` iterations can be guarded with a single comparison.
+    fn is_monotonic(info: &UnrollInfo) -> bool {
+        match info.op {
+            ICmpOp::Slt | ICmpOp::Sle => info.trip_count.step > 0,
+            ICmpOp::Sgt | ICmpOp::Sge => info.trip_count.step < 0,
+            _ => false,
+        }
+    }
+
+    /// Get distance of induction variable `factor - 1` steps ahead, if it does not overflow.
+    /// Constant bound must also stay in range after subtracting the distance.
+    fn get_ahead(info: &UnrollInfo, factor: usize) -> Option<i32> {
+        let ahead = (factor as i32 - 1).checked_mul(info.trip_count.step)?;
+        match info.bound {
+            Operand::Constant(Constant::Int(bound)) => bound.checked_sub(ahead).map(|_| ahead),
+            _ => Some(ahead),
+        }
+    }
+
+    /// Replace loop with `trip_count` copies of its body.
+    /// Head is kept to compute exit values, and it jumps to exit unconditionally.
+    fn full_unroll(&mut self, lo: LoopPtr, info: &UnrollInfo, trip_count: usize) -> Result<()> {
+        let mut head = info.head;
+        let mut preheader = info.preheader;
+
+        // Chain copies of body, starting from preheader
+        let mut values = self.initial_values(info)?;
+        let mut last = preheader;
+        for i in 0..trip_count {
+            let (entry, latch) = self.clone_body(lo, info, &mut values)?;
+            if i == 0 {
+                preheader.replace_succ_bb_only(head, entry);
+            } else {
+                last.set_true_bb(entry);
+            }
+            last = latch;
+        }
+
+        // Wire last copy to head, so that head phis get exit values
+        if last == preheader {
+            preheader.replace_succ_bb_only(head, head);
+        } else {
+            last.set_true_bb(head);
+        }
+        for (mut phi, value) in info.phis.iter().cloned().zip(values) {
+            let phi = downcast_mut::<Phi>(phi.as_mut());
+            phi.remove_incoming_value(info.preheader.id);
+            phi.add_incoming_value(value, last);
+        }
+
+        // Head always exits, remove the branch to body
+        let mut br = head.get_last_inst();
+        br.insert_before(self.program.mem_pool.get_br(None));
+        br.remove_self();
+        head.remove_true_bb();
+
+        // Remove original body, this also removes latch from head phis
+        for bb in info.body.iter() {
+            bb.clone().remove_self();
+        }
+        for bb in info.body.iter() {
+            for mut inst in bb.iter() {
+                inst.remove_self();
+            }
+        }
+        Ok(())
+    }
+
+    /// Unroll loop by `factor` into a new loop, and keep original loop for remaining iterations.
+    /// The new loop runs while the condition holds `factor - 1` steps ahead, which is checked
+    /// against `bound - ahead` so that the induction variable itself never overflows.
+    fn partial_unroll(
+        &mut self,
+        lo: LoopPtr,
+        info: &UnrollInfo,
+        factor: usize,
+        ahead: i32,
+    ) -> Result<()> {
+        let head = info.head;
+        let mut preheader = info.preheader;
+
+        // Compute limit in preheader. If it wraps around, unrolled loop is skipped
+        let (limit, in_range) = match info.bound {
+            Operand::Constant(Constant::Int(bound)) => {
+                (Constant::Int(bound.wrapping_sub(ahead)).into(), None)
+            }
+            _ => {
+                let limit = self
+                    .program
+                    .mem_pool
+                    .get_sub(info.bound.clone(), Constant::Int(ahead).into());
+                let (op, edge) = if ahead > 0 {
+                    (ICmpOp::Sge, i32::MIN + ahead)
+                } else {
+                    (ICmpOp::Sle, i32::MAX + ahead)
+                };
+                let in_range = self.program.mem_pool.get_icmp(
+                    op,
+                    ValueType::Int,
+                    info.bound.clone(),
+                    Constant::Int(edge).into(),
+                );
+                let mut br = preheader.get_last_inst();
+                br.insert_before(limit);
+                br.insert_before(in_range);
+                (limit.into(), Some(in_range))
+            }
+        };
+
+        // Build head of unrolled loop, with phis taking initial values from preheader
+        let mut new_head = new_block(&mut self.program.mem_pool, &head.name, "unroll");
+        preheader.replace_succ_bb_only(head, new_head);
+        let mut new_phis = Vec::new();
+        for phi in info.phis.iter() {
+            let init = downcast_ref::<Phi>(phi.as_ref().as_ref())
+                .get_incoming_value(info.preheader)
+                .cloned()
+                .ok_or_else(|| anyhow!("{} has no incoming value from preheader", phi))
+                .with_context(|| context!())?;
+            let new_phi = self
+                .program
+                .mem_pool
+                .get_phi(phi.get_value_type(), vec![(init, preheader)]);
+            new_head.push_back(new_phi);
+            new_phis.push(new_phi);
+        }
+        let indvar_index = info
+            .phis
+            .iter()
+            .position(|phi| *phi == info.indvar)
+            .unwrap();
+        let new_cond = self.program.mem_pool.get_icmp(
+            info.op,
+            ValueType::Int,
+            new_phis[indvar_index].into(),
+            limit,
+        );
+        new_head.push_back(new_cond);
+        let new_cond = match in_range {
+            Some(in_range) => {
+                let and = self
+                    .program
+                    .mem_pool
+                    .get_and(in_range.into(), new_cond.into());
+                new_head.push_back(and);
+                and
+            }
+            None => new_cond,
+        };
+        new_head.push_back(self.program.mem_pool.get_br(Some(new_cond.into())));
+
+        // Chain copies of body
+        let mut values: Vec<Operand> = new_phis.iter().map(|phi| (*phi).into()).collect();
+        let mut last = new_head;
+        for _ in 0..factor {
+            let (entry, latch) = self.clone_body(lo, info, &mut values)?;
+            last.set_true_bb(entry);
+            last = latch;
+        }
+        last.set_true_bb(new_head);
+        for (mut phi, value) in new_phis.iter().cloned().zip(values) {
+            downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(value, last);
+        }
+
+        // Original loop handles remaining iterations, with phis coming from unrolled loop
+        new_head.set_false_bb(head);
+        for (mut phi, new_phi) in info.phis.iter().cloned().zip(new_phis.iter()) {
+            let phi = downcast_mut::<Phi>(phi.as_mut());
+            phi.replace_incoming_value(info.preheader, new_head);
+            phi.replace_incoming_value_at(new_head, (*new_phi).into());
+        }
+        if let Some(mut parent) = lo.parent_loop {
+            parent.blocks.insert(new_head);
+        }
+        Ok(())
+    }
+
+    fn initial_values(&self, info: &UnrollInfo) -> Result<Vec<Operand>> {
+        info.phis
+            .iter()
+            .map(|phi| {
+                downcast_ref::<Phi>(phi.as_ref().as_ref())
+                    .get_incoming_value(info.preheader)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("{} has no incoming value from preheader", phi))
+                    .with_context(|| context!())
+            })
+            .collect()
+    }
+
+    /// Clone loop body for one iteration, where head phis take given values.
+    /// Values are updated to incoming values from latch of the copy.
+    /// Returns entry and latch of the copy, latch has no successor yet.
+    fn clone_body(
+        &mut self,
+        lo: LoopPtr,
+        info: &UnrollInfo,
+        values: &mut [Operand],
+    ) -> Result<(BBPtr, BBPtr)> {
+        let mut operand_map: HashMap<Operand, Operand> = HashMap::new();
+        for (phi, value) in info.phis.iter().zip(values.iter()) {
+            operand_map.insert((*phi).into(), value.clone());
+        }
+        operand_map.insert(info.cond.into(), Constant::Bool(true).into());
+        let clone = RegionClone::new(
+            &mut self.program.mem_pool,
+            &info.body,
+            "unroll",
+            operand_map,
+            |_| true,
+        );
+        if let Some(mut parent) = lo.parent_loop {
+            parent.blocks.extend(clone.block_map.values());
+        }
+
+        // Copy edges inside body, back edge is left for caller
+        clone.copy_edges(|succ| (succ != info.head).then(|| clone.map_block(succ)));
+
+        // Update values for next iteration
+        for (phi, value) in info.phis.iter().zip(values.iter_mut()) {
+            let next = downcast_ref::<Phi>(phi.as_ref().as_ref())
+                .get_incoming_value(info.latch)
+                .ok_or_else(|| anyhow!("{} has no incoming value from latch", phi))
+                .with_context(|| context!())?;
+            *value = clone.map_operand(next);
+        }
+        Ok((
+            clone.block_map[&info.body_entry],
+            clone.block_map[&info.latch],
+        ))
+    }
+}
@@ -0,0 +1,226 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::loop_tools::{LoopForest, LoopPtr},
+    ir::{
+        instruction::{downcast_mut, misc_inst::Phi, InstType},
+        BBPtr, Constant, FunPtr, Operand,
+    },
+    Program,
+};
+
+use super::{
+    lcssa::{get_exits, LCSSA},
+    loop_optimization::loop_forest_post_order,
+    loop_simplify::LoopSimplifier,
+    pass_stats,
+    pass_stats::IrSize,
+    region_clone::{new_block, RegionClone},
+    Transform,
+};
+
+/// Loops with more instructions than this are not duplicated.
+const MAX_LOOP_SIZE: usize = 128;
+
+/// Functions with more instructions than this are not grown further.
+const MAX_FUNC_SIZE: usize = 4000;
+
+/// Hoist branches on loop-invariant conditions out of loops,
+/// by duplicating the loop for each side of the condition.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    LoopUnswitch::new(program).run_and_log()
+}
+
+pub struct LoopUnswitch<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for LoopUnswitch<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "loop_unswitch".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() || IrSize::of_func(func).insts > MAX_FUNC_SIZE {
+                continue;
+            }
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+
+            // Loop forest is outdated after unswitching, so unswitch at most one loop per run
+            let mut unswitched = false;
+            loop_forest_post_order(&mut forest, |lo| {
+                if !unswitched {
+                    unswitched = self.unswitch_loop(func, lo)?;
+                }
+                Ok(())
+            })?;
+            changed |= unswitched;
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> LoopUnswitch<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    /// Duplicate loop, make preheader branch to the original loop if an invariant condition
+    /// holds, and to the copy otherwise. Both versions get their own preheader.
+    fn unswitch_loop(&mut self, func: FunPtr, mut lo: LoopPtr) -> Result<bool> {
+        let Some(mut preheader) = lo.pre_header else {
+            return Ok(false);
+        };
+        let blocks = lo.get_all_blocks();
+        let block_set: HashSet<BBPtr> = blocks.iter().cloned().collect();
+        let size: usize = blocks.iter().map(|bb| bb.iter().count()).sum();
+        if size > MAX_LOOP_SIZE {
+            return Ok(false);
+        }
+
+        // Find a branch inside loop on an invariant condition
+        let Some(cond) = blocks.iter().find_map(|bb| {
+            let succ = bb.get_succ_bb();
+            if succ.len() != 2 || !succ.iter().all(|next| block_set.contains(next)) {
+                return None;
+            }
+            let cond = bb.get_last_inst().get_operand().first()?.clone();
+            let is_constant = matches!(cond, Operand::Constant(_));
+            (!is_constant && lo.is_invariant(&cond)).then_some(cond)
+        }) else {
+            return Ok(false);
+        };
+
+        for bb in blocks.iter() {
+            let succ = bb.get_succ_bb();
+            if succ.is_empty() || (succ.len() == 2 && succ[0] == succ[1]) {
+                return Ok(false);
+            }
+        }
+
+        // Values used after loop only leave it through exit phis, which are extended for the copy
+        LCSSA::new(&mut self.program.mem_pool).form_loop(func, lo)?;
+        let exits = get_exits(lo);
+
+        // Condition is false in the copy
+        let operand_map = HashMap::from([(cond.clone(), Constant::Bool(false).into())]);
+        let clone = RegionClone::new(
+            &mut self.program.mem_pool,
+            &blocks,
+            "unswitch",
+            operand_map,
+            |_| true,
+        );
+        clone.copy_edges(|succ| Some(clone.map_block(succ)));
+        for exit in exits.iter() {
+            clone.extend_phis(*exit);
+        }
+
+        // Preheader branches to new preheaders of both versions
+        let head = lo.head;
+        let new_head = clone.block_map[&head];
+        let mut true_pre = new_block(&mut self.program.mem_pool, &preheader.name, "unswitch");
+        let mut false_pre = new_block(&mut self.program.mem_pool, &preheader.name, "unswitch");
+        preheader.replace_succ_bb_only(head, true_pre);
+        preheader.set_false_bb(false_pre);
+        true_pre.push_back(self.program.mem_pool.get_br(None));
+        true_pre.set_true_bb(head);
+        false_pre.push_back(self.program.mem_pool.get_br(None));
+        false_pre.set_true_bb(new_head);
+        for (head, pre) in [(head, true_pre), (new_head, false_pre)] {
+            for mut inst in head.iter() {
+                if inst.get_type() != InstType::Phi {
+                    break;
+                }
+                downcast_mut::<Phi>(inst.as_mut()).replace_incoming_value(preheader, pre);
+            }
+        }
+        let mut br = preheader.get_last_inst();
+        br.insert_after(self.program.mem_pool.get_br(Some(cond.clone())));
+        br.remove_self();
+        let clones: Vec<BBPtr> = blocks.iter().map(|bb| clone.block_map[bb]).collect();
+
+        // Condition is true in the original loop
+        for bb in blocks.iter() {
+            for mut inst in bb.iter() {
+                for index in 0..inst.get_operand().len() {
+                    if inst.get_operand()[index] == cond {
+                        inst.set_operand(index, Constant::Bool(true).into());
+                    }
+                }
+            }
+        }
+
+        // Fold branches on the condition, and remove blocks no longer reachable
+        let all_blocks: Vec<BBPtr> = blocks.iter().chain(clones.iter()).cloned().collect();
+        for bb in all_blocks.iter() {
+            self.fold_branch(*bb);
+        }
+        let reachable: HashSet<BBPtr> = func.dfs_iter().collect();
+        for mut bb in all_blocks {
+            if reachable.contains(&bb) {
+                continue;
+            }
+            bb.remove_self();
+            for mut inst in bb.iter().collect::<Vec<_>>() {
+                inst.remove_self();
+            }
+        }
+
+        if let Some(mut parent) = lo.parent_loop {
+            parent.blocks.extend([true_pre, false_pre]);
+            parent
+                .blocks
+                .extend(clones.iter().filter(|bb| reachable.contains(bb)));
+        }
+        lo.pre_header = Some(true_pre);
+        pass_stats::bump(Self::name, "loops unswitched", 1);
+        Ok(true)
+    }
+
+    /// Replace branch on a constant condition with a jump.
+    fn fold_branch(&mut self, mut bb: BBPtr) {
+        if bb.is_empty() || bb.get_succ_bb().len() != 2 {
+            return;
+        }
+        let mut br = bb.get_last_inst();
+        let Some(Operand::Constant(Constant::Bool(cond))) = br.get_operand().first().cloned()
+        else {
+            return;
+        };
+        if cond {
+            bb.remove_false_bb();
+        } else {
+            bb.remove_true_bb();
+        }
+        br.insert_after(self.program.mem_pool.get_br(None));
+        br.remove_self();
+    }
+}
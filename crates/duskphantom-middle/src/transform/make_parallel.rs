@@ -17,14 +17,17 @@
 use crate::ir::instruction::downcast_ref;
 use crate::{
     analysis::{
+        dependence_analysis::DependenceAnalysis,
         dominator_tree::DominatorTree,
         effect_analysis::{Effect, EffectAnalysis},
         loop_tools::{self, LoopForest, LoopPtr},
+        scalar_evolution::{ScalarEvolution, Scev},
     },
+    config::CONFIG,
     ir::{
         instruction::{
             downcast_mut,
-            misc_inst::{ICmp, ICmpOp, Phi},
+            misc_inst::{ICmp, ICmpOp, Phi, Select},
             InstType,
         },
         BBPtr, Constant, InstPtr, Operand, ValueType,
@@ -33,11 +36,41 @@ use crate::{
 };
 use anyhow::Result;
 use duskphantom_utils::cprintln;
-use std::collections::{HashMap, HashSet};
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+};
+
+use super::{loop_simplify, pass_stats, Transform};
+
+/// Estimated trip count of loops whose trip count is unknown.
+const DEFAULT_TRIP_COUNT: usize = 100;
+
+/// Minimum number of instructions each thread should run for parallelization to pay off.
+const MIN_WORK_PER_THREAD: usize = 8;
+
+/// Default number of threads a parallelized loop runs on.
+pub const DEFAULT_THREAD_COUNT: usize = 5;
+
+thread_local! {
+    static THREAD_COUNT: Cell<usize> = const { Cell::new(DEFAULT_THREAD_COUNT) };
+}
+
+/// Set number of threads a parallelized loop runs on, on current thread.
+pub fn set_thread_count(thread_count: usize) {
+    THREAD_COUNT.with(|t| t.set(thread_count));
+}
 
-use super::{loop_simplify, Transform};
+/// Get number of threads a parallelized loop runs on.
+fn get_thread_count() -> usize {
+    THREAD_COUNT.with(|t| t.get())
+}
 
-pub fn optimize_program<const N_THREAD: i32>(program: &mut Program) -> Result<bool> {
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let n_thread = get_thread_count();
+    if n_thread <= 1 {
+        return Ok(false);
+    }
     let mut changed = false;
     let effect_analysis = EffectAnalysis::new(program);
     for func in program.module.functions.clone() {
@@ -46,22 +79,30 @@ pub fn optimize_program<const N_THREAD: i32>(program: &mut Program) -> Result<bo
         };
         loop_simplify::LoopSimplifier::new(&mut program.mem_pool).run(&mut forest)?;
         let mut dom_tree = DominatorTree::new(func);
-        changed |=
-            MakeParallel::<N_THREAD>::new(program, &mut forest, &mut dom_tree, &effect_analysis)
-                .run_and_log()?;
+        changed |= MakeParallel::new(
+            program,
+            &mut forest,
+            &mut dom_tree,
+            &effect_analysis,
+            n_thread as i32,
+        )
+        .run_and_log()?;
     }
     Ok(changed)
 }
 
-pub struct MakeParallel<'a, const N_THREAD: i32> {
+pub struct MakeParallel<'a> {
     program: &'a mut Program,
     loop_forest: &'a mut LoopForest,
     dom_tree: &'a mut DominatorTree,
     effect_analysis: &'a EffectAnalysis,
+    n_thread: i32,
+    scev: ScalarEvolution,
+    dependence: DependenceAnalysis,
     stack_ref: HashMap<LoopPtr, HashSet<InstPtr>>,
 }
 
-impl<'a, const N_THREAD: i32> Transform for MakeParallel<'a, N_THREAD> {
+impl<'a> Transform for MakeParallel<'a> {
     fn get_program_mut(&mut self) -> &mut Program {
         self.program
     }
@@ -86,18 +127,23 @@ impl<'a, const N_THREAD: i32> Transform for MakeParallel<'a, N_THREAD> {
     }
 }
 
-impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
+impl<'a> MakeParallel<'a> {
     pub fn new(
         program: &'a mut Program,
         loop_forest: &'a mut LoopForest,
         dom_tree: &'a mut DominatorTree,
         effect_analysis: &'a EffectAnalysis,
+        n_thread: i32,
     ) -> Self {
+        let dependence = DependenceAnalysis::new(loop_forest);
         Self {
             program,
             loop_forest,
             dom_tree,
             effect_analysis,
+            n_thread,
+            scev: ScalarEvolution::new(),
+            dependence,
             stack_ref: HashMap::new(),
         }
     }
@@ -161,9 +207,49 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
         Ok(Some(effect))
     }
 
+    /// Find the outermost parallel loops with enough work, descending into sub loops
+    /// of sequential loops. If only one sub loop is parallelized, try hoisting its
+    /// thread creation out of the sequential loop.
     fn make_candidate(&mut self, result: &mut Vec<Candidate>, lo: LoopPtr) -> Result<()> {
         #[allow(unused)]
         let pre_header = lo.pre_header.unwrap();
+        if let Some(candidate) = self.get_candidate(lo)? {
+            if self.has_enough_work(lo) {
+                cprintln!(
+                    "[INFO] loop {} is made candidate {}!",
+                    pre_header.name,
+                    candidate.dump()
+                );
+                result.push(candidate);
+                return Ok(());
+            }
+            cprintln!("[INFO] loop {} does not have enough work", pre_header.name);
+        }
+
+        // Check sub loops instead
+        let mut sub_result = Vec::new();
+        for sub_loop in lo.sub_loops.iter() {
+            self.make_candidate(&mut sub_result, *sub_loop)?;
+        }
+        if let [candidate] = sub_result.as_mut_slice() {
+            if let Some(join_bb) = self.get_hoist_exit(candidate, lo) {
+                cprintln!(
+                    "[INFO] thread creation of loop {} is hoisted out of loop {}",
+                    candidate.init_bb.name,
+                    pre_header.name
+                );
+                candidate.region = lo;
+                candidate.join_bb = join_bb;
+            }
+        }
+        result.extend(sub_result);
+        Ok(())
+    }
+
+    /// Get candidate if loop can be parallelized.
+    fn get_candidate(&mut self, lo: LoopPtr) -> Result<Option<Candidate>> {
+        #[allow(unused)]
+        let pre_header = lo.pre_header.unwrap();
 
         // Get all exit edges
         // TODO-TLE: ignore all bb with one succ
@@ -174,7 +260,7 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
         // If there are multiple exit edges, then it can't be parallelized
         if exit.len() != 1 {
             cprintln!("[INFO] loop {} has multiple exit edges", pre_header.name);
-            return Ok(());
+            return Ok(None);
         }
 
         // If succ of pre_header is not exit, then it can't be parallelized
@@ -186,38 +272,187 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
                 pre_header.name,
                 pre_header.name
             );
-            return Ok(());
+            return Ok(None);
         }
 
         // Get induction var from exit. If failed, check sub loops instead
-        let Some(candidate) = Candidate::from_exit(exit, lo, self.dom_tree) else {
+        let Some(candidate) = Candidate::from_exit(exit, lo, self.dom_tree, &mut self.scev) else {
             cprintln!("[INFO] loop {} does not have indvar", pre_header.name);
-            return Ok(());
+            return Ok(None);
         };
 
         // If effect range collides, then it can't be parallelized, check sub loops instead
+        // Effect range is coarse, so check dependence of array accesses before giving up
         if self
             .get_loop_effect(lo, &candidate.indvar.into())?
             .is_none()
+            && self.has_carried_dependence(lo)
         {
             cprintln!("[INFO] loop {} has conflict effect", pre_header.name);
-            return Ok(());
+            return Ok(None);
         }
+        Ok(Some(candidate))
+    }
 
-        // Insert candidate to results
-        cprintln!(
-            "[INFO] loop {} is made candidate {}!",
-            pre_header.name,
-            candidate.dump()
-        );
-        result.push(candidate);
-        Ok(())
+    /// Check if memory accesses in different iterations of loop may conflict.
+    /// Loops with io or calls are always considered conflicting.
+    fn has_carried_dependence(&mut self, lo: LoopPtr) -> bool {
+        let mut accesses = Vec::new();
+        for bb in lo.get_all_blocks() {
+            for inst in bb.iter() {
+                if self.effect_analysis.has_io(inst) {
+                    return true;
+                }
+                if self.effect_analysis.inst_effect.contains_key(&inst) {
+                    match inst.get_type() {
+                        InstType::Load | InstType::Store => accesses.push(inst),
+                        _ => return true,
+                    }
+                }
+            }
+        }
+        for (i, src) in accesses.iter().enumerate() {
+            for dst in accesses[i..].iter() {
+                if src.get_type() != InstType::Store && dst.get_type() != InstType::Store {
+                    continue;
+                }
+                let Some(dep) = self.dependence.get_dependence(*src, *dst) else {
+                    continue;
+                };
+                if dep
+                    .get_level(lo)
+                    .is_none_or(|level| dep.is_carried_at(level))
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Check if loop runs enough instructions for each thread.
+    /// Loops with unknown trip count are assumed to have enough work.
+    fn has_enough_work(&mut self, lo: LoopPtr) -> bool {
+        let Some(trip_count) = self.get_trip_count(lo) else {
+            return true;
+        };
+        let n_thread = self.n_thread as usize;
+        trip_count >= n_thread && self.get_work(lo, None) >= MIN_WORK_PER_THREAD * n_thread
+    }
+
+    /// Get trip count of loop, or `None` if it's not constant.
+    fn get_trip_count(&mut self, lo: LoopPtr) -> Option<usize> {
+        self.scev.get_trip_count(lo)?.get_constant()
+    }
+
+    /// Get estimated number of instructions run by loop, excluding those in `skip` loop.
+    fn get_work(&mut self, lo: LoopPtr, skip: Option<LoopPtr>) -> usize {
+        if skip == Some(lo) {
+            return 0;
+        }
+        let mut work: usize = lo.blocks.iter().map(|bb| bb.iter().count()).sum();
+        for sub_loop in lo.sub_loops.iter() {
+            work += self.get_work(*sub_loop, skip);
+        }
+        let trip_count = self.get_trip_count(lo).unwrap_or(DEFAULT_TRIP_COUNT);
+        work.saturating_mul(trip_count)
+    }
+
+    /// Get exit block of `outer` if thread creation of candidate can be hoisted out of it.
+    /// Each thread then runs `outer` redundantly, and only splits iterations of candidate.
+    /// This requires `outer` to have no effect outside candidate, and each thread to access
+    /// the same addresses in candidate throughout `outer`, so no thread sees memory written
+    /// by another thread. Hoisting is profitable if redundant work doesn't exceed split work.
+    fn get_hoist_exit(&mut self, candidate: &Candidate, outer: LoopPtr) -> Option<BBPtr> {
+        #[allow(unused)]
+        let pre_header = outer.pre_header?;
+
+        // Reductions are combined after each run of candidate
+        if !candidate.reductions.is_empty() {
+            return None;
+        }
+
+        // Thread should get the same bounds in each iteration of `outer`
+        if !outer.is_invariant(&candidate.init_val) || !outer.is_invariant(&candidate.exit_val) {
+            cprintln!(
+                "[INFO] loop {} has bounds variant in loop {}",
+                candidate.init_bb.name,
+                pre_header.name
+            );
+            return None;
+        }
+
+        // Threads are joined at the only exit of `outer`
+        let mut exit = Vec::new();
+        get_exit_inst(outer, outer, &mut exit);
+        let [exit] = exit.as_slice() else {
+            return None;
+        };
+        let exit_bb = *exit
+            .get_parent_bb()?
+            .get_succ_bb()
+            .iter()
+            .find(|bb| !outer.is_in_loop(bb))?;
+        if exit_bb.get_pred_bb().len() != 1
+            || exit_bb.iter().any(|inst| inst.get_type() == InstType::Phi)
+        {
+            return None;
+        }
+
+        // Instructions run redundantly should have no effect
+        for bb in outer.get_all_blocks() {
+            if candidate.lo.is_in_loop(&bb) {
+                continue;
+            }
+            for inst in bb.iter() {
+                if self.effect_analysis.has_io(inst)
+                    || self.effect_analysis.inst_effect.contains_key(&inst)
+                {
+                    cprintln!(
+                        "[INFO] loop {} has effect {} out of loop {}",
+                        pre_header.name,
+                        inst.gen_llvm_ir(),
+                        candidate.init_bb.name
+                    );
+                    return None;
+                }
+            }
+        }
+
+        // Addresses accessed in candidate should only depend on its indvar
+        for bb in candidate.lo.get_all_blocks() {
+            for inst in bb.iter() {
+                let ptr = match inst.get_type() {
+                    InstType::Load => &inst.get_operand()[0],
+                    InstType::Store => &inst.get_operand()[1],
+                    _ if self.effect_analysis.inst_effect.contains_key(&inst) => return None,
+                    _ => continue,
+                };
+                if !is_private_address(ptr, candidate, outer) {
+                    cprintln!(
+                        "[INFO] loop {} accesses {} variant in loop {}",
+                        candidate.init_bb.name,
+                        ptr,
+                        pre_header.name
+                    );
+                    return None;
+                }
+            }
+        }
+
+        // Redundant work should not exceed split work
+        let redundant = self.get_work(outer, Some(candidate.lo));
+        let total = self.get_work(outer, None);
+        (redundant <= total - redundant).then_some(exit_bb)
     }
 
     fn make_parallel(&mut self, mut candidate: Candidate) -> Result<bool> {
-        // Copy global array address to local stack with consistent order
+        // Copy global array address to local stack with consistent order,
+        // before region where threads are created
+        let region_pre_header = candidate.region.pre_header.unwrap();
+        let hoisted = candidate.region != candidate.lo;
         let mut map = HashMap::new();
-        if let Some(stack_ref) = self.stack_ref.get(&candidate.lo) {
+        if let Some(stack_ref) = self.stack_ref.get(&candidate.region) {
             let mut vec = stack_ref.iter().cloned().collect::<Vec<_>>();
             vec.sort_by_key(|inst| inst.get_id());
             for inst in vec {
@@ -226,11 +461,11 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
                     inst.into(),
                     vec![Constant::Int(0).into()],
                 );
-                candidate.init_bb.get_last_inst().insert_before(gep_zero);
+                region_pre_header.get_last_inst().insert_before(gep_zero);
                 map.insert(inst, gep_zero);
             }
         }
-        replace_stack_reference(candidate.lo, &map)?;
+        replace_stack_reference(candidate.region, &map)?;
 
         // Get current thread ID
         let func_create = self
@@ -243,15 +478,15 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
         let inst_create = self
             .program
             .mem_pool
-            .get_call(*func_create, vec![Constant::Int(N_THREAD - 1).into()]);
-        candidate.init_bb.get_last_inst().insert_before(inst_create);
+            .get_call(*func_create, vec![Constant::Int(self.n_thread - 1).into()]);
+        region_pre_header.get_last_inst().insert_before(inst_create);
 
         // Create parallelized exit and indvar
         //
         // i = init_val
         // d = next_delta
         // e = exit_value
-        // N = N_THREAD
+        // N = n_thread
         // n = current_thread
         //
         // Before: i, i + d, i + 2d, ..., i + d(X = (e - i) ceildiv d)
@@ -261,43 +496,43 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
 
         // e - i
         let inst_sub = self.program.mem_pool.get_sub(e.clone(), i.clone());
-        candidate.init_bb.get_last_inst().insert_before(inst_sub);
+        region_pre_header.get_last_inst().insert_before(inst_sub);
 
         // (e - i) * n
         let inst_mul = self
             .program
             .mem_pool
             .get_mul(inst_create.into(), inst_sub.into());
-        candidate.init_bb.get_last_inst().insert_before(inst_mul);
+        region_pre_header.get_last_inst().insert_before(inst_mul);
 
         // (e - i) * n / N
         let inst_div = self
             .program
             .mem_pool
-            .get_sdiv(inst_mul.into(), Constant::Int(N_THREAD).into());
-        candidate.init_bb.get_last_inst().insert_before(inst_div);
+            .get_sdiv(inst_mul.into(), Constant::Int(self.n_thread).into());
+        region_pre_header.get_last_inst().insert_before(inst_div);
 
         // Lower bound: i + (e - i) * n / N
         let inst_lb = self.program.mem_pool.get_add(inst_div.into(), i.clone());
-        candidate.init_bb.get_last_inst().insert_before(inst_lb);
+        region_pre_header.get_last_inst().insert_before(inst_lb);
 
         // (e - i) * n + e - i
         let inst_add = self
             .program
             .mem_pool
             .get_add(inst_mul.into(), inst_sub.into());
-        candidate.init_bb.get_last_inst().insert_before(inst_add);
+        region_pre_header.get_last_inst().insert_before(inst_add);
 
         // ((e - i) * n + e - i) / N
         let inst_div = self
             .program
             .mem_pool
-            .get_sdiv(inst_add.into(), Constant::Int(N_THREAD).into());
-        candidate.init_bb.get_last_inst().insert_before(inst_div);
+            .get_sdiv(inst_add.into(), Constant::Int(self.n_thread).into());
+        region_pre_header.get_last_inst().insert_before(inst_div);
 
         // Upper bound: i + ((e - i) * n + e - i) / N
         let inst_ub = self.program.mem_pool.get_add(inst_div.into(), i.clone());
-        candidate.init_bb.get_last_inst().insert_before(inst_ub);
+        region_pre_header.get_last_inst().insert_before(inst_ub);
 
         // Replace indvar to parallelized indvar
         let phi = downcast_mut::<Phi>(candidate.indvar.as_mut());
@@ -322,7 +557,7 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
             .find(|f| f.name == "thrd_join")
             .unwrap();
         let mut inst_join = self.program.mem_pool.get_call(*func_join, vec![]);
-        candidate.exit_bb.push_front(inst_join);
+        candidate.join_bb.push_front(inst_join);
 
         // For out-of-loop indvar, replace with predicted value:
         // i + ((e - i - 1) / delta + 1) * delta
@@ -330,7 +565,11 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
             .program
             .mem_pool
             .get_sub(inst_sub.into(), Constant::Int(1).into());
-        inst_join.insert_after(inst_sub);
+        if hoisted {
+            candidate.exit_bb.push_front(inst_sub);
+        } else {
+            inst_join.insert_after(inst_sub);
+        }
 
         // (e - i - 1) / delta
         let mut inst_div = self
@@ -358,13 +597,110 @@ impl<'a, const N_THREAD: i32> MakeParallel<'a, N_THREAD> {
         inst_mul.insert_after(inst_pred);
 
         // Iterate all indvar users, if not in loop, replace with predicted value
-        for mut user in candidate.indvar.get_user().iter().cloned() {
-            if !candidate.lo.is_in_loop(&user.get_parent_bb().unwrap()) {
-                user.replace_operand(&candidate.indvar.into(), &inst_pred.into());
+        replace_uses_out_of_loop(candidate.indvar, candidate.lo, &inst_pred.into(), None);
+
+        // Each thread reduces from identity and saves its partial result to its own slot,
+        // slots are combined with initial value after threads join
+        let mut pos = inst_pred;
+        for reduction in candidate.reductions.iter() {
+            let mut phi = reduction.phi;
+            let ty = phi.get_value_type();
+            let slot_ty = ValueType::Array(Box::new(ty.clone()), self.n_thread as usize);
+            let slots = self.program.mem_pool.new_global_variable(
+                format!("__reduce_{}", phi.get_id()),
+                slot_ty.clone(),
+                true,
+                slot_ty.default_initializer()?,
+            );
+            self.program.module.global_variables.push(slots);
+            downcast_mut::<Phi>(phi.as_mut())
+                .replace_incoming_value_at(candidate.init_bb, reduction.kind.get_identity(&ty)?);
+
+            // Save partial result before join
+            let slot = self.program.mem_pool.get_getelementptr(
+                slot_ty.clone(),
+                slots.into(),
+                vec![Constant::Int(0).into(), inst_create.into()],
+            );
+            inst_join.insert_before(slot);
+            let inst_save = self.program.mem_pool.get_store(phi.into(), slot.into());
+            inst_join.insert_before(inst_save);
+
+            // Combine partial results after join
+            let mut result = reduction.init_val.clone();
+            for n in 0..self.n_thread {
+                let slot = self.program.mem_pool.get_getelementptr(
+                    slot_ty.clone(),
+                    slots.into(),
+                    vec![Constant::Int(0).into(), Constant::Int(n).into()],
+                );
+                let partial = self.program.mem_pool.get_load(ty.clone(), slot.into());
+                let mut combined = self.get_combine(reduction.kind, result, partial.into());
+                for inst in [slot, partial].iter_mut().chain(combined.iter_mut()) {
+                    pos.insert_after(*inst);
+                    pos = *inst;
+                }
+                result = pos.into();
             }
+
+            // Replace reduction phi out of loop with combined result
+            replace_uses_out_of_loop(phi, candidate.lo, &result, Some(inst_save));
+            pass_stats::bump(Self::name, "reductions parallelized", 1);
+        }
+        if hoisted {
+            pass_stats::bump(Self::name, "thread creations hoisted", 1);
         }
         Ok(true)
     }
+
+    /// Get instructions combining two values of a reduction, the last one is the result.
+    fn get_combine(&mut self, kind: ReductionKind, lhs: Operand, rhs: Operand) -> Vec<InstPtr> {
+        let mem_pool = &mut self.program.mem_pool;
+        match kind {
+            ReductionKind::Add => vec![mem_pool.get_add(lhs, rhs)],
+            ReductionKind::Mul => vec![mem_pool.get_mul(lhs, rhs)],
+            ReductionKind::FAdd => vec![mem_pool.get_fadd(lhs, rhs)],
+            ReductionKind::Xor => vec![mem_pool.get_xor(lhs, rhs)],
+            ReductionKind::Min | ReductionKind::Max => {
+                let op = if kind == ReductionKind::Min {
+                    ICmpOp::Slt
+                } else {
+                    ICmpOp::Sgt
+                };
+                let cond = mem_pool.get_icmp(op, ValueType::Int, lhs.clone(), rhs.clone());
+                let select = mem_pool.get_select(cond.into(), lhs, rhs);
+                vec![cond, select]
+            }
+        }
+    }
+}
+
+/// Replace uses of `inst` out of loop with `value`, except for `skip`.
+/// Phi users have their incoming values replaced, so that operands stay in sync.
+fn replace_uses_out_of_loop(inst: InstPtr, lo: LoopPtr, value: &Operand, skip: Option<InstPtr>) {
+    let op: Operand = inst.into();
+    let mut users = inst.get_user().to_vec();
+    users.sort_by_key(|user| user.get_id());
+    users.dedup();
+    for mut user in users {
+        if Some(user) == skip || user.get_parent_bb().is_none_or(|bb| lo.is_in_loop(&bb)) {
+            continue;
+        }
+        if user.get_type() != InstType::Phi {
+            user.replace_operand(&op, value);
+            continue;
+        }
+        let phi = downcast_mut::<Phi>(user.as_mut());
+        let preds: Vec<BBPtr> = phi
+            .get_incoming_values()
+            .iter()
+            .filter(|(incoming, _)| *incoming == op)
+            .map(|(_, pred)| *pred)
+            .collect();
+        for pred in preds {
+            phi.replace_incoming_value_at(pred, value.clone());
+        }
+    }
 }
 
 /// Get all exit `br` in loop.
@@ -379,6 +715,29 @@ fn get_exit_inst(lo: LoopPtr, parent: LoopPtr, result: &mut Vec<InstPtr>) {
     }
 }
 
+/// Check if address only depends on indvar of candidate and values invariant in `outer`.
+fn is_private_address(op: &Operand, candidate: &Candidate, outer: LoopPtr) -> bool {
+    let Operand::Instruction(inst) = op else {
+        return true;
+    };
+    if *inst == candidate.indvar || outer.is_invariant(op) {
+        return true;
+    }
+    if !inst
+        .get_parent_bb()
+        .is_some_and(|bb| candidate.lo.is_in_loop(&bb))
+    {
+        return false;
+    }
+    match inst.get_type() {
+        InstType::Phi | InstType::Load | InstType::Call => false,
+        _ => inst
+            .get_operand()
+            .iter()
+            .all(|op| is_private_address(op, candidate, outer)),
+    }
+}
+
 /// Get base pointer of load / store / gep instruction, return if it's alloc.
 fn get_base_alloc(inst: InstPtr) -> Option<InstPtr> {
     if inst.get_type() == InstType::Alloca {
@@ -488,6 +847,9 @@ fn merge_effect(a: &mut Effect, b: &Effect, indvar: &Operand) -> Result<bool> {
 /// init_val = 2
 /// init_bb = pre_header
 /// exit_val = 6
+///
+/// Threads are created before `region` and joined at `join_bb`, where `region` is `lo`
+/// unless thread creation is hoisted out of enclosing loops.
 struct Candidate {
     lo: LoopPtr,
     indvar: InstPtr,
@@ -497,6 +859,9 @@ struct Candidate {
     init_bb: BBPtr,
     exit_val: Operand,
     exit_bb: BBPtr,
+    reductions: Vec<Reduction>,
+    region: LoopPtr,
+    join_bb: BBPtr,
 }
 
 impl Candidate {
@@ -510,6 +875,7 @@ impl Candidate {
         init_bb: BBPtr,
         exit_val: Operand,
         exit_bb: BBPtr,
+        reductions: Vec<Reduction>,
     ) -> Self {
         Self {
             lo,
@@ -520,6 +886,9 @@ impl Candidate {
             init_bb,
             exit_val,
             exit_bb,
+            reductions,
+            region: lo,
+            join_bb: exit_bb,
         }
     }
 
@@ -540,7 +909,12 @@ impl Candidate {
     /// Get induction variable from exit instruction.
     /// Exit instruction should shape like:
     /// `exit = br (indvar < N), loop, exit`
-    fn from_exit(exit: InstPtr, lo: LoopPtr, dom_tree: &mut DominatorTree) -> Option<Self> {
+    fn from_exit(
+        exit: InstPtr,
+        lo: LoopPtr,
+        dom_tree: &mut DominatorTree,
+        scev: &mut ScalarEvolution,
+    ) -> Option<Self> {
         let pre_header = lo.pre_header.unwrap();
         if exit.get_type() != InstType::Br {
             cprintln!(
@@ -569,8 +943,17 @@ impl Candidate {
             return None;
         }
 
+        // Thread join is inserted at the start of exit block, which should not have phis
+        if exit_bb.iter().any(|inst| inst.get_type() == InstType::Phi) {
+            cprintln!(
+                "[INFO] loop {} fails because {} has phi",
+                pre_header.name,
+                exit_bb.name
+            );
+            return None;
+        }
+
         // Condition should be `indvar < op`, get `indvar` from condition
-        // TODO-PERF: use induction variable analysis to get `indvar` consistently
         let Operand::Instruction(cond) = exit.get_operand().first()? else {
             cprintln!(
                 "[INFO] loop {} fails because {}'s first operand is not inst",
@@ -618,7 +1001,7 @@ impl Candidate {
             }
         }
 
-        // Indvar should be `phi [init_val, init_bb], [indvar + delta, next_bb]`
+        // Indvar should be `phi [init_val, init_bb], [next_val, next_bb]` with constant step
         // `indvar` should be the only phi in its block (other phi can be non-trivial)
         // `init_bb` should be `pre_header`
         // `next_bb` should be in loop
@@ -650,30 +1033,15 @@ impl Candidate {
             );
             return None;
         }
-        let Operand::Instruction(next_val) = inc[1].0 else {
+        let Scev::AddRec { step, .. } = scev.get_scev(&(*indvar).into(), lo) else {
             cprintln!(
-                "[INFO] loop {} fails because {}'s second incoming value is not inst",
+                "[INFO] loop {} fails because {} is not induction variable",
                 pre_header.name,
                 indvar.gen_llvm_ir()
             );
             return None;
         };
-        if next_val.get_type() != InstType::Add {
-            cprintln!(
-                "[INFO] loop {} fails because {} is not add",
-                pre_header.name,
-                next_val.gen_llvm_ir()
-            );
-            return None;
-        }
-        let Operand::Constant(Constant::Int(delta)) = next_val.get_operand()[1] else {
-            cprintln!(
-                "[INFO] loop {} fails because {}'s second operand is not constant",
-                pre_header.name,
-                next_val.gen_llvm_ir()
-            );
-            return None;
-        };
+        let delta = step.get_constant()?;
         let next_bb = inc[1].1;
         if !lo.is_in_loop(&next_bb) {
             cprintln!(
@@ -683,20 +1051,164 @@ impl Candidate {
             );
             return None;
         }
+
+        // Other phis in head should be reductions, whose partial results are combined after join
+        let mut reductions = Vec::new();
         for inst in indvar.get_parent_bb().unwrap().iter() {
-            if inst.get_type() == InstType::Phi && inst != *indvar {
-                cprintln!(
-                    "[INFO] loop {} fails because {} has multiple phi",
-                    pre_header.name,
-                    inst.gen_llvm_ir()
-                );
-                return None;
+            if inst.get_type() != InstType::Phi || inst == *indvar {
+                continue;
+            }
+            match Reduction::from_phi(inst, lo, init_bb) {
+                Some(reduction) => reductions.push(reduction),
+                None => {
+                    cprintln!(
+                        "[INFO] loop {} fails because {} is not a reduction",
+                        pre_header.name,
+                        inst.gen_llvm_ir()
+                    );
+                    return None;
+                }
             }
         }
 
         // Construct induction variable
         Some(Self::new(
-            lo, *indvar, exit, delta, init_val, init_bb, exit_val, *exit_bb,
+            lo, *indvar, exit, delta, init_val, init_bb, exit_val, *exit_bb, reductions,
         ))
     }
 }
+
+/// Associative and commutative operation of a reduction.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum ReductionKind {
+    Add,
+    Mul,
+    Min,
+    Max,
+    FAdd,
+    Xor,
+}
+
+impl ReductionKind {
+    /// Get the value of type `ty` that leaves the other operand unchanged when combined.
+    fn get_identity(self, ty: &ValueType) -> Result<Operand> {
+        Ok(match self {
+            ReductionKind::Add => Constant::Int(0).into(),
+            ReductionKind::Mul => Constant::Int(1).into(),
+            ReductionKind::Min => Constant::Int(i32::MAX).into(),
+            ReductionKind::Max => Constant::Int(i32::MIN).into(),
+            ReductionKind::FAdd => Constant::Float(0.0).into(),
+            ReductionKind::Xor => ty.default_initializer()?.into(),
+        })
+    }
+}
+
+/// A reduction in a candidate loop.
+/// For example:
+///
+/// ```llvm
+/// sum = phi [init_val, pre_header], [sum + x, loop]
+/// max = phi [init_val, pre_header], [select (max < x), x, max, loop]
+/// ```
+///
+/// The phi should only be used by its update in loop, and the update only by the phi.
+struct Reduction {
+    phi: InstPtr,
+    kind: ReductionKind,
+    init_val: Operand,
+}
+
+impl Reduction {
+    /// Get reduction from a head phi of loop.
+    fn from_phi(phi: InstPtr, lo: LoopPtr, init_bb: BBPtr) -> Option<Self> {
+        let incoming = downcast_ref::<Phi>(phi.as_ref().as_ref()).get_incoming_values();
+        if incoming.len() != 2 {
+            return None;
+        }
+        let init_val = incoming.iter().find(|(_, bb)| *bb == init_bb)?.0.clone();
+        let (Operand::Instruction(update), latch) =
+            incoming.iter().find(|(_, bb)| *bb != init_bb)?
+        else {
+            return None;
+        };
+        if !lo.is_in_loop(latch) || get_loop_users(*update, lo) != [phi] {
+            return None;
+        }
+
+        // Update is `phi op x`, or `select (phi cmp x), phi, x` for min and max
+        let op: Operand = phi.into();
+        let ty = phi.get_value_type();
+        let kind = match update.get_type() {
+            InstType::Add | InstType::Mul | InstType::FAdd | InstType::Xor => {
+                let operands = update.get_operand();
+                let other = if operands[0] == op {
+                    &operands[1]
+                } else {
+                    &operands[0]
+                };
+                if *other == op || get_loop_users(phi, lo) != [*update] {
+                    return None;
+                }
+                match update.get_type() {
+                    InstType::Add => ReductionKind::Add,
+                    InstType::Mul => ReductionKind::Mul,
+                    InstType::Xor => ReductionKind::Xor,
+                    _ if CONFIG.open_fast_math => ReductionKind::FAdd,
+                    _ => return None,
+                }
+            }
+            InstType::Select if ty == ValueType::Int => {
+                let select = downcast_ref::<Select>(update.as_ref().as_ref());
+                let Operand::Instruction(cond) = select.get_cond() else {
+                    return None;
+                };
+                if cond.get_type() != InstType::ICmp || get_loop_users(*cond, lo) != [*update] {
+                    return None;
+                }
+                let mut phi_users = get_loop_users(phi, lo);
+                phi_users.sort_by_key(|user| user.get_id());
+                let mut expected = vec![*cond, *update];
+                expected.sort_by_key(|user| user.get_id());
+                if phi_users != expected {
+                    return None;
+                }
+                let icmp = downcast_ref::<ICmp>(cond.as_ref().as_ref());
+                let (lhs, rhs) = (icmp.get_lhs(), icmp.get_rhs());
+                let (true_val, false_val) = (select.get_true_val(), select.get_false_val());
+                let same_operands =
+                    (lhs == true_val && rhs == false_val) || (lhs == false_val && rhs == true_val);
+                if !same_operands || lhs == rhs || (*lhs != op && *rhs != op) {
+                    return None;
+                }
+                let is_less = match icmp.op {
+                    ICmpOp::Slt | ICmpOp::Sle => true,
+                    ICmpOp::Sgt | ICmpOp::Sge => false,
+                    _ => return None,
+                };
+                if (lhs == true_val) == is_less {
+                    ReductionKind::Min
+                } else {
+                    ReductionKind::Max
+                }
+            }
+            _ => return None,
+        };
+        Some(Self {
+            phi,
+            kind,
+            init_val,
+        })
+    }
+}
+
+/// Get users of instruction in loop, without duplicates.
+fn get_loop_users(inst: InstPtr, lo: LoopPtr) -> Vec<InstPtr> {
+    let mut users: Vec<InstPtr> = inst
+        .get_user()
+        .iter()
+        .filter(|user| user.get_parent_bb().is_some_and(|bb| lo.is_in_loop(&bb)))
+        .cloned()
+        .collect();
+    users.dedup();
+    users
+}
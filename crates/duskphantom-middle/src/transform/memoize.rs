@@ -0,0 +1,272 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::effect_analysis::EffectAnalysis,
+    context,
+    ir::{
+        instruction::{downcast_ref, misc_inst::Call, misc_inst::ICmpOp, InstType},
+        BBPtr, Constant, FunPtr, GlobalPtr, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{pass_stats, region_clone::new_block, Transform};
+
+/// Number of bits of each argument used as table index, indexed by number of parameters.
+const INDEX_BITS: [usize; 3] = [0, 16, 8];
+
+/// Cache results of pure recursive functions in global tables.
+/// Arguments out of table range fall back to normal computation.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let effect_analysis = EffectAnalysis::new(program);
+    Memoize::new(program, effect_analysis).run_and_log()
+}
+
+pub struct Memoize<'a> {
+    program: &'a mut Program,
+    effect_analysis: EffectAnalysis,
+}
+
+impl<'a> Transform for Memoize<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "memoize".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if self.can_memoize(func) {
+                self.process_func(func)?;
+                changed = true;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> Memoize<'a> {
+    pub fn new(program: &'a mut Program, effect_analysis: EffectAnalysis) -> Self {
+        Self {
+            program,
+            effect_analysis,
+        }
+    }
+
+    /// Function should be pure, take and return integers, and recurse more than once.
+    fn can_memoize(&self, func: FunPtr) -> bool {
+        if func.is_lib() || func.is_main() || func.return_type != ValueType::Int {
+            return false;
+        }
+        if func.entry == func.exit {
+            return false;
+        }
+        if func.params.is_empty()
+            || func.params.len() >= INDEX_BITS.len()
+            || func.params.iter().any(|p| p.value_type != ValueType::Int)
+        {
+            return false;
+        }
+        let effect = &self.effect_analysis;
+        if effect.has_io_input.contains(&func)
+            || effect.has_io_output.contains(&func)
+            || effect.has_mem_input.contains(&func)
+            || effect.has_mem_output.contains(&func)
+        {
+            return false;
+        }
+
+        // A single recursive call is linear, caching does not pay off
+        let self_calls = func
+            .dfs_iter()
+            .flat_map(|bb| bb.iter())
+            .filter(|inst| {
+                inst.get_type() == InstType::Call
+                    && downcast_ref::<Call>(inst.as_ref().as_ref()).func == func
+            })
+            .count();
+        self_calls >= 2
+    }
+
+    /// Look up table before computing, and save result to table after computing.
+    fn process_func(&mut self, mut func: FunPtr) -> Result<()> {
+        let mut entry = func
+            .entry
+            .ok_or_else(|| anyhow!("function `{}` has no entry", func.name))
+            .with_context(|| context!())?;
+        let mut exit = func
+            .exit
+            .ok_or_else(|| anyhow!("function `{}` has no exit", func.name))
+            .with_context(|| context!())?;
+        let bits = INDEX_BITS[func.params.len()];
+        let size = 1 << (bits * func.params.len());
+        let values = self.new_table(func, "val", size)?;
+        let computed = self.new_table(func, "set", size)?;
+
+        // Split allocas to entry, and make the rest of entry the function body
+        let mut body = new_block(&mut self.program.mem_pool, &entry.name, "memo_body");
+        for inst in entry.iter() {
+            if inst.get_type() != InstType::Alloca {
+                body.push_back(inst);
+            }
+        }
+        entry.replace_exit(body);
+
+        // Compute table index and check if arguments are in range in entry
+        let mut in_range = None;
+        let mut index: Option<Operand> = None;
+        for param in func.params.clone() {
+            let param: Operand = param.into();
+            let ge = self.program.mem_pool.get_icmp(
+                ICmpOp::Sge,
+                ValueType::Int,
+                param.clone(),
+                Constant::Int(0).into(),
+            );
+            let lt = self.program.mem_pool.get_icmp(
+                ICmpOp::Slt,
+                ValueType::Int,
+                param.clone(),
+                Constant::Int(1 << bits).into(),
+            );
+            let both = self.program.mem_pool.get_and(ge.into(), lt.into());
+            entry.push_back(ge);
+            entry.push_back(lt);
+            entry.push_back(both);
+            in_range = Some(match in_range {
+                Some(prev) => {
+                    let and = self.program.mem_pool.get_and(prev, both.into());
+                    entry.push_back(and);
+                    and.into()
+                }
+                None => both.into(),
+            });
+            index = Some(match index {
+                Some(prev) => {
+                    let shl = self
+                        .program
+                        .mem_pool
+                        .get_shl(prev, Constant::Int(bits as i32).into());
+                    let add = self.program.mem_pool.get_add(shl.into(), param);
+                    entry.push_back(shl);
+                    entry.push_back(add);
+                    add.into()
+                }
+                None => param,
+            });
+        }
+        let in_range = in_range.unwrap();
+        let index = index.unwrap();
+
+        // Look up table if arguments are in range
+        let mut lookup = new_block(&mut self.program.mem_pool, &entry.name, "memo_lookup");
+        let mut hit = new_block(&mut self.program.mem_pool, &entry.name, "memo_hit");
+        entry.push_back(self.program.mem_pool.get_br(Some(in_range.clone())));
+        entry.set_true_bb(lookup);
+        entry.set_false_bb(body);
+        let flag = self.load_table(lookup, computed, size, index.clone());
+        let is_hit = self.program.mem_pool.get_icmp(
+            ICmpOp::Ne,
+            ValueType::Int,
+            flag.into(),
+            Constant::Int(0).into(),
+        );
+        lookup.push_back(is_hit);
+        lookup.push_back(self.program.mem_pool.get_br(Some(is_hit.into())));
+        lookup.set_true_bb(hit);
+        lookup.set_false_bb(body);
+        let cached = self.load_table(hit, values, size, index.clone());
+
+        // Save result if arguments are in range, then return from new exit
+        let mut ret = exit.get_last_inst();
+        let result = ret.get_operand()[0].clone();
+        ret.remove_self();
+        let mut save = new_block(&mut self.program.mem_pool, &exit.name, "memo_save");
+        let mut new_exit = new_block(&mut self.program.mem_pool, &exit.name, "memo_exit");
+        exit.push_back(self.program.mem_pool.get_br(Some(in_range)));
+        exit.set_true_bb(save);
+        exit.set_false_bb(new_exit);
+        let value_ptr = self.get_table_ptr(values, size, index.clone());
+        let flag_ptr = self.get_table_ptr(computed, size, index);
+        let store_value = self
+            .program
+            .mem_pool
+            .get_store(result.clone(), value_ptr.into());
+        let store_flag = self
+            .program
+            .mem_pool
+            .get_store(Constant::Int(1).into(), flag_ptr.into());
+        save.push_back(value_ptr);
+        save.push_back(store_value);
+        save.push_back(flag_ptr);
+        save.push_back(store_flag);
+        save.push_back(self.program.mem_pool.get_br(None));
+        save.set_true_bb(new_exit);
+        hit.push_back(self.program.mem_pool.get_br(None));
+        hit.set_true_bb(new_exit);
+
+        let ret_phi = self.program.mem_pool.get_phi(
+            ValueType::Int,
+            vec![(result.clone(), exit), (result, save), (cached.into(), hit)],
+        );
+        new_exit.push_back(ret_phi);
+        new_exit.push_back(self.program.mem_pool.get_ret(Some(ret_phi.into())));
+        func.exit = Some(new_exit);
+        pass_stats::bump(Self::name, "functions memoized", 1);
+        Ok(())
+    }
+
+    fn new_table(&mut self, func: FunPtr, meta: &str, size: usize) -> Result<GlobalPtr> {
+        let ty = ValueType::Array(Box::new(ValueType::Int), size);
+        let initializer = ty.default_initializer()?;
+        let table = self.program.mem_pool.new_global_variable(
+            format!("__{}_memo_{}", func.name, meta),
+            ty,
+            true,
+            initializer,
+        );
+        self.program.module.global_variables.push(table);
+        Ok(table)
+    }
+
+    fn get_table_ptr(&mut self, table: GlobalPtr, size: usize, index: Operand) -> InstPtr {
+        self.program.mem_pool.get_getelementptr(
+            ValueType::Array(Box::new(ValueType::Int), size),
+            table.into(),
+            vec![Constant::Int(0).into(), index],
+        )
+    }
+
+    fn load_table(
+        &mut self,
+        mut bb: BBPtr,
+        table: GlobalPtr,
+        size: usize,
+        index: Operand,
+    ) -> InstPtr {
+        let ptr = self.get_table_ptr(table, size, index);
+        let load = self.program.mem_pool.get_load(ValueType::Int, ptr.into());
+        bb.push_back(ptr);
+        bb.push_back(load);
+        load
+    }
+}
@@ -24,21 +24,42 @@ use duskphantom_utils::{cprintln, diff::diff};
 
 pub mod block_fuse;
 pub mod constant_fold;
+pub mod dead_arg_elim;
 pub mod dead_code_elim;
 pub mod func_inline;
+pub mod func_specialize;
+pub mod global_localize;
+pub mod gvn_pre;
+pub mod if_convert;
 pub mod inst_combine;
+pub mod jump_thread;
+pub mod lcssa;
 pub mod ldce;
 pub mod licm;
 pub mod load_elim;
 pub mod load_store_elim;
 pub mod loop_depth;
+pub mod loop_fission;
+pub mod loop_fusion;
+pub mod loop_interchange;
 pub mod loop_optimization;
+pub mod loop_peel;
+pub mod loop_rotate;
 pub mod loop_simplify;
+pub mod loop_unroll;
+pub mod loop_unswitch;
 pub mod make_parallel;
 pub mod mem2reg;
+pub mod memoize;
+pub mod pass_stats;
+pub mod reassociate;
 pub mod redundance_elim;
+pub mod region_clone;
+pub mod sccp;
 pub mod sink_code;
 pub mod store_elim;
+pub mod strength_reduce;
+pub mod tail_recursion_elim;
 pub mod ultimate_pass;
 
 pub trait Transform {
@@ -48,20 +69,38 @@ pub trait Transform {
 
     fn run(&mut self) -> Result<bool>;
 
-    #[allow(unused)]
     fn run_and_log(&mut self) -> Result<bool> {
         let time_before = Instant::now();
+        #[cfg(feature = "log_enabled")]
         let program_before = self.get_program_mut().module.gen_llvm_ir();
+        let size_before = pass_stats::is_enabled().then(|| {
+            let size = pass_stats::IrSize::of_module(&self.get_program_mut().module);
+            (pass_stats::enter_pass(), size)
+        });
         let changed = self.run()?;
-        let elapsed = time_before.elapsed().as_micros();
-        let program_after = self.get_program_mut().module.gen_llvm_ir();
-        cprintln!(
-            "## Pass {} {}\n\nTime elapsed = {} µs\n\nDiff:\n\n```diff\n{}```\n",
-            Self::name(),
-            if changed { "[CHANGED]" } else { "" },
-            elapsed,
-            diff(&program_before, &program_after)
-        );
+        let elapsed = time_before.elapsed();
+        if let Some((depth, size_before)) = size_before {
+            let size_after = pass_stats::IrSize::of_module(&self.get_program_mut().module);
+            pass_stats::record_pass(
+                &Self::name(),
+                changed,
+                elapsed,
+                depth,
+                &size_before,
+                &size_after,
+            );
+        }
+        #[cfg(feature = "log_enabled")]
+        {
+            let program_after = self.get_program_mut().module.gen_llvm_ir();
+            cprintln!(
+                "## Pass {} {}\n\nTime elapsed = {} µs\n\nDiff:\n\n```diff\n{}```\n",
+                Self::name(),
+                if changed { "[CHANGED]" } else { "" },
+                elapsed.as_micros(),
+                diff(&program_before, &program_after)
+            );
+        }
         Ok(changed)
     }
 }
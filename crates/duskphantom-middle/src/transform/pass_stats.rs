@@ -0,0 +1,377 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+//! Cheap per-pass instrumentation.
+//!
+//! Nothing is recorded until [`enable`] is called, so passes can report
+//! unconditionally. `Transform::run_and_log` feeds pass timing and IR size,
+//! passes add per-function timing with [`FuncTimer`] and named counters with
+//! [`bump`]. The collected data is retrieved with [`take`] at the end of
+//! compilation and rendered with [`Statistics::to_table`] or
+//! [`Statistics::to_json`].
+//!
+//! Data is kept in a thread local, so the pipeline must run on the thread that
+//! called [`enable`]; anything reported from other threads is dropped.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    fmt::Write,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::{Serialize, Serializer};
+
+use crate::ir::{FunPtr, Module};
+
+thread_local! {
+    static STATS: RefCell<Option<Statistics>> = const { RefCell::new(None) };
+}
+
+/// Start collecting statistics on current thread, discarding previous data.
+pub fn enable() {
+    STATS.with(|s| *s.borrow_mut() = Some(Statistics::default()));
+}
+
+/// Check if statistics are being collected on current thread.
+pub fn is_enabled() -> bool {
+    STATS.with(|s| s.borrow().is_some())
+}
+
+/// Stop collecting statistics and return collected data.
+pub fn take() -> Option<Statistics> {
+    STATS.with(|s| s.borrow_mut().take())
+}
+
+/// Add `n` to counter `counter` of pass `pass`.
+/// Pass name is given as a function so that it's only built when enabled.
+pub fn bump(pass: fn() -> String, counter: &str, n: usize) {
+    if n == 0 {
+        return;
+    }
+    with_stats(|stats| {
+        *stats
+            .counters
+            .entry((pass(), counter.to_string()))
+            .or_default() += n;
+    });
+}
+
+/// Mark the start of a pass run, returning its nesting depth for [`record_pass`].
+pub fn enter_pass() -> usize {
+    STATS.with(|s| match s.borrow_mut().as_mut() {
+        Some(stats) => {
+            stats.nested.push(Duration::ZERO);
+            stats.nested.len() - 1
+        }
+        None => 0,
+    })
+}
+
+/// Record a finished pass run started at `depth`, with IR size of each function
+/// before and after it. Time spent in passes nested in it is not counted.
+pub fn record_pass(
+    pass: &str,
+    changed: bool,
+    elapsed: Duration,
+    depth: usize,
+    before: &[(String, IrSize)],
+    after: &[(String, IrSize)],
+) {
+    with_stats(|stats| {
+        // Deeper entries are left by nested runs that failed, drop them too
+        let nested = stats.nested.get(depth).copied().unwrap_or_default();
+        stats.nested.truncate(depth);
+        if let Some(parent) = stats.nested.last_mut() {
+            *parent += elapsed;
+        }
+        let record = stats.pass_mut(pass);
+        record.runs += 1;
+        record.changed_runs += changed as usize;
+        record.time += elapsed.saturating_sub(nested);
+        for (func, size) in before {
+            record.func_mut(func).before += *size;
+        }
+        for (func, size) in after {
+            record.func_mut(func).after += *size;
+        }
+    });
+}
+
+fn with_stats(f: impl FnOnce(&mut Statistics)) {
+    STATS.with(|s| {
+        if let Some(stats) = s.borrow_mut().as_mut() {
+            f(stats);
+        }
+    });
+}
+
+/// Number of instructions and basic blocks.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct IrSize {
+    pub insts: usize,
+    pub blocks: usize,
+}
+
+impl IrSize {
+    pub fn of_func(func: FunPtr) -> Self {
+        let mut size = Self::default();
+        for bb in func.dfs_iter() {
+            size.blocks += 1;
+            size.insts += bb.iter().count();
+        }
+        size
+    }
+
+    /// Size of each non-library function in module, paired with function name.
+    pub fn of_module(module: &Module) -> Vec<(String, IrSize)> {
+        module
+            .functions
+            .iter()
+            .filter(|func| !func.is_lib())
+            .map(|func| (func.name.clone(), Self::of_func(*func)))
+            .collect()
+    }
+}
+
+impl std::ops::AddAssign for IrSize {
+    fn add_assign(&mut self, rhs: Self) {
+        self.insts += rhs.insts;
+        self.blocks += rhs.blocks;
+    }
+}
+
+/// Measures time spent by a pass on a function until dropped.
+pub struct FuncTimer {
+    start: Option<(String, String, Instant)>,
+}
+
+impl FuncTimer {
+    pub fn new(pass: fn() -> String, func: FunPtr) -> Self {
+        let start = is_enabled().then(|| (pass(), func.name.clone(), Instant::now()));
+        Self { start }
+    }
+}
+
+impl Drop for FuncTimer {
+    fn drop(&mut self) {
+        if let Some((pass, func, start)) = self.start.take() {
+            let elapsed = start.elapsed();
+            with_stats(|stats| stats.pass_mut(&pass).func_mut(&func).time += elapsed);
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct FuncRecord {
+    pub name: String,
+    #[serde(rename = "time_us", serialize_with = "as_micros")]
+    pub time: Duration,
+    pub before: IrSize,
+    pub after: IrSize,
+}
+
+/// Accumulated data of a pass, over all its runs.
+/// Sizes are summed over runs, so `after - before` is the net effect of the pass.
+#[derive(Debug, Default, Clone)]
+pub struct PassRecord {
+    pub name: String,
+    pub runs: usize,
+    pub changed_runs: usize,
+    pub time: Duration,
+    pub funcs: Vec<FuncRecord>,
+}
+
+impl PassRecord {
+    pub fn before(&self) -> IrSize {
+        let mut size = IrSize::default();
+        self.funcs.iter().for_each(|f| size += f.before);
+        size
+    }
+
+    pub fn after(&self) -> IrSize {
+        let mut size = IrSize::default();
+        self.funcs.iter().for_each(|f| size += f.after);
+        size
+    }
+
+    fn func_mut(&mut self, name: &str) -> &mut FuncRecord {
+        let index = match self.funcs.iter().position(|f| f.name == name) {
+            Some(index) => index,
+            None => {
+                self.funcs.push(FuncRecord {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.funcs.len() - 1
+            }
+        };
+        &mut self.funcs[index]
+    }
+}
+
+/// Statistics collected during compilation. Passes are kept in order of first run.
+#[derive(Debug, Default, Clone)]
+pub struct Statistics {
+    pub passes: Vec<PassRecord>,
+    pub counters: BTreeMap<(String, String), usize>,
+
+    /// Time spent in nested passes, for each pass run in progress.
+    nested: Vec<Duration>,
+}
+
+impl Statistics {
+    fn pass_mut(&mut self, name: &str) -> &mut PassRecord {
+        let index = match self.passes.iter().position(|p| p.name == name) {
+            Some(index) => index,
+            None => {
+                self.passes.push(PassRecord {
+                    name: name.to_string(),
+                    ..Default::default()
+                });
+                self.passes.len() - 1
+            }
+        };
+        &mut self.passes[index]
+    }
+
+    /// Render pass timing table.
+    /// Per-function rows and counters are included if `verbose` is set.
+    pub fn to_table(&self, verbose: bool) -> String {
+        let mut out = String::new();
+        let total: Duration = self.passes.iter().map(|p| p.time).sum();
+        writeln!(
+            out,
+            "{:<24} {:>6} {:>8} {:>12} {:>7} {:>17} {:>13}",
+            "pass", "runs", "changed", "time(us)", "time%", "insts", "blocks"
+        )
+        .unwrap();
+        for pass in self.passes.iter() {
+            let (before, after) = (pass.before(), pass.after());
+            writeln!(
+                out,
+                "{:<24} {:>6} {:>8} {:>12} {:>6.1}% {:>17} {:>13}",
+                pass.name,
+                pass.runs,
+                pass.changed_runs,
+                pass.time.as_micros(),
+                percent(pass.time, total),
+                format!("{} -> {}", before.insts, after.insts),
+                format!("{} -> {}", before.blocks, after.blocks),
+            )
+            .unwrap();
+            if !verbose {
+                continue;
+            }
+            for func in pass.funcs.iter() {
+                writeln!(
+                    out,
+                    "  {:<22} {:>6} {:>8} {:>12} {:>7} {:>17} {:>13}",
+                    func.name,
+                    "",
+                    "",
+                    func.time.as_micros(),
+                    "",
+                    format!("{} -> {}", func.before.insts, func.after.insts),
+                    format!("{} -> {}", func.before.blocks, func.after.blocks),
+                )
+                .unwrap();
+            }
+        }
+        writeln!(
+            out,
+            "{:<24} {:>6} {:>8} {:>12}",
+            "total",
+            "",
+            "",
+            total.as_micros()
+        )
+        .unwrap();
+        if verbose && !self.counters.is_empty() {
+            writeln!(out).unwrap();
+            writeln!(out, "{:<24} {:<32} {:>8}", "pass", "counter", "value").unwrap();
+            for ((pass, counter), value) in self.counters.iter() {
+                writeln!(out, "{:<24} {:<32} {:>8}", pass, counter, value).unwrap();
+            }
+        }
+        out
+    }
+
+    /// Render all data as a JSON object, times are in microseconds.
+    pub fn to_json(&self) -> Result<String> {
+        let passes = self
+            .passes
+            .iter()
+            .map(|pass| PassJson {
+                name: &pass.name,
+                runs: pass.runs,
+                changed_runs: pass.changed_runs,
+                time: pass.time,
+                before: pass.before(),
+                after: pass.after(),
+                functions: &pass.funcs,
+            })
+            .collect();
+        let counters = self
+            .counters
+            .iter()
+            .map(|((pass, counter), value)| CounterJson {
+                pass,
+                counter,
+                value: *value,
+            })
+            .collect();
+        Ok(serde_json::to_string(&StatisticsJson { passes, counters })?)
+    }
+}
+
+#[derive(Serialize)]
+struct StatisticsJson<'a> {
+    passes: Vec<PassJson<'a>>,
+    counters: Vec<CounterJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct PassJson<'a> {
+    name: &'a str,
+    runs: usize,
+    changed_runs: usize,
+    #[serde(rename = "time_us", serialize_with = "as_micros")]
+    time: Duration,
+    before: IrSize,
+    after: IrSize,
+    functions: &'a [FuncRecord],
+}
+
+#[derive(Serialize)]
+struct CounterJson<'a> {
+    pass: &'a str,
+    counter: &'a str,
+    value: usize,
+}
+
+fn as_micros<S: Serializer>(time: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u128(time.as_micros())
+}
+
+fn percent(part: Duration, total: Duration) -> f64 {
+    if total.is_zero() {
+        0.0
+    } else {
+        part.as_secs_f64() / total.as_secs_f64() * 100.0
+    }
+}
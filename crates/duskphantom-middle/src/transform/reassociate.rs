@@ -0,0 +1,531 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+
+use crate::{
+    ir::{instruction::InstType, BBPtr, Constant, FunPtr, InstPtr, Operand},
+    Program,
+};
+
+use super::{
+    pass_stats::{self, FuncTimer},
+    Transform,
+};
+
+/// Reassociate trees of associative and commutative instructions.
+/// Operands are ordered by rank, which is higher for values computed later in the function,
+/// so that constants are folded together and loop-invariant operands form subexpressions
+/// hoistable by licm. Like terms of sums are combined and common factors are extracted.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    Reassociate::new(program).run_and_log()
+}
+
+pub struct Reassociate<'a> {
+    program: &'a mut Program,
+    rank: HashMap<InstPtr, usize>,
+    block_rank: HashMap<BBPtr, usize>,
+    params: HashMap<Operand, usize>,
+    created: Vec<InstPtr>,
+}
+
+impl<'a> Transform for Reassociate<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "reassociate".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let _timer = FuncTimer::new(Self::name, func);
+            changed |= self.run_func(func);
+        }
+        Ok(changed)
+    }
+}
+
+/// A term of a sum, which is a product of factors and a constant coefficient.
+struct Term {
+    factors: Vec<Operand>,
+    coef: i32,
+}
+
+impl<'a> Reassociate<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self {
+            program,
+            rank: HashMap::new(),
+            block_rank: HashMap::new(),
+            params: HashMap::new(),
+            created: Vec::new(),
+        }
+    }
+
+    fn run_func(&mut self, func: FunPtr) -> bool {
+        self.build_rank(func);
+
+        // Collect roots first, as rewriting removes instructions
+        let mut roots = Vec::new();
+        for bb in func.rpo_iter() {
+            for inst in bb.iter() {
+                if is_root(inst) {
+                    roots.push(inst);
+                }
+            }
+        }
+
+        let mut count = 0;
+        for root in roots {
+            if self.rewrite(root) {
+                count += 1;
+            }
+        }
+        pass_stats::bump(Self::name, "trees reassociated", count);
+        count > 0
+    }
+
+    /// Rank parameters by position, and blocks by reverse post order. Instructions that can be
+    /// moved have rank one more than their operands, and others have rank of their block.
+    fn build_rank(&mut self, func: FunPtr) {
+        self.rank.clear();
+        self.block_rank.clear();
+        self.params.clear();
+        for (i, param) in func.params.iter().enumerate() {
+            self.params.insert(Operand::Parameter(*param), i + 1);
+        }
+        for (i, bb) in func.rpo_iter().enumerate() {
+            let bb_rank = (func.params.len() + i + 1) << 16;
+            self.block_rank.insert(bb, bb_rank);
+            for inst in bb.iter() {
+                let rank = if is_movable(inst.get_type()) {
+                    self.get_inst_rank(inst, bb_rank)
+                } else {
+                    bb_rank
+                };
+                self.rank.insert(inst, rank);
+            }
+        }
+    }
+
+    fn get_inst_rank(&self, inst: InstPtr, bb_rank: usize) -> usize {
+        let max = inst
+            .get_operand()
+            .iter()
+            .map(|op| self.get_rank(op))
+            .max()
+            .unwrap_or(0);
+        max.saturating_add(1).min(bb_rank)
+    }
+
+    fn get_rank(&self, op: &Operand) -> usize {
+        match op {
+            Operand::Instruction(inst) => self.rank.get(inst).cloned().unwrap_or(usize::MAX),
+            Operand::Parameter(_) => self.params.get(op).cloned().unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Get sort key of an operand, ordering by rank and then by definition order.
+    fn get_key(&self, op: &Operand) -> (usize, usize) {
+        match op {
+            Operand::Instruction(inst) => (self.get_rank(op), inst.get_id()),
+            _ => (self.get_rank(op), 0),
+        }
+    }
+
+    /// Rewrite tree rooted at `root` to canonical form. Returns true if changed.
+    fn rewrite(&mut self, mut root: InstPtr) -> bool {
+        // Root may be removed as part of a rewritten tree
+        let Some(bb) = root.get_parent_bb() else {
+            return false;
+        };
+        let mut nodes = vec![root];
+        self.created.clear();
+        let result = match get_kind(root.get_type()) {
+            InstType::Add => {
+                let mut terms = Vec::new();
+                collect_sum(&root.get_operand()[0], false, bb, &mut terms, &mut nodes);
+                let rhs_negated = root.get_type() == InstType::Sub;
+                collect_sum(
+                    &root.get_operand()[1],
+                    rhs_negated,
+                    bb,
+                    &mut terms,
+                    &mut nodes,
+                );
+                self.build_sum(terms, root)
+            }
+            InstType::Mul => {
+                let mut factors = Vec::new();
+                let mut coef = 1;
+                for op in root.get_operand() {
+                    collect_product(op, bb, &mut factors, &mut coef, &mut nodes);
+                }
+                self.build_product(factors, coef, root)
+            }
+            kind => {
+                let mut leaves = Vec::new();
+                for op in root.get_operand() {
+                    collect_leaves(op, kind, bb, &mut leaves, &mut nodes);
+                }
+                self.build_bool(kind, leaves, root)
+            }
+        };
+
+        // Discard rewritten tree if it is the same as original one
+        if self.same_expr(&result, &root.into()) {
+            for mut inst in self.created.drain(..).rev() {
+                inst.remove_self();
+            }
+            return false;
+        }
+        root.replace_self(&result);
+        for mut node in nodes.into_iter().skip(1) {
+            if node.get_user().is_empty() {
+                node.remove_self();
+            }
+        }
+        true
+    }
+
+    /// Build canonical form of a sum before `pos`.
+    fn build_sum(&mut self, terms: Vec<Term>, pos: InstPtr) -> Operand {
+        // Combine like terms, after sorting factors so that equal products compare equal
+        let mut combined: Vec<Term> = Vec::new();
+        for mut term in terms {
+            term.factors.sort_by_key(|op| self.get_key(op));
+            match combined.iter_mut().find(|t| t.factors == term.factors) {
+                Some(t) => t.coef = t.coef.wrapping_add(term.coef),
+                None => combined.push(term),
+            }
+        }
+        combined.retain(|t| t.coef != 0);
+
+        // Extract common factors, the most common one first
+        let mut terms = Vec::new();
+        while let Some(factor) = self.get_common_factor(&combined) {
+            let (with, without): (Vec<Term>, Vec<Term>) = combined
+                .into_iter()
+                .partition(|t| t.factors.contains(&factor));
+            let inner: Vec<Term> = with
+                .into_iter()
+                .map(|mut t| {
+                    let index = t.factors.iter().position(|f| *f == factor).unwrap_or(0);
+                    t.factors.remove(index);
+                    t
+                })
+                .collect();
+            let inner = self.build_sum(inner, pos);
+            let mut factors = vec![factor, inner];
+            factors.sort_by_key(|op| self.get_key(op));
+            terms.push(Term { factors, coef: 1 });
+            combined = without;
+        }
+        terms.extend(combined);
+
+        // Emit terms in order of rank, constant last
+        let constant: i32 = terms
+            .iter()
+            .filter(|t| t.factors.is_empty())
+            .fold(0, |acc, t| acc.wrapping_add(t.coef));
+        terms.retain(|t| !t.factors.is_empty());
+        terms.sort_by_key(|t| t.factors.iter().map(|f| self.get_key(f)).max());
+        let mut acc: Option<Operand> = None;
+        for term in terms {
+            let negative = term.coef < 0 && term.coef != i32::MIN;
+            let coef = if negative { -term.coef } else { term.coef };
+            let value = self.build_product(term.factors, coef, pos);
+            acc = Some(match (acc, negative) {
+                (None, false) => value,
+                (None, true) => self.emit(InstType::Sub, Constant::Int(0).into(), value, pos),
+                (Some(acc), false) => self.emit(InstType::Add, acc, value, pos),
+                (Some(acc), true) => self.emit(InstType::Sub, acc, value, pos),
+            });
+        }
+        match acc {
+            None => Constant::Int(constant).into(),
+            Some(acc) if constant == 0 => acc,
+            Some(acc) => self.emit(InstType::Add, acc, Constant::Int(constant).into(), pos),
+        }
+    }
+
+    /// Get a non-constant factor shared by at least two terms.
+    fn get_common_factor(&self, terms: &[Term]) -> Option<Operand> {
+        let mut count: Vec<(Operand, usize)> = Vec::new();
+        for term in terms {
+            let mut factors = term.factors.clone();
+            factors.dedup();
+            for factor in factors {
+                match count.iter_mut().find(|(f, _)| *f == factor) {
+                    Some((_, n)) => *n += 1,
+                    None => count.push((factor, 1)),
+                }
+            }
+        }
+        count
+            .into_iter()
+            .filter(|(_, n)| *n >= 2)
+            .min_by_key(|(f, n)| (usize::MAX - n, self.get_key(f)))
+            .map(|(f, _)| f)
+    }
+
+    /// Build canonical form of a product before `pos`.
+    fn build_product(&mut self, mut factors: Vec<Operand>, coef: i32, pos: InstPtr) -> Operand {
+        if coef == 0 {
+            return Constant::Int(0).into();
+        }
+        factors.sort_by_key(|op| self.get_key(op));
+        let mut acc: Option<Operand> = None;
+        for factor in factors {
+            acc = Some(match acc {
+                None => factor,
+                Some(acc) => self.emit(InstType::Mul, acc, factor, pos),
+            });
+        }
+        match acc {
+            None => Constant::Int(coef).into(),
+            Some(acc) if coef == 1 => acc,
+            Some(acc) => self.emit(InstType::Mul, acc, Constant::Int(coef).into(), pos),
+        }
+    }
+
+    /// Build canonical form of a boolean `and`, `or` or `xor` before `pos`.
+    fn build_bool(&mut self, kind: InstType, leaves: Vec<Operand>, pos: InstPtr) -> Operand {
+        let mut constant = kind == InstType::And;
+        let mut operands: Vec<Operand> = Vec::new();
+        for leaf in leaves {
+            if let Operand::Constant(Constant::Bool(b)) = leaf {
+                match kind {
+                    InstType::And => constant &= b,
+                    InstType::Or => constant |= b,
+                    _ => constant ^= b,
+                }
+                continue;
+            }
+
+            // Duplicates are idempotent in `and` and `or`, and cancel in `xor`
+            match operands.iter().position(|op| *op == leaf) {
+                Some(index) if kind == InstType::Xor => {
+                    operands.remove(index);
+                }
+                Some(_) => (),
+                None => operands.push(leaf),
+            }
+        }
+
+        // Constant that absorbs all operands
+        if kind == InstType::And && !constant || kind == InstType::Or && constant {
+            return Constant::Bool(constant).into();
+        }
+        operands.sort_by_key(|op| self.get_key(op));
+        let mut acc: Option<Operand> = None;
+        for op in operands {
+            acc = Some(match acc {
+                None => op,
+                Some(acc) => self.emit(kind, acc, op, pos),
+            });
+        }
+        match acc {
+            None => Constant::Bool(constant).into(),
+            Some(acc) if kind == InstType::Xor && constant => {
+                self.emit(kind, acc, Constant::Bool(true).into(), pos)
+            }
+            Some(acc) => acc,
+        }
+    }
+
+    /// Check if rewritten operand `new` computes the same expression as `old` with the same
+    /// instructions. Only newly created instructions are looked through.
+    fn same_expr(&self, new: &Operand, old: &Operand) -> bool {
+        if new == old {
+            return true;
+        }
+        let (Operand::Instruction(new), Operand::Instruction(old)) = (new, old) else {
+            return false;
+        };
+        self.created.contains(new)
+            && new.get_type() == old.get_type()
+            && new.get_operand().len() == old.get_operand().len()
+            && new
+                .get_operand()
+                .iter()
+                .zip(old.get_operand().iter())
+                .all(|(new, old)| self.same_expr(new, old))
+    }
+
+    /// Insert a binary instruction before `pos`, and rank it like its operands.
+    fn emit(&mut self, ty: InstType, lhs: Operand, rhs: Operand, mut pos: InstPtr) -> Operand {
+        let mem_pool = &mut self.program.mem_pool;
+        let inst = match ty {
+            InstType::Add => mem_pool.get_add(lhs, rhs),
+            InstType::Sub => mem_pool.get_sub(lhs, rhs),
+            InstType::Mul => mem_pool.get_mul(lhs, rhs),
+            InstType::And => mem_pool.get_and(lhs, rhs),
+            InstType::Or => mem_pool.get_or(lhs, rhs),
+            _ => mem_pool.get_xor(lhs, rhs),
+        };
+        pos.insert_before(inst);
+        let bb_rank = pos
+            .get_parent_bb()
+            .and_then(|bb| self.block_rank.get(&bb).cloned())
+            .unwrap_or(usize::MAX);
+        let rank = self.get_inst_rank(inst, bb_rank);
+        self.rank.insert(inst, rank);
+        self.created.push(inst);
+        inst.into()
+    }
+}
+
+/// Get kind of tree an instruction can be in. Subtraction is in the same tree as addition.
+fn get_kind(ty: InstType) -> InstType {
+    match ty {
+        InstType::Sub => InstType::Add,
+        _ => ty,
+    }
+}
+
+/// Check if instruction type is associative and commutative, or is subtraction.
+fn is_tree_node(ty: InstType) -> bool {
+    matches!(
+        ty,
+        InstType::Add
+            | InstType::Sub
+            | InstType::Mul
+            | InstType::And
+            | InstType::Or
+            | InstType::Xor
+    )
+}
+
+/// Check if instruction can be moved freely, as it has no side effects and no fixed position.
+fn is_movable(ty: InstType) -> bool {
+    is_tree_node(ty)
+        || matches!(
+            ty,
+            InstType::SDiv
+                | InstType::UDiv
+                | InstType::SRem
+                | InstType::URem
+                | InstType::Shl
+                | InstType::LShr
+                | InstType::AShr
+                | InstType::ICmp
+                | InstType::ZextTo
+                | InstType::SextTo
+        )
+}
+
+/// Check if instruction is an interior node of a tree in `bb`, which is used only once.
+fn is_interior(inst: InstPtr, bb: BBPtr) -> bool {
+    inst.get_user().len() == 1 && inst.get_parent_bb() == Some(bb)
+}
+
+/// Check if instruction is root of a tree. Products used by a sum belong to the sum.
+fn is_root(inst: InstPtr) -> bool {
+    if !is_tree_node(inst.get_type()) {
+        return false;
+    }
+    let Some(bb) = inst.get_parent_bb() else {
+        return false;
+    };
+    if !is_interior(inst, bb) {
+        return true;
+    }
+    let user = inst.get_user()[0];
+    if user.get_parent_bb() != Some(bb) {
+        return true;
+    }
+    let kind = get_kind(inst.get_type());
+    let user_kind = get_kind(user.get_type());
+    !(kind == user_kind || kind == InstType::Mul && user_kind == InstType::Add)
+}
+
+/// Collect terms of a sum, negating them if `negated`.
+fn collect_sum(
+    op: &Operand,
+    negated: bool,
+    bb: BBPtr,
+    terms: &mut Vec<Term>,
+    nodes: &mut Vec<InstPtr>,
+) {
+    if let Operand::Instruction(inst) = op {
+        let ty = inst.get_type();
+        if get_kind(ty) == InstType::Add && is_interior(*inst, bb) {
+            nodes.push(*inst);
+            collect_sum(&inst.get_operand()[0], negated, bb, terms, nodes);
+            let rhs_negated = negated ^ (ty == InstType::Sub);
+            collect_sum(&inst.get_operand()[1], rhs_negated, bb, terms, nodes);
+            return;
+        }
+    }
+    let mut factors = Vec::new();
+    let mut coef = 1;
+    collect_product(op, bb, &mut factors, &mut coef, nodes);
+    if negated {
+        coef = coef.wrapping_neg();
+    }
+    terms.push(Term { factors, coef });
+}
+
+/// Collect factors of a product, multiplying constants into `coef`.
+fn collect_product(
+    op: &Operand,
+    bb: BBPtr,
+    factors: &mut Vec<Operand>,
+    coef: &mut i32,
+    nodes: &mut Vec<InstPtr>,
+) {
+    match op {
+        Operand::Constant(Constant::Int(c)) => *coef = coef.wrapping_mul(*c),
+        Operand::Instruction(inst)
+            if inst.get_type() == InstType::Mul && is_interior(*inst, bb) =>
+        {
+            nodes.push(*inst);
+            for op in inst.get_operand() {
+                collect_product(op, bb, factors, coef, nodes);
+            }
+        }
+        _ => factors.push(op.clone()),
+    }
+}
+
+/// Collect leaves of a boolean tree of given kind.
+fn collect_leaves(
+    op: &Operand,
+    kind: InstType,
+    bb: BBPtr,
+    leaves: &mut Vec<Operand>,
+    nodes: &mut Vec<InstPtr>,
+) {
+    match op {
+        Operand::Instruction(inst) if inst.get_type() == kind && is_interior(*inst, bb) => {
+            nodes.push(*inst);
+            for op in inst.get_operand() {
+                collect_leaves(op, kind, bb, leaves, nodes);
+            }
+        }
+        _ => leaves.push(op.clone()),
+    }
+}
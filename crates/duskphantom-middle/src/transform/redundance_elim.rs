@@ -29,7 +29,7 @@ use crate::{
     Program,
 };
 
-use super::Transform;
+use super::{pass_stats::FuncTimer, Transform};
 
 pub fn optimize_program(program: &mut Program) -> Result<bool> {
     let effect_analysis = EffectAnalysis::new(program);
@@ -59,6 +59,7 @@ impl<'a> Transform for RedundanceElim<'a> {
             if func.is_lib() {
                 continue;
             }
+            let _timer = FuncTimer::new(Self::name, func);
             let mut dom_tree = DominatorTree::new(func);
 
             // Implementation of Expr::Hash does not use it's mutable content,
@@ -0,0 +1,160 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+
+use crate::{
+    ir::{
+        instruction::{downcast_mut, downcast_ref, misc_inst::Phi, InstType},
+        BBPtr, InstPtr, Operand,
+    },
+    IRBuilder,
+};
+
+/// Copy of a region of blocks, where copied instructions use copied values.
+/// Edges of the copy are left for the caller, see `copy_edges`.
+pub struct RegionClone {
+    blocks: Vec<BBPtr>,
+
+    /// Mapping from original blocks to copies
+    pub block_map: HashMap<BBPtr, BBPtr>,
+
+    /// Mapping from original values to copies, including values given before copying
+    pub operand_map: HashMap<Operand, Operand>,
+}
+
+impl RegionClone {
+    /// Copy blocks and their instructions, naming new blocks with `meta` tag.
+    /// Instructions mapped in `operand_map` are replaced by the given values instead of copied,
+    /// and so are instructions rejected by `keep`, which are dropped.
+    pub fn new(
+        ir_builder: &mut IRBuilder,
+        blocks: &[BBPtr],
+        meta: &str,
+        operand_map: HashMap<Operand, Operand>,
+        keep: impl Fn(InstPtr) -> bool,
+    ) -> Self {
+        let mut res = Self {
+            blocks: blocks.to_vec(),
+            block_map: HashMap::new(),
+            operand_map,
+        };
+
+        // Copy blocks and instructions
+        let mut inst_map: Vec<(InstPtr, InstPtr)> = Vec::new();
+        for bb in blocks.iter() {
+            let mut new_bb = new_block(ir_builder, &bb.name, meta);
+            for inst in bb.iter() {
+                if res.operand_map.contains_key(&inst.into()) || !keep(inst) {
+                    continue;
+                }
+                let new_inst = ir_builder.copy_instruction(inst.as_ref().as_ref());
+                new_bb.push_back(new_inst);
+                res.operand_map.insert(inst.into(), new_inst.into());
+                inst_map.push((inst, new_inst));
+            }
+            res.block_map.insert(*bb, new_bb);
+        }
+
+        // Copy operands, phi operands may refer to later instructions
+        for (inst, mut new_inst) in inst_map {
+            if inst.get_type() == InstType::Phi {
+                let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+                let new_phi = downcast_mut::<Phi>(new_inst.as_mut());
+                for (op, bb) in phi.get_incoming_values() {
+                    new_phi.add_incoming_value(res.map_operand(op), res.map_block(*bb));
+                }
+            } else {
+                for op in inst.get_operand() {
+                    new_inst.add_operand(res.map_operand(op));
+                }
+            }
+        }
+        res
+    }
+
+    pub fn map_operand(&self, op: &Operand) -> Operand {
+        self.operand_map.get(op).cloned().unwrap_or(op.clone())
+    }
+
+    /// Get copy of block, or the block itself if it's not copied.
+    pub fn map_block(&self, bb: BBPtr) -> BBPtr {
+        self.block_map.get(&bb).cloned().unwrap_or(bb)
+    }
+
+    /// Copy edges of original blocks, with successors mapped by `map_succ`.
+    /// Edges mapped to `None` are left out.
+    pub fn copy_edges(&self, map_succ: impl Fn(BBPtr) -> Option<BBPtr>) {
+        for bb in self.blocks.iter() {
+            let mut new_bb = self.block_map[bb];
+            for succ in bb.get_succ_bb().clone() {
+                let Some(target) = map_succ(succ) else {
+                    continue;
+                };
+                if new_bb.get_succ_bb().is_empty() {
+                    new_bb.set_true_bb(target);
+                } else {
+                    new_bb.set_false_bb(target);
+                }
+            }
+        }
+    }
+
+    /// Add incoming values to phis in `bb` for copies of its predecessors,
+    /// which should already jump to `bb`.
+    pub fn extend_phis(&self, bb: BBPtr) {
+        for mut inst in bb.iter() {
+            if inst.get_type() != InstType::Phi {
+                break;
+            }
+            let phi = downcast_mut::<Phi>(inst.as_mut());
+            let incoming: Vec<(Operand, BBPtr)> = phi
+                .get_incoming_values()
+                .iter()
+                .filter_map(|(op, pred)| {
+                    let new_pred = self.block_map.get(pred)?;
+                    new_pred
+                        .get_succ_bb()
+                        .contains(&bb)
+                        .then(|| (self.map_operand(op), *new_pred))
+                })
+                .collect();
+            for (op, pred) in incoming {
+                phi.add_incoming_value(op, pred);
+            }
+        }
+    }
+}
+
+/// Allocate an empty block named `{base}_{tag}{id}`.
+pub fn new_block(ir_builder: &mut IRBuilder, base: &str, tag: &str) -> BBPtr {
+    let mut bb = ir_builder.new_basicblock(String::new());
+    bb.name = format!("{}_{}{}", base, tag, bb.id);
+    bb
+}
+
+/// Copy instruction with given operands. Phi is copied without incoming values.
+pub fn copy_with_operands(
+    ir_builder: &mut IRBuilder,
+    inst: InstPtr,
+    operands: impl IntoIterator<Item = Operand>,
+) -> InstPtr {
+    let mut new_inst = ir_builder.copy_instruction(inst.as_ref().as_ref());
+    for op in operands {
+        new_inst.add_operand(op);
+    }
+    new_inst
+}
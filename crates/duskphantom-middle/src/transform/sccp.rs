@@ -0,0 +1,408 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::Result;
+
+use crate::{
+    analysis::call_graph::CallGraph,
+    ir::{
+        instruction::{
+            downcast_ref,
+            misc_inst::{Call, Phi},
+            InstType,
+        },
+        BBPtr, Constant, FunPtr, InstPtr, Operand, ParaPtr,
+    },
+    Program,
+};
+
+use super::{constant_fold::fold_inst, pass_stats, Transform};
+
+/// Propagate constants through phis, branches, call arguments and return values,
+/// and prune blocks that are never executed.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    let call_graph = CallGraph::new(program);
+    SCCP::new(program, call_graph).run_and_log()
+}
+
+/// Lattice value of an SSA value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Lattice {
+    /// Not yet known, might be any constant
+    Unknown,
+    Constant(Constant),
+    /// Not a constant
+    Varying,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Unknown, x) | (x, Lattice::Unknown) => x.clone(),
+            (Lattice::Constant(a), Lattice::Constant(b)) if a == b => self.clone(),
+            _ => Lattice::Varying,
+        }
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+pub struct SCCP<'a> {
+    program: &'a mut Program,
+    call_graph: CallGraph,
+    exits: HashMap<BBPtr, FunPtr>,
+    values: HashMap<InstPtr, Lattice>,
+    params: HashMap<ParaPtr, Lattice>,
+    returns: HashMap<FunPtr, Lattice>,
+    executable_blocks: HashSet<BBPtr>,
+    executable_edges: HashSet<(BBPtr, BBPtr)>,
+    executable_funcs: HashSet<FunPtr>,
+    block_worklist: Vec<BBPtr>,
+    inst_worklist: Vec<InstPtr>,
+}
+
+impl<'a> Transform for SCCP<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "sccp".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let Some(main) = self
+            .program
+            .module
+            .functions
+            .iter()
+            .find(|func| func.name == "main")
+            .cloned()
+        else {
+            return Ok(false);
+        };
+        self.mark_func(main);
+        self.solve();
+
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if self.executable_funcs.contains(&func) {
+                changed |= self.rewrite_func(func);
+            }
+        }
+        Ok(changed)
+    }
+}
+
+impl<'a> SCCP<'a> {
+    pub fn new(program: &'a mut Program, call_graph: CallGraph) -> Self {
+        let exits = program
+            .module
+            .functions
+            .iter()
+            .filter_map(|func| Some((func.exit?, *func)))
+            .collect();
+        Self {
+            program,
+            call_graph,
+            exits,
+            values: HashMap::new(),
+            params: HashMap::new(),
+            returns: HashMap::new(),
+            executable_blocks: HashSet::new(),
+            executable_edges: HashSet::new(),
+            executable_funcs: HashSet::new(),
+            block_worklist: Vec::new(),
+            inst_worklist: Vec::new(),
+        }
+    }
+
+    /// Run both worklists until lattice values and executable edges no longer change.
+    fn solve(&mut self) {
+        loop {
+            if let Some(bb) = self.block_worklist.pop() {
+                for inst in bb.iter() {
+                    self.visit(inst);
+                }
+            } else if let Some(inst) = self.inst_worklist.pop() {
+                let parent = inst.get_parent_bb();
+                if parent.is_some_and(|bb| self.executable_blocks.contains(&bb)) {
+                    self.visit(inst);
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn mark_func(&mut self, func: FunPtr) {
+        if !self.executable_funcs.insert(func) {
+            return;
+        }
+        if let Some(entry) = func.entry {
+            self.executable_blocks.insert(entry);
+            self.block_worklist.push(entry);
+        }
+    }
+
+    fn mark_edge(&mut self, from: BBPtr, to: BBPtr) {
+        if !self.executable_edges.insert((from, to)) {
+            return;
+        }
+        if self.executable_blocks.insert(to) {
+            self.block_worklist.push(to);
+        } else {
+            // A new incoming edge only affects phis of an executed block
+            for inst in to.iter() {
+                if inst.get_type() == InstType::Phi {
+                    self.inst_worklist.push(inst);
+                }
+            }
+        }
+    }
+
+    fn get_lattice(&self, op: &Operand) -> Lattice {
+        match op {
+            Operand::Constant(c) => Some(Lattice::Constant(c.clone())),
+            Operand::Instruction(inst) => self.values.get(inst).cloned(),
+            Operand::Parameter(param) => self.params.get(param).cloned(),
+            Operand::Global(_) => Some(Lattice::Varying),
+        }
+        .unwrap_or(Lattice::Unknown)
+    }
+
+    /// Lower lattice value of instruction, and revisit its users if changed.
+    fn update_inst(&mut self, inst: InstPtr, value: Lattice) {
+        let old = self.values.get(&inst).cloned().unwrap_or(Lattice::Unknown);
+        let new = old.meet(&value);
+        if new != old {
+            self.values.insert(inst, new);
+            self.inst_worklist.extend(inst.get_user().iter().cloned());
+        }
+    }
+
+    fn update_param(&mut self, param: ParaPtr, value: Lattice) {
+        let old = self.params.get(&param).cloned().unwrap_or(Lattice::Unknown);
+        let new = old.meet(&value);
+        if new != old {
+            self.params.insert(param, new);
+            self.inst_worklist.extend(param.get_user().iter().cloned());
+        }
+    }
+
+    fn update_return(&mut self, func: FunPtr, value: Lattice) {
+        let old = self.returns.get(&func).cloned().unwrap_or(Lattice::Unknown);
+        let new = old.meet(&value);
+        if new != old {
+            self.returns.insert(func, new);
+            for edge in self.call_graph.get_called_by(func) {
+                self.inst_worklist.push(edge.inst);
+            }
+        }
+    }
+
+    fn visit(&mut self, inst: InstPtr) {
+        let Some(bb) = inst.get_parent_bb() else {
+            return;
+        };
+        match inst.get_type() {
+            InstType::Phi => {
+                let phi = downcast_ref::<Phi>(inst.as_ref().as_ref());
+                let mut value = Lattice::Unknown;
+                for (op, pred) in phi.get_incoming_values() {
+                    if self.executable_edges.contains(&(*pred, bb)) {
+                        value = value.meet(&self.get_lattice(op));
+                    }
+                }
+                self.update_inst(inst, value);
+            }
+            InstType::Br => {
+                let succ = bb.get_succ_bb().clone();
+                if succ.len() == 1 {
+                    self.mark_edge(bb, succ[0]);
+                    return;
+                }
+                match self.get_lattice(&inst.get_operand()[0]) {
+                    Lattice::Unknown => (),
+                    Lattice::Constant(Constant::Bool(cond)) => {
+                        self.mark_edge(bb, succ[if cond { 0 } else { 1 }]);
+                    }
+                    _ => {
+                        for next in succ {
+                            self.mark_edge(bb, next);
+                        }
+                    }
+                }
+            }
+            InstType::Ret => {
+                if let (Some(func), Some(op)) = (self.exits.get(&bb), inst.get_operand().first()) {
+                    let value = self.get_lattice(op);
+                    self.update_return(*func, value);
+                }
+            }
+            InstType::Select => {
+                // Only the chosen operand matters if condition is known
+                let operands = inst.get_operand();
+                let value = match self.get_lattice(&operands[0]) {
+                    Lattice::Unknown => Lattice::Unknown,
+                    Lattice::Constant(Constant::Bool(cond)) => {
+                        self.get_lattice(&operands[if cond { 1 } else { 2 }])
+                    }
+                    _ => self
+                        .get_lattice(&operands[1])
+                        .meet(&self.get_lattice(&operands[2])),
+                };
+                self.update_inst(inst, value);
+            }
+            InstType::Call => {
+                let callee = downcast_ref::<Call>(inst.as_ref().as_ref()).func;
+                if callee.is_lib() {
+                    self.update_inst(inst, Lattice::Varying);
+                    return;
+                }
+
+                // Arguments flow into parameters, return value flows back to call
+                self.mark_func(callee);
+                for (param, arg) in callee.params.iter().zip(inst.get_operand().iter()) {
+                    let value = self.get_lattice(arg);
+                    self.update_param(*param, value);
+                }
+                let value = self.returns.get(&callee).cloned();
+                self.update_inst(inst, value.unwrap_or(Lattice::Unknown));
+            }
+            _ => {
+                let operands: Vec<Lattice> = inst
+                    .get_operand()
+                    .iter()
+                    .map(|op| self.get_lattice(op))
+                    .collect();
+                let value = if operands.contains(&Lattice::Varying) {
+                    Lattice::Varying
+                } else if operands.contains(&Lattice::Unknown) {
+                    Lattice::Unknown
+                } else {
+                    let operands = operands
+                        .into_iter()
+                        .filter_map(|x| match x {
+                            Lattice::Constant(c) => Some(c),
+                            _ => None,
+                        })
+                        .collect();
+                    fold_inst(inst, operands).map_or(Lattice::Varying, Lattice::Constant)
+                };
+                self.update_inst(inst, value);
+            }
+        }
+    }
+
+    /// Replace constant values of function, fold decided branches and remove dead blocks.
+    fn rewrite_func(&mut self, func: FunPtr) -> bool {
+        let mut changed = false;
+        let blocks: Vec<BBPtr> = func.dfs_iter().collect();
+
+        // Replace parameters known to be constant
+        for param in func.params.iter() {
+            if let Some(Lattice::Constant(c)) = self.params.get(param) {
+                let op = Operand::Parameter(*param);
+                changed |= replace_uses(param.get_user().to_vec(), &op, c);
+            }
+        }
+
+        // Replace instructions known to be constant, calls are kept for side effects
+        for bb in blocks.iter() {
+            if !self.executable_blocks.contains(bb) {
+                continue;
+            }
+            for mut inst in bb.iter().collect::<Vec<_>>() {
+                let Some(Lattice::Constant(c)) = self.values.get(&inst) else {
+                    continue;
+                };
+                if inst.get_type() == InstType::Call {
+                    changed |= replace_uses(inst.get_user().to_vec(), &inst.into(), c);
+                } else {
+                    inst.replace_self(&c.clone().into());
+                    pass_stats::bump(Self::name, "values replaced", 1);
+                    changed = true;
+                }
+            }
+        }
+
+        // Pruning a function that never returns would remove its exit
+        if !func
+            .exit
+            .is_some_and(|exit| self.executable_blocks.contains(&exit))
+        {
+            return changed;
+        }
+
+        // Fold branches with one executable successor
+        for mut bb in blocks.iter().cloned() {
+            if !self.executable_blocks.contains(&bb) || bb.get_succ_bb().len() != 2 {
+                continue;
+            }
+            let mut br = bb.get_last_inst();
+            let succ = bb.get_succ_bb().clone();
+            let true_taken = self.executable_edges.contains(&(bb, succ[0]));
+            let false_taken = self.executable_edges.contains(&(bb, succ[1]));
+            if true_taken == false_taken {
+                continue;
+            }
+            if true_taken {
+                bb.remove_false_bb();
+            } else {
+                bb.remove_true_bb();
+            }
+            br.insert_after(self.program.mem_pool.get_br(None));
+            br.remove_self();
+            pass_stats::bump(Self::name, "branches folded", 1);
+            changed = true;
+        }
+
+        // Remove blocks no longer reachable
+        let reachable: HashSet<BBPtr> = func.dfs_iter().collect();
+        for mut bb in blocks {
+            if reachable.contains(&bb) {
+                continue;
+            }
+            bb.remove_self();
+            for mut inst in bb.iter().collect::<Vec<_>>() {
+                inst.remove_self();
+            }
+            pass_stats::bump(Self::name, "blocks removed", 1);
+            changed = true;
+        }
+        changed
+    }
+}
+
+/// Replace `op` with constant `c` in all given users.
+fn replace_uses(mut users: Vec<InstPtr>, op: &Operand, c: &Constant) -> bool {
+    users.dedup();
+    let mut replaced = false;
+    for mut user in users {
+        for index in 0..user.get_operand().len() {
+            if user.get_operand()[index] == *op {
+                user.set_operand(index, c.clone().into());
+                replaced = true;
+            }
+        }
+    }
+    if replaced {
+        pass_stats::bump(SCCP::name, "values replaced", 1);
+    }
+    replaced
+}
@@ -22,7 +22,7 @@ use crate::{
     Program,
 };
 
-use super::Transform;
+use super::{pass_stats, Transform};
 
 pub fn optimize_program<'a>(
     program: &'a mut Program,
@@ -116,6 +116,9 @@ impl<'a> StoreElim<'a> {
     /// Remove instruction and recurse into operands.
     fn remove_inst(&mut self, mut inst: InstPtr) -> Result<()> {
         let operands: Vec<_> = inst.get_operand().into();
+        if inst.get_type() == InstType::Store {
+            pass_stats::bump(Self::name, "stores eliminated", 1);
+        }
         inst.remove_self();
         for op in operands {
             if let Operand::Instruction(inst) = op {
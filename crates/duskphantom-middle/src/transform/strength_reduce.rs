@@ -0,0 +1,482 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+
+use crate::{
+    analysis::{
+        dominator_tree::DominatorTree,
+        loop_tools::{LoopForest, LoopPtr},
+        scalar_evolution::{get_exiting_block, swap_predicate, Linear, ScalarEvolution, Scev},
+    },
+    ir::{
+        instruction::{
+            downcast_mut, downcast_ref,
+            memory_op_inst::GetElementPtr,
+            misc_inst::{ICmp, ICmpOp, Phi},
+            InstType,
+        },
+        BBPtr, Constant, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{
+    loop_optimization::loop_forest_post_order, loop_simplify::LoopSimplifier, pass_stats, Transform,
+};
+
+/// Rewrite induction expressions of `getelementptr` and `mul` into recurrences updated
+/// by addition, and test loop exit with a rewritten pointer if the counter becomes useless.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    StrengthReduce::new(program).run_and_log()
+}
+
+pub struct StrengthReduce<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for StrengthReduce<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "strength_reduce".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            let Some(mut forest) = LoopForest::make_forest(func) else {
+                continue;
+            };
+            LoopSimplifier::new(&mut self.program.mem_pool).run(&mut forest)?;
+            let mut dom_tree = DominatorTree::new(func);
+            loop_forest_post_order(&mut forest, |lo| {
+                changed |= self.reduce_loop(lo, &mut dom_tree)?;
+                Ok(())
+            })?;
+        }
+        Ok(changed)
+    }
+}
+
+/// Pointer recurrence `phi [gep base, start, preheader], [gep phi, step, latch]`,
+/// where start and step are in units of the pointed element.
+struct PointerRec {
+    phi: InstPtr,
+    base: Operand,
+
+    /// Element type and index count of `gep` on base, with leading zero indices
+    gep_type: ValueType,
+    depth: usize,
+
+    start: Linear,
+    step: Linear,
+
+    /// If the pointer is computed in every iteration, so that it's safe to test exit with
+    every_iteration: bool,
+}
+
+impl<'a> StrengthReduce<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    fn reduce_loop(&mut self, lo: LoopPtr, dom_tree: &mut DominatorTree) -> Result<bool> {
+        let Some(preheader) = lo.pre_header else {
+            return Ok(false);
+        };
+        let head_pred = lo.head.get_pred_bb();
+        if head_pred.len() != 2 {
+            return Ok(false);
+        }
+        let Some(latch) = head_pred.iter().find(|bb| **bb != preheader).cloned() else {
+            return Ok(false);
+        };
+
+        let mut scev = ScalarEvolution::new();
+        let recs = self.reduce_gep(lo, preheader, latch, &mut scev, dom_tree);
+        let mut changed = !recs.is_empty();
+        changed |= self.reduce_mul(lo, preheader, latch, &mut scev);
+        changed |= self.replace_exit_test(lo, preheader, &recs);
+        Ok(changed)
+    }
+
+    /// Replace `gep` with induction indices by pointer recurrences.
+    /// Pointers with the same base and step share a recurrence if they differ by a constant.
+    fn reduce_gep(
+        &mut self,
+        lo: LoopPtr,
+        preheader: BBPtr,
+        latch: BBPtr,
+        scev: &mut ScalarEvolution,
+        dom_tree: &mut DominatorTree,
+    ) -> Vec<PointerRec> {
+        // Analyze all pointers before rewriting, so that chained `gep` are flattened
+        let mut candidates: Vec<(InstPtr, Operand, Linear, Linear)> = Vec::new();
+        for bb in lo.get_all_blocks() {
+            for inst in bb.iter() {
+                if inst.get_type() != InstType::GetElementPtr {
+                    continue;
+                }
+                let Some((base, Scev::AddRec { start, step })) =
+                    get_pointer_scev(&inst.into(), lo, scev)
+                else {
+                    continue;
+                };
+                if step.get_constant() != Some(0) {
+                    candidates.push((inst, base, start, step));
+                }
+            }
+        }
+
+        // Pointers only used as base of other candidates need no recurrence
+        let reduced: HashSet<InstPtr> = candidates.iter().map(|(inst, ..)| *inst).collect();
+        let mut recs: Vec<PointerRec> = Vec::new();
+        for (mut inst, base, start, step) in candidates {
+            if inst.get_user().iter().all(|user| reduced.contains(user)) {
+                continue;
+            }
+            let ty = inst.get_value_type();
+            let found = recs.iter().enumerate().find_map(|(i, rec)| {
+                if rec.base != base || rec.phi.get_value_type() != ty || rec.step != step {
+                    return None;
+                }
+                start
+                    .sub(&rec.start)
+                    .get_constant()
+                    .map(|offset| (i, offset))
+            });
+            let (index, offset) = match found {
+                Some(found) => found,
+                None => {
+                    let Some(gep_type) = base.get_type().get_sub_type().cloned() else {
+                        continue;
+                    };
+                    let Some(depth) = get_depth(&gep_type, ty.get_sub_type().unwrap()) else {
+                        continue;
+                    };
+                    let rec = self
+                        .new_pointer_rec(lo, preheader, latch, base, gep_type, depth, start, step);
+                    recs.push(rec);
+                    (recs.len() - 1, 0)
+                }
+            };
+
+            // Replace pointer with recurrence plus constant offset
+            let rec = &mut recs[index];
+            rec.every_iteration |= inst
+                .get_parent_bb()
+                .is_some_and(|bb| dom_tree.is_dominate(bb, latch));
+            if offset == 0 {
+                inst.replace_self(&rec.phi.into());
+            } else {
+                let element_type = ty.get_sub_type().cloned().unwrap();
+                let new_gep = self.program.mem_pool.get_getelementptr(
+                    element_type,
+                    rec.phi.into(),
+                    vec![Constant::Int(offset).into()],
+                );
+                inst.insert_before(new_gep);
+                inst.replace_self(&new_gep.into());
+            }
+            pass_stats::bump(Self::name, "pointers reduced", 1);
+        }
+        recs
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn new_pointer_rec(
+        &mut self,
+        lo: LoopPtr,
+        preheader: BBPtr,
+        latch: BBPtr,
+        base: Operand,
+        gep_type: ValueType,
+        depth: usize,
+        start: Linear,
+        step: Linear,
+    ) -> PointerRec {
+        let init = self.emit_pointer(preheader, &base, &gep_type, depth, &start);
+        let step_op = step.emit(&mut self.program.mem_pool, preheader.get_last_inst());
+        let ty = init.get_value_type();
+        let mut phi = self
+            .program
+            .mem_pool
+            .get_phi(ty.clone(), vec![(init.into(), preheader)]);
+        let mut head = lo.head;
+        head.push_front(phi);
+        let next = self.program.mem_pool.get_getelementptr(
+            ty.get_sub_type().cloned().unwrap(),
+            phi.into(),
+            vec![step_op],
+        );
+        latch.get_last_inst().insert_before(next);
+        downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(next.into(), latch);
+        PointerRec {
+            phi,
+            base,
+            gep_type,
+            depth,
+            start,
+            step,
+            every_iteration: false,
+        }
+    }
+
+    /// Build `gep ty, base, 0, ..., 0, offset` at the end of preheader.
+    fn emit_pointer(
+        &mut self,
+        preheader: BBPtr,
+        base: &Operand,
+        gep_type: &ValueType,
+        depth: usize,
+        offset: &Linear,
+    ) -> InstPtr {
+        let mut pos = preheader.get_last_inst();
+        let offset = offset.emit(&mut self.program.mem_pool, pos);
+        let mut index: Vec<Operand> = vec![Constant::Int(0).into(); depth];
+        index.push(offset);
+        let gep = self
+            .program
+            .mem_pool
+            .get_getelementptr(gep_type.clone(), base.clone(), index);
+        pos.insert_before(gep);
+        gep
+    }
+
+    /// Replace multiplications of induction variables by additive recurrences.
+    /// Products with the same step share a recurrence if they differ by a constant.
+    fn reduce_mul(
+        &mut self,
+        lo: LoopPtr,
+        preheader: BBPtr,
+        latch: BBPtr,
+        scev: &mut ScalarEvolution,
+    ) -> bool {
+        let mut recs: Vec<(Linear, Linear, InstPtr)> = Vec::new();
+        for bb in lo.get_all_blocks() {
+            let insts: Vec<InstPtr> = bb.iter().collect();
+            for mut inst in insts {
+                if !matches!(inst.get_type(), InstType::Mul | InstType::Shl)
+                    || inst.get_user().is_empty()
+                {
+                    continue;
+                }
+                let Scev::AddRec { start, step } = scev.get_scev(&inst.into(), lo) else {
+                    continue;
+                };
+                let found = recs.iter().find_map(|(s, d, phi)| {
+                    if *d != step {
+                        return None;
+                    }
+                    start.sub(s).get_constant().map(|offset| (*phi, offset))
+                });
+                let (phi, offset) = match found {
+                    Some(found) => found,
+                    None => {
+                        let pos = preheader.get_last_inst();
+                        let init = start.emit(&mut self.program.mem_pool, pos);
+                        let step_op = step.emit(&mut self.program.mem_pool, pos);
+                        let mut phi = self
+                            .program
+                            .mem_pool
+                            .get_phi(ValueType::Int, vec![(init, preheader)]);
+                        let mut head = lo.head;
+                        head.push_front(phi);
+                        let next = self.program.mem_pool.get_add(phi.into(), step_op);
+                        latch.get_last_inst().insert_before(next);
+                        downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(next.into(), latch);
+                        recs.push((start, step, phi));
+                        (phi, 0)
+                    }
+                };
+
+                // Replace product with recurrence plus constant offset
+                if offset == 0 {
+                    inst.replace_self(&phi.into());
+                } else {
+                    let add = self
+                        .program
+                        .mem_pool
+                        .get_add(phi.into(), Constant::Int(offset).into());
+                    inst.insert_before(add);
+                    inst.replace_self(&add.into());
+                }
+                pass_stats::bump(Self::name, "multiplies reduced", 1);
+            }
+        }
+        !recs.is_empty()
+    }
+
+    /// Test loop exit with a pointer recurrence instead of the counter,
+    /// if the counter is only used to test exit.
+    fn replace_exit_test(&mut self, lo: LoopPtr, preheader: BBPtr, recs: &[PointerRec]) -> bool {
+        let mut scev = ScalarEvolution::new();
+        let (Some(trip_count), Some(exiting)) = (scev.get_trip_count(lo), get_exiting_block(lo))
+        else {
+            return false;
+        };
+        let Some(Operand::Instruction(mut cond)) =
+            exiting.get_last_inst().get_operand().first().cloned()
+        else {
+            return false;
+        };
+        if cond.get_user().len() != 1 {
+            return false;
+        }
+        let icmp = downcast_ref::<ICmp>(cond.as_ref().as_ref());
+        let op = icmp.op;
+        if !matches!(
+            op,
+            ICmpOp::Eq | ICmpOp::Ne | ICmpOp::Slt | ICmpOp::Sle | ICmpOp::Sgt | ICmpOp::Sge
+        ) {
+            return false;
+        }
+        let counter_is_lhs = matches!(scev.get_scev(icmp.get_lhs(), lo), Scev::AddRec { .. });
+        let counter = if counter_is_lhs {
+            icmp.get_lhs()
+        } else {
+            icmp.get_rhs()
+        };
+        if !is_exit_only_counter(lo, counter, cond) {
+            return false;
+        }
+
+        // Pointer `p = s + k * m * step` reaches `s + m * (bound - start)` when counter reaches bound
+        let Some((rec, m)) = recs.iter().find_map(|rec| {
+            let c = rec.step.get_constant()?;
+            let m = (c % trip_count.step == 0).then_some(c / trip_count.step)?;
+            (rec.every_iteration && m != 0).then_some((rec, m))
+        }) else {
+            return false;
+        };
+        let distance = trip_count.bound.sub(&trip_count.start);
+        match distance.get_constant() {
+            Some(d) if i32::try_from(d as i64 * m as i64).is_err() => return false,
+            None if m.abs() != 1 => return false,
+            _ => (),
+        }
+        let end = rec.start.add(&distance.scale(m));
+        let end = self.emit_pointer(preheader, &rec.base, &rec.gep_type, rec.depth, &end);
+
+        // Flip comparison if pointer moves in opposite direction
+        let op = if m > 0 { op } else { swap_predicate(op) };
+        let (lhs, rhs) = if counter_is_lhs {
+            (rec.phi.into(), end.into())
+        } else {
+            (end.into(), rec.phi.into())
+        };
+        let new_cond = self
+            .program
+            .mem_pool
+            .get_icmp(op, rec.phi.get_value_type(), lhs, rhs);
+        cond.insert_before(new_cond);
+        cond.replace_self(&new_cond.into());
+        pass_stats::bump(Self::name, "exit tests replaced", 1);
+        true
+    }
+}
+
+/// Get address as offset from a loop invariant base pointer, in units of the pointed element.
+/// Chained `gep` in loop are folded into a single offset.
+fn get_pointer_scev(
+    ptr: &Operand,
+    lo: LoopPtr,
+    scev: &mut ScalarEvolution,
+) -> Option<(Operand, Scev)> {
+    if lo.is_invariant(ptr) {
+        return Some((ptr.clone(), Scev::Invariant(Linear::new_constant(0))));
+    }
+    let Operand::Instruction(inst) = ptr else {
+        return None;
+    };
+    if inst.get_type() != InstType::GetElementPtr {
+        return None;
+    }
+    let operands = inst.get_operand();
+    let (base, base_offset) = get_pointer_scev(&operands[0], lo, scev)?;
+    let gep = downcast_ref::<GetElementPtr>(inst.as_ref().as_ref());
+    let element_size = inst.get_value_type().get_sub_type()?.size();
+    let mut ty = gep.element_type.clone();
+    let stride = i32::try_from(ty.size() / element_size).ok()?;
+    let mut offset = base_offset.scale(stride);
+    for (i, index) in operands[1..].iter().enumerate() {
+        if i > 0 {
+            ty = ty.get_sub_type()?.clone();
+        }
+        let stride = i32::try_from(ty.size() / element_size).ok()?;
+        offset = offset.add(&scev.get_scev(index, lo).scale(stride));
+    }
+    Some((base, offset))
+}
+
+/// Get number of indices to go from `ty` down to `target`.
+fn get_depth(ty: &ValueType, target: &ValueType) -> Option<usize> {
+    let mut ty = ty;
+    let mut depth = 0;
+    while ty != target {
+        ty = ty.get_sub_type()?;
+        depth += 1;
+    }
+    Some(depth)
+}
+
+/// Check if instruction is arithmetic whose result is never used.
+fn is_dead(inst: &InstPtr) -> bool {
+    matches!(
+        inst.get_type(),
+        InstType::Add | InstType::Sub | InstType::Mul | InstType::Shl | InstType::GetElementPtr
+    ) && inst.get_user().iter().all(is_dead)
+}
+
+/// Check if counter is a head phi or its increment, and the pair is only used by exit test.
+fn is_exit_only_counter(lo: LoopPtr, counter: &Operand, cond: InstPtr) -> bool {
+    let Operand::Instruction(counter) = counter else {
+        return false;
+    };
+    let is_head_phi =
+        |inst: &InstPtr| inst.get_type() == InstType::Phi && inst.get_parent_bb() == Some(lo.head);
+    let phi = if is_head_phi(counter) {
+        *counter
+    } else {
+        match counter.get_user().iter().find(|user| is_head_phi(user)) {
+            Some(phi) => *phi,
+            None => return false,
+        }
+    };
+    let incoming = downcast_ref::<Phi>(phi.as_ref().as_ref()).get_incoming_values();
+    let Some(Operand::Instruction(next)) = incoming
+        .iter()
+        .find(|(_, bb)| lo.is_in_loop(bb))
+        .map(|(op, _)| op.clone())
+    else {
+        return false;
+    };
+    phi.get_user()
+        .iter()
+        .all(|user| *user == next || *user == cond || is_dead(user))
+        && next
+            .get_user()
+            .iter()
+            .all(|user| *user == phi || *user == cond || is_dead(user))
+}
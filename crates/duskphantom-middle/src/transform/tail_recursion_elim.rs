@@ -0,0 +1,276 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::{
+    analysis::alias_analysis::get_base_object,
+    context,
+    ir::{
+        instruction::{
+            downcast_mut, downcast_ref,
+            misc_inst::{Call, Phi},
+            InstType,
+        },
+        BBPtr, Constant, FunPtr, InstPtr, Operand, ValueType,
+    },
+    Program,
+};
+
+use super::{pass_stats, region_clone::new_block, Transform};
+
+/// Turn self-recursive calls in tail position into jumps back to function start.
+/// Calls whose result is added to or multiplied by a value before return use an accumulator.
+pub fn optimize_program(program: &mut Program) -> Result<bool> {
+    TailRecursionElim::new(program).run_and_log()
+}
+
+pub struct TailRecursionElim<'a> {
+    program: &'a mut Program,
+}
+
+impl<'a> Transform for TailRecursionElim<'a> {
+    fn get_program_mut(&mut self) -> &mut Program {
+        self.program
+    }
+
+    fn name() -> String {
+        "tail_recursion_elim".to_string()
+    }
+
+    fn run(&mut self) -> Result<bool> {
+        let mut changed = false;
+        for func in self.program.module.functions.clone() {
+            if func.is_lib() {
+                continue;
+            }
+            changed |= self.process_func(func)?;
+        }
+        Ok(changed)
+    }
+}
+
+/// Recursive call whose result is returned from `pred` of function exit.
+struct TailCall {
+    pred: BBPtr,
+    call: InstPtr,
+
+    /// Instruction `call op x` returned instead of the call
+    combine: Option<InstPtr>,
+}
+
+impl<'a> TailRecursionElim<'a> {
+    pub fn new(program: &'a mut Program) -> Self {
+        Self { program }
+    }
+
+    fn process_func(&mut self, func: FunPtr) -> Result<bool> {
+        if !func.may_return() {
+            return Ok(false);
+        }
+        let mut entry = func
+            .entry
+            .ok_or_else(|| anyhow!("function `{}` has no entry", func.name))
+            .with_context(|| context!())?;
+        let mut exit = func
+            .exit
+            .ok_or_else(|| anyhow!("function `{}` has no exit", func.name))
+            .with_context(|| context!())?;
+
+        // Exit should only contain return value phi and return
+        let mut ret = exit.get_last_inst();
+        let ret_phi = match ret.get_operand().first() {
+            Some(Operand::Instruction(inst))
+                if inst.get_type() == InstType::Phi && inst.get_parent_bb() == Some(exit) =>
+            {
+                Some(*inst)
+            }
+            _ => None,
+        };
+        if exit.iter().count() != 1 + ret_phi.is_some() as usize {
+            return Ok(false);
+        }
+
+        // Collect tail calls, keep only one kind of accumulation
+        let mut tail_calls: Vec<TailCall> = exit
+            .get_pred_bb()
+            .iter()
+            .filter_map(|pred| {
+                let returned = match ret_phi {
+                    Some(phi) => downcast_ref::<Phi>(phi.as_ref().as_ref())
+                        .get_incoming_value(*pred)
+                        .cloned(),
+                    None => ret.get_operand().first().cloned(),
+                };
+                get_tail_call(func, *pred, returned)
+            })
+            .collect();
+        let acc_type = tail_calls
+            .iter()
+            .find_map(|tc| tc.combine.map(|inst| inst.get_type()));
+        tail_calls.retain(|tc| {
+            tc.combine
+                .is_none_or(|inst| Some(inst.get_type()) == acc_type)
+        });
+
+        // Keep at least one path to exit, otherwise the function never returns
+        if tail_calls.is_empty() || tail_calls.len() == exit.get_pred_bb().len() {
+            return Ok(false);
+        }
+
+        // Split allocas to entry, and make the rest of entry the loop header
+        let mut header = new_block(&mut self.program.mem_pool, &entry.name, "tailrec");
+        for inst in entry.iter() {
+            if inst.get_type() != InstType::Alloca {
+                header.push_back(inst);
+            }
+        }
+        entry.replace_exit(header);
+        entry.push_back(self.program.mem_pool.get_br(None));
+        entry.set_true_bb(header);
+
+        // Replace parameters with header phis
+        let mut param_phis = Vec::new();
+        for param in func.params.iter().cloned() {
+            let mut phi = self
+                .program
+                .mem_pool
+                .get_phi(param.value_type.clone(), vec![]);
+            let mut users = param.get_user().to_vec();
+            users.dedup();
+            for mut user in users {
+                for index in 0..user.get_operand().len() {
+                    if user.get_operand()[index] == Operand::Parameter(param) {
+                        user.set_operand(index, phi.into());
+                    }
+                }
+            }
+            downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(param.into(), entry);
+            header.push_front(phi);
+            param_phis.push(phi);
+        }
+
+        // Accumulator starts from identity of accumulation
+        let acc_phi = acc_type.map(|ty| {
+            let identity = if ty == InstType::Add { 0 } else { 1 };
+            let phi = self.program.mem_pool.get_phi(
+                ValueType::Int,
+                vec![(Constant::Int(identity).into(), entry)],
+            );
+            header.push_front(phi);
+            phi
+        });
+
+        // Replace tail calls with jumps to header
+        for tc in tail_calls {
+            let mut pred = tc.pred;
+            let mut call = tc.call;
+            for (mut phi, arg) in param_phis.iter().cloned().zip(call.get_operand().to_vec()) {
+                downcast_mut::<Phi>(phi.as_mut()).add_incoming_value(arg, pred);
+            }
+            if let Some(mut acc_phi) = acc_phi {
+                let next = match tc.combine {
+                    Some(mut combine) => {
+                        let operand = get_other_operand(combine, call);
+                        let next = self.get_combine(acc_type.unwrap(), acc_phi.into(), operand);
+                        pred.get_last_inst().insert_before(next);
+                        combine.remove_self();
+                        next
+                    }
+                    None => acc_phi,
+                };
+                downcast_mut::<Phi>(acc_phi.as_mut()).add_incoming_value(next.into(), pred);
+            }
+            pred.replace_succ_bb_only(exit, header);
+            exit.remove_pred_bb(pred);
+            call.remove_self();
+            pass_stats::bump(Self::name, "tail calls eliminated", 1);
+        }
+
+        // Apply accumulator to returned value
+        if let Some(acc_phi) = acc_phi {
+            let value = ret.get_operand()[0].clone();
+            let result = self.get_combine(acc_type.unwrap(), acc_phi.into(), value);
+            ret.insert_before(result);
+            ret.set_operand(0, result.into());
+        }
+        Ok(true)
+    }
+
+    fn get_combine(&mut self, ty: InstType, lhs: Operand, rhs: Operand) -> InstPtr {
+        if ty == InstType::Add {
+            self.program.mem_pool.get_add(lhs, rhs)
+        } else {
+            self.program.mem_pool.get_mul(lhs, rhs)
+        }
+    }
+}
+
+/// Find recursive call at the end of `pred`, whose result (possibly combined) is returned.
+fn get_tail_call(func: FunPtr, pred: BBPtr, returned: Option<Operand>) -> Option<TailCall> {
+    if pred.get_succ_bb().len() != 1 {
+        return None;
+    }
+    let insts: Vec<InstPtr> = pred.iter().collect();
+    let last = *insts.iter().rev().nth(1)?;
+    let (call, combine) = if is_self_call(func, last) {
+        (last, None)
+    } else {
+        // Accumulate with `call + x` or `call * x`
+        let call = *insts.iter().rev().nth(2)?;
+        if !matches!(last.get_type(), InstType::Add | InstType::Mul) || !is_self_call(func, call) {
+            return None;
+        }
+        if get_other_operand(last, call) == call.into() || call.get_user().len() != 1 {
+            return None;
+        }
+        (call, Some(last))
+    };
+
+    // Returned value should only be used by return
+    let result = combine.unwrap_or(call);
+    if let Some(returned) = returned {
+        if returned != result.into() || result.get_user().len() != 1 {
+            return None;
+        }
+    }
+
+    // Local arrays are reused by the next iteration, so they should not be passed on
+    if call.get_operand().iter().any(|arg| {
+        matches!(get_base_object(arg), Operand::Instruction(inst) if inst.get_type() == InstType::Alloca)
+    }) {
+        return None;
+    }
+    Some(TailCall {
+        pred,
+        call,
+        combine,
+    })
+}
+
+fn is_self_call(func: FunPtr, inst: InstPtr) -> bool {
+    inst.get_type() == InstType::Call && downcast_ref::<Call>(inst.as_ref().as_ref()).func == func
+}
+
+/// Get operand of binary `inst` other than `op`.
+fn get_other_operand(inst: InstPtr, op: InstPtr) -> Operand {
+    let operands = inst.get_operand();
+    if operands[0] == op.into() {
+        operands[1].clone()
+    } else {
+        operands[0].clone()
+    }
+}
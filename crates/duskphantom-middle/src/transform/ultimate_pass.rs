@@ -19,17 +19,31 @@ use anyhow::Result;
 use crate::{config::CONFIG, Program};
 
 use super::{
-    block_fuse, dead_code_elim, func_inline, inst_combine, load_store_elim, loop_optimization,
-    make_parallel, mem2reg, redundance_elim, sink_code,
+    block_fuse, dead_arg_elim, dead_code_elim, func_inline, func_specialize, global_localize,
+    gvn_pre, if_convert, inst_combine, jump_thread, load_store_elim, loop_fission, loop_fusion,
+    loop_interchange, loop_optimization, loop_peel, loop_rotate, loop_unroll, loop_unswitch,
+    make_parallel, mem2reg, memoize, reassociate, redundance_elim, sccp, sink_code,
+    strength_reduce, tail_recursion_elim,
 };
 
 #[allow(unused)]
 pub fn optimize_program(program: &mut Program, level: usize) -> Result<bool> {
+    global_localize::optimize_program(program)?;
     mem2reg::optimize_program(program)?;
+    memoize::optimize_program(program)?;
     main_loop(program)?;
+    loop_interchange::optimize_program(program)?;
     if CONFIG.open_auto_parallel {
-        make_parallel::optimize_program::<5>(program)?;
+        loop_fission::optimize_program(program)?;
+        make_parallel::optimize_program(program)?;
     }
+    if loop_unroll::optimize_program(program, true)? {
+        block_fuse::optimize_program(program)?;
+    }
+    strength_reduce::optimize_program(program)?;
+
+    // Rotate loops last, because passes above match loops in while form
+    loop_rotate::optimize_program(program)?;
     eval_and_prune(program)?;
     sink_code::optimize_program(program)?;
     Ok(true)
@@ -39,20 +53,56 @@ pub fn main_loop(program: &mut Program) -> Result<bool> {
     loop {
         let mut changed = false;
 
+        // Turn tail recursion into loops
+        changed |= tail_recursion_elim::optimize_program(program)?;
+
         // Inline functions
         changed |= func_inline::optimize_program(program)?;
 
+        // Clone functions for constant arguments
+        changed |= func_specialize::optimize_program(program)?;
+
+        // Propagate constants across branches and calls
+        changed |= sccp::optimize_program(program)?;
+
+        // Remove unused arguments and return values
+        changed |= dead_arg_elim::optimize_program(program)?;
+
         // Simplify code
         changed |= eval_and_prune(program)?;
 
+        // Reassociate expressions to fold constants and group invariant operands
+        changed |= reassociate::optimize_program(program)?;
+
         // Remove redundancy
         changed |= redundance_elim::optimize_program(program)?;
 
+        // Remove partial redundancy at merge blocks
+        changed |= gvn_pre::optimize_program(program)?;
+
+        // Thread branches with conditions known from predecessors
+        changed |= jump_thread::optimize_program(program)?;
+
+        // Turn small branches into selects
+        changed |= if_convert::optimize_program(program)?;
+
         // Optimize loop
         // TODO add changed and timing info for this pass
         // TODO remove inst_combine in loop_optimization
         loop_optimization::optimize_program(program)?;
 
+        // Unswitch loops on invariant conditions
+        changed |= loop_unswitch::optimize_program(program)?;
+
+        // Peel iterations after which loop phis become invariant
+        changed |= loop_peel::optimize_program(program)?;
+
+        // Fuse adjacent loops passing data in the same iteration
+        changed |= loop_fusion::optimize_program(program)?;
+
+        // Fully unroll small loops
+        changed |= loop_unroll::optimize_program(program, false)?;
+
         // Fuse blocks
         changed |= block_fuse::optimize_program(program)?;
 
@@ -0,0 +1,85 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(test)]
+pub mod tests_demanded_bits {
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        analysis::demanded_bits::DemandedBits,
+        irgen::gen,
+        transform::{block_fuse, dead_code_elim, mem2reg},
+    };
+    use insta::assert_snapshot;
+
+    #[test]
+    fn test_mul_chain() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = (a + 3) * 4;
+            int c = b * 65536 + a % 8;
+            return c;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        assert_snapshot!(DemandedBits::new(func).dump(func), @r###"
+        %Add_10: ???????????????????????????????? demanded 0x00003fff
+        %Mul_11: ??????????????????????????????00 demanded 0x0000ffff
+        "###);
+    }
+
+    #[test]
+    fn test_loop_phi() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 1;
+            while (i < n) {
+                s = s * 2 + i;
+                i = i + 1;
+            }
+            return s * 1024;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        assert_snapshot!(DemandedBits::new(func).dump(func), @r###"
+        %phi_34: ???????????????????????????????? demanded 0x003fffff
+        %Mul_17: ???????????????????????????????0 demanded 0x003fffff
+        %Add_19: ???????????????????????????????? demanded 0x003fffff
+        "###);
+    }
+}
@@ -0,0 +1,231 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(test)]
+pub mod tests_dependence_analysis {
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        analysis::{dependence_analysis::DependenceAnalysis, loop_tools::LoopForest},
+        irgen::gen,
+        transform::{loop_simplify::LoopSimplifier, mem2reg},
+    };
+    use insta::assert_snapshot;
+
+    #[test]
+    fn test_constant_distance() {
+        let code = r#"
+        int a[100];
+        int main() {
+            int i = 2;
+            while (i < 100) {
+                a[i] = a[i - 2] + 1;
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(DependenceAnalysis::new(&forest).dump(func), @r###"
+        %load_14 -> %store_18: (>) distance (-2)
+        %store_18 -> %store_18: (=) distance (0)
+        "###);
+    }
+
+    #[test]
+    fn test_distinct_elements() {
+        let code = r#"
+        int a[100];
+        int b[100];
+        int main() {
+            int i = 0;
+            while (i < 50) {
+                a[0] = a[1] + b[i];
+                b[i] = 1;
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(DependenceAnalysis::new(&forest).dump(func), @r###"
+        %load_14 -> %store_18: independent
+        %load_14 -> %store_21: independent
+        %load_15 -> %store_18: independent
+        %load_15 -> %store_21: (=) distance (0)
+        %store_18 -> %store_18: (<) (=) (>) distance (?)
+        %store_18 -> %store_21: independent
+        %store_21 -> %store_21: (=) distance (0)
+        "###);
+    }
+
+    #[test]
+    fn test_gcd() {
+        let code = r#"
+        int a[200];
+        int main() {
+            int i = 0;
+            while (i < 50) {
+                a[2 * i] = a[2 * i + 1] + 1;
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(DependenceAnalysis::new(&forest).dump(func), @r###"
+        %load_15 -> %store_20: independent
+        %store_20 -> %store_20: (=) distance (0)
+        "###);
+    }
+
+    #[test]
+    fn test_nest() {
+        let code = r#"
+        int a[100][100];
+        int main() {
+            int i = 1;
+            while (i < 100) {
+                int j = 0;
+                while (j < 99) {
+                    a[i][j] = a[i - 1][j + 1];
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(DependenceAnalysis::new(&forest).dump(func), @r###"
+        %load_27 -> %store_28: (>, <) distance (-1, 1)
+        %store_28 -> %store_28: (=, =) distance (0, 0)
+        "###);
+    }
+
+    #[test]
+    fn test_banerjee() {
+        let code = r#"
+        int a[300];
+        int main() {
+            int i = 0;
+            while (i < 100) {
+                a[i] = a[i + 200] + a[2 * i + 1];
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(DependenceAnalysis::new(&forest).dump(func), @r###"
+        %load_18 -> %store_23: independent
+        %load_19 -> %store_23: (<) distance (?)
+        %store_23 -> %store_23: (=) distance (0)
+        "###);
+    }
+
+    #[test]
+    fn test_symbolic_offset() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int a[100];
+            int i = 0;
+            while (i < n) {
+                a[i] = a[i + n];
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(DependenceAnalysis::new(&forest).dump(func), @r###"
+        %load_21 -> %store_22: (<) (=) (>) distance (?)
+        %store_22 -> %store_22: (=) distance (0)
+        "###);
+    }
+}
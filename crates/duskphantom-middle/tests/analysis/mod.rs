@@ -14,5 +14,9 @@
 //
 // SPDX-License-Identifier: Apache-2.0
 
+mod demanded_bits;
+mod dependence_analysis;
 mod effect_analysis;
 mod memory_ssa;
+mod scalar_evolution;
+mod value_range;
@@ -0,0 +1,143 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(test)]
+pub mod tests_scalar_evolution {
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        analysis::{loop_tools::LoopForest, scalar_evolution::ScalarEvolution},
+        irgen::gen,
+        transform::{loop_simplify::LoopSimplifier, mem2reg},
+    };
+    use insta::assert_snapshot;
+
+    #[test]
+    fn test_symbolic() {
+        let code = r#"
+        int a[100];
+        int main() {
+            int n = getint();
+            int i = 1;
+            while (i < n) {
+                a[i * 4 + n] = i;
+                i = i + 3;
+            }
+            return i;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(ScalarEvolution::new().dump(&forest), @r###"
+        loop cond0:
+            trip count: {1,+,3} slt %call_6
+            %phi_32 = {1,+,3}
+            %Mul_15 = {4,+,12}
+            %Add_17 = {%call_6 + 4,+,12}
+            %Add_22 = {4,+,3}
+        "###);
+    }
+
+    #[test]
+    fn test_constant() {
+        let code = r#"
+        int a[100];
+        int main() {
+            int i = 20;
+            int s = 0;
+            while (i >= 0) {
+                int j = 0;
+                while (j != 10) {
+                    s = s + a[j];
+                    j = j + 2;
+                }
+                i = i - 3;
+            }
+            return s;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(ScalarEvolution::new().dump(&forest), @r###"
+        loop cond0:
+            trip count: {20,+,-3} sge 0 = 7
+            exit value of %phi_42: -1
+            %phi_42 = {20,+,-3}
+            %Sub_33 = {17,+,-3}
+        loop cond3:
+            trip count: {0,+,2} ne 10 = 5
+            exit value of %phi_46: 10
+            %phi_46 = {0,+,2}
+            %Add_26 = {2,+,2}
+        "###);
+    }
+
+    #[test]
+    fn test_unknown() {
+        let code = r#"
+        int main() {
+            int i = 0;
+            int j = 1;
+            while (i < 100) {
+                if (getint()) {
+                    break;
+                }
+                j = j * 2;
+                i = i + j;
+            }
+            return i;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        assert_snapshot!(ScalarEvolution::new().dump(&forest), @r###"
+        loop cond0:
+            trip count: unknown
+        "###);
+    }
+}
@@ -0,0 +1,164 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+#[cfg(test)]
+pub mod tests_value_range {
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        analysis::value_range::ValueRange,
+        irgen::gen,
+        transform::{block_fuse, dead_code_elim, mem2reg},
+    };
+    use insta::assert_snapshot;
+
+    #[test]
+    fn test_arithmetic() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = a % 8;
+            int c = b + 10;
+            int d = c / 2;
+            int e = d * d - 1;
+            return e;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        assert_snapshot!(ValueRange::new(func).dump(func), @r###"
+        %SRem_10: [-7, 7]
+        %Add_14: [3, 17]
+        %SDiv_18: [1, 8]
+        %Mul_23: [1, 64]
+        %Sub_24: [0, 63]
+        "###);
+    }
+
+    #[test]
+    fn test_branch() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int x = 0;
+            if (a > 0) {
+                if (a < 100) {
+                    x = a * 2;
+                }
+            }
+            return x;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        assert_snapshot!(ValueRange::new(func).dump(func), @r###"
+        %Mul_27: [2, 198]
+        %phi_37: [0, 198]
+        %phi_36: [0, 198]
+        "###);
+    }
+
+    #[test]
+    fn test_loop() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + i;
+                i = i + 1;
+            }
+            int j = 0;
+            while (j != 10) {
+                j = j + 2;
+            }
+            return s + i + j;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        assert_snapshot!(ValueRange::new(func).dump(func), @r###"
+        %phi_48: [0, 2147483647]
+        %phi_50: [0, 10]
+        %Add_35: [2, 11]
+        %Add_21: [1, 2147483647]
+        "###);
+    }
+
+    #[test]
+    fn test_nested_loop() {
+        let code = r#"
+        int a[10][20];
+        int main() {
+            int i = 0;
+            while (i < 10) {
+                int j = i;
+                while (j < 20) {
+                    a[i][j] = i + j;
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.name == "main")
+            .unwrap();
+        assert_snapshot!(ValueRange::new(func).dump(func), @r###"
+        %phi_42: [0, 10]
+        %phi_44: [0, 20]
+        %Add_34: [1, 10]
+        %Add_20: [0, 28]
+        %Add_27: [1, 20]
+        "###);
+    }
+}
@@ -21,7 +21,10 @@ pub mod tests_block_fuse {
     use duskphantom_frontend::parse;
     use duskphantom_middle::{
         irgen::gen,
-        transform::{block_fuse, constant_fold, dead_code_elim, inst_combine, mem2reg},
+        transform::{
+            block_fuse, constant_fold, dead_code_elim, inst_combine, loop_peel, loop_unroll,
+            mem2reg,
+        },
     };
 
     use super::*;
@@ -155,6 +158,102 @@ pub mod tests_block_fuse {
         ret i32 0
 
 
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_folded_exit_merge() {
+        let code = r#"
+        int main() {
+            int i = 0;
+            int s = 0;
+            int p = 0;
+            while (i < 3) {
+                s = s + p;
+                p = 4;
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Peeling adds a dedicated exit, and full unrolling folds the branch to it
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        loop_peel::optimize_program(&mut program).unwrap();
+        loop_unroll::optimize_program(&mut program, false).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        block_fuse::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        [-] entry:
+        [+] cond0_peel6:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %alloca_7 = alloca i32
+        %alloca_9 = alloca i32
+        [-] br label %cond0_peel6
+        [+] br label %final2_dedicated8
+
+        [-] cond0_peel6:
+        [-] %icmp_35 = icmp slt i32 0, 3
+        [-] br i1 %icmp_35, label %body1_peel7, label %final2
+        [-] 
+        [-] body1_peel7:
+        [-] %Add_38 = add i32 0, 0
+        [-] %Add_39 = add i32 0, 1
+        [-] br label %body1_unroll9
+        [-] 
+        [-] final2:
+        [-] %phi_33 = phi i32 [0, %cond0_peel6], [%phi_43, %final2_dedicated8]
+        [+] final2_dedicated8:
+        br label %exit
+
+        [-] body1_unroll9:
+        [-] %Add_45 = add i32 %Add_38, 4
+        [-] %Add_46 = add i32 %Add_39, 1
+        [-] br label %body1_unroll10
+        [-] 
+        exit:
+        [-] ret i32 %phi_33
+        [-] 
+        [-] body1_unroll10:
+        [-] %Add_49 = add i32 %Add_45, 4
+        [-] %Add_50 = add i32 %Add_46, 1
+        [-] br label %cond0
+        [-] 
+        [-] cond0:
+        [-] %icmp_25 = icmp slt i32 %Add_50, 3
+        [-] br label %final2_dedicated8
+        [-] 
+        [-] final2_dedicated8:
+        [-] %phi_43 = phi i32 [%Add_49, %cond0]
+        [-] br label %final2
+        [+] ret i32 8
+
+
         }
         "###);
     }
@@ -0,0 +1,284 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_dead_arg_elim {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_arg_elim, dead_code_elim, inst_combine, mem2reg},
+    };
+
+    #[test]
+    fn test_dead_param() {
+        let code = r#"
+        int f(int a, int b, int n) {
+            if (a <= 0) {
+                return 0;
+            }
+            putint(a);
+            return f(a - 1, b, n);
+        }
+        int main() {
+            return f(3, getint(), 7);
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        dead_arg_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        [-] define i32 @f(i32 %a, i32 %b, i32 %n) {
+        [+] define i32 @f(i32 %a) {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %alloca_7 = alloca i32
+        %alloca_9 = alloca i32
+        br label %cond0
+
+        cond0:
+        %icmp_17 = icmp sle i32 %a, 0
+        br i1 %icmp_17, label %then1, label %alt2
+
+        then1:
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        [-] %phi_40 = phi i32 [0, %then1], [%call_28, %final3]
+        [+] %phi_40 = phi i32 [0, %then1], [%call_41, %final3]
+        ret i32 %phi_40
+
+        final3:
+        call void @putint(i32 %a)
+        %Sub_25 = sub i32 %a, 1
+        [-] %call_28 = call i32 @f(i32 %Sub_25, i32 %b, i32 %n)
+        [+] %call_41 = call i32 @f(i32 %Sub_25)
+        br label %exit
+
+
+        }
+        define i32 @main() {
+        entry:
+        %alloca_33 = alloca i32
+        %call_36 = call i32 @getint()
+        [-] %call_37 = call i32 @f(i32 3, i32 %call_36, i32 7)
+        [+] %call_42 = call i32 @f(i32 3)
+        br label %exit
+
+        exit:
+        [-] ret i32 %call_37
+        [+] ret i32 %call_42
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_dead_return() {
+        let code = r#"
+        int g(int x) {
+            putint(x);
+            return x * 2;
+        }
+        int h(int x) {
+            putint(x);
+            return x + 1;
+        }
+        int main() {
+            g(1);
+            h(2);
+            return h(3);
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization, only `g` returns void
+        dead_arg_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        [-] define i32 @g(i32 %x) {
+        [+] define void @g(i32 %x) {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        call void @putint(i32 %x)
+        [-] %Mul_10 = mul i32 %x, 2
+        br label %exit
+
+        exit:
+        [-] ret i32 %Mul_10
+        [+] ret void
+
+
+        }
+        define i32 @h(i32 %x) {
+        entry:
+        [-] %alloca_15 = alloca i32
+        [-] %alloca_18 = alloca i32
+        call void @putint(i32 %x)
+        %Add_23 = add i32 %x, 1
+        br label %exit
+
+        exit:
+        ret i32 %Add_23
+
+
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_28 = alloca i32
+        [-] %call_31 = call i32 @g(i32 1)
+        [+] call void @g(i32 1)
+        %call_32 = call i32 @h(i32 2)
+        %call_33 = call i32 @h(i32 3)
+        br label %exit
+
+        exit:
+        ret i32 %call_33
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_never_return() {
+        let code = r#"
+        int f(int n) {
+            putint(1);
+            while (1) {}
+            return n;
+        }
+        int main() {
+            f(getint());
+            return 0;
+        }
+        "#;
+
+        // Check before optimization, exit of `f` is pruned as unreachable
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization, only the parameter is removed
+        dead_arg_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        [-] define i32 @f(i32 %n) {
+        [+] define i32 @f() {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        call void @putint(i32 1)
+        br label %cond0
+
+        cond0:
+        br label %body1
+
+        body1:
+        br label %cond0
+
+
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_20 = alloca i32
+        %call_23 = call i32 @getint()
+        [-] %call_24 = call i32 @f(i32 %call_23)
+        [+] %call_28 = call i32 @f()
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+}
@@ -288,12 +288,12 @@ pub mod tests_dce {
 
         cond0:
         %phi_26 = phi i32 [2, %entry], [%Add_17, %body1]
-        %phi_25 = phi i32 [8, %entry], [%Add_14, %body1]
+        [-] %phi_25 = phi i32 [8, %entry], [%Add_14, %body1]
         %icmp_21 = icmp slt i32 %phi_26, 9
         br i1 %icmp_21, label %body1, label %final2
 
         body1:
-        %Add_14 = add i32 %phi_25, 1
+        [-] %Add_14 = add i32 %phi_25, 1
         %Add_17 = add i32 %phi_26, 1
         br label %cond0
 
@@ -25,6 +25,7 @@ pub mod tests_func_inline {
         irgen::gen,
         transform::{
             block_fuse, constant_fold, dead_code_elim, func_inline, inst_combine, mem2reg,
+            pass_stats, ultimate_pass,
         },
     };
 
@@ -95,19 +96,18 @@ pub mod tests_func_inline {
         [+] %call_18 = call i32 @getint()
         %icmp_26 = icmp sgt i32 %call_18, 10
         [-] br i1 %icmp_26, label %alt4, label %final5
-        [+] br i1 %icmp_26, label %alt4_split2, label %final5
+        [+] br i1 %icmp_26, label %alt4_split13, label %final5
 
         [-] alt4:
         [-] %call_31 = call i32 @func(i32 %call_18)
         [-] %icmp_32 = icmp ne i32 %call_31, 0
-        [+] alt4_split2:
+        [+] alt4_split13:
         [+] call void @putint(i32 %call_18)
-        [+] %icmp_32 = icmp ne i32 %call_18, 0
         br label %final5
 
         final5:
         [-] %phi_34 = phi i1 [false, %cond0], [%icmp_32, %alt4]
-        [+] %phi_34 = phi i1 [false, %cond0], [%icmp_32, %alt4_split2]
+        [+] %phi_34 = phi i1 [false, %cond0], [true, %alt4_split13]
         br i1 %phi_34, label %then1, label %alt2
 
         then1:
@@ -279,4 +279,366 @@ pub mod tests_func_inline {
         }
         "###);
     }
+
+    #[test]
+    fn test_non_leaf() {
+        let code = r#"
+        int g(int x) {
+            return x * 2;
+        }
+        int f(int x) {
+            return g(x) + g(x + 1);
+        }
+        int main() {
+            return f(getint());
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        func_inline::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        [-] define i32 @g(i32 %x) {
+        [-] entry:
+        [-] %Mul_8 = mul i32 %x, 2
+        [-] br label %exit
+        [-] 
+        [-] exit:
+        [-] ret i32 %Mul_8
+        [-] 
+        [-] 
+        [-] }
+        [-] define i32 @f(i32 %x) {
+        [-] entry:
+        [-] %call_19 = call i32 @g(i32 %x)
+        [-] %Add_21 = add i32 %x, 1
+        [-] %call_22 = call i32 @g(i32 %Add_21)
+        [-] %Add_23 = add i32 %call_19, %call_22
+        [-] br label %exit
+        [-] 
+        [-] exit:
+        [-] ret i32 %Add_23
+        [-] 
+        [-] 
+        [-] }
+        define i32 @main() {
+        [-] entry:
+        [-] %call_31 = call i32 @getint()
+        [-] %call_32 = call i32 @f(i32 %call_31)
+        [-] br label %exit
+        [-] 
+        exit:
+        [-] ret i32 %call_32
+        [+] %call_31 = call i32 @getint()
+        [+] %Mul_54 = mul i32 %call_31, 2
+        [+] %Add_59 = add i32 %call_31, 1
+        [+] %Mul_62 = mul i32 %Add_59, 2
+        [+] %Add_67 = add i32 %Mul_54, %Mul_62
+        [+] ret i32 %Add_67
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_threshold() {
+        let code = r#"
+        int work(int x) {
+            int y = x * x + 3;
+            y = y * x + y / 7 - x % 5;
+            return y * y - x;
+        }
+        int main() {
+            int s = work(getint());
+            int i = 0;
+            while (i < 10) {
+                s = s + work(i);
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Only call in loop is cheap enough with a small threshold
+        func_inline::set_threshold(8);
+        func_inline::optimize_program(&mut program).unwrap();
+        func_inline::set_threshold(func_inline::DEFAULT_THRESHOLD);
+        block_fuse::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @work(i32 %x) {
+        [-] entry:
+        [+] exit:
+        %Mul_10 = mul i32 %x, %x
+        %Add_11 = add i32 %Mul_10, 3
+        %Mul_15 = mul i32 %Add_11, %x
+        %SDiv_17 = sdiv i32 %Add_11, 7
+        %Add_18 = add i32 %Mul_15, %SDiv_17
+        %SRem_20 = srem i32 %x, 5
+        %Sub_21 = sub i32 %Add_18, %SRem_20
+        %Mul_25 = mul i32 %Sub_21, %Sub_21
+        %Sub_27 = sub i32 %Mul_25, %x
+        [-] br label %exit
+        [-] 
+        [-] exit:
+        ret i32 %Sub_27
+
+
+        }
+        define i32 @main() {
+        entry:
+        %call_36 = call i32 @getint()
+        %call_37 = call i32 @work(i32 %call_36)
+        br label %cond0
+
+        cond0:
+        [-] %phi_61 = phi i32 [0, %entry], [%Add_51, %body1]
+        [-] %phi_60 = phi i32 [%call_37, %entry], [%Add_48, %body1]
+        [+] %phi_61 = phi i32 [0, %entry], [%Add_51, %body1_split10]
+        [+] %phi_60 = phi i32 [%call_37, %entry], [%Add_48, %body1_split10]
+        %icmp_55 = icmp slt i32 %phi_61, 10
+        [-] br i1 %icmp_55, label %body1, label %final2
+        [+] br i1 %icmp_55, label %body1_split10, label %exit
+
+        [-] body1:
+        [-] %call_46 = call i32 @work(i32 %phi_61)
+        [-] %Add_48 = add i32 %phi_60, %call_46
+        [+] body1_split10:
+        [+] %Mul_63 = mul i32 %phi_61, %phi_61
+        [+] %Add_64 = add i32 %Mul_63, 3
+        [+] %Mul_65 = mul i32 %Add_64, %phi_61
+        [+] %SDiv_66 = sdiv i32 %Add_64, 7
+        [+] %Add_67 = add i32 %Mul_65, %SDiv_66
+        [+] %SRem_68 = srem i32 %phi_61, 5
+        [+] %Sub_69 = sub i32 %Add_67, %SRem_68
+        [+] %Mul_70 = mul i32 %Sub_69, %Sub_69
+        [+] %Sub_71 = sub i32 %Mul_70, %phi_61
+        [+] %Add_48 = add i32 %phi_60, %Sub_71
+        %Add_51 = add i32 %phi_61, 1
+        br label %cond0
+        [-] 
+        [-] final2:
+        [-] br label %exit
+
+        exit:
+        ret i32 %phi_60
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_recursive() {
+        let code = r#"
+        int fib(int n) {
+            if (n <= 1) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        int main() {
+            return fib(getint());
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+
+        // Recursive inlining stops when function exceeds threshold
+        func_inline::set_threshold(40);
+        for _ in 0..5 {
+            func_inline::optimize_program(&mut program).unwrap();
+            dead_code_elim::optimize_program(&mut program).unwrap();
+        }
+        assert!(!func_inline::optimize_program(&mut program).unwrap());
+        func_inline::set_threshold(func_inline::DEFAULT_THRESHOLD);
+        let llvm = program.module.gen_llvm_ir();
+        assert_eq!(llvm.matches("call i32 @fib").count(), 6);
+    }
+
+    #[test]
+    fn test_call_before_ret() {
+        let code = r#"
+        int g(int x) {
+            putint(x);
+            return x + 1;
+        }
+
+        int f(int x) {
+            return g(x);
+        }
+
+        int main() {
+            putint(f(getint()));
+            return 0;
+        }
+        "#;
+
+        // Inlining `g` splits exit block of `f`, which is then inlined to `main`
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        func_inline::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm = program.module.gen_llvm_ir();
+        assert_snapshot!(llvm, @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        exit_split12:
+        %alloca_47 = alloca i32
+        %alloca_46 = alloca i32
+        %alloca_45 = alloca i32
+        %alloca_44 = alloca i32
+        %alloca_26 = alloca i32
+        %call_29 = call i32 @getint()
+        call void @putint(i32 %call_29)
+        %Add_51 = add i32 %call_29, 1
+        call void @putint(i32 %Add_51)
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_caller_growth() {
+        let code = r#"
+        int f1(int x) {
+            int y = x * x + 3;
+            y = y * x + y / 7 - x % 5;
+            return y * y - x;
+        }
+        int f2(int x) {
+            int y = x * x + 5;
+            y = y * x + y / 7 - x % 5;
+            return y * y - x;
+        }
+        int f3(int x) {
+            int y = x * x + 7;
+            y = y * x + y / 7 - x % 5;
+            return y * y - x;
+        }
+        int f4(int x) {
+            int y = x * x + 9;
+            y = y * x + y / 7 - x % 5;
+            return y * y - x;
+        }
+        int main() {
+            int x = getint();
+            return f1(x) + f2(x) + f3(x) + f4(x);
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+
+        // Growth of main is limited to 25 instructions, which is counted over runs
+        func_inline::set_threshold(1);
+        func_inline::optimize_program(&mut program).unwrap();
+        let llvm = program.module.gen_llvm_ir();
+        func_inline::optimize_program(&mut program).unwrap();
+        func_inline::set_threshold(func_inline::DEFAULT_THRESHOLD);
+        assert_eq!(program.module.gen_llvm_ir(), llvm);
+        assert_eq!(llvm.matches("call i32 @f").count(), 2);
+    }
+
+    #[test]
+    fn test_recursive_converge() {
+        let code = r#"
+        int f(int x) {
+            if (x > 0) return f(x) - 1;
+            return x;
+        }
+        int main() {
+            putint(f(getint()));
+            putint(f(1));
+            return 0;
+        }
+        "#;
+
+        // Clone of `f` for constant argument only calls itself, and simplifies back
+        // to the same size after being inlined into itself
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        pass_stats::enable();
+        ultimate_pass::main_loop(&mut program).unwrap();
+        let stats = pass_stats::take().unwrap();
+        let iterations = stats
+            .passes
+            .iter()
+            .find(|pass| pass.name == "block_fuse")
+            .unwrap()
+            .runs;
+        assert!(iterations <= 10, "main loop runs {} iterations", iterations);
+    }
 }
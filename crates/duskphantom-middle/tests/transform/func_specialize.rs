@@ -0,0 +1,346 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_func_specialize {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{func_specialize, inst_combine, mem2reg, ultimate_pass},
+    };
+
+    #[test]
+    fn test_recursive() {
+        let code = r#"
+        int sum(int n, int step) {
+            if (n <= 0) {
+                return 0;
+            }
+            return n + sum(n - step, step);
+        }
+        int main() {
+            putint(sum(getint(), 2));
+            putint(sum(getint(), 3));
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        func_specialize::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        [-] define i32 @sum(i32 %n, i32 %step) {
+        [-] entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        [-] br label %cond0
+        [+] define i32 @sum_spec9(i32 %n) {
+        [+] entry_spec9:
+        [+] %alloca_44 = alloca i32
+        [+] %alloca_45 = alloca i32
+        [+] %alloca_46 = alloca i32
+        [+] br label %cond0_spec10
+
+        [-] cond0:
+        [-] %icmp_15 = icmp sle i32 %n, 0
+        [-] br i1 %icmp_15, label %then1, label %alt2
+        [+] cond0_spec10:
+        [+] %icmp_49 = icmp sle i32 %n, 0
+        [+] br i1 %icmp_49, label %then1_spec14, label %alt2_spec11
+
+        [-] then1:
+        [-] br label %exit
+        [+] then1_spec14:
+        [+] br label %exit_spec13
+
+        [-] alt2:
+        [-] br label %final3
+        [+] alt2_spec11:
+        [+] br label %final3_spec12
+
+        [-] exit:
+        [-] %phi_42 = phi i32 [0, %then1], [%Add_26, %final3]
+        [-] ret i32 %phi_42
+        [+] exit_spec13:
+        [+] %phi_59 = phi i32 [0, %then1_spec14], [%Add_56, %final3_spec12]
+        [+] ret i32 %phi_59
+
+        [-] final3:
+        [-] %Sub_22 = sub i32 %n, %step
+        [-] %call_24 = call i32 @sum(i32 %Sub_22, i32 %step)
+        [-] %Add_26 = add i32 %n, %call_24
+        [-] br label %exit
+        [+] final3_spec12:
+        [+] %Sub_54 = sub i32 %n, 2
+        [+] %call_63 = call i32 @sum_spec9(i32 %Sub_54)
+        [+] %Add_56 = add i32 %n, %call_63
+        [+] br label %exit_spec13
+
+
+        }
+        [+] define i32 @sum_spec15(i32 %n) {
+        [+] entry_spec15:
+        [+] %alloca_66 = alloca i32
+        [+] %alloca_67 = alloca i32
+        [+] %alloca_68 = alloca i32
+        [+] br label %cond0_spec16
+        [+] 
+        [+] cond0_spec16:
+        [+] %icmp_71 = icmp sle i32 %n, 0
+        [+] br i1 %icmp_71, label %then1_spec20, label %alt2_spec17
+        [+] 
+        [+] then1_spec20:
+        [+] br label %exit_spec19
+        [+] 
+        [+] alt2_spec17:
+        [+] br label %final3_spec18
+        [+] 
+        [+] exit_spec19:
+        [+] %phi_81 = phi i32 [0, %then1_spec20], [%Add_78, %final3_spec18]
+        [+] ret i32 %phi_81
+        [+] 
+        [+] final3_spec18:
+        [+] %Sub_76 = sub i32 %n, 3
+        [+] %call_85 = call i32 @sum_spec15(i32 %Sub_76)
+        [+] %Add_78 = add i32 %n, %call_85
+        [+] br label %exit_spec19
+        [+] 
+        [+] 
+        [+] }
+        define i32 @main() {
+        entry:
+        %alloca_31 = alloca i32
+        %call_34 = call i32 @getint()
+        [-] %call_35 = call i32 @sum(i32 %call_34, i32 2)
+        [-] call void @putint(i32 %call_35)
+        [+] %call_64 = call i32 @sum_spec9(i32 %call_34)
+        [+] call void @putint(i32 %call_64)
+        %call_37 = call i32 @getint()
+        [-] %call_38 = call i32 @sum(i32 %call_37, i32 3)
+        [-] call void @putint(i32 %call_38)
+        [+] %call_86 = call i32 @sum_spec15(i32 %call_37)
+        [+] call void @putint(i32 %call_86)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_loop_call() {
+        let code = r#"
+        int scale(int x, int k) {
+            return x * k;
+        }
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + scale(i, 4);
+                i = i + 1;
+            }
+            putint(s);
+            putint(scale(n, 5));
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization, only the call in loop is specialized
+        func_specialize::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @scale(i32 %x, i32 %k) {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %alloca_7 = alloca i32
+        %Mul_11 = mul i32 %x, %k
+        br label %exit
+
+        exit:
+        ret i32 %Mul_11
+
+
+        }
+        [+] define i32 @scale_spec8(i32 %x) {
+        [+] entry_spec8:
+        [+] %alloca_53 = alloca i32
+        [+] %alloca_54 = alloca i32
+        [+] %alloca_55 = alloca i32
+        [+] %Mul_56 = mul i32 %x, 4
+        [+] br label %exit_spec9
+        [+] 
+        [+] exit_spec9:
+        [+] ret i32 %Mul_56
+        [+] 
+        [+] 
+        [+] }
+        define i32 @main() {
+        entry:
+        %alloca_16 = alloca i32
+        %alloca_19 = alloca i32
+        %call_20 = call i32 @getint()
+        %alloca_22 = alloca i32
+        %alloca_24 = alloca i32
+        br label %cond0
+
+        cond0:
+        %phi_51 = phi i32 [0, %entry], [%Add_33, %body1]
+        %phi_50 = phi i32 [0, %entry], [%Add_36, %body1]
+        %icmp_41 = icmp slt i32 %phi_50, %call_20
+        br i1 %icmp_41, label %body1, label %final2
+
+        body1:
+        [-] %call_31 = call i32 @scale(i32 %phi_50, i32 4)
+        [-] %Add_33 = add i32 %phi_51, %call_31
+        [+] %call_60 = call i32 @scale_spec8(i32 %phi_50)
+        [+] %Add_33 = add i32 %phi_51, %call_60
+        %Add_36 = add i32 %phi_50, 1
+        br label %cond0
+
+        final2:
+        call void @putint(i32 %phi_51)
+        %call_46 = call i32 @scale(i32 %call_20, i32 5)
+        call void @putint(i32 %call_46)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_clone_budget() {
+        let code = r#"
+        int fib(int n) {
+            if (n <= 1) return n;
+            return fib(n - 1) + fib(n - 2);
+        }
+        int main() {
+            int i = 0;
+            while (i < 10) {
+                putint(fib(i));
+                i = i + 1;
+            }
+            putint(fib(30));
+            return 0;
+        }
+        "#;
+
+        // Clones inlined away still count, so specializing and inlining terminate
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        ultimate_pass::main_loop(&mut program).unwrap();
+        let clones = program
+            .module
+            .functions
+            .iter()
+            .filter(|func| func.name.starts_with("fib_spec"))
+            .count();
+        assert!(clones <= 8);
+    }
+
+    #[test]
+    fn test_total_clone_budget() {
+        let mut code = String::new();
+        let mut calls = String::new();
+        for i in 0..10 {
+            code.push_str(&format!("int f{i}(int x) {{ return x * {i}; }}\n"));
+            for x in 0..4 {
+                calls.push_str(&format!("putint(f{i}({x}));\n"));
+            }
+        }
+        code.push_str(&format!(
+            "int main() {{ int i = 0; while (i < 10) {{ {calls} i = i + 1; }} return 0; }}"
+        ));
+
+        // 40 hot call sites with different constants, but clones are limited in program
+        let parsed = parse(&code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        for _ in 0..2 {
+            func_specialize::optimize_program(&mut program).unwrap();
+            let clones = program
+                .module
+                .functions
+                .iter()
+                .filter(|func| func.name.contains("_spec"))
+                .count();
+            assert_eq!(clones, 32);
+        }
+    }
+}
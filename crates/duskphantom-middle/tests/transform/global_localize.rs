@@ -0,0 +1,228 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_global_localize {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{global_localize, mem2reg},
+    };
+
+    #[test]
+    fn test_localize() {
+        let code = r#"
+        int n = 10;
+        int sum;
+        int shared;
+        int f() {
+            return shared + 1;
+        }
+        int main() {
+            int i = 0;
+            while (i < n) {
+                sum = sum + i;
+                i = i + 1;
+            }
+            shared = sum;
+            return f();
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        global_localize::optimize_program(&mut program).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        [-] @n = dso_local global i32 10
+        [-] @sum = dso_local global i32 0
+        @shared = dso_local global i32 0
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @f() {
+        entry:
+        %alloca_2 = alloca i32
+        %load_5 = load i32, ptr @shared
+        %Add_6 = add i32 %load_5, 1
+        br label %exit
+
+        exit:
+        ret i32 %Add_6
+
+
+        }
+        define i32 @main() {
+        entry:
+        [+] %alloca_40 = alloca i32
+        [+] %alloca_38 = alloca i32
+        %alloca_11 = alloca i32
+        %alloca_14 = alloca i32
+        br label %cond0
+
+        cond0:
+        [+] %phi_42 = phi i32 [0, %entry], [%Add_22, %body1]
+        %phi_37 = phi i32 [0, %entry], [%Add_25, %body1]
+        [-] %load_29 = load i32, ptr @n
+        [-] %icmp_30 = icmp slt i32 %phi_37, %load_29
+        [+] %icmp_30 = icmp slt i32 %phi_37, 10
+        br i1 %icmp_30, label %body1, label %final2
+
+        body1:
+        [-] %load_20 = load i32, ptr @sum
+        [-] %Add_22 = add i32 %load_20, %phi_37
+        [-] store i32 %Add_22, ptr @sum
+        [+] %Add_22 = add i32 %phi_42, %phi_37
+        %Add_25 = add i32 %phi_37, 1
+        br label %cond0
+
+        final2:
+        [-] %load_32 = load i32, ptr @sum
+        [-] store i32 %load_32, ptr @shared
+        [+] store i32 %phi_42, ptr @shared
+        %call_34 = call i32 @f()
+        br label %exit
+
+        exit:
+        ret i32 %call_34
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_write_only() {
+        let code = r#"
+        int a[10][10];
+        int b[10];
+        int f(int x) {
+            a[x][x] = x;
+            return x;
+        }
+        int main() {
+            b[1] = f(2);
+            b[2] = 3;
+            putint(b[2]);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization, only `a` is removed
+        global_localize::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        [-] @a = dso_local global [10 x [10 x i32]] zeroinitializer
+        @b = dso_local global [10 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @f(i32 %x) {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        [-] %getelementptr_9 = getelementptr [10 x [10 x i32]], ptr @a, i32 0, i32 %x
+        [-] %getelementptr_10 = getelementptr [10 x i32], ptr %getelementptr_9, i32 0, i32 %x
+        [-] store i32 %x, ptr %getelementptr_10
+        br label %exit
+
+        exit:
+        ret i32 %x
+
+
+        }
+        define i32 @main() {
+        entry:
+        %alloca_18 = alloca i32
+        %call_21 = call i32 @f(i32 2)
+        %getelementptr_22 = getelementptr [10 x i32], ptr @b, i32 0, i32 1
+        store i32 %call_21, ptr %getelementptr_22
+        %getelementptr_24 = getelementptr [10 x i32], ptr @b, i32 0, i32 2
+        store i32 3, ptr %getelementptr_24
+        %getelementptr_26 = getelementptr [10 x i32], ptr @b, i32 0, i32 2
+        %load_27 = load i32, ptr %getelementptr_26
+        call void @putint(i32 %load_27)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_recursive_main() {
+        let code = r#"
+        int depth;
+        int main() {
+            depth = depth + 1;
+            if (depth < 3) {
+                main();
+            }
+            return depth;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        assert!(!global_localize::optimize_program(&mut program).unwrap());
+    }
+}
@@ -0,0 +1,373 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+#[cfg(test)]
+pub mod tests_gvn_pre {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_code_elim, gvn_pre, mem2reg},
+    };
+
+    #[test]
+    fn test_partial_redundancy() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            if (a > b) {
+                putint(a * b);
+            } else {
+                putint(0);
+            }
+            return a * b;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        gvn_pre::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_18 = icmp sgt i32 %call_6, %call_9
+        br i1 %icmp_18, label %then1, label %alt2
+
+        then1:
+        %Mul_22 = mul i32 %call_6, %call_9
+        call void @putint(i32 %Mul_22)
+        br label %final3
+
+        alt2:
+        call void @putint(i32 0)
+        [+] %Mul_32 = mul i32 %call_6, %call_9
+        br label %final3
+
+        final3:
+        [-] %Mul_29 = mul i32 %call_6, %call_9
+        [+] %phi_33 = phi i32 [%Mul_22, %then1], [%Mul_32, %alt2]
+        br label %exit
+
+        exit:
+        [-] ret i32 %Mul_29
+        [+] ret i32 %phi_33
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_phi_of_ops() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            int x;
+            int y;
+            if (a > b) {
+                x = a;
+                y = a + 1;
+            } else {
+                x = b;
+                y = b + 1;
+            }
+            putint(y);
+            return x + 1;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        gvn_pre::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_20 = icmp sgt i32 %call_6, %call_9
+        br i1 %icmp_20, label %then1, label %alt2
+
+        then1:
+        %Add_25 = add i32 %call_6, 1
+        br label %final3
+
+        alt2:
+        %Add_31 = add i32 %call_9, 1
+        br label %final3
+
+        final3:
+        %phi_41 = phi i32 [%Add_25, %then1], [%Add_31, %alt2]
+        [-] %phi_40 = phi i32 [%call_6, %then1], [%call_9, %alt2]
+        call void @putint(i32 %phi_41)
+        [-] %Add_37 = add i32 %phi_40, 1
+        br label %exit
+
+        exit:
+        [-] ret i32 %Add_37
+        [+] ret i32 %phi_41
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_congruent_phi() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            int x;
+            int y;
+            if (a > b) {
+                x = a * 2;
+                y = a * 2;
+            } else {
+                x = b - a;
+                y = b - a;
+            }
+            return x - y;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        gvn_pre::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_20 = icmp sgt i32 %call_6, %call_9
+        br i1 %icmp_20, label %then1, label %alt2
+
+        then1:
+        %Mul_23 = mul i32 %call_6, 2
+        [-] %Mul_26 = mul i32 %call_6, 2
+        br label %final3
+
+        alt2:
+        %Sub_31 = sub i32 %call_9, %call_6
+        [-] %Sub_35 = sub i32 %call_9, %call_6
+        br label %final3
+
+        final3:
+        [-] %phi_44 = phi i32 [%Mul_26, %then1], [%Sub_35, %alt2]
+        [-] %phi_43 = phi i32 [%Mul_23, %then1], [%Sub_31, %alt2]
+        [-] %Sub_40 = sub i32 %phi_43, %phi_44
+        [+] %phi_44 = phi i32 [%Mul_23, %then1], [%Sub_31, %alt2]
+        [+] %Sub_40 = sub i32 %phi_44, %phi_44
+        br label %exit
+
+        exit:
+        ret i32 %Sub_40
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_load() {
+        let code = r#"
+        int A[10];
+        int main() {
+            int i = getint();
+            if (i > 5) {
+                putint(A[i]);
+            } else {
+                A[i] = 3;
+            }
+            return A[i];
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        gvn_pre::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [10 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_14 = icmp sgt i32 %call_6, 5
+        br i1 %icmp_14, label %then1, label %alt2
+
+        then1:
+        %getelementptr_17 = getelementptr [10 x i32], ptr @A, i32 0, i32 %call_6
+        %load_18 = load i32, ptr %getelementptr_17
+        call void @putint(i32 %load_18)
+        br label %final3
+
+        alt2:
+        %getelementptr_22 = getelementptr [10 x i32], ptr @A, i32 0, i32 %call_6
+        store i32 3, ptr %getelementptr_22
+        br label %final3
+
+        final3:
+        [-] %getelementptr_26 = getelementptr [10 x i32], ptr @A, i32 0, i32 %call_6
+        [-] %load_27 = load i32, ptr %getelementptr_26
+        [+] %phi_31 = phi i32 [%load_18, %then1], [3, %alt2]
+        br label %exit
+
+        exit:
+        [-] ret i32 %load_27
+        [+] ret i32 %phi_31
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_no_insert_on_critical_edge() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            if (a > b) {
+                putint(a / b);
+            }
+            return a / b;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        gvn_pre::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm = program.module.gen_llvm_ir();
+        assert_eq!(llvm.matches("sdiv").count(), 2);
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_if_convert {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{if_convert, mem2reg},
+    };
+
+    #[test]
+    fn test_diamond() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            int x;
+            if (a > b) {
+                x = a - b;
+            } else {
+                x = b - a;
+            }
+            return x;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        if_convert::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %call_9 = call i32 @getint()
+        %alloca_11 = alloca i32
+        br label %cond0
+
+        cond0:
+        %icmp_19 = icmp sgt i32 %call_6, %call_9
+        [-] br i1 %icmp_19, label %then1, label %alt2
+        [-] 
+        [-] then1:
+        %Sub_23 = sub i32 %call_6, %call_9
+        [-] br label %final3
+        [-] 
+        [-] alt2:
+        %Sub_28 = sub i32 %call_9, %call_6
+        [+] %select_35 = select i1 %icmp_19, i32 %Sub_23, i32 %Sub_28
+        br label %final3
+
+        final3:
+        [-] %phi_34 = phi i32 [%Sub_23, %then1], [%Sub_28, %alt2]
+        br label %exit
+
+        exit:
+        [-] ret i32 %phi_34
+        [+] ret i32 %select_35
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_triangle() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            if (a < 0) {
+                a = -a;
+            }
+            return a;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        if_convert::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_14 = icmp slt i32 %call_6, 0
+        [-] br i1 %icmp_14, label %then1, label %alt2
+        [-] 
+        [-] then1:
+        %Sub_17 = sub i32 0, %call_6
+        [+] %select_25 = select i1 %icmp_14, i32 %Sub_17, i32 %call_6
+        br label %final3
+
+        [-] alt2:
+        [-] br label %final3
+        [-] 
+        final3:
+        [-] %phi_24 = phi i32 [%Sub_17, %then1], [%call_6, %alt2]
+        br label %exit
+
+        exit:
+        [-] ret i32 %phi_24
+        [+] ret i32 %select_25
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_side_effect() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            if (a < 0) {
+                putint(a);
+                a = 0;
+            }
+            return a;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Branch with call is kept
+        let changed = if_convert::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(!changed);
+        assert_eq!(llvm_before, llvm_after);
+    }
+}
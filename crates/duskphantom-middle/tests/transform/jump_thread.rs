@@ -0,0 +1,513 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+#[cfg(test)]
+pub mod tests_jump_thread {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{block_fuse, dead_code_elim, jump_thread, mem2reg},
+    };
+
+    #[test]
+    fn test_phi_of_constants() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int f;
+            if (a > 0) {
+                f = 1;
+                putint(1);
+            } else {
+                f = 0;
+                putint(2);
+            }
+            if (f) {
+                putint(3);
+            } else {
+                putint(4);
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        jump_thread::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        cond0:
+        %call_6 = call i32 @getint()
+        %icmp_15 = icmp sgt i32 %call_6, 0
+        br i1 %icmp_15, label %then1, label %alt2
+
+        then1:
+        call void @putint(i32 1)
+        [-] br label %cond4
+        [+] br label %cond4_thread11
+
+        alt2:
+        call void @putint(i32 2)
+        br label %cond4
+
+        [+] cond4_thread11:
+        [+] br label %then5
+        [+] 
+        cond4:
+        [-] %phi_37 = phi i32 [1, %then1], [0, %alt2]
+        [-] %icmp_29 = icmp ne i32 %phi_37, 0
+        [-] br i1 %icmp_29, label %then5, label %alt6
+        [+] br label %alt6
+
+        then5:
+        call void @putint(i32 3)
+        br label %exit
+
+        alt6:
+        call void @putint(i32 4)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_dominating_compare() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            if (a < b) {
+                if (a >= b) {
+                    putint(1);
+                } else {
+                    putint(2);
+                }
+            }
+            if (a < 5) {
+                if (a < 10) {
+                    putint(3);
+                }
+                if (a == 7) {
+                    putint(4);
+                }
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        jump_thread::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        cond0:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        %icmp_18 = icmp slt i32 %call_6, %call_9
+        br i1 %icmp_18, label %cond4, label %alt2
+
+        cond4:
+        [-] %icmp_27 = icmp sge i32 %call_6, %call_9
+        [-] br i1 %icmp_27, label %then5, label %alt6
+        [+] br label %alt6
+
+        alt2:
+        br label %cond8
+
+        [-] then5:
+        [-] call void @putint(i32 1)
+        [-] br label %final7
+        [-] 
+        alt6:
+        call void @putint(i32 2)
+        br label %final7
+
+        cond8:
+        %icmp_41 = icmp slt i32 %call_6, 5
+        br i1 %icmp_41, label %cond12, label %alt10
+
+        final7:
+        br label %cond8
+
+        cond12:
+        [-] %icmp_49 = icmp slt i32 %call_6, 10
+        [-] br i1 %icmp_49, label %then13, label %alt14
+        [+] br label %then13
+
+        alt10:
+        br label %exit
+
+        then13:
+        call void @putint(i32 3)
+        br label %cond16
+
+        [-] alt14:
+        [-] br label %cond16
+        [-] 
+        exit:
+        ret i32 0
+
+        cond16:
+        [-] %icmp_60 = icmp eq i32 %call_6, 7
+        [-] br i1 %icmp_60, label %then17, label %alt18
+        [-] 
+        [-] then17:
+        [-] call void @putint(i32 4)
+        [-] br label %final19
+        [+] br label %alt18
+
+        alt18:
+        br label %final19
+
+        final19:
+        br label %exit
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_repair_escaping_values() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int x;
+            if (a > 0) {
+                x = a + 1;
+            } else {
+                x = a - 1;
+            }
+            int y = x * 2;
+            if (a > 0) {
+                putint(y);
+            } else {
+                putint(0);
+            }
+            putint(x);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        jump_thread::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        cond0:
+        %call_6 = call i32 @getint()
+        %icmp_15 = icmp sgt i32 %call_6, 0
+        br i1 %icmp_15, label %then1, label %alt2
+
+        then1:
+        %Add_18 = add i32 %call_6, 1
+        [-] br label %cond4
+        [+] br label %cond4_thread11
+
+        alt2:
+        %Sub_22 = sub i32 %call_6, 1
+        br label %cond4
+
+        [+] cond4_thread11:
+        [+] %Mul_48 = mul i32 %Add_18, 2
+        [+] br label %then5
+        [+] 
+        cond4:
+        [-] %phi_46 = phi i32 [%Add_18, %then1], [%Sub_22, %alt2]
+        [-] %Mul_27 = mul i32 %phi_46, 2
+        [-] %icmp_35 = icmp sgt i32 %call_6, 0
+        [-] br i1 %icmp_35, label %then5, label %alt6
+        [+] br label %alt6
+
+        then5:
+        [-] call void @putint(i32 %Mul_27)
+        [+] call void @putint(i32 %Mul_48)
+        br label %exit
+
+        alt6:
+        call void @putint(i32 0)
+        br label %exit
+
+        exit:
+        [-] call void @putint(i32 %phi_46)
+        [+] %phi_51 = phi i32 [%Add_18, %then5], [%Sub_22, %alt6]
+        [+] call void @putint(i32 %phi_51)
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_no_thread_loop_header() {
+        let code = r#"
+        int main() {
+            int i = 0;
+            while (i < 10) {
+                putint(i);
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        jump_thread::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        br label %cond0
+
+        cond0:
+        %phi_22 = phi i32 [0, %entry], [%Add_14, %body1]
+        %icmp_18 = icmp slt i32 %phi_22, 10
+        br i1 %icmp_18, label %body1, label %exit
+
+        body1:
+        call void @putint(i32 %phi_22)
+        %Add_14 = add i32 %phi_22, 1
+        br label %cond0
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_repair_repeated_use() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int x;
+            if (a > 0) {
+                putint(1);
+                x = 2;
+            } else {
+                putint(2);
+                x = 14;
+            }
+            if (x == 1) {
+                return 0;
+            }
+            if (x * x > 100) {
+                putint(3);
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization, both uses of `x` in `x * x` are repaired
+        jump_thread::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        cond0:
+        %call_6 = call i32 @getint()
+        %icmp_15 = icmp sgt i32 %call_6, 0
+        br i1 %icmp_15, label %then1, label %alt2
+
+        then1:
+        call void @putint(i32 1)
+        [-] br label %cond4
+        [+] br label %cond4_thread15
+
+        alt2:
+        call void @putint(i32 2)
+        br label %cond4
+
+        [+] cond4_thread15:
+        [+] br label %cond8
+        [+] 
+        cond4:
+        [-] %phi_50 = phi i32 [2, %then1], [14, %alt2]
+        [-] %icmp_29 = icmp eq i32 %phi_50, 1
+        [-] br i1 %icmp_29, label %then5, label %cond8
+        [+] br label %cond8_thread16
+
+        [-] then5:
+        [-] br label %exit
+        [-] 
+        cond8:
+        [-] %Mul_41 = mul i32 %phi_50, %phi_50
+        [+] %Mul_41 = mul i32 2, 2
+        %icmp_42 = icmp sgt i32 %Mul_41, 100
+        br i1 %icmp_42, label %then9, label %alt10
+
+        [-] exit:
+        [-] %phi_49 = phi i32 [0, %then5], [0, %final11]
+        [-] ret i32 %phi_49
+        [+] cond8_thread16:
+        [+] br label %then9
+
+        then9:
+        call void @putint(i32 3)
+        br label %final11
+
+        alt10:
+        br label %final11
+
+        final11:
+        br label %exit
+        [+] 
+        [+] exit:
+        [+] ret i32 0
+
+
+        }
+        "###);
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+#[cfg(test)]
+pub mod tests_lcssa {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        analysis::loop_tools::LoopForest,
+        irgen::gen,
+        transform::{
+            lcssa::{is_lcssa, LCSSA},
+            inst_combine,
+            loop_simplify::LoopSimplifier,
+            mem2reg,
+        },
+    };
+
+    #[test]
+    fn test_break_exit() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + i;
+                if (s > 100) break;
+                i = i + 1;
+            }
+            return s + i;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let func = program.module.functions.last().cloned().unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+        assert!(!is_lcssa(forest.forest[0]));
+
+        // Check after optimization
+        LCSSA::new(&mut program.mem_pool)
+            .run(func, &mut forest)
+            .unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(is_lcssa(forest.forest[0]));
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %alloca_10 = alloca i32
+        br label %cond0
+
+        cond0:
+        %phi_44 = phi i32 [0, %entry], [%Add_18, %final6]
+        %phi_43 = phi i32 [0, %entry], [%Add_31, %final6]
+        %icmp_36 = icmp slt i32 %phi_43, %call_6
+        [-] br i1 %icmp_36, label %body1, label %final2
+        [+] br i1 %icmp_36, label %body1, label %final2_dedicated10
+
+        body1:
+        %Add_18 = add i32 %phi_44, %phi_43
+        br label %cond3
+
+        [-] final2:
+        [-] %phi_45 = phi i32 [%Add_18, %then4], [%phi_44, %cond0]
+        [-] %Add_40 = add i32 %phi_45, %phi_43
+        [-] br label %exit
+        [+] final2_dedicated10:
+        [+] %phi_49 = phi i32 [%phi_43, %cond0]
+        [+] %phi_48 = phi i32 [%phi_44, %cond0]
+        [+] br label %final2
+
+        cond3:
+        %icmp_26 = icmp sgt i32 %Add_18, 100
+        br i1 %icmp_26, label %then4, label %alt5
+
+        [-] exit:
+        [-] ret i32 %Add_40
+        [+] final2:
+        [+] %phi_51 = phi i32 [%phi_50, %then4], [%phi_49, %final2_dedicated10]
+        [+] %phi_45 = phi i32 [%phi_52, %then4], [%phi_48, %final2_dedicated10]
+        [+] %Add_40 = add i32 %phi_45, %phi_51
+        [+] br label %exit
+
+        then4:
+        [+] %phi_52 = phi i32 [%Add_18, %cond3]
+        [+] %phi_50 = phi i32 [%phi_43, %cond3]
+        br label %final2
+
+        alt5:
+        br label %final6
+        [+] 
+        [+] exit:
+        [+] ret i32 %Add_40
+
+        final6:
+        %Add_31 = add i32 %phi_43, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_folded_exit() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int k = 0;
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + i;
+                if (k > 0) {
+                    putint(s);
+                    break;
+                }
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Exit branch folds to constant, leaving use of `s` in unreachable block
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let func = program.module.functions.last().cloned().unwrap();
+        let mut forest = LoopForest::make_forest(func).unwrap();
+        LoopSimplifier::new(&mut program.mem_pool)
+            .run(&mut forest)
+            .unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        LCSSA::new(&mut program.mem_pool)
+            .run(func, &mut forest)
+            .unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(is_lcssa(forest.forest[0]));
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %alloca_10 = alloca i32
+        %alloca_12 = alloca i32
+        br label %cond0
+
+        cond0:
+        %phi_46 = phi i32 [0, %entry], [%Add_20, %final6]
+        %phi_45 = phi i32 [0, %entry], [%Add_35, %final6]
+        %icmp_40 = icmp slt i32 %phi_45, %call_6
+        br i1 %icmp_40, label %body1, label %final2
+
+        body1:
+        %Add_20 = add i32 %phi_46, %phi_45
+        br label %cond3
+
+        final2:
+        [+] %phi_49 = phi i32 [%phi_46, %cond0]
+        br label %exit
+
+        cond3:
+        br label %alt5
+
+        exit:
+        [-] ret i32 %phi_46
+        [+] ret i32 %phi_49
+
+        alt5:
+        br label %final6
+
+        final6:
+        %Add_35 = add i32 %phi_45, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+}
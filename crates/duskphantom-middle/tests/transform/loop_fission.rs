@@ -0,0 +1,168 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+#[cfg(test)]
+pub mod tests_loop_fission {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{loop_fission, mem2reg},
+    };
+
+    #[test]
+    fn test_split_independent_updates() {
+        let code = r#"
+        int a[100];
+        int b[100];
+        int c[100];
+        int main() {
+            int n = getint();
+            int i = 1;
+            while (i < n) {
+                a[i] = a[i - 1] + 1;
+                b[i] = c[i] * 2;
+                i = i + 1;
+            }
+            return a[n - 1] + b[n - 1];
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_fission::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [100 x i32] zeroinitializer
+        @b = dso_local global [100 x i32] zeroinitializer
+        @c = dso_local global [100 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        [+] br label %cond0_fission6
+        [+] 
+        [+] cond0_fission6:
+        [+] %phi_50 = phi i32 [1, %entry], [%Add_62, %body1_fission7]
+        [+] %icmp_51 = icmp slt i32 %phi_50, %call_6
+        [+] br i1 %icmp_51, label %body1_fission7, label %entry_fission8
+        [+] 
+        [+] body1_fission7:
+        [+] %getelementptr_57 = getelementptr [100 x i32], ptr @c, i32 0, i32 %phi_50
+        [+] %load_58 = load i32, ptr %getelementptr_57
+        [+] %Mul_59 = mul i32 %load_58, 2
+        [+] %getelementptr_60 = getelementptr [100 x i32], ptr @b, i32 0, i32 %phi_50
+        [+] store i32 %Mul_59, ptr %getelementptr_60
+        [+] %Add_62 = add i32 %phi_50, 1
+        [+] br label %cond0_fission6
+        [+] 
+        [+] entry_fission8:
+        br label %cond0
+
+        cond0:
+        [-] %phi_48 = phi i32 [1, %entry], [%Add_30, %body1]
+        [+] %phi_48 = phi i32 [1, %entry_fission8], [%Add_30, %body1]
+        %icmp_35 = icmp slt i32 %phi_48, %call_6
+        br i1 %icmp_35, label %body1, label %final2
+
+        body1:
+        %Sub_15 = sub i32 %phi_48, 1
+        %getelementptr_16 = getelementptr [100 x i32], ptr @a, i32 0, i32 %Sub_15
+        %load_17 = load i32, ptr %getelementptr_16
+        %Add_18 = add i32 %load_17, 1
+        %getelementptr_20 = getelementptr [100 x i32], ptr @a, i32 0, i32 %phi_48
+        store i32 %Add_18, ptr %getelementptr_20
+        [-] %getelementptr_23 = getelementptr [100 x i32], ptr @c, i32 0, i32 %phi_48
+        [-] %load_24 = load i32, ptr %getelementptr_23
+        [-] %Mul_25 = mul i32 %load_24, 2
+        [-] %getelementptr_27 = getelementptr [100 x i32], ptr @b, i32 0, i32 %phi_48
+        [-] store i32 %Mul_25, ptr %getelementptr_27
+        %Add_30 = add i32 %phi_48, 1
+        br label %cond0
+
+        final2:
+        %Sub_38 = sub i32 %call_6, 1
+        %getelementptr_39 = getelementptr [100 x i32], ptr @a, i32 0, i32 %Sub_38
+        %Sub_41 = sub i32 %call_6, 1
+        %getelementptr_42 = getelementptr [100 x i32], ptr @b, i32 0, i32 %Sub_41
+        %load_43 = load i32, ptr %getelementptr_39
+        %load_44 = load i32, ptr %getelementptr_42
+        %Add_45 = add i32 %load_43, %load_44
+        br label %exit
+
+        exit:
+        ret i32 %Add_45
+
+
+        }
+        "###);
+
+        // Both parts can not be split further
+        assert!(!loop_fission::optimize_program(&mut program).unwrap());
+    }
+
+    #[test]
+    fn test_no_split_parallel() {
+        let code = r#"
+        int a[100];
+        int b[100];
+        int main() {
+            int n = getint();
+            int i = 0;
+            while (i < n) {
+                a[i] = i;
+                b[i] = i * 2;
+                i = i + 1;
+            }
+            return a[n - 1] + b[n - 1];
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let changed = loop_fission::optimize_program(&mut program).unwrap();
+        assert!(!changed);
+    }
+}
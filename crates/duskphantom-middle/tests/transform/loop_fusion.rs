@@ -0,0 +1,167 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+#[cfg(test)]
+pub mod tests_loop_fusion {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_code_elim, loop_fusion, mem2reg},
+    };
+
+    #[test]
+    fn test_fuse_init_and_accumulate() {
+        let code = r#"
+        int a[100];
+        int main() {
+            int n = getint();
+            int i = 0;
+            while (i < n) {
+                a[i] = i * 2;
+                i = i + 1;
+            }
+            int s = 0;
+            int j = 0;
+            while (j < n) {
+                s = s + a[j];
+                j = j + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_fusion::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [100 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        [-] %phi_52 = phi i32 [0, %entry], [%Add_20, %body1]
+        [+] %phi_52 = phi i32 [0, %entry], [%Add_20, %body4]
+        [+] %phi_54 = phi i32 [0, %entry], [%Add_42, %body4]
+        [+] %phi_53 = phi i32 [0, %entry], [%Add_39, %body4]
+        %icmp_25 = icmp slt i32 %phi_52, %call_6
+        [-] br i1 %icmp_25, label %body1, label %final2
+        [+] br i1 %icmp_25, label %body1, label %final5
+
+        body1:
+        %Mul_15 = mul i32 %phi_52, 2
+        %getelementptr_17 = getelementptr [100 x i32], ptr @a, i32 0, i32 %phi_52
+        store i32 %Mul_15, ptr %getelementptr_17
+        %Add_20 = add i32 %phi_52, 1
+        [-] br label %cond0
+        [-] 
+        [-] final2:
+        br label %cond3
+
+        [+] final5:
+        [+] br label %exit
+        [+] 
+        cond3:
+        [-] %phi_54 = phi i32 [0, %final2], [%Add_42, %body4]
+        [-] %phi_53 = phi i32 [0, %final2], [%Add_39, %body4]
+        %icmp_47 = icmp slt i32 %phi_54, %call_6
+        [-] br i1 %icmp_47, label %body4, label %final5
+        [+] br label %body4
+
+        [+] exit:
+        [+] ret i32 %phi_53
+        [+] 
+        body4:
+        %getelementptr_36 = getelementptr [100 x i32], ptr @a, i32 0, i32 %phi_54
+        %load_38 = load i32, ptr %getelementptr_36
+        %Add_39 = add i32 %phi_53, %load_38
+        %Add_42 = add i32 %phi_54, 1
+        [-] br label %cond3
+        [-] 
+        [-] final5:
+        [-] br label %exit
+        [-] 
+        [-] exit:
+        [-] ret i32 %phi_53
+        [+] br label %cond0
+
+
+        }
+        "###);
+
+        // Fused loop has nothing to fuse with
+        assert!(!loop_fusion::optimize_program(&mut program).unwrap());
+    }
+
+    #[test]
+    fn test_no_fuse_shifted_access() {
+        let code = r#"
+        int a[100];
+        int main() {
+            int n = getint();
+            int i = 0;
+            while (i < n) {
+                a[i] = i * 2;
+                i = i + 1;
+            }
+            int s = 0;
+            int j = 0;
+            while (j < n) {
+                s = s + a[j + 1];
+                j = j + 1;
+            }
+            return s;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let changed = loop_fusion::optimize_program(&mut program).unwrap();
+        assert!(!changed);
+    }
+}
@@ -0,0 +1,400 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+#[cfg(test)]
+pub mod tests_loop_interchange {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_code_elim, loop_interchange, mem2reg},
+    };
+
+    #[test]
+    fn test_column_major() {
+        let code = r#"
+        int a[100][100];
+        int b[100][100];
+        int main() {
+            int j = 0;
+            while (j < 100) {
+                int i = 0;
+                while (i < 100) {
+                    a[i][j] = b[i][j] + 1;
+                    i = i + 1;
+                }
+                j = j + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_interchange::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [100 x [100 x i32]] zeroinitializer
+        @b = dso_local global [100 x [100 x i32]] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        br label %cond0
+
+        cond0:
+        [-] %phi_44 = phi i32 [0, %entry], [%Add_36, %final5]
+        [-] %icmp_40 = icmp slt i32 %phi_44, 100
+        [-] br i1 %icmp_40, label %body1, label %final2
+        [+] %phi_46 = phi i32 [0, %entry], [%Add_29, %final5]
+        [+] %icmp_33 = icmp slt i32 %phi_46, 100
+        [+] br i1 %icmp_33, label %body1, label %final2
+
+        body1:
+        br label %cond3
+
+        final2:
+        br label %exit
+
+        cond3:
+        [-] %phi_46 = phi i32 [0, %body1], [%Add_29, %body4]
+        [-] %icmp_33 = icmp slt i32 %phi_46, 100
+        [-] br i1 %icmp_33, label %body4, label %final5
+        [+] %phi_44 = phi i32 [0, %body1], [%Add_36, %body4]
+        [+] %icmp_40 = icmp slt i32 %phi_44, 100
+        [+] br i1 %icmp_40, label %body4, label %final5
+
+        exit:
+        ret i32 0
+
+        body4:
+        %getelementptr_19 = getelementptr [100 x [100 x i32]], ptr @b, i32 0, i32 %phi_46
+        %getelementptr_20 = getelementptr [100 x i32], ptr %getelementptr_19, i32 0, i32 %phi_44
+        %load_21 = load i32, ptr %getelementptr_20
+        %Add_22 = add i32 %load_21, 1
+        %getelementptr_25 = getelementptr [100 x [100 x i32]], ptr @a, i32 0, i32 %phi_46
+        %getelementptr_26 = getelementptr [100 x i32], ptr %getelementptr_25, i32 0, i32 %phi_44
+        store i32 %Add_22, ptr %getelementptr_26
+        [-] %Add_29 = add i32 %phi_46, 1
+        [+] %Add_36 = add i32 %phi_44, 1
+        br label %cond3
+
+        final5:
+        [-] %Add_36 = add i32 %phi_44, 1
+        [+] %Add_29 = add i32 %phi_46, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_matrix_multiply() {
+        let code = r#"
+        int a[64][64];
+        int b[64][64];
+        int c[64][64];
+        int main() {
+            int n = getint();
+            int i = 0;
+            while (i < n) {
+                int j = 0;
+                while (j < n) {
+                    int k = 0;
+                    while (k < n) {
+                        c[i][j] = c[i][j] + a[i][k] * b[k][j];
+                        k = k + 1;
+                    }
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_interchange::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [64 x [64 x i32]] zeroinitializer
+        @b = dso_local global [64 x [64 x i32]] zeroinitializer
+        @c = dso_local global [64 x [64 x i32]] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %phi_74 = phi i32 [0, %entry], [%Add_65, %final5]
+        %icmp_70 = icmp slt i32 %phi_74, %call_6
+        br i1 %icmp_70, label %body1, label %final2
+
+        body1:
+        br label %cond3
+
+        final2:
+        br label %exit
+
+        cond3:
+        [-] %phi_76 = phi i32 [0, %body1], [%Add_57, %final8]
+        [-] %icmp_62 = icmp slt i32 %phi_76, %call_6
+        [-] br i1 %icmp_62, label %body4, label %final5
+        [+] %phi_79 = phi i32 [0, %body1], [%Add_49, %final8]
+        [+] %icmp_54 = icmp slt i32 %phi_79, %call_6
+        [+] br i1 %icmp_54, label %body4, label %final5
+
+        exit:
+        ret i32 0
+
+        body4:
+        br label %cond6
+
+        final5:
+        %Add_65 = add i32 %phi_74, 1
+        br label %cond0
+
+        cond6:
+        [-] %phi_79 = phi i32 [0, %body4], [%Add_49, %body7]
+        [-] %icmp_54 = icmp slt i32 %phi_79, %call_6
+        [-] br i1 %icmp_54, label %body7, label %final8
+        [+] %phi_76 = phi i32 [0, %body4], [%Add_57, %body7]
+        [+] %icmp_62 = icmp slt i32 %phi_76, %call_6
+        [+] br i1 %icmp_62, label %body7, label %final8
+
+        body7:
+        %getelementptr_28 = getelementptr [64 x [64 x i32]], ptr @c, i32 0, i32 %phi_74
+        %getelementptr_29 = getelementptr [64 x i32], ptr %getelementptr_28, i32 0, i32 %phi_76
+        %getelementptr_32 = getelementptr [64 x [64 x i32]], ptr @a, i32 0, i32 %phi_74
+        %getelementptr_33 = getelementptr [64 x i32], ptr %getelementptr_32, i32 0, i32 %phi_79
+        %getelementptr_36 = getelementptr [64 x [64 x i32]], ptr @b, i32 0, i32 %phi_79
+        %getelementptr_37 = getelementptr [64 x i32], ptr %getelementptr_36, i32 0, i32 %phi_76
+        %load_38 = load i32, ptr %getelementptr_33
+        %load_39 = load i32, ptr %getelementptr_37
+        %Mul_40 = mul i32 %load_38, %load_39
+        %load_41 = load i32, ptr %getelementptr_29
+        %Add_42 = add i32 %load_41, %Mul_40
+        %getelementptr_45 = getelementptr [64 x [64 x i32]], ptr @c, i32 0, i32 %phi_74
+        %getelementptr_46 = getelementptr [64 x i32], ptr %getelementptr_45, i32 0, i32 %phi_76
+        store i32 %Add_42, ptr %getelementptr_46
+        [-] %Add_49 = add i32 %phi_79, 1
+        [+] %Add_57 = add i32 %phi_76, 1
+        br label %cond6
+
+        final8:
+        [-] %Add_57 = add i32 %phi_76, 1
+        [+] %Add_49 = add i32 %phi_79, 1
+        br label %cond3
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_reversed_dependence() {
+        let code = r#"
+        int a[100][100];
+        int main() {
+            int j = 0;
+            while (j < 99) {
+                int i = 1;
+                while (i < 100) {
+                    a[i][j] = a[i - 1][j + 1] + 1;
+                    i = i + 1;
+                }
+                j = j + 1;
+            }
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let changed = loop_interchange::optimize_program(&mut program).unwrap();
+        assert!(!changed);
+    }
+
+    #[test]
+    fn test_tile() {
+        let code = r#"
+        int a[100][100];
+        int b[100][100];
+        int main() {
+            int n = getint();
+            int i = 0;
+            while (i < n) {
+                int j = 0;
+                while (j < n) {
+                    a[i][j] = b[j][i];
+                    j = j + 1;
+                }
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        loop_interchange::set_tile_size(Some(16));
+        let changed = loop_interchange::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [100 x [100 x i32]] zeroinitializer
+        @b = dso_local global [100 x [100 x i32]] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        [+] br label %cond0_tile9
+        [+] 
+        [+] cond0_tile9:
+        [+] %phi_54 = phi i32 [0, %entry], [%Add_61, %cond0_tile11]
+        [+] %icmp_55 = icmp slt i32 %phi_54, %call_6
+        [+] br i1 %icmp_55, label %cond0_tile10, label %final2
+        [+] 
+        [+] cond0_tile10:
+        [+] %Add_57 = add i32 %phi_54, 16
+        [+] %icmp_58 = icmp slt i32 %Add_57, %call_6
+        [+] %select_59 = select i1 %icmp_58, i32 %Add_57, i32 %call_6
+        br label %cond0
+
+        [+] final2:
+        [+] br label %exit
+        [+] 
+        cond0:
+        [-] %phi_48 = phi i32 [0, %entry], [%Add_39, %final5]
+        [+] %phi_48 = phi i32 [0, %cond0_tile10], [%Add_39, %final5]
+        %icmp_44 = icmp slt i32 %phi_48, %call_6
+        [-] br i1 %icmp_44, label %body1, label %final2
+        [+] br i1 %icmp_44, label %body1, label %cond0_tile11
+
+        [+] exit:
+        [+] ret i32 0
+        [+] 
+        body1:
+        br label %cond3
+
+        [-] final2:
+        [-] br label %exit
+        [+] cond0_tile11:
+        [+] %Add_61 = add i32 %phi_54, 16
+        [+] br label %cond0_tile9
+
+        cond3:
+        [-] %phi_50 = phi i32 [0, %body1], [%Add_31, %body4]
+        [-] %icmp_36 = icmp slt i32 %phi_50, %call_6
+        [+] %phi_50 = phi i32 [%phi_54, %body1], [%Add_31, %body4]
+        [+] %icmp_36 = icmp slt i32 %phi_50, %select_59
+        br i1 %icmp_36, label %body4, label %final5
+        [-] 
+        [-] exit:
+        [-] ret i32 0
+
+        body4:
+        %getelementptr_22 = getelementptr [100 x [100 x i32]], ptr @b, i32 0, i32 %phi_50
+        %getelementptr_23 = getelementptr [100 x i32], ptr %getelementptr_22, i32 0, i32 %phi_48
+        %getelementptr_26 = getelementptr [100 x [100 x i32]], ptr @a, i32 0, i32 %phi_48
+        %getelementptr_27 = getelementptr [100 x i32], ptr %getelementptr_26, i32 0, i32 %phi_50
+        %load_28 = load i32, ptr %getelementptr_23
+        store i32 %load_28, ptr %getelementptr_27
+        %Add_31 = add i32 %phi_50, 1
+        br label %cond3
+
+        final5:
+        %Add_39 = add i32 %phi_48, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+}
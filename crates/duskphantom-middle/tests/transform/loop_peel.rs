@@ -0,0 +1,184 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+#[cfg(test)]
+pub mod tests_loop_peel {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{loop_peel, mem2reg},
+    };
+
+    #[test]
+    fn test_peel_first_iteration() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int first = 1;
+            int i = 0;
+            while (i < n) {
+                if (first) {
+                    putint(i);
+                }
+                first = 0;
+                i = i + 1;
+            }
+            return i;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_peel::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %alloca_10 = alloca i32
+        [+] br label %cond0_peel10
+        [+] 
+        [+] cond0_peel10:
+        [+] %icmp_44 = icmp slt i32 0, %call_6
+        [+] br i1 %icmp_44, label %body1_peel11, label %final2
+        [+] 
+        [+] body1_peel11:
+        [+] br label %cond3_peel12
+        [+] 
+        [+] final2:
+        [+] %phi_42 = phi i32 [0, %cond0_peel10], [%phi_61, %final2_dedicated16]
+        [+] br label %exit
+        [+] 
+        [+] cond3_peel12:
+        [+] %icmp_49 = icmp ne i32 1, 0
+        [+] br i1 %icmp_49, label %then4_peel13, label %alt5_peel14
+        [+] 
+        [+] exit:
+        [+] ret i32 %phi_42
+        [+] 
+        [+] then4_peel13:
+        [+] call void @putint(i32 0)
+        [+] br label %final6_peel15
+        [+] 
+        [+] alt5_peel14:
+        [+] br label %final6_peel15
+        [+] 
+        [+] final6_peel15:
+        [+] %Add_57 = add i32 0, 1
+        br label %cond0
+
+        cond0:
+        [-] %phi_41 = phi i32 [0, %entry], [%Add_30, %final6]
+        [-] %phi_40 = phi i32 [1, %entry], [0, %final6]
+        [+] %phi_41 = phi i32 [%Add_57, %final6_peel15], [%Add_30, %final6]
+        [+] %phi_40 = phi i32 [0, %final6_peel15], [0, %final6]
+        %icmp_35 = icmp slt i32 %phi_41, %call_6
+        [-] br i1 %icmp_35, label %body1, label %final2
+        [+] br i1 %icmp_35, label %body1, label %final2_dedicated16
+
+        body1:
+        br label %cond3
+
+        [-] final2:
+        [-] br label %exit
+        [+] final2_dedicated16:
+        [+] %phi_61 = phi i32 [%phi_41, %cond0]
+        [+] br label %final2
+
+        cond3:
+        %icmp_22 = icmp ne i32 %phi_40, 0
+        br i1 %icmp_22, label %then4, label %alt5
+        [-] 
+        [-] exit:
+        [-] ret i32 %phi_41
+
+        then4:
+        call void @putint(i32 %phi_41)
+        br label %final6
+
+        alt5:
+        br label %final6
+
+        final6:
+        %Add_30 = add i32 %phi_41, 1
+        br label %cond0
+
+
+        }
+        "###);
+
+        // Flag is constant in the remaining loop, so it's not peeled again
+        let changed = loop_peel::optimize_program(&mut program).unwrap();
+        assert!(!changed);
+    }
+
+    #[test]
+    fn test_no_peel() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + i;
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Induction variables do not become invariant
+        let changed = loop_peel::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(!changed);
+        assert_eq!(llvm_before, llvm_after);
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+#[cfg(test)]
+pub mod tests_loop_rotate {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{loop_rotate, mem2reg},
+    };
+
+    #[test]
+    fn test_rotate() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + i;
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_rotate::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %alloca_10 = alloca i32
+        [-] br label %cond0
+        [+] %icmp_34 = icmp slt i32 0, %call_6
+        [+] br i1 %icmp_34, label %entry_rotate6, label %final2
+
+        [-] cond0:
+        [-] %phi_32 = phi i32 [0, %entry], [%Add_18, %body1]
+        [-] %phi_31 = phi i32 [0, %entry], [%Add_21, %body1]
+        [-] %icmp_26 = icmp slt i32 %phi_31, %call_6
+        [-] br i1 %icmp_26, label %body1, label %final2
+        [+] entry_rotate6:
+        [+] br label %body1
+
+        [-] body1:
+        [-] %Add_18 = add i32 %phi_32, %phi_31
+        [-] %Add_21 = add i32 %phi_31, 1
+        [-] br label %cond0
+        [-] 
+        final2:
+        [+] %phi_33 = phi i32 [0, %entry], [%phi_42, %final2_dedicated7]
+        br label %exit
+
+        [+] body1:
+        [+] %phi_39 = phi i32 [0, %entry_rotate6], [%Add_21, %cond0]
+        [+] %phi_38 = phi i32 [0, %entry_rotate6], [%Add_18, %cond0]
+        [+] %Add_18 = add i32 %phi_38, %phi_39
+        [+] %Add_21 = add i32 %phi_39, 1
+        [+] br label %cond0
+        [+] 
+        exit:
+        [-] ret i32 %phi_32
+        [+] ret i32 %phi_33
+        [+] 
+        [+] cond0:
+        [+] %icmp_26 = icmp slt i32 %Add_21, %call_6
+        [+] br i1 %icmp_26, label %body1, label %final2_dedicated7
+        [+] 
+        [+] final2_dedicated7:
+        [+] %phi_42 = phi i32 [%Add_18, %cond0]
+        [+] br label %final2
+
+
+        }
+        "###);
+
+        // Rotated loop is not rotated again
+        let changed = loop_rotate::optimize_program(&mut program).unwrap();
+        assert!(!changed);
+    }
+}
@@ -0,0 +1,361 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+#[cfg(test)]
+pub mod tests_loop_unroll {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{block_fuse, dead_code_elim, inst_combine, loop_unroll, mem2reg},
+    };
+
+    #[test]
+    fn test_full_unroll() {
+        let code = r#"
+        int a[3];
+        int main() {
+            int i = 0;
+            while (i < 3) {
+                a[i] = i * 2;
+                i = i + 1;
+            }
+            putarray(3, a);
+            return i;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        loop_unroll::optimize_program(&mut program, false).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [3 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        [-] entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] br label %cond0
+        [-] 
+        [-] cond0:
+        [-] %phi_28 = phi i32 [0, %entry], [%Add_17, %body1]
+        [-] %icmp_21 = icmp slt i32 %phi_28, 3
+        [-] br i1 %icmp_21, label %body1, label %final2
+        [-] 
+        [-] body1:
+        [-] %Mul_12 = mul i32 %phi_28, 2
+        [-] %getelementptr_14 = getelementptr [3 x i32], ptr @a, i32 0, i32 %phi_28
+        [-] store i32 %Mul_12, ptr %getelementptr_14
+        [-] %Add_17 = add i32 %phi_28, 1
+        [-] br label %cond0
+        [-] 
+        [-] final2:
+        [+] exit:
+        [+] %getelementptr_31 = getelementptr [3 x i32], ptr @a, i32 0, i32 0
+        [+] store i32 0, ptr %getelementptr_31
+        [+] %getelementptr_37 = getelementptr [3 x i32], ptr @a, i32 0, i32 1
+        [+] store i32 2, ptr %getelementptr_37
+        [+] %getelementptr_43 = getelementptr [3 x i32], ptr @a, i32 0, i32 2
+        [+] store i32 4, ptr %getelementptr_43
+        %getelementptr_23 = getelementptr [3 x i32], ptr @a, i32 0, i32 0
+        call void @putarray(i32 3, i32* %getelementptr_23)
+        [-] br label %exit
+        [-] 
+        [-] exit:
+        [-] ret i32 %phi_28
+        [+] ret i32 3
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_partial_unroll() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int s = 0;
+            int i = 0;
+            while (i < n) {
+                s = s + i;
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Partial unrolling is disabled
+        loop_unroll::optimize_program(&mut program, false).unwrap();
+        assert_eq!(program.module.gen_llvm_ir(), llvm_before);
+
+        // Check after optimization
+        loop_unroll::optimize_program(&mut program, true).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %alloca_10 = alloca i32
+        [-] br label %cond0
+        [+] %Sub_33 = sub i32 %call_6, 7
+        [+] %icmp_34 = icmp sge i32 %call_6, -2147483641
+        [+] br label %cond0_unroll6
+
+        [+] cond0_unroll6:
+        [+] %phi_36 = phi i32 [0, %entry], [%Add_71, %body1_unroll14]
+        [+] %phi_37 = phi i32 [0, %entry], [%Add_70, %body1_unroll14]
+        [+] %icmp_38 = icmp slt i32 %phi_36, %Sub_33
+        [+] %And_39 = and i1 %icmp_34, %icmp_38
+        [+] br i1 %And_39, label %body1_unroll7, label %cond0
+        [+] 
+        [+] body1_unroll7:
+        [+] %Add_42 = add i32 %phi_37, %phi_36
+        [+] %Add_43 = add i32 %phi_36, 1
+        [+] br label %body1_unroll8
+        [+] 
+        cond0:
+        [-] %phi_32 = phi i32 [0, %entry], [%Add_21, %body1]
+        [-] %phi_31 = phi i32 [0, %entry], [%Add_18, %body1]
+        [+] %phi_32 = phi i32 [%phi_36, %cond0_unroll6], [%Add_21, %body1]
+        [+] %phi_31 = phi i32 [%phi_37, %cond0_unroll6], [%Add_18, %body1]
+        %icmp_26 = icmp slt i32 %phi_32, %call_6
+        br i1 %icmp_26, label %body1, label %final2
+
+        [+] body1_unroll8:
+        [+] %Add_46 = add i32 %Add_42, %Add_43
+        [+] %Add_47 = add i32 %Add_43, 1
+        [+] br label %body1_unroll9
+        [+] 
+        body1:
+        %Add_18 = add i32 %phi_31, %phi_32
+        %Add_21 = add i32 %phi_32, 1
+        br label %cond0
+
+        final2:
+        br label %exit
+
+        [+] body1_unroll9:
+        [+] %Add_50 = add i32 %Add_46, %Add_47
+        [+] %Add_51 = add i32 %Add_47, 1
+        [+] br label %body1_unroll10
+        [+] 
+        exit:
+        ret i32 %phi_31
+        [+] 
+        [+] body1_unroll10:
+        [+] %Add_54 = add i32 %Add_50, %Add_51
+        [+] %Add_55 = add i32 %Add_51, 1
+        [+] br label %body1_unroll11
+        [+] 
+        [+] body1_unroll11:
+        [+] %Add_58 = add i32 %Add_54, %Add_55
+        [+] %Add_59 = add i32 %Add_55, 1
+        [+] br label %body1_unroll12
+        [+] 
+        [+] body1_unroll12:
+        [+] %Add_62 = add i32 %Add_58, %Add_59
+        [+] %Add_63 = add i32 %Add_59, 1
+        [+] br label %body1_unroll13
+        [+] 
+        [+] body1_unroll13:
+        [+] %Add_66 = add i32 %Add_62, %Add_63
+        [+] %Add_67 = add i32 %Add_63, 1
+        [+] br label %body1_unroll14
+        [+] 
+        [+] body1_unroll14:
+        [+] %Add_70 = add i32 %Add_66, %Add_67
+        [+] %Add_71 = add i32 %Add_67, 1
+        [+] br label %cond0_unroll6
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_zero_trip() {
+        let code = r#"
+        int main() {
+            int i = 5;
+            int s = 1;
+            while (i < 3) {
+                s = s * 2;
+                i = i + 1;
+            }
+            return s + i;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        loop_unroll::optimize_program(&mut program, false).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        [-] entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        [-] br label %cond0
+        [-] 
+        [-] cond0:
+        [-] %phi_29 = phi i32 [1, %entry], [%Mul_14, %body1]
+        [-] %phi_28 = phi i32 [5, %entry], [%Add_17, %body1]
+        [-] %icmp_21 = icmp slt i32 %phi_28, 3
+        [-] br i1 %icmp_21, label %body1, label %final2
+        [-] 
+        [-] body1:
+        [-] %Mul_14 = mul i32 %phi_29, 2
+        [-] %Add_17 = add i32 %phi_28, 1
+        [-] br label %cond0
+        [-] 
+        [-] final2:
+        [-] %Add_25 = add i32 %phi_29, %phi_28
+        [-] br label %exit
+        [-] 
+        exit:
+        [-] ret i32 %Add_25
+        [+] ret i32 6
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_early_exit() {
+        let code = r#"
+        int main() {
+            int i = 0;
+            while (i < 4) {
+                if (getint() == 0) {
+                    break;
+                }
+                i = i + 1;
+            }
+            return i;
+        }
+        "#;
+
+        // Loops with break are not unrolled
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let changed = loop_unroll::optimize_program(&mut program, true).unwrap();
+        assert!(!changed);
+    }
+
+    #[test]
+    fn test_partial_unroll_near_limit() {
+        let code = r#"
+        int main() {
+            int i = getint();
+            int s = 0;
+            while (i < -2147483645) {
+                s = s + i;
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Unrolled loop can not compare with `bound - 7` without overflow
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+        assert!(!loop_unroll::optimize_program(&mut program, true).unwrap());
+        assert_eq!(program.module.gen_llvm_ir(), llvm_before);
+    }
+}
@@ -0,0 +1,188 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_loop_unswitch {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{loop_optimization, loop_unswitch, mem2reg},
+    };
+
+    #[test]
+    fn test_invariant_branch() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int flag = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                if (flag) {
+                    s = s + i;
+                } else {
+                    s = s - i;
+                }
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        loop_optimization::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        let changed = loop_unswitch::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(changed);
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        %alloca_8 = alloca i32
+        %call_9 = call i32 @getint()
+        %alloca_11 = alloca i32
+        %alloca_13 = alloca i32
+        %icmp_25 = icmp ne i32 %call_9, 0
+        [+] br i1 %icmp_25, label %entry_unswitch16, label %entry_unswitch17
+        [+] 
+        [+] entry_unswitch16:
+        br label %cond0
+
+        [+] entry_unswitch17:
+        [+] br label %cond0_unswitch10
+        [+] 
+        cond0:
+        [-] %phi_49 = phi i32 [0, %entry], [%phi_50, %final6]
+        [-] %phi_48 = phi i32 [0, %entry], [%Add_38, %final6]
+        [+] %phi_49 = phi i32 [0, %entry_unswitch16], [%Add_29, %final6]
+        [+] %phi_48 = phi i32 [0, %entry_unswitch16], [%Add_38, %final6]
+        %icmp_43 = icmp slt i32 %phi_48, %call_6
+        br i1 %icmp_43, label %body1, label %final2
+
+        [+] cond0_unswitch10:
+        [+] %phi_53 = phi i32 [0, %entry_unswitch17], [%Sub_65, %final6_unswitch15]
+        [+] %phi_54 = phi i32 [0, %entry_unswitch17], [%Add_69, %final6_unswitch15]
+        [+] %icmp_55 = icmp slt i32 %phi_54, %call_6
+        [+] br i1 %icmp_55, label %body1_unswitch11, label %final2
+        [+] 
+        body1:
+        br label %cond3
+
+        final2:
+        [+] %phi_51 = phi i32 [%phi_49, %cond0], [%phi_53, %cond0_unswitch10]
+        br label %exit
+
+        [+] body1_unswitch11:
+        [+] br label %cond3_unswitch12
+        [+] 
+        cond3:
+        [-] br i1 %icmp_25, label %then4, label %alt5
+        [+] br label %then4
+
+        exit:
+        [-] ret i32 %phi_49
+        [+] ret i32 %phi_51
+
+        [+] cond3_unswitch12:
+        [+] br label %alt5_unswitch14
+        [+] 
+        then4:
+        %Add_29 = add i32 %phi_49, %phi_48
+        br label %final6
+
+        [-] alt5:
+        [-] %Sub_34 = sub i32 %phi_49, %phi_48
+        [-] br label %final6
+        [+] alt5_unswitch14:
+        [+] %Sub_65 = sub i32 %phi_53, %phi_54
+        [+] br label %final6_unswitch15
+
+        final6:
+        [-] %phi_50 = phi i32 [%Add_29, %then4], [%Sub_34, %alt5]
+        %Add_38 = add i32 %phi_48, 1
+        br label %cond0
+        [+] 
+        [+] final6_unswitch15:
+        [+] %Add_69 = add i32 %phi_54, 1
+        [+] br label %cond0_unswitch10
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_variant_branch() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                if (i % 2) {
+                    s = s + i;
+                } else {
+                    s = s - i;
+                }
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        loop_optimization::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Condition changes in each iteration, so loop is kept
+        let changed = loop_unswitch::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert!(!changed);
+        assert_eq!(llvm_before, llvm_after);
+    }
+}
@@ -22,6 +22,10 @@ pub mod tests_make_parallel {
     use super::*;
     use duskphantom_frontend::parse;
     use duskphantom_middle::{
+        ir::{
+            instruction::{downcast_mut, misc_inst::ICmpOp, misc_inst::Phi, InstType},
+            Constant, Operand, ValueType,
+        },
         irgen::gen,
         transform::{
             dead_code_elim, inst_combine, make_parallel, mem2reg, redundance_elim, sink_code,
@@ -52,7 +56,7 @@ pub mod tests_make_parallel {
         let llvm_before = program.module.gen_llvm_ir();
 
         // Check after optimization
-        make_parallel::optimize_program::<5>(&mut program).unwrap();
+        make_parallel::optimize_program(&mut program).unwrap();
         inst_combine::optimize_program(&mut program).unwrap();
         dead_code_elim::optimize_program(&mut program).unwrap();
         let llvm_after = program.module.gen_llvm_ir();
@@ -144,7 +148,7 @@ pub mod tests_make_parallel {
         let llvm_before = program.module.gen_llvm_ir();
 
         // Check after optimization
-        make_parallel::optimize_program::<5>(&mut program).unwrap();
+        make_parallel::optimize_program(&mut program).unwrap();
         inst_combine::optimize_program(&mut program).unwrap();
         dead_code_elim::optimize_program(&mut program).unwrap();
         let llvm_after = program.module.gen_llvm_ir();
@@ -236,7 +240,7 @@ pub mod tests_make_parallel {
         let llvm_before = program.module.gen_llvm_ir();
 
         // Check after optimization
-        make_parallel::optimize_program::<5>(&mut program).unwrap();
+        make_parallel::optimize_program(&mut program).unwrap();
         inst_combine::optimize_program(&mut program).unwrap();
         dead_code_elim::optimize_program(&mut program).unwrap();
         let llvm_after = program.module.gen_llvm_ir();
@@ -325,7 +329,7 @@ pub mod tests_make_parallel {
         let llvm_before = program.module.gen_llvm_ir();
 
         // Check after optimization
-        make_parallel::optimize_program::<5>(&mut program).unwrap();
+        make_parallel::optimize_program(&mut program).unwrap();
         inst_combine::optimize_program(&mut program).unwrap();
         dead_code_elim::optimize_program(&mut program).unwrap();
         let llvm_after = program.module.gen_llvm_ir();
@@ -414,7 +418,7 @@ pub mod tests_make_parallel {
         let llvm_before = program.module.gen_llvm_ir();
 
         // Check after optimization
-        make_parallel::optimize_program::<5>(&mut program).unwrap();
+        make_parallel::optimize_program(&mut program).unwrap();
         inst_combine::optimize_program(&mut program).unwrap();
         sink_code::optimize_program(&mut program).unwrap();
         dead_code_elim::optimize_program(&mut program).unwrap();
@@ -463,4 +467,1007 @@ pub mod tests_make_parallel {
         }
         "###);
     }
+
+    #[test]
+    fn test_sum_reduction() {
+        let code = r#"
+        int A[9];
+        int main() {
+            int i = 0;
+            int sum = 0;
+            getarray(A);
+            while (i < 8) {
+                sum = sum + A[i];
+                i = i + 1;
+            }
+            return sum;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [9 x i32] zeroinitializer
+        [+] @__reduce_32 = dso_local global [5 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %getelementptr_9 = getelementptr [9 x i32], ptr @A, i32 0, i32 0
+        %call_10 = call i32 @getarray(i32* %getelementptr_9)
+        [+] %call_33 = call i32 @thrd_create(i32 4)
+        [+] %Mul_35 = mul i32 %call_33, 8
+        [+] %SDiv_36 = sdiv i32 %Mul_35, 5
+        [+] %Add_38 = add i32 %Mul_35, 8
+        [+] %SDiv_39 = sdiv i32 %Add_38, 5
+        br label %cond0
+
+        cond0:
+        %phi_32 = phi i32 [0, %entry], [%Add_19, %body1]
+        [-] %phi_31 = phi i32 [0, %entry], [%Add_22, %body1]
+        [-] %icmp_26 = icmp slt i32 %phi_31, 8
+        [-] br i1 %icmp_26, label %body1, label %final2
+        [+] %phi_31 = phi i32 [%SDiv_36, %entry], [%Add_22, %body1]
+        [+] %icmp_41 = icmp slt i32 %phi_31, %SDiv_39
+        [+] br i1 %icmp_41, label %body1, label %final2
+
+        body1:
+        %getelementptr_16 = getelementptr [9 x i32], ptr @A, i32 0, i32 %phi_31
+        %load_18 = load i32, ptr %getelementptr_16
+        %Add_19 = add i32 %phi_32, %load_18
+        %Add_22 = add i32 %phi_31, 1
+        br label %cond0
+
+        final2:
+        [+] %getelementptr_48 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 %call_33
+        [+] store i32 %phi_32, ptr %getelementptr_48
+        [+] call void @thrd_join()
+        [+] %getelementptr_50 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 0
+        [+] %load_51 = load i32, ptr %getelementptr_50
+        [+] %getelementptr_53 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 1
+        [+] %load_54 = load i32, ptr %getelementptr_53
+        [+] %Add_55 = add i32 %load_51, %load_54
+        [+] %getelementptr_56 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 2
+        [+] %load_57 = load i32, ptr %getelementptr_56
+        [+] %Add_58 = add i32 %Add_55, %load_57
+        [+] %getelementptr_59 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 3
+        [+] %load_60 = load i32, ptr %getelementptr_59
+        [+] %Add_61 = add i32 %Add_58, %load_60
+        [+] %getelementptr_62 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 4
+        [+] %load_63 = load i32, ptr %getelementptr_62
+        [+] %Add_64 = add i32 %Add_61, %load_63
+        br label %exit
+
+        exit:
+        [-] ret i32 %phi_32
+        [+] ret i32 %Add_64
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_xor_reduction() {
+        let code = r#"
+        int A[9];
+        int main() {
+            int i = 0;
+            int sum = 0;
+            getarray(A);
+            while (i < 8) {
+                sum = sum + A[i];
+                i = i + 1;
+            }
+            return sum;
+        }
+        "#;
+
+        // Frontend has no boolean variables, so turn sum into parity of positive elements
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.is_main())
+            .unwrap();
+        let is_phi = |op: &Operand| matches!(op, Operand::Instruction(inst) if inst.get_type() == InstType::Phi);
+        let mut add = func
+            .dfs_iter()
+            .flat_map(|bb| bb.iter())
+            .find(|inst| inst.get_type() == InstType::Add && is_phi(&inst.get_operand()[0]))
+            .unwrap();
+        let Operand::Instruction(mut sum) = add.get_operand()[0].clone() else {
+            unreachable!()
+        };
+        let body = add.get_parent_bb().unwrap();
+        let entry = func.entry.unwrap();
+        let mem_pool = &mut program.mem_pool;
+        let positive = mem_pool.get_icmp(
+            ICmpOp::Sgt,
+            ValueType::Int,
+            add.get_operand()[1].clone(),
+            Constant::Int(0).into(),
+        );
+        add.insert_before(positive);
+        let false_val: Operand = Constant::Bool(false).into();
+        let mut parity = mem_pool.get_phi(
+            ValueType::Bool,
+            vec![(false_val.clone(), entry), (false_val, body)],
+        );
+        sum.insert_before(parity);
+        let xor = mem_pool.get_xor(parity.into(), positive.into());
+        add.insert_before(xor);
+        downcast_mut::<Phi>(parity.as_mut()).replace_incoming_value_at(body, xor.into());
+        let mut ret = func.exit.unwrap().get_last_inst();
+        let zext = mem_pool.get_zext(parity.into());
+        ret.insert_before(zext);
+        ret.replace_operand(&sum.into(), &zext.into());
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [9 x i32] zeroinitializer
+        [+] @__reduce_34 = dso_local global [5 x i1] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %getelementptr_9 = getelementptr [9 x i32], ptr @A, i32 0, i32 0
+        %call_10 = call i32 @getarray(i32* %getelementptr_9)
+        [+] %call_37 = call i32 @thrd_create(i32 4)
+        [+] %Mul_39 = mul i32 %call_37, 8
+        [+] %SDiv_40 = sdiv i32 %Mul_39, 5
+        [+] %Add_42 = add i32 %Mul_39, 8
+        [+] %SDiv_43 = sdiv i32 %Add_42, 5
+        br label %cond0
+
+        cond0:
+        %phi_34 = phi i1 [false, %entry], [%Xor_35, %body1]
+        [-] %phi_31 = phi i32 [0, %entry], [%Add_22, %body1]
+        [-] %icmp_26 = icmp slt i32 %phi_31, 8
+        [-] br i1 %icmp_26, label %body1, label %final2
+        [+] %phi_31 = phi i32 [%SDiv_40, %entry], [%Add_22, %body1]
+        [+] %icmp_45 = icmp slt i32 %phi_31, %SDiv_43
+        [+] br i1 %icmp_45, label %body1, label %final2
+
+        body1:
+        %getelementptr_16 = getelementptr [9 x i32], ptr @A, i32 0, i32 %phi_31
+        %load_18 = load i32, ptr %getelementptr_16
+        %icmp_33 = icmp sgt i32 %load_18, 0
+        %Xor_35 = xor i1 %phi_34, %icmp_33
+        %Add_22 = add i32 %phi_31, 1
+        br label %cond0
+
+        final2:
+        [+] %getelementptr_52 = getelementptr [5 x i1], ptr @__reduce_34, i32 0, i32 %call_37
+        [+] store i1 %phi_34, ptr %getelementptr_52
+        [+] call void @thrd_join()
+        [+] %getelementptr_54 = getelementptr [5 x i1], ptr @__reduce_34, i32 0, i32 0
+        [+] %load_55 = load i1, ptr %getelementptr_54
+        [+] %Xor_56 = xor i1 false, %load_55
+        [+] %getelementptr_57 = getelementptr [5 x i1], ptr @__reduce_34, i32 0, i32 1
+        [+] %load_58 = load i1, ptr %getelementptr_57
+        [+] %Xor_59 = xor i1 %Xor_56, %load_58
+        [+] %getelementptr_60 = getelementptr [5 x i1], ptr @__reduce_34, i32 0, i32 2
+        [+] %load_61 = load i1, ptr %getelementptr_60
+        [+] %Xor_62 = xor i1 %Xor_59, %load_61
+        [+] %getelementptr_63 = getelementptr [5 x i1], ptr @__reduce_34, i32 0, i32 3
+        [+] %load_64 = load i1, ptr %getelementptr_63
+        [+] %Xor_65 = xor i1 %Xor_62, %load_64
+        [+] %getelementptr_66 = getelementptr [5 x i1], ptr @__reduce_34, i32 0, i32 4
+        [+] %load_67 = load i1, ptr %getelementptr_66
+        [+] %Xor_68 = xor i1 %Xor_65, %load_67
+        br label %exit
+
+        exit:
+        [-] %zext_36 = zext i1 %phi_34 to i32
+        [+] %zext_36 = zext i1 %Xor_68 to i32
+        ret i32 %zext_36
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_int_xor_reduction() {
+        let code = r#"
+        int A[9];
+        int main() {
+            int i = 0;
+            int sum = 5;
+            getarray(A);
+            while (i < 8) {
+                sum = sum + A[i];
+                i = i + 1;
+            }
+            return sum;
+        }
+        "#;
+
+        // Frontend has no bitwise operators, so turn sum into xor of all elements
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let func = *program
+            .module
+            .functions
+            .iter()
+            .find(|f| f.is_main())
+            .unwrap();
+        let is_phi = |op: &Operand| matches!(op, Operand::Instruction(inst) if inst.get_type() == InstType::Phi);
+        let mut add = func
+            .dfs_iter()
+            .flat_map(|bb| bb.iter())
+            .find(|inst| inst.get_type() == InstType::Add && is_phi(&inst.get_operand()[0]))
+            .unwrap();
+        let xor = program
+            .mem_pool
+            .get_xor(add.get_operand()[0].clone(), add.get_operand()[1].clone());
+        add.insert_before(xor);
+        add.replace_self(&xor.into());
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [9 x i32] zeroinitializer
+        [+] @__reduce_32 = dso_local global [5 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %getelementptr_9 = getelementptr [9 x i32], ptr @A, i32 0, i32 0
+        %call_10 = call i32 @getarray(i32* %getelementptr_9)
+        [+] %call_34 = call i32 @thrd_create(i32 4)
+        [+] %Mul_36 = mul i32 %call_34, 8
+        [+] %SDiv_37 = sdiv i32 %Mul_36, 5
+        [+] %Add_39 = add i32 %Mul_36, 8
+        [+] %SDiv_40 = sdiv i32 %Add_39, 5
+        br label %cond0
+
+        cond0:
+        [-] %phi_32 = phi i32 [5, %entry], [%Xor_33, %body1]
+        [-] %phi_31 = phi i32 [0, %entry], [%Add_22, %body1]
+        [-] %icmp_26 = icmp slt i32 %phi_31, 8
+        [-] br i1 %icmp_26, label %body1, label %final2
+        [+] %phi_32 = phi i32 [0, %entry], [%Xor_33, %body1]
+        [+] %phi_31 = phi i32 [%SDiv_37, %entry], [%Add_22, %body1]
+        [+] %icmp_42 = icmp slt i32 %phi_31, %SDiv_40
+        [+] br i1 %icmp_42, label %body1, label %final2
+
+        body1:
+        %getelementptr_16 = getelementptr [9 x i32], ptr @A, i32 0, i32 %phi_31
+        %load_18 = load i32, ptr %getelementptr_16
+        %Xor_33 = xor i1 %phi_32, %load_18
+        %Add_22 = add i32 %phi_31, 1
+        br label %cond0
+
+        final2:
+        [+] %getelementptr_49 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 %call_34
+        [+] store i32 %phi_32, ptr %getelementptr_49
+        [+] call void @thrd_join()
+        [+] %getelementptr_51 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 0
+        [+] %load_52 = load i32, ptr %getelementptr_51
+        [+] %Xor_53 = xor i1 5, %load_52
+        [+] %getelementptr_54 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 1
+        [+] %load_55 = load i32, ptr %getelementptr_54
+        [+] %Xor_56 = xor i1 %Xor_53, %load_55
+        [+] %getelementptr_57 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 2
+        [+] %load_58 = load i32, ptr %getelementptr_57
+        [+] %Xor_59 = xor i1 %Xor_56, %load_58
+        [+] %getelementptr_60 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 3
+        [+] %load_61 = load i32, ptr %getelementptr_60
+        [+] %Xor_62 = xor i1 %Xor_59, %load_61
+        [+] %getelementptr_63 = getelementptr [5 x i32], ptr @__reduce_32, i32 0, i32 4
+        [+] %load_64 = load i32, ptr %getelementptr_63
+        [+] %Xor_65 = xor i1 %Xor_62, %load_64
+        br label %exit
+
+        exit:
+        [-] ret i32 %phi_32
+        [+] ret i32 %Xor_65
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_reduction_in_outer_loop() {
+        let code = r#"
+        int A[64];
+        int main() {
+            int j = 0;
+            int sum = 0;
+            getarray(A);
+            while (j < 4) {
+                int i = 0;
+                while (i < 64) {
+                    sum = sum + A[i];
+                    i = i + 1;
+                }
+                A[0] = sum;
+                j = j + 1;
+            }
+            return sum;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [64 x i32] zeroinitializer
+        [+] @__reduce_49 = dso_local global [5 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %getelementptr_9 = getelementptr [64 x i32], ptr @A, i32 0, i32 0
+        %call_10 = call i32 @getarray(i32* %getelementptr_9)
+        br label %cond0
+
+        cond0:
+        [-] %phi_48 = phi i32 [0, %entry], [%phi_49, %final5]
+        [+] %phi_48 = phi i32 [0, %entry], [%Add_83, %final5]
+        %phi_47 = phi i32 [0, %entry], [%Add_38, %final5]
+        %icmp_42 = icmp slt i32 %phi_47, 4
+        br i1 %icmp_42, label %body1, label %final2
+
+        body1:
+        [+] %call_52 = call i32 @thrd_create(i32 4)
+        [+] %Mul_54 = mul i32 %call_52, 64
+        [+] %SDiv_55 = sdiv i32 %Mul_54, 5
+        [+] %Add_57 = add i32 %Mul_54, 64
+        [+] %SDiv_58 = sdiv i32 %Add_57, 5
+        br label %cond3
+
+        final2:
+        br label %exit
+
+        cond3:
+        [-] %phi_51 = phi i32 [0, %body1], [%Add_28, %body4]
+        [-] %phi_49 = phi i32 [%phi_48, %body1], [%Add_25, %body4]
+        [-] %icmp_32 = icmp slt i32 %phi_51, 64
+        [-] br i1 %icmp_32, label %body4, label %final5
+        [+] %phi_51 = phi i32 [%SDiv_55, %body1], [%Add_28, %body4]
+        [+] %phi_49 = phi i32 [0, %body1], [%Add_25, %body4]
+        [+] %icmp_60 = icmp slt i32 %phi_51, %SDiv_58
+        [+] br i1 %icmp_60, label %body4, label %final5
+
+        exit:
+        ret i32 %phi_48
+
+        body4:
+        %getelementptr_22 = getelementptr [64 x i32], ptr @A, i32 0, i32 %phi_51
+        %load_24 = load i32, ptr %getelementptr_22
+        %Add_25 = add i32 %phi_49, %load_24
+        %Add_28 = add i32 %phi_51, 1
+        br label %cond3
+
+        final5:
+        [-] store i32 %phi_49, ptr %getelementptr_9
+        [+] %getelementptr_67 = getelementptr [5 x i32], ptr @__reduce_49, i32 0, i32 %call_52
+        [+] store i32 %phi_49, ptr %getelementptr_67
+        [+] call void @thrd_join()
+        [+] %getelementptr_69 = getelementptr [5 x i32], ptr @__reduce_49, i32 0, i32 0
+        [+] %load_70 = load i32, ptr %getelementptr_69
+        [+] %Add_71 = add i32 %phi_48, %load_70
+        [+] %getelementptr_72 = getelementptr [5 x i32], ptr @__reduce_49, i32 0, i32 1
+        [+] %load_73 = load i32, ptr %getelementptr_72
+        [+] %Add_74 = add i32 %Add_71, %load_73
+        [+] %getelementptr_75 = getelementptr [5 x i32], ptr @__reduce_49, i32 0, i32 2
+        [+] %load_76 = load i32, ptr %getelementptr_75
+        [+] %Add_77 = add i32 %Add_74, %load_76
+        [+] %getelementptr_78 = getelementptr [5 x i32], ptr @__reduce_49, i32 0, i32 3
+        [+] %load_79 = load i32, ptr %getelementptr_78
+        [+] %Add_80 = add i32 %Add_77, %load_79
+        [+] %getelementptr_81 = getelementptr [5 x i32], ptr @__reduce_49, i32 0, i32 4
+        [+] %load_82 = load i32, ptr %getelementptr_81
+        [+] %Add_83 = add i32 %Add_80, %load_82
+        [+] store i32 %Add_83, ptr %getelementptr_9
+        %Add_38 = add i32 %phi_47, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_reduction_used_by_ret() {
+        let code = r#"
+        int A[64];
+        int main() {
+            int i = 0;
+            int sum = 0;
+            getarray(A);
+            while (i < 64) {
+                sum = sum + A[i];
+                i = i + 1;
+            }
+            return sum % 256;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [64 x i32] zeroinitializer
+        [+] @__reduce_33 = dso_local global [5 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %getelementptr_9 = getelementptr [64 x i32], ptr @A, i32 0, i32 0
+        %call_10 = call i32 @getarray(i32* %getelementptr_9)
+        [+] %call_34 = call i32 @thrd_create(i32 4)
+        [+] %Mul_36 = mul i32 %call_34, 64
+        [+] %SDiv_37 = sdiv i32 %Mul_36, 5
+        [+] %Add_39 = add i32 %Mul_36, 64
+        [+] %SDiv_40 = sdiv i32 %Add_39, 5
+        br label %cond0
+
+        cond0:
+        %phi_33 = phi i32 [0, %entry], [%Add_19, %body1]
+        [-] %phi_32 = phi i32 [0, %entry], [%Add_22, %body1]
+        [-] %icmp_26 = icmp slt i32 %phi_32, 64
+        [-] br i1 %icmp_26, label %body1, label %final2
+        [+] %phi_32 = phi i32 [%SDiv_37, %entry], [%Add_22, %body1]
+        [+] %icmp_42 = icmp slt i32 %phi_32, %SDiv_40
+        [+] br i1 %icmp_42, label %body1, label %final2
+
+        body1:
+        %getelementptr_16 = getelementptr [64 x i32], ptr @A, i32 0, i32 %phi_32
+        %load_18 = load i32, ptr %getelementptr_16
+        %Add_19 = add i32 %phi_33, %load_18
+        %Add_22 = add i32 %phi_32, 1
+        br label %cond0
+
+        final2:
+        [-] %SRem_29 = srem i32 %phi_33, 256
+        [+] %getelementptr_49 = getelementptr [5 x i32], ptr @__reduce_33, i32 0, i32 %call_34
+        [+] store i32 %phi_33, ptr %getelementptr_49
+        [+] call void @thrd_join()
+        [+] %getelementptr_51 = getelementptr [5 x i32], ptr @__reduce_33, i32 0, i32 0
+        [+] %load_52 = load i32, ptr %getelementptr_51
+        [+] %getelementptr_54 = getelementptr [5 x i32], ptr @__reduce_33, i32 0, i32 1
+        [+] %load_55 = load i32, ptr %getelementptr_54
+        [+] %Add_56 = add i32 %load_52, %load_55
+        [+] %getelementptr_57 = getelementptr [5 x i32], ptr @__reduce_33, i32 0, i32 2
+        [+] %load_58 = load i32, ptr %getelementptr_57
+        [+] %Add_59 = add i32 %Add_56, %load_58
+        [+] %getelementptr_60 = getelementptr [5 x i32], ptr @__reduce_33, i32 0, i32 3
+        [+] %load_61 = load i32, ptr %getelementptr_60
+        [+] %Add_62 = add i32 %Add_59, %load_61
+        [+] %getelementptr_63 = getelementptr [5 x i32], ptr @__reduce_33, i32 0, i32 4
+        [+] %load_64 = load i32, ptr %getelementptr_63
+        [+] %Add_65 = add i32 %Add_62, %load_64
+        [+] %SRem_29 = srem i32 %Add_65, 256
+        br label %exit
+
+        exit:
+        ret i32 %SRem_29
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_inner_loop() {
+        let code = r#"
+        int A[9];
+        int main() {
+            int j = 0;
+            int n = getint();
+            while (j < n) {
+                int i = 0;
+                while (i < 9) {
+                    A[i] = A[i] + j;
+                    i = i + 1;
+                }
+                putint(A[0]);
+                j = j + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [9 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_8 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %phi_48 = phi i32 [0, %entry], [%Add_39, %final5]
+        %icmp_44 = icmp slt i32 %phi_48, %call_8
+        br i1 %icmp_44, label %body1, label %final2
+
+        body1:
+        [+] %call_51 = call i32 @thrd_create(i32 4)
+        [+] %Mul_53 = mul i32 %call_51, 9
+        [+] %SDiv_54 = sdiv i32 %Mul_53, 5
+        [+] %Add_56 = add i32 %Mul_53, 9
+        [+] %SDiv_57 = sdiv i32 %Add_56, 5
+        br label %cond3
+
+        final2:
+        br label %exit
+
+        cond3:
+        [-] %phi_50 = phi i32 [0, %body1], [%Add_29, %body4]
+        [-] %icmp_33 = icmp slt i32 %phi_50, 9
+        [-] br i1 %icmp_33, label %body4, label %final5
+        [+] %phi_50 = phi i32 [%SDiv_54, %body1], [%Add_29, %body4]
+        [+] %icmp_59 = icmp slt i32 %phi_50, %SDiv_57
+        [+] br i1 %icmp_59, label %body4, label %final5
+
+        exit:
+        ret i32 0
+
+        body4:
+        %getelementptr_21 = getelementptr [9 x i32], ptr @A, i32 0, i32 %phi_50
+        %load_22 = load i32, ptr %getelementptr_21
+        %Add_24 = add i32 %load_22, %phi_48
+        store i32 %Add_24, ptr %getelementptr_21
+        %Add_29 = add i32 %phi_50, 1
+        br label %cond3
+
+        final5:
+        [+] call void @thrd_join()
+        %getelementptr_35 = getelementptr [9 x i32], ptr @A, i32 0, i32 0
+        %load_36 = load i32, ptr %getelementptr_35
+        call void @putint(i32 %load_36)
+        %Add_39 = add i32 %phi_48, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_carried_outer_loop() {
+        let code = r#"
+        int A[100][100];
+        int main() {
+            int j = 1;
+            while (j < 100) {
+                int i = 0;
+                while (i < 100) {
+                    A[j][i] = A[j - 1][i] + 1;
+                    i = i + 1;
+                }
+                j = j + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [100 x [100 x i32]] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        br label %cond0
+
+        cond0:
+        %phi_45 = phi i32 [1, %entry], [%Add_37, %final5]
+        %icmp_41 = icmp slt i32 %phi_45, 100
+        br i1 %icmp_41, label %body1, label %final2
+
+        body1:
+        [+] %call_48 = call i32 @thrd_create(i32 4)
+        [+] %Mul_50 = mul i32 %call_48, 100
+        [+] %SDiv_51 = sdiv i32 %Mul_50, 5
+        [+] %Add_53 = add i32 %Mul_50, 100
+        [+] %SDiv_54 = sdiv i32 %Add_53, 5
+        br label %cond3
+
+        final2:
+        br label %exit
+
+        cond3:
+        [-] %phi_47 = phi i32 [0, %body1], [%Add_30, %body4]
+        [-] %icmp_34 = icmp slt i32 %phi_47, 100
+        [-] br i1 %icmp_34, label %body4, label %final5
+        [+] %phi_47 = phi i32 [%SDiv_51, %body1], [%Add_30, %body4]
+        [+] %icmp_56 = icmp slt i32 %phi_47, %SDiv_54
+        [+] br i1 %icmp_56, label %body4, label %final5
+
+        exit:
+        ret i32 0
+
+        body4:
+        %Sub_19 = sub i32 %phi_45, 1
+        %getelementptr_20 = getelementptr [100 x [100 x i32]], ptr @A, i32 0, i32 %Sub_19
+        %getelementptr_21 = getelementptr [100 x i32], ptr %getelementptr_20, i32 0, i32 %phi_47
+        %load_22 = load i32, ptr %getelementptr_21
+        %Add_23 = add i32 %load_22, 1
+        %getelementptr_26 = getelementptr [100 x [100 x i32]], ptr @A, i32 0, i32 %phi_45
+        %getelementptr_27 = getelementptr [100 x i32], ptr %getelementptr_26, i32 0, i32 %phi_47
+        store i32 %Add_23, ptr %getelementptr_27
+        %Add_30 = add i32 %phi_47, 1
+        br label %cond3
+
+        final5:
+        [+] call void @thrd_join()
+        %Add_37 = add i32 %phi_45, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_hoist_thread_creation() {
+        let code = r#"
+        int A[9];
+        int B[9];
+        int main() {
+            int t = 0;
+            int n = getint();
+            getarray(B);
+            while (t < n) {
+                int i = 0;
+                while (i < 9) {
+                    A[i] = A[i] + B[i] * t;
+                    i = i + 1;
+                }
+                t = t + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [9 x i32] zeroinitializer
+        @B = dso_local global [9 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_8 = call i32 @getint()
+        %getelementptr_10 = getelementptr [9 x i32], ptr @B, i32 0, i32 0
+        %call_11 = call i32 @getarray(i32* %getelementptr_10)
+        [+] %call_54 = call i32 @thrd_create(i32 4)
+        [+] %Mul_56 = mul i32 %call_54, 9
+        [+] %SDiv_57 = sdiv i32 %Mul_56, 5
+        [+] %Add_59 = add i32 %Mul_56, 9
+        [+] %SDiv_60 = sdiv i32 %Add_59, 5
+        br label %cond0
+
+        cond0:
+        %phi_51 = phi i32 [0, %entry], [%Add_42, %final5]
+        %icmp_47 = icmp slt i32 %phi_51, %call_8
+        br i1 %icmp_47, label %body1, label %final2
+
+        body1:
+        br label %cond3
+
+        final2:
+        [+] call void @thrd_join()
+        br label %exit
+
+        cond3:
+        [-] %phi_53 = phi i32 [0, %body1], [%Add_35, %body4]
+        [-] %icmp_39 = icmp slt i32 %phi_53, 9
+        [-] br i1 %icmp_39, label %body4, label %final5
+        [+] %phi_53 = phi i32 [%SDiv_57, %body1], [%Add_35, %body4]
+        [+] %icmp_62 = icmp slt i32 %phi_53, %SDiv_60
+        [+] br i1 %icmp_62, label %body4, label %final5
+
+        exit:
+        ret i32 0
+
+        body4:
+        %getelementptr_23 = getelementptr [9 x i32], ptr @A, i32 0, i32 %phi_53
+        %getelementptr_25 = getelementptr [9 x i32], ptr @B, i32 0, i32 %phi_53
+        %load_26 = load i32, ptr %getelementptr_25
+        %Mul_28 = mul i32 %load_26, %phi_51
+        %load_29 = load i32, ptr %getelementptr_23
+        %Add_30 = add i32 %load_29, %Mul_28
+        store i32 %Add_30, ptr %getelementptr_23
+        %Add_35 = add i32 %phi_53, 1
+        br label %cond3
+
+        final5:
+        %Add_42 = add i32 %phi_51, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_thread_count() {
+        let code = r#"
+        int A[9];
+        int main() {
+            int i = 0;
+            while (i < 8) {
+                A[i] = i;
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        redundance_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization, with 2 threads
+        make_parallel::set_thread_count(2);
+        make_parallel::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @A = dso_local global [9 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        [+] %call_25 = call i32 @thrd_create(i32 1)
+        [+] %Mul_27 = mul i32 %call_25, 8
+        [+] %SDiv_28 = sdiv i32 %Mul_27, 2
+        [+] %Add_30 = add i32 %Mul_27, 8
+        [+] %SDiv_31 = sdiv i32 %Add_30, 2
+        br label %cond0
+
+        cond0:
+        [-] %phi_24 = phi i32 [0, %entry], [%Add_16, %body1]
+        [-] %icmp_20 = icmp slt i32 %phi_24, 8
+        [-] br i1 %icmp_20, label %body1, label %final2
+        [+] %phi_24 = phi i32 [%SDiv_28, %entry], [%Add_16, %body1]
+        [+] %icmp_33 = icmp slt i32 %phi_24, %SDiv_31
+        [+] br i1 %icmp_33, label %body1, label %final2
+
+        body1:
+        %getelementptr_12 = getelementptr [9 x i32], ptr @A, i32 0, i32 %phi_24
+        store i32 %phi_24, ptr %getelementptr_12
+        %Add_16 = add i32 %phi_24, 1
+        br label %cond0
+
+        final2:
+        [+] call void @thrd_join()
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+
+        // Single thread disables parallelization
+        make_parallel::set_thread_count(1);
+        assert!(!make_parallel::optimize_program(&mut program).unwrap());
+    }
 }
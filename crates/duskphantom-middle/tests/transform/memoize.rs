@@ -0,0 +1,299 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_memoize {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{mem2reg, memoize},
+    };
+
+    #[test]
+    fn test_fib() {
+        let code = r#"
+        int fib(int n) {
+            if (n < 2) {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+        int main() {
+            return fib(getint());
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        memoize::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        [+] @__fib_memo_val = dso_local global [65536 x i32] zeroinitializer
+        [+] @__fib_memo_set = dso_local global [65536 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @fib(i32 %n) {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        [+] %icmp_39 = icmp sge i32 %n, 0
+        [+] %icmp_40 = icmp slt i32 %n, 65536
+        [+] %And_41 = and i1 %icmp_39, %icmp_40
+        [+] br i1 %And_41, label %entry_memo_lookup10, label %entry_memo_body9
+        [+] 
+        [+] entry_memo_lookup10:
+        [+] %getelementptr_45 = getelementptr [65536 x i32], ptr @__fib_memo_set, i32 0, i32 %n
+        [+] %load_46 = load i32, ptr %getelementptr_45
+        [+] %icmp_47 = icmp ne i32 %load_46, 0
+        [+] br i1 %icmp_47, label %entry_memo_hit11, label %entry_memo_body9
+        [+] 
+        [+] entry_memo_body9:
+        br label %cond0
+
+        [+] entry_memo_hit11:
+        [+] %getelementptr_49 = getelementptr [65536 x i32], ptr @__fib_memo_val, i32 0, i32 %n
+        [+] %load_50 = load i32, ptr %getelementptr_49
+        [+] br label %exit_memo_exit13
+        [+] 
+        cond0:
+        %icmp_13 = icmp slt i32 %n, 2
+        br i1 %icmp_13, label %then1, label %alt2
+
+        [+] exit_memo_exit13:
+        [+] %phi_60 = phi i32 [%phi_37, %exit], [%phi_37, %exit_memo_save12], [%load_50, %entry_memo_hit11]
+        [+] ret i32 %phi_60
+        [+] 
+        then1:
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        %phi_37 = phi i32 [%n, %then1], [%Add_25, %final3]
+        [-] ret i32 %phi_37
+        [+] br i1 %And_41, label %exit_memo_save12, label %exit_memo_exit13
+
+        final3:
+        %Sub_20 = sub i32 %n, 1
+        %call_21 = call i32 @fib(i32 %Sub_20)
+        %Sub_23 = sub i32 %n, 2
+        %call_24 = call i32 @fib(i32 %Sub_23)
+        %Add_25 = add i32 %call_21, %call_24
+        br label %exit
+        [+] 
+        [+] exit_memo_save12:
+        [+] %getelementptr_54 = getelementptr [65536 x i32], ptr @__fib_memo_val, i32 0, i32 %n
+        [+] store i32 %phi_37, ptr %getelementptr_54
+        [+] %getelementptr_55 = getelementptr [65536 x i32], ptr @__fib_memo_set, i32 0, i32 %n
+        [+] store i32 1, ptr %getelementptr_55
+        [+] br label %exit_memo_exit13
+
+
+        }
+        define i32 @main() {
+        entry:
+        %alloca_30 = alloca i32
+        %call_33 = call i32 @getint()
+        %call_34 = call i32 @fib(i32 %call_33)
+        br label %exit
+
+        exit:
+        ret i32 %call_34
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_two_params() {
+        let code = r#"
+        int binom(int n, int k) {
+            if (k == 0 || k == n) {
+                return 1;
+            }
+            return binom(n - 1, k - 1) + binom(n - 1, k);
+        }
+        int main() {
+            return binom(getint(), getint());
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        memoize::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        [+] @__binom_memo_val = dso_local global [65536 x i32] zeroinitializer
+        [+] @__binom_memo_set = dso_local global [65536 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @binom(i32 %n, i32 %k) {
+        entry:
+        %alloca_2 = alloca i32
+        %alloca_5 = alloca i32
+        %alloca_7 = alloca i32
+        [+] %icmp_52 = icmp sge i32 %n, 0
+        [+] %icmp_53 = icmp slt i32 %n, 256
+        [+] %And_54 = and i1 %icmp_52, %icmp_53
+        [+] %icmp_55 = icmp sge i32 %k, 0
+        [+] %icmp_56 = icmp slt i32 %k, 256
+        [+] %And_57 = and i1 %icmp_55, %icmp_56
+        [+] %And_58 = and i1 %And_54, %And_57
+        [+] %Shl_59 = shl i32 %n, 8
+        [+] %Add_60 = add i32 %Shl_59, %k
+        [+] br i1 %And_58, label %entry_memo_lookup12, label %entry_memo_body11
+        [+] 
+        [+] entry_memo_lookup12:
+        [+] %getelementptr_64 = getelementptr [65536 x i32], ptr @__binom_memo_set, i32 0, i32 %Add_60
+        [+] %load_65 = load i32, ptr %getelementptr_64
+        [+] %icmp_66 = icmp ne i32 %load_65, 0
+        [+] br i1 %icmp_66, label %entry_memo_hit13, label %entry_memo_body11
+        [+] 
+        [+] entry_memo_body11:
+        br label %cond0
+
+        [+] entry_memo_hit13:
+        [+] %getelementptr_68 = getelementptr [65536 x i32], ptr @__binom_memo_val, i32 0, i32 %Add_60
+        [+] %load_69 = load i32, ptr %getelementptr_68
+        [+] br label %exit_memo_exit15
+        [+] 
+        cond0:
+        %icmp_15 = icmp eq i32 %k, 0
+        br i1 %icmp_15, label %final5, label %alt4
+
+        [+] exit_memo_exit15:
+        [+] %phi_79 = phi i32 [%phi_50, %exit], [%phi_50, %exit_memo_save14], [%load_69, %entry_memo_hit13]
+        [+] ret i32 %phi_79
+        [+] 
+        final5:
+        %phi_23 = phi i1 [true, %cond0], [%icmp_21, %alt4]
+        br i1 %phi_23, label %then1, label %alt2
+
+        alt4:
+        %icmp_21 = icmp eq i32 %k, %n
+        br label %final5
+
+        then1:
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        %phi_50 = phi i32 [1, %then1], [%Add_37, %final3]
+        [-] ret i32 %phi_50
+        [+] br i1 %And_58, label %exit_memo_save14, label %exit_memo_exit15
+
+        final3:
+        %Sub_29 = sub i32 %n, 1
+        %Sub_31 = sub i32 %k, 1
+        %call_32 = call i32 @binom(i32 %Sub_29, i32 %Sub_31)
+        %Sub_34 = sub i32 %n, 1
+        %call_36 = call i32 @binom(i32 %Sub_34, i32 %k)
+        %Add_37 = add i32 %call_32, %call_36
+        br label %exit
+        [+] 
+        [+] exit_memo_save14:
+        [+] %getelementptr_73 = getelementptr [65536 x i32], ptr @__binom_memo_val, i32 0, i32 %Add_60
+        [+] store i32 %phi_50, ptr %getelementptr_73
+        [+] %getelementptr_74 = getelementptr [65536 x i32], ptr @__binom_memo_set, i32 0, i32 %Add_60
+        [+] store i32 1, ptr %getelementptr_74
+        [+] br label %exit_memo_exit15
+
+
+        }
+        define i32 @main() {
+        entry:
+        %alloca_42 = alloca i32
+        %call_45 = call i32 @getint()
+        %call_46 = call i32 @getint()
+        %call_47 = call i32 @binom(i32 %call_45, i32 %call_46)
+        br label %exit
+
+        exit:
+        ret i32 %call_47
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_impure() {
+        let code = r#"
+        int count;
+        int fib(int n) {
+            count = count + 1;
+            if (n < 2) {
+                return n;
+            }
+            return fib(n - 1) + fib(n - 2);
+        }
+        int main() {
+            return fib(getint());
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        assert!(!memoize::optimize_program(&mut program).unwrap());
+    }
+}
@@ -16,15 +16,35 @@
 
 mod block_fuse;
 mod constant_fold;
+mod dead_arg_elim;
 mod dead_code_elim;
 mod func_inline;
+mod func_specialize;
+mod global_localize;
+mod gvn_pre;
+mod if_convert;
+mod jump_thread;
+mod lcssa;
 mod load_elim;
+mod loop_fission;
+mod loop_fusion;
+mod loop_interchange;
 mod loop_optimization;
+mod loop_peel;
+mod loop_rotate;
+mod loop_unroll;
+mod loop_unswitch;
 mod make_parallel;
 mod mem2reg;
+mod memoize;
+mod pass_stats;
+mod reassociate;
 mod redundance_elim;
+mod sccp;
 mod store_elim;
+mod strength_reduce;
 mod symbolic_eval;
+mod tail_recursion_elim;
 
 pub use duskphantom_frontend::parse;
 pub use duskphantom_utils::diff::diff;
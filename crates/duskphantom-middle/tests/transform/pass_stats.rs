@@ -0,0 +1,159 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+#[cfg(test)]
+pub mod tests_pass_stats {
+    use std::{thread, time::Duration};
+
+    use anyhow::Result;
+    use insta::assert_snapshot;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_code_elim, func_inline, mem2reg, pass_stats, Transform},
+        Program,
+    };
+
+    #[test]
+    fn test_disabled() {
+        let code = r#"
+        int main() {
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        assert!(pass_stats::take().is_none());
+    }
+
+    #[test]
+    fn test_table() {
+        let code = r#"
+        int f(int x) {
+            return x + 1;
+        }
+
+        int main() {
+            int a = 1;
+            int b = a * 2;
+            putint(f(a));
+            putint(f(a));
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        pass_stats::enable();
+        mem2reg::optimize_program(&mut program).unwrap();
+        func_inline::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let mut stats = pass_stats::take().unwrap();
+        assert!(!pass_stats::is_enabled());
+
+        // Erase timing to get stable output
+        for pass in stats.passes.iter_mut() {
+            pass.time = Duration::ZERO;
+            for func in pass.funcs.iter_mut() {
+                func.time = Duration::ZERO;
+            }
+        }
+        assert_snapshot!(stats.to_table(true), @r###"
+        pass                       runs  changed     time(us)   time%             insts        blocks
+        mem2reg                       1        1            0    0.0%          26 -> 15        4 -> 4
+          f                                                 0                    9 -> 5        2 -> 2
+          main                                              0                  17 -> 10        2 -> 2
+        func_inline                   1        1            0    0.0%          15 -> 20        4 -> 8
+          f                                                 0                    5 -> 0        2 -> 0
+          main                                              0                  10 -> 20        2 -> 8
+        dead_code_elim                1        1            0    0.0%          20 -> 12        8 -> 8
+          main                                              0                  20 -> 12        8 -> 8
+        total                                               0
+
+        pass                     counter                             value
+        dead_code_elim           instructions removed                    8
+        func_inline              call sites inlined                      2
+        func_inline              functions removed                       1
+        "###);
+        assert_snapshot!(stats.to_json().unwrap(), @r#"{"passes":[{"name":"mem2reg","runs":1,"changed_runs":1,"time_us":0,"before":{"insts":26,"blocks":4},"after":{"insts":15,"blocks":4},"functions":[{"name":"f","time_us":0,"before":{"insts":9,"blocks":2},"after":{"insts":5,"blocks":2}},{"name":"main","time_us":0,"before":{"insts":17,"blocks":2},"after":{"insts":10,"blocks":2}}]},{"name":"func_inline","runs":1,"changed_runs":1,"time_us":0,"before":{"insts":15,"blocks":4},"after":{"insts":20,"blocks":8},"functions":[{"name":"f","time_us":0,"before":{"insts":5,"blocks":2},"after":{"insts":0,"blocks":0}},{"name":"main","time_us":0,"before":{"insts":10,"blocks":2},"after":{"insts":20,"blocks":8}}]},{"name":"dead_code_elim","runs":1,"changed_runs":1,"time_us":0,"before":{"insts":20,"blocks":8},"after":{"insts":12,"blocks":8},"functions":[{"name":"main","time_us":0,"before":{"insts":20,"blocks":8},"after":{"insts":12,"blocks":8}}]}],"counters":[{"pass":"dead_code_elim","counter":"instructions removed","value":8},{"pass":"func_inline","counter":"call sites inlined","value":2},{"pass":"func_inline","counter":"functions removed","value":1}]}"#);
+    }
+
+    struct Outer<'a> {
+        program: &'a mut Program,
+    }
+
+    impl Transform for Outer<'_> {
+        fn name() -> String {
+            "outer".to_string()
+        }
+
+        fn get_program_mut(&mut self) -> &mut Program {
+            self.program
+        }
+
+        fn run(&mut self) -> Result<bool> {
+            Inner {
+                program: self.program,
+            }
+            .run_and_log()
+        }
+    }
+
+    struct Inner<'a> {
+        program: &'a mut Program,
+    }
+
+    impl Transform for Inner<'_> {
+        fn name() -> String {
+            "inner".to_string()
+        }
+
+        fn get_program_mut(&mut self) -> &mut Program {
+            self.program
+        }
+
+        fn run(&mut self) -> Result<bool> {
+            thread::sleep(Duration::from_millis(50));
+            Ok(false)
+        }
+    }
+
+    #[test]
+    fn test_nested_self_time() {
+        let code = r#"
+        int main() {
+            return 0;
+        }
+        "#;
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        pass_stats::enable();
+        Outer {
+            program: &mut program,
+        }
+        .run_and_log()
+        .unwrap();
+        let stats = pass_stats::take().unwrap();
+
+        // Sleep in inner pass is only counted once
+        let names: Vec<&str> = stats.passes.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["inner", "outer"]);
+        let (inner, outer) = (&stats.passes[0], &stats.passes[1]);
+        assert_eq!((inner.runs, outer.runs), (1, 1));
+        assert!(inner.time >= Duration::from_millis(50));
+        assert!(outer.time < Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,388 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+
+#[cfg(test)]
+pub mod tests_reassociate {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{block_fuse, dead_code_elim, mem2reg, reassociate},
+    };
+
+    #[test]
+    fn test_fold_constants() {
+        let code = r#"
+        int main() {
+            int a = getint();
+            int b = getint();
+            putint(a * 4 + b + a * 4 + 3 + 5);
+            putint(a - b + 7 + b - 3 + a);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        reassociate::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+
+        // Reassociated code is already canonical
+        assert!(!reassociate::optimize_program(&mut program).unwrap());
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        exit:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        [-] %Mul_12 = mul i32 %call_6, 4
+        [-] %Add_14 = add i32 %Mul_12, %call_9
+        [-] %Mul_16 = mul i32 %call_6, 4
+        [-] %Add_17 = add i32 %Add_14, %Mul_16
+        [-] %Add_18 = add i32 %Add_17, 3
+        [-] %Add_19 = add i32 %Add_18, 5
+        [-] call void @putint(i32 %Add_19)
+        [-] %Sub_23 = sub i32 %call_6, %call_9
+        [-] %Add_24 = add i32 %Sub_23, 7
+        [-] %Add_26 = add i32 %Add_24, %call_9
+        [-] %Sub_27 = sub i32 %Add_26, 3
+        [-] %Add_29 = add i32 %Sub_27, %call_6
+        [-] call void @putint(i32 %Add_29)
+        [+] %Mul_33 = mul i32 %call_6, 8
+        [+] %Add_34 = add i32 %Mul_33, %call_9
+        [+] %Add_35 = add i32 %Add_34, 8
+        [+] call void @putint(i32 %Add_35)
+        [+] %Mul_36 = mul i32 %call_6, 2
+        [+] %Add_37 = add i32 %Mul_36, 4
+        [+] call void @putint(i32 %Add_37)
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_common_factor() {
+        let code = r#"
+        int f(int a, int b, int c) {
+            return a * b + c * a + a * 3 - b * c;
+        }
+
+        int main() {
+            putint(f(getint(), getint(), getint()));
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        reassociate::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+
+        // Reassociated code is already canonical
+        assert!(!reassociate::optimize_program(&mut program).unwrap());
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @f(i32 %a, i32 %b, i32 %c) {
+        exit:
+        [-] %Mul_13 = mul i32 %a, %b
+        [-] %Mul_16 = mul i32 %c, %a
+        [-] %Add_17 = add i32 %Mul_13, %Mul_16
+        [-] %Mul_19 = mul i32 %a, 3
+        [-] %Add_20 = add i32 %Add_17, %Mul_19
+        [-] %Mul_23 = mul i32 %b, %c
+        [-] %Sub_24 = sub i32 %Add_20, %Mul_23
+        [-] ret i32 %Sub_24
+        [+] %Add_39 = add i32 %b, %c
+        [+] %Add_40 = add i32 %Add_39, 3
+        [+] %Mul_41 = mul i32 %b, %c
+        [+] %Sub_42 = sub i32 0, %Mul_41
+        [+] %Mul_43 = mul i32 %a, %Add_40
+        [+] %Add_44 = add i32 %Sub_42, %Mul_43
+        [+] ret i32 %Add_44
+
+
+        }
+        define i32 @main() {
+        exit:
+        %call_32 = call i32 @getint()
+        %call_33 = call i32 @getint()
+        %call_34 = call i32 @getint()
+        %call_35 = call i32 @f(i32 %call_32, i32 %call_33, i32 %call_34)
+        call void @putint(i32 %call_35)
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_group_invariants() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int m = getint();
+            int i = 0;
+            int s = 0;
+            while (i < n) {
+                s = s + i * 2 + n + m * 3 + 1;
+                i = i + 1;
+            }
+            putint(s);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        reassociate::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+
+        // Reassociated code is already canonical
+        assert!(!reassociate::optimize_program(&mut program).unwrap());
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        [-] %phi_43 = phi i32 [0, %entry], [%Add_28, %body1]
+        [+] %phi_43 = phi i32 [0, %entry], [%Add_49, %body1]
+        %phi_42 = phi i32 [0, %entry], [%Add_31, %body1]
+        %icmp_36 = icmp slt i32 %phi_42, %call_6
+        br i1 %icmp_36, label %body1, label %exit
+
+        body1:
+        [-] %Mul_20 = mul i32 %phi_42, 2
+        [-] %Add_22 = add i32 %phi_43, %Mul_20
+        [-] %Add_24 = add i32 %Add_22, %call_6
+        [-] %Mul_26 = mul i32 %call_9, 3
+        [-] %Add_27 = add i32 %Add_24, %Mul_26
+        [-] %Add_28 = add i32 %Add_27, 1
+        [+] %Mul_44 = mul i32 %call_9, 3
+        [+] %Add_45 = add i32 %call_6, %Mul_44
+        [+] %Mul_46 = mul i32 %phi_42, 2
+        [+] %Add_47 = add i32 %Add_45, %Mul_46
+        [+] %Add_48 = add i32 %Add_47, %phi_43
+        [+] %Add_49 = add i32 %Add_48, 1
+        %Add_31 = add i32 %phi_42, 1
+        br label %cond0
+
+        exit:
+        call void @putint(i32 %phi_43)
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_loop_phis() {
+        let code = r#"
+        int main() {
+            int n = getint();
+            int k = 0;
+            int t = 0;
+            while (k < 3) {
+                int i = 0;
+                while (i < n) {
+                    i = i + 1;
+                }
+                int j = 0;
+                int s = 0;
+                while (j < n) {
+                    s = s + j;
+                    j = j + 1;
+                }
+                t = t + s + i + j;
+                k = k + 1;
+            }
+            putint(t);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        block_fuse::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        reassociate::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+
+        // Reassociated code is already canonical
+        assert!(!reassociate::optimize_program(&mut program).unwrap());
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        [-] %phi_70 = phi i32 [0, %entry], [%Add_56, %final8]
+        [+] %phi_70 = phi i32 [0, %entry], [%Add_79, %final8]
+        %phi_69 = phi i32 [0, %entry], [%Add_59, %final8]
+        %icmp_63 = icmp slt i32 %phi_69, 3
+        br i1 %icmp_63, label %body1, label %exit
+
+        body1:
+        br label %cond3
+
+        exit:
+        call void @putint(i32 %phi_70)
+        ret i32 0
+
+        cond3:
+        %phi_72 = phi i32 [0, %body1], [%Add_23, %body4]
+        %icmp_28 = icmp slt i32 %phi_72, %call_6
+        br i1 %icmp_28, label %body4, label %final5
+
+        body4:
+        %Add_23 = add i32 %phi_72, 1
+        br label %cond3
+
+        final5:
+        br label %cond6
+
+        cond6:
+        [-] %phi_76 = phi i32 [0, %final5], [%Add_40, %body7]
+        [+] %phi_76 = phi i32 [0, %final5], [%Add_81, %body7]
+        %phi_74 = phi i32 [0, %final5], [%Add_43, %body7]
+        %icmp_48 = icmp slt i32 %phi_74, %call_6
+        br i1 %icmp_48, label %body7, label %final8
+
+        body7:
+        [-] %Add_40 = add i32 %phi_76, %phi_74
+        [+] %Add_81 = add i32 %phi_74, %phi_76
+        %Add_43 = add i32 %phi_74, 1
+        br label %cond6
+
+        final8:
+        [-] %Add_52 = add i32 %phi_70, %phi_76
+        [-] %Add_54 = add i32 %Add_52, %phi_72
+        [-] %Add_56 = add i32 %Add_54, %phi_74
+        [+] %Add_77 = add i32 %phi_70, %phi_72
+        [+] %Add_78 = add i32 %Add_77, %phi_74
+        [+] %Add_79 = add i32 %Add_78, %phi_76
+        %Add_59 = add i32 %phi_69, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+}
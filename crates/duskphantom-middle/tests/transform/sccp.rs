@@ -0,0 +1,300 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+
+use super::*;
+#[cfg(test)]
+pub mod tests_sccp {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_code_elim, mem2reg, sccp},
+    };
+
+    #[test]
+    fn test_loop_constant() {
+        let code = r#"
+        int main() {
+            int x = 1;
+            int i = 0;
+            while (i < getint()) {
+                if (x != 1) {
+                    x = 2;
+                }
+                i = i + 1;
+            }
+            return x;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        sccp::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        br label %cond0
+
+        cond0:
+        %phi_37 = phi i32 [0, %entry], [%Add_25, %final6]
+        [-] %phi_35 = phi i32 [1, %entry], [%phi_36, %final6]
+        %call_28 = call i32 @getint()
+        %icmp_30 = icmp slt i32 %phi_37, %call_28
+        br i1 %icmp_30, label %body1, label %final2
+
+        body1:
+        br label %cond3
+
+        final2:
+        br label %exit
+
+        cond3:
+        [-] %icmp_19 = icmp ne i32 %phi_35, 1
+        [-] br i1 %icmp_19, label %then4, label %alt5
+        [+] br label %alt5
+
+        exit:
+        [-] ret i32 %phi_35
+        [+] ret i32 1
+
+        [-] then4:
+        [-] br label %final6
+        [-] 
+        alt5:
+        br label %final6
+
+        final6:
+        [-] %phi_36 = phi i32 [2, %then4], [%phi_35, %alt5]
+        %Add_25 = add i32 %phi_37, 1
+        br label %cond0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_constant_call() {
+        let code = r#"
+        int f(int a, int b) {
+            if (a > 0) {
+                return a + b;
+            }
+            return 0 - b;
+        }
+        int main() {
+            putint(f(3, 4));
+            putint(f(3, 4));
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        sccp::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @f(i32 %a, i32 %b) {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        br label %cond0
+
+        cond0:
+        [-] %icmp_15 = icmp sgt i32 %a, 0
+        [-] br i1 %icmp_15, label %then1, label %alt2
+        [+] br label %then1
+
+        then1:
+        [-] %Add_19 = add i32 %a, %b
+        br label %exit
+
+        [-] alt2:
+        [-] br label %final3
+        [-] 
+        exit:
+        [-] %phi_38 = phi i32 [%Add_19, %then1], [%Sub_24, %final3]
+        [-] ret i32 %phi_38
+        [+] ret i32 7
+
+        [-] final3:
+        [-] %Sub_24 = sub i32 0, %b
+        [-] br label %exit
+
+        [-] 
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_29 = alloca i32
+        [-] %call_32 = call i32 @f(i32 3, i32 4)
+        [-] call void @putint(i32 %call_32)
+        [-] %call_34 = call i32 @f(i32 3, i32 4)
+        [-] call void @putint(i32 %call_34)
+        [+] call void @putint(i32 7)
+        [+] call void @putint(i32 7)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_varying_argument() {
+        let code = r#"
+        int f(int a, int b) {
+            if (a > 0) {
+                return a + b;
+            }
+            return 0 - b;
+        }
+        int main() {
+            putint(f(3, 4));
+            putint(f(getint(), 4));
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        sccp::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @f(i32 %a, i32 %b) {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        br label %cond0
+
+        cond0:
+        %icmp_15 = icmp sgt i32 %a, 0
+        br i1 %icmp_15, label %then1, label %alt2
+
+        then1:
+        [-] %Add_19 = add i32 %a, %b
+        [+] %Add_19 = add i32 %a, 4
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        [-] %phi_39 = phi i32 [%Add_19, %then1], [%Sub_24, %final3]
+        [+] %phi_39 = phi i32 [%Add_19, %then1], [-4, %final3]
+        ret i32 %phi_39
+
+        final3:
+        [-] %Sub_24 = sub i32 0, %b
+        br label %exit
+
+
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_29 = alloca i32
+        %call_32 = call i32 @f(i32 3, i32 4)
+        call void @putint(i32 %call_32)
+        %call_34 = call i32 @getint()
+        %call_35 = call i32 @f(i32 %call_34, i32 4)
+        call void @putint(i32 %call_35)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+}
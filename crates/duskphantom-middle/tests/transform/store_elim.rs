@@ -297,12 +297,12 @@ pub mod tests_store_elim {
         [-] store i32 2, ptr @b
         [-] %load_25 = load i32, ptr @b
         [-] store i32 %load_25, ptr @b
-        %call_34 = call i32 @getint()
-        [-] store i32 %call_34, ptr @b
+        %call_43 = call i32 @getint()
+        [-] store i32 %call_43, ptr @b
         [-] %load_28 = load i32, ptr @a
         [-] %load_29 = load i32, ptr @b
         [-] %Add_30 = add i32 %load_28, %load_29
-        [+] %Add_30 = add i32 3, %call_34
+        [+] %Add_30 = add i32 3, %call_43
         ret i32 %Add_30
 
 
@@ -0,0 +1,290 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+#[cfg(test)]
+pub mod tests_strength_reduce {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{
+            dead_code_elim, inst_combine, load_store_elim, loop_rotate, mem2reg, strength_reduce,
+        },
+    };
+
+    #[test]
+    fn test_exit_test_replaced() {
+        let code = r#"
+        int a[100];
+        int main() {
+            int n = getint();
+            int i = 0;
+            while (i < n) {
+                a[i] = a[i + 1] + 3;
+                i = i + 1;
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        strength_reduce::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [100 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        %call_6 = call i32 @getint()
+        [-] %alloca_8 = alloca i32
+        [+] %getelementptr_33 = getelementptr [100 x i32], ptr @a, i32 0, i32 1
+        [+] %Add_37 = add i32 %call_6, 1
+        [+] %getelementptr_38 = getelementptr [100 x i32], ptr @a, i32 0, i32 %Add_37
+        br label %cond0
+
+        cond0:
+        [-] %phi_32 = phi i32 [0, %entry], [%Add_23, %body1]
+        [-] %icmp_28 = icmp slt i32 %phi_32, %call_6
+        [-] br i1 %icmp_28, label %body1, label %final2
+        [+] %phi_34 = phi i32* [%getelementptr_33, %entry], [%getelementptr_35, %body1]
+        [+] %icmp_39 = icmp slt i32* %phi_34, %getelementptr_38
+        [+] br i1 %icmp_39, label %body1, label %final2
+
+        body1:
+        [-] %Add_15 = add i32 %phi_32, 1
+        [-] %getelementptr_16 = getelementptr [100 x i32], ptr @a, i32 0, i32 %Add_15
+        [-] %load_17 = load i32, ptr %getelementptr_16
+        [+] %load_17 = load i32, ptr %phi_34
+        %Add_18 = add i32 %load_17, 3
+        [-] %getelementptr_20 = getelementptr [100 x i32], ptr @a, i32 0, i32 %phi_32
+        [-] store i32 %Add_18, ptr %getelementptr_20
+        [-] %Add_23 = add i32 %phi_32, 1
+        [+] %getelementptr_36 = getelementptr i32, ptr %phi_34, i32 -1
+        [+] store i32 %Add_18, ptr %getelementptr_36
+        [+] %getelementptr_35 = getelementptr i32, ptr %phi_34, i32 1
+        br label %cond0
+
+        final2:
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_mul() {
+        let code = r#"
+        int a[10][20];
+        int main() {
+            int i = 0;
+            int s = 0;
+            while (i < 10) {
+                a[i][i * 2] = i;
+                s = s + i * 5 + (i + 1) * 5;
+                i = i + 1;
+            }
+            return s;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        strength_reduce::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @a = dso_local global [10 x [20 x i32]] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        [+] %getelementptr_41 = getelementptr [10 x [20 x i32]], ptr @a, i32 0, i32 0, i32 0
+        br label %cond0
+
+        cond0:
+        [+] %phi_44 = phi i32 [0, %entry], [%Add_45, %body1]
+        [+] %phi_42 = phi i32* [%getelementptr_41, %entry], [%getelementptr_43, %body1]
+        %phi_40 = phi i32 [0, %entry], [%Add_27, %body1]
+        %phi_39 = phi i32 [0, %entry], [%Add_30, %body1]
+        %icmp_34 = icmp slt i32 %phi_39, 10
+        br i1 %icmp_34, label %body1, label %final2
+
+        body1:
+        [-] %Mul_14 = mul i32 %phi_39, 2
+        [-] %getelementptr_16 = getelementptr [10 x [20 x i32]], ptr @a, i32 0, i32 %phi_39
+        [-] %getelementptr_17 = getelementptr [20 x i32], ptr %getelementptr_16, i32 0, i32 %Mul_14
+        [-] store i32 %phi_39, ptr %getelementptr_17
+        [-] %Mul_21 = mul i32 %phi_39, 5
+        [-] %Add_23 = add i32 %phi_40, %Mul_21
+        [-] %Add_25 = add i32 %phi_39, 1
+        [-] %Mul_26 = mul i32 %Add_25, 5
+        [-] %Add_27 = add i32 %Add_23, %Mul_26
+        [+] store i32 %phi_39, ptr %phi_42
+        [+] %Add_23 = add i32 %phi_40, %phi_44
+        [+] %Add_46 = add i32 %phi_44, 5
+        [+] %Add_27 = add i32 %Add_23, %Add_46
+        %Add_30 = add i32 %phi_39, 1
+        [+] %getelementptr_43 = getelementptr i32, ptr %phi_42, i32 22
+        [+] %Add_45 = add i32 %phi_44, 5
+        br label %cond0
+
+        final2:
+        br label %exit
+
+        exit:
+        ret i32 %phi_40
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_flattened_walk_alias() {
+        let code = r#"
+        int c[24];
+        int main() {
+            int i = 1;
+            while (i < 2) {
+                c[i] = c[i + 1] + i;
+                i = i + 1;
+            }
+            return c[5];
+        }
+        "#;
+
+        // Loop runs once, so pointer phi is folded into nested GEPs with constant offsets
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        strength_reduce::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        loop_rotate::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        load_store_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        @c = dso_local global [24 x i32] zeroinitializer
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        [-] %getelementptr_32 = getelementptr [24 x i32], ptr @c, i32 0, i32 2
+        br label %entry_rotate6
+
+        entry_rotate6:
+        br label %body1
+
+        body1:
+        [-] %load_14 = load i32, ptr %getelementptr_32
+        [-] %Add_16 = add i32 %load_14, 1
+        [-] %getelementptr_35 = getelementptr i32, ptr %getelementptr_32, i32 -1
+        [-] store i32 %Add_16, ptr %getelementptr_35
+        [-] %Add_21 = add i32 1, 1
+        [-] %getelementptr_34 = getelementptr i32, ptr %getelementptr_32, i32 1
+        br label %cond0
+
+        cond0:
+        br label %final2_dedicated7
+
+        final2_dedicated7:
+        br label %final2
+
+        final2:
+        [-] %getelementptr_27 = getelementptr [24 x i32], ptr @c, i32 0, i32 5
+        [-] %load_28 = load i32, ptr %getelementptr_27
+        br label %exit
+
+        exit:
+        [-] ret i32 %load_28
+        [+] ret i32 0
+
+
+        }
+
+        "###);
+    }
+}
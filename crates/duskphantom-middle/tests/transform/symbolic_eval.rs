@@ -514,6 +514,520 @@ pub mod tests_symbolic_eval {
         ret i32 %Add_9
 
 
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_range_cmp() {
+        let code = r#"
+        int main() {
+            int x = getint();
+            if (x > 0) {
+                if (x < 100) {
+                    int y = x % 10 + 5;
+                    if (y >= 0) putint(1);
+                    if (y > 20) putint(2);
+                    if (x * 2 != 0) putint(3);
+                }
+            }
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        constant_fold::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after),@r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_14 = icmp sgt i32 %call_6, 0
+        br i1 %icmp_14, label %then1, label %alt2
+
+        then1:
+        br label %cond4
+
+        alt2:
+        br label %final3
+
+        cond4:
+        %icmp_22 = icmp slt i32 %call_6, 100
+        br i1 %icmp_22, label %then5, label %alt6
+
+        final3:
+        br label %exit
+
+        then5:
+        [-] %SRem_26 = srem i32 %call_6, 10
+        [-] %Add_27 = add i32 %SRem_26, 5
+        br label %cond8
+
+        alt6:
+        br label %final7
+
+        exit:
+        ret i32 0
+
+        cond8:
+        [-] %icmp_35 = icmp sge i32 %Add_27, 0
+        [-] br i1 %icmp_35, label %then9, label %alt10
+        [+] br label %then9
+
+        final7:
+        br label %final3
+
+        then9:
+        call void @putint(i32 1)
+        br label %final11
+
+        [-] alt10:
+        [-] br label %final11
+        [-] 
+        final11:
+        br label %cond12
+
+        cond12:
+        [-] %icmp_46 = icmp sgt i32 %Add_27, 20
+        [-] br i1 %icmp_46, label %then13, label %alt14
+        [+] br label %alt14
+
+        [-] then13:
+        [-] call void @putint(i32 2)
+        [-] br label %final15
+        [-] 
+        alt14:
+        br label %final15
+
+        final15:
+        br label %cond16
+
+        cond16:
+        [-] %Mul_57 = mul i32 %call_6, 2
+        [-] %icmp_58 = icmp ne i32 %Mul_57, 0
+        [-] br i1 %icmp_58, label %then17, label %alt18
+        [+] br label %then17
+
+        then17:
+        call void @putint(i32 3)
+        [-] br label %final19
+        [-] 
+        [-] alt18:
+        br label %final19
+
+        final19:
+        br label %final7
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_range_div() {
+        let code = r#"
+        int main() {
+            int x = getint();
+            int y = getint();
+            if (x >= 0) putint(x / 8);
+            putint(y / 8);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        constant_fold::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after),@r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %call_9 = call i32 @getint()
+        br label %cond0
+
+        cond0:
+        %icmp_17 = icmp sge i32 %call_6, 0
+        br i1 %icmp_17, label %then1, label %alt2
+
+        then1:
+        [-] %SDiv_20 = sdiv i32 %call_6, 8
+        [-] call void @putint(i32 %SDiv_20)
+        [+] %AShr_29 = ashr i32 %call_6, 3
+        [+] call void @putint(i32 %AShr_29)
+        br label %final3
+
+        alt2:
+        br label %final3
+
+        final3:
+        %SDiv_25 = sdiv i32 %call_9, 8
+        call void @putint(i32 %SDiv_25)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_known_bits() {
+        let code = r#"
+ int main() {
+            int x = getint();
+            putint(x * 16 % 16);
+            if (x * 2 == 1) putint(1);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        constant_fold::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after),@r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        [-] %Mul_9 = mul i32 %call_6, 16
+        [-] %SRem_10 = srem i32 %Mul_9, 16
+        [-] call void @putint(i32 %SRem_10)
+        [+] call void @putint(i32 0)
+        br label %cond0
+
+        cond0:
+        [-] %Mul_18 = mul i32 %call_6, 2
+        [-] %icmp_19 = icmp eq i32 %Mul_18, 1
+        [-] br i1 %icmp_19, label %then1, label %alt2
+        [-] 
+        [-] then1:
+        [-] call void @putint(i32 1)
+        [-] br label %final3
+        [+] br label %alt2
+
+        alt2:
+        br label %final3
+
+        final3:
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_demanded_bits() {
+        let code = r#"
+ int main() {
+            int x = getint();
+            putint((x + 65536) * 65536);
+            putint((x - 2097152) * 2048 + 1);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        constant_fold::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after),@r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        [-] %Add_9 = add i32 %call_6, 65536
+        [-] %Mul_10 = mul i32 %Add_9, 65536
+        [+] %Mul_10 = mul i32 %call_6, 65536
+        call void @putint(i32 %Mul_10)
+        [-] %Sub_13 = sub i32 %call_6, 2097152
+        [-] %Mul_14 = mul i32 %Sub_13, 2048
+        [+] %Mul_14 = mul i32 %call_6, 2048
+        %Add_15 = add i32 %Mul_14, 1
+        call void @putint(i32 %Add_15)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_redundant_shift() {
+        let code = r#"
+ int main() {
+            int c = getint() < 10;
+            putint(c * 4 / 4);
+            putint(c * 8 / 4 * 4);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        constant_fold::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after),@r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %icmp_7 = icmp slt i32 %call_6, 10
+        %zext_8 = zext i1 %icmp_7 to i32
+        [-] %Mul_11 = mul i32 %zext_8, 4
+        [-] %SDiv_12 = sdiv i32 %Mul_11, 4
+        [-] call void @putint(i32 %SDiv_12)
+        [+] call void @putint(i32 %zext_8)
+        %Mul_15 = mul i32 %zext_8, 8
+        [-] %SDiv_16 = sdiv i32 %Mul_15, 4
+        [-] %Mul_17 = mul i32 %SDiv_16, 4
+        [-] call void @putint(i32 %Mul_17)
+        [+] call void @putint(i32 %Mul_15)
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_bool_ext_cmp() {
+        let code = r#"
+ int main() {
+            int c = getint() < 10;
+            if (c) putint(1);
+            if (c == 0) putint(2);
+            if (c < 2) putint(3);
+            return 0;
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        constant_fold::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        inst_combine::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after),@r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @main() {
+        entry:
+        %call_6 = call i32 @getint()
+        %icmp_7 = icmp slt i32 %call_6, 10
+        [-] %zext_8 = zext i1 %icmp_7 to i32
+        br label %cond0
+
+        cond0:
+        [-] %icmp_16 = icmp ne i32 %zext_8, 0
+        [-] br i1 %icmp_16, label %then1, label %alt2
+        [+] br i1 %icmp_7, label %then1, label %alt2
+
+        then1:
+        call void @putint(i32 1)
+        br label %final3
+
+        alt2:
+        br label %final3
+
+        final3:
+        br label %cond4
+
+        cond4:
+        [-] %icmp_27 = icmp eq i32 %zext_8, 0
+        [-] br i1 %icmp_27, label %then5, label %alt6
+        [+] %Xor_45 = xor i1 %icmp_7, true
+        [+] br i1 %Xor_45, label %then5, label %alt6
+
+        then5:
+        call void @putint(i32 2)
+        br label %final7
+
+        alt6:
+        br label %final7
+
+        final7:
+        br label %cond8
+
+        cond8:
+        [-] %icmp_38 = icmp slt i32 %zext_8, 2
+        [-] br i1 %icmp_38, label %then9, label %alt10
+        [+] br label %then9
+
+        then9:
+        call void @putint(i32 3)
+        [-] br label %final11
+        [-] 
+        [-] alt10:
+        br label %final11
+
+        final11:
+        br label %exit
+
+        exit:
+        ret i32 0
+
+
         }
         "###);
     }
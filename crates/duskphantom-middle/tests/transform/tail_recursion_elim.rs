@@ -0,0 +1,354 @@
+// Copyright 2024 Duskphantom Authors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+// SPDX-License-Identifier: Apache-2.0
+use super::*;
+#[cfg(test)]
+pub mod tests_tail_recursion_elim {
+    use insta::assert_snapshot;
+
+    use super::*;
+
+    use duskphantom_frontend::parse;
+    use duskphantom_middle::{
+        irgen::gen,
+        transform::{dead_code_elim, inst_combine, mem2reg, tail_recursion_elim},
+    };
+
+    #[test]
+    fn test_tail_call() {
+        let code = r#"
+        int sum(int n, int acc) {
+            if (n == 0) return acc;
+            return sum(n - 1, acc + n);
+        }
+        int main() {
+            return sum(10, 0);
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        tail_recursion_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @sum(i32 %n, i32 %acc) {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [-] %alloca_7 = alloca i32
+        [+] br label %entry_tailrec9
+        [+] 
+        [+] entry_tailrec9:
+        [+] %phi_41 = phi i32 [%acc, %entry], [%Add_25, %final3]
+        [+] %phi_40 = phi i32 [%n, %entry], [%Sub_22, %final3]
+        br label %cond0
+
+        cond0:
+        [-] %icmp_15 = icmp eq i32 %n, 0
+        [+] %icmp_15 = icmp eq i32 %phi_40, 0
+        br i1 %icmp_15, label %then1, label %alt2
+
+        then1:
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        [-] %phi_37 = phi i32 [%acc, %then1], [%call_26, %final3]
+        [-] ret i32 %phi_37
+        [+] ret i32 %phi_41
+
+        final3:
+        [-] %Sub_22 = sub i32 %n, 1
+        [-] %Add_25 = add i32 %acc, %n
+        [-] %call_26 = call i32 @sum(i32 %Sub_22, i32 %Add_25)
+        [-] br label %exit
+        [+] %Sub_22 = sub i32 %phi_40, 1
+        [+] %Add_25 = add i32 %phi_41, %phi_40
+        [+] br label %entry_tailrec9
+
+
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_31 = alloca i32
+        %call_34 = call i32 @sum(i32 10, i32 0)
+        br label %exit
+
+        exit:
+        ret i32 %call_34
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_accumulator() {
+        let code = r#"
+        int fact(int n) {
+            if (n <= 1) return 1;
+            return n * fact(n - 1);
+        }
+        int main() {
+            return fact(5);
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        tail_recursion_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @fact(i32 %n) {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        [+] br label %entry_tailrec9
+        [+] 
+        [+] entry_tailrec9:
+        [+] %phi_37 = phi i32 [1, %entry], [%Mul_38, %final3]
+        [+] %phi_36 = phi i32 [%n, %entry], [%Sub_19, %final3]
+        br label %cond0
+
+        cond0:
+        [-] %icmp_13 = icmp sle i32 %n, 1
+        [+] %icmp_13 = icmp sle i32 %phi_36, 1
+        br i1 %icmp_13, label %then1, label %alt2
+
+        then1:
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        [-] %phi_33 = phi i32 [1, %then1], [%Mul_22, %final3]
+        [-] ret i32 %phi_33
+        [+] %Mul_39 = mul i32 %phi_37, 1
+        [+] ret i32 %Mul_39
+
+        final3:
+        [-] %Sub_19 = sub i32 %n, 1
+        [-] %call_20 = call i32 @fact(i32 %Sub_19)
+        [-] %Mul_22 = mul i32 %n, %call_20
+        [-] br label %exit
+        [+] %Sub_19 = sub i32 %phi_36, 1
+        [+] %Mul_38 = mul i32 %phi_37, %phi_36
+        [+] br label %entry_tailrec9
+
+
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_27 = alloca i32
+        %call_30 = call i32 @fact(i32 5)
+        br label %exit
+
+        exit:
+        ret i32 %call_30
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_partial_recursion() {
+        let code = r#"
+        int fib(int n) {
+            int b[2] = {1, 2};
+            if (n <= 1) return n;
+            if (n == 5) return fib(b[0]);
+            return fib(n - 1) + fib(n - 2);
+        }
+        int main() {
+            return fib(10);
+        }
+        "#;
+
+        // Check before optimization
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+
+        // Check after optimization
+        tail_recursion_elim::optimize_program(&mut program).unwrap();
+        dead_code_elim::optimize_program(&mut program).unwrap();
+        let llvm_after = program.module.gen_llvm_ir();
+        assert_snapshot!(diff(&llvm_before, &llvm_after), @r###"
+        declare i32 @getint()
+        declare i32 @getch()
+        declare float @getfloat()
+        declare void @putint(i32 %p0)
+        declare void @putch(i32 %p0)
+        declare void @putfloat(float %p0)
+        declare i32 @getarray(i32* %p0)
+        declare i32 @getfarray(float* %p0)
+        declare void @putarray(i32 %p0, i32* %p1)
+        declare void @putfarray(i32 %p0, float* %p1)
+        declare void @_sysy_starttime(i32 %p0)
+        declare void @_sysy_stoptime(i32 %p0)
+        declare i32 @thrd_create(i32 %p0)
+        declare void @thrd_join()
+        declare void @putf()
+        declare void @llvm.memset.p0.i32(i32* %p0, i8 %p1, i32 %p2, i1 %p3)
+        define i32 @fib(i32 %n) {
+        entry:
+        [-] %alloca_2 = alloca i32
+        [-] %alloca_5 = alloca i32
+        %alloca_7 = alloca [2 x i32]
+        [+] br label %entry_tailrec13
+        [+] 
+        [+] entry_tailrec13:
+        [+] %phi_60 = phi i32 [0, %entry], [%phi_60, %then5], [%Add_61, %final7]
+        [+] %phi_59 = phi i32 [%n, %entry], [%load_34, %then5], [%Sub_43, %final7]
+        call void @llvm.memset.p0.i32([2 x i32]* %alloca_7, i8 0, i32 8, i1 false)
+        %getelementptr_9 = getelementptr [2 x i32], ptr %alloca_7, i32 0, i32 0
+        store i32 1, ptr %getelementptr_9
+        %getelementptr_11 = getelementptr [2 x i32], ptr %alloca_7, i32 0, i32 1
+        store i32 2, ptr %getelementptr_11
+        br label %cond0
+
+        cond0:
+        [-] %icmp_19 = icmp sle i32 %n, 1
+        [+] %icmp_19 = icmp sle i32 %phi_59, 1
+        br i1 %icmp_19, label %then1, label %alt2
+
+        then1:
+        br label %exit
+
+        alt2:
+        br label %final3
+
+        exit:
+        [-] %phi_56 = phi i32 [%n, %then1], [%call_35, %then5], [%Add_45, %final7]
+        [-] ret i32 %phi_56
+        [+] %Add_62 = add i32 %phi_60, %phi_59
+        [+] ret i32 %Add_62
+
+        final3:
+        br label %cond4
+
+        cond4:
+        [-] %icmp_31 = icmp eq i32 %n, 5
+        [+] %icmp_31 = icmp eq i32 %phi_59, 5
+        br i1 %icmp_31, label %then5, label %alt6
+
+        then5:
+        %getelementptr_33 = getelementptr [2 x i32], ptr %alloca_7, i32 0, i32 0
+        %load_34 = load i32, ptr %getelementptr_33
+        [-] %call_35 = call i32 @fib(i32 %load_34)
+        [-] br label %exit
+        [+] br label %entry_tailrec13
+
+        alt6:
+        br label %final7
+
+        final7:
+        [-] %Sub_40 = sub i32 %n, 1
+        [+] %Sub_40 = sub i32 %phi_59, 1
+        %call_41 = call i32 @fib(i32 %Sub_40)
+        [-] %Sub_43 = sub i32 %n, 2
+        [-] %call_44 = call i32 @fib(i32 %Sub_43)
+        [-] %Add_45 = add i32 %call_41, %call_44
+        [-] br label %exit
+        [+] %Sub_43 = sub i32 %phi_59, 2
+        [+] %Add_61 = add i32 %phi_60, %call_41
+        [+] br label %entry_tailrec13
+
+
+        }
+        define i32 @main() {
+        entry:
+        [-] %alloca_50 = alloca i32
+        %call_53 = call i32 @fib(i32 10)
+        br label %exit
+
+        exit:
+        ret i32 %call_53
+
+
+        }
+        "###);
+    }
+
+    #[test]
+    fn test_never_return() {
+        let code = r#"
+        int main() {
+            putint(1);
+            while (1) {}
+            return 0;
+        }
+        "#;
+
+        // Exit is pruned as unreachable, leaving nothing to rewrite
+        let parsed = parse(code).unwrap();
+        let mut program = gen(&parsed).unwrap();
+        mem2reg::optimize_program(&mut program).unwrap();
+        inst_combine::optimize_program(&mut program).unwrap();
+        let llvm_before = program.module.gen_llvm_ir();
+        let changed = tail_recursion_elim::optimize_program(&mut program).unwrap();
+        assert!(!changed);
+        assert_eq!(llvm_before, program.module.gen_llvm_ir());
+    }
+}
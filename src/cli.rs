@@ -30,6 +30,24 @@ pub struct Cli {
     pub output: String,
     #[arg(short, long, value_name = "llvm_path")]
     pub ll: Option<String>,
+    /// Print time spent in each middle pass
+    #[arg(long)]
+    pub time_passes: bool,
+    /// Print per-function timing and named counters of middle passes
+    #[arg(long)]
+    pub stats: bool,
+    /// Write collected pass statistics to given path as JSON
+    #[arg(long, value_name = "json_path")]
+    pub stats_json: Option<String>,
+    /// Cost threshold of inlining a call site, measured in IR instructions
+    #[arg(long, value_name = "threshold")]
+    pub inline_threshold: Option<usize>,
+    /// Number of threads a parallelized loop runs on, including main thread
+    #[arg(long, value_name = "threads")]
+    pub threads: Option<usize>,
+    /// Tile interchanged loop nests, running this many inner iterations in each tile
+    #[arg(long, value_name = "size")]
+    pub tile_size: Option<usize>,
 }
 
 #[cfg(test)]
@@ -67,4 +85,46 @@ mod tests {
         assert!(cli.asm);
         assert_eq!(cli.ll, Some("1.ll".to_string()));
     }
+
+    #[test]
+    fn test_stats() {
+        let cli = super::Cli::parse_from([
+            BIN,
+            "1.sy",
+            "-S",
+            "-o",
+            "1.s",
+            "--time-passes",
+            "--stats-json",
+            "stats.json",
+        ]);
+        assert!(cli.time_passes);
+        assert!(!cli.stats);
+        assert_eq!(cli.stats_json, Some("stats.json".to_string()));
+    }
+
+    #[test]
+    fn test_inline_threshold() {
+        let cli =
+            super::Cli::parse_from([BIN, "1.sy", "-S", "-o", "1.s", "--inline-threshold", "50"]);
+        assert_eq!(cli.inline_threshold, Some(50));
+        let cli = super::Cli::parse_from([BIN, "1.sy", "-S", "-o", "1.s"]);
+        assert_eq!(cli.inline_threshold, None);
+    }
+
+    #[test]
+    fn test_threads() {
+        let cli = super::Cli::parse_from([BIN, "1.sy", "-S", "-o", "1.s", "--threads", "4"]);
+        assert_eq!(cli.threads, Some(4));
+        let cli = super::Cli::parse_from([BIN, "1.sy", "-S", "-o", "1.s"]);
+        assert_eq!(cli.threads, None);
+    }
+
+    #[test]
+    fn test_tile_size() {
+        let cli = super::Cli::parse_from([BIN, "1.sy", "-S", "-o", "1.s", "--tile-size", "32"]);
+        assert_eq!(cli.tile_size, Some(32));
+        let cli = super::Cli::parse_from([BIN, "1.sy", "-S", "-o", "1.s"]);
+        assert_eq!(cli.tile_size, None);
+    }
 }
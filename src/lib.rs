@@ -33,6 +33,7 @@ pub use duskphantom_frontend as frontend;
 pub use duskphantom_middle as middle;
 
 use clap::arg;
+use middle::transform::make_parallel::DEFAULT_THREAD_COUNT;
 
 /// compile sysy source code to rv64gc asm
 pub fn compile(cli: &Cli) -> Result<(), CompilerError> {
@@ -43,13 +44,11 @@ pub fn compile(cli: &Cli) -> Result<(), CompilerError> {
     }
 
     let mut program = middle::Program::try_from(program)?;
-    if cli.optimize != 0 {
-        middle::optimize(&mut program, cli.optimize);
-    }
+    let thread_count = optimize_middle(&mut program, cli)?;
     if let Some(ll_path) = cli.ll.as_ref() {
         std::fs::write(ll_path, program.module.gen_llvm_ir()).with_context(|| context!())?;
     }
-    let mut program = backend::from_self::gen_from_self(&program)?;
+    let mut program = backend::from_self::gen_from_self(&program, thread_count)?;
 
     if cli.optimize != 0 {
         backend::optimize(&mut program)?;
@@ -61,6 +60,34 @@ pub fn compile(cli: &Cli) -> Result<(), CompilerError> {
     output(asm, &cli.output, cli.asm)
 }
 
+/// Optimize middle IR with options from command line, and report pass statistics if requested.
+/// Return number of threads parallelized loops run on.
+fn optimize_middle(program: &mut middle::Program, cli: &Cli) -> Result<usize, CompilerError> {
+    if cli.time_passes || cli.stats || cli.stats_json.is_some() {
+        middle::transform::pass_stats::enable();
+    }
+    if let Some(threshold) = cli.inline_threshold {
+        middle::transform::func_inline::set_threshold(threshold);
+    }
+    let thread_count = cli.threads.unwrap_or(DEFAULT_THREAD_COUNT);
+    middle::transform::make_parallel::set_thread_count(thread_count);
+    if let Some(size) = cli.tile_size {
+        middle::transform::loop_interchange::set_tile_size(Some(size));
+    }
+    if cli.optimize != 0 {
+        middle::optimize(program, cli.optimize);
+    }
+    if let Some(stats) = middle::transform::pass_stats::take() {
+        if cli.time_passes || cli.stats {
+            eprint!("{}", stats.to_table(cli.stats));
+        }
+        if let Some(json_path) = cli.stats_json.as_ref() {
+            std::fs::write(json_path, stats.to_json()?).with_context(|| context!())?;
+        }
+    }
+    Ok(thread_count)
+}
+
 #[cfg(feature = "clang_enabled")]
 /// compile from clang
 pub fn compile_clang(
@@ -78,7 +105,7 @@ pub fn compile_clang(
     if let Some(ll_path) = ll_path {
         std::fs::write(ll_path, program.emit_llvm_ir()).map_err(CompilerError::IOError)?;
     }
-    let mut program = backend::from_llvm::gen_from_clang(&program)
+    let mut program = backend::from_llvm::gen_from_clang(&program, DEFAULT_THREAD_COUNT)
         .map_err(|e| BackendError::GenFromLlvmError(format!("{e:?}")))?;
     if opt_flag {
         backend::optimize(&mut program)?;
@@ -120,9 +147,7 @@ pub fn compile_self_llc(cli: &Cli) -> Result<(), CompilerError> {
     }
 
     let mut program = middle::Program::try_from(program)?;
-    if cli.optimize != 0 {
-        middle::optimize(&mut program, cli.optimize);
-    }
+    optimize_middle(&mut program, cli)?;
 
     // 中端接clang
     let llvm_ir = program.module.gen_llvm_ir();